@@ -0,0 +1,68 @@
+// Reports the active tab to `vigil browser-host`, which forwards it to the running collector.
+// Only the host name is sent unless full URL capture is enabled on the options page.
+const api = globalThis.browser ?? globalThis.chrome;
+const HOST_NAME = "vigil.browser_host";
+
+let port = null;
+let lastMessage = null;
+
+function connect() {
+  if (port) {
+    return port;
+  }
+  port = api.runtime.connectNative(HOST_NAME);
+  port.onDisconnect.addListener(() => {
+    port = null;
+    lastMessage = null;
+  });
+  return port;
+}
+
+async function describeTab(tab) {
+  if (!tab || !tab.url) {
+    return { domain: null };
+  }
+  let parsed;
+  try {
+    parsed = new URL(tab.url);
+  } catch {
+    return { domain: null };
+  }
+  if (parsed.protocol !== "http:" && parsed.protocol !== "https:") {
+    return { domain: null };
+  }
+  const { sendFullUrls } = await api.storage.local.get({ sendFullUrls: false });
+  return { domain: parsed.hostname, url: sendFullUrls ? tab.url : null };
+}
+
+async function reportActiveTab() {
+  const [tab] = await api.tabs.query({ active: true, lastFocusedWindow: true });
+  const message = await describeTab(tab);
+  const encoded = JSON.stringify(message);
+  if (encoded === lastMessage) {
+    return;
+  }
+  lastMessage = encoded;
+  connect().postMessage(message);
+}
+
+api.tabs.onActivated.addListener(() => reportActiveTab());
+api.tabs.onUpdated.addListener((_tabId, changeInfo, tab) => {
+  if (tab.active && changeInfo.url) {
+    reportActiveTab();
+  }
+});
+api.windows.onFocusChanged.addListener((windowId) => {
+  if (windowId === api.windows.WINDOW_ID_NONE) {
+    lastMessage = null;
+    connect().postMessage({ domain: null });
+  } else {
+    reportActiveTab();
+  }
+});
+api.storage.onChanged.addListener(() => {
+  lastMessage = null;
+  reportActiveTab();
+});
+
+reportActiveTab();
@@ -0,0 +1,10 @@
+const api = globalThis.browser ?? globalThis.chrome;
+const checkbox = document.getElementById("send-full-urls");
+
+api.storage.local.get({ sendFullUrls: false }).then(({ sendFullUrls }) => {
+  checkbox.checked = sendFullUrls;
+});
+
+checkbox.addEventListener("change", () => {
+  api.storage.local.set({ sendFullUrls: checkbox.checked });
+});
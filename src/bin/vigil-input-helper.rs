@@ -0,0 +1,347 @@
+//! `vigil-input-helper`: the only part of vigil that needs to read `/dev/input`.
+//!
+//! It opens the keyboards and mice once, gives up the `input` group right after, and serves
+//! per-second activity counters on a Unix socket to the collector (see
+//! `platform::linux::input_helper::protocol`). Key codes never leave this process, only how
+//! many keys were pressed, so a collector that is compromised or just misconfigured can't turn
+//! into a keylogger. Install it setgid `input`, or run it as a service with
+//! `CAP_DAC_READ_SEARCH`, instead of adding your user to the `input` group.
+
+#[cfg(target_os = "linux")]
+#[path = "../input_bindings.rs"]
+mod input_bindings;
+
+#[cfg(target_os = "linux")]
+#[path = "../platform/linux/evdev.rs"]
+mod evdev;
+
+#[cfg(target_os = "linux")]
+#[path = "../platform/linux/input_helper/protocol.rs"]
+mod protocol;
+
+#[cfg(target_os = "linux")]
+mod helper {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+    use clap::Parser;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::signal::unix::{signal, SignalKind};
+    use tokio::sync::{broadcast, mpsc};
+    use tracing::*;
+    use tracing_subscriber::EnvFilter;
+
+    use crate::evdev::{
+        spawn_input_listeners, DeviceFilter, DeviceRule, DroppedEvents, InputEvent, KeyPressState,
+    };
+    use crate::input_bindings::*;
+    use crate::protocol::{helper_socket_path, HelperDevice, HelperMessage, InputCounters};
+
+    /// How often counters are sent to the collector.
+    const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+    #[derive(Debug, Parser)]
+    #[command(
+        name = "vigil-input-helper",
+        version,
+        about = "Reads /dev/input on behalf of the vigil collector and serves activity counters"
+    )]
+    struct Args {
+        /// Socket to listen on. Defaults to $VIGIL_INPUT_HELPER_SOCKET, or
+        /// vigil-input-helper.sock in $XDG_RUNTIME_DIR.
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        /// User allowed to connect; repeat for several. Defaults to the user running the helper.
+        #[arg(long = "allow-uid")]
+        allow_uids: Vec<u32>,
+        /// Always count matching devices: name:<text>, bus:<bus>, virtual or physical.
+        #[arg(long = "include-device", value_name = "RULE")]
+        include_devices: Vec<DeviceRule>,
+        /// Ignore matching devices. Virtual devices are ignored unless included.
+        #[arg(long = "exclude-device", value_name = "RULE")]
+        exclude_devices: Vec<DeviceRule>,
+    }
+
+    /// Sums up the events of one report interval.
+    #[derive(Debug, Default)]
+    struct Aggregator {
+        counters: InputCounters,
+        /// Pointer deltas per device since its last `SYN_REPORT`.
+        pending_motion: HashMap<usize, (f64, f64)>,
+    }
+
+    impl Aggregator {
+        fn record(&mut self, input: InputEvent) {
+            match input {
+                InputEvent::Keyboard { event } => {
+                    if event.type_ as u32 == EV_KEY && event.value == KeyPressState::Down as i32 {
+                        self.counters.key_presses += 1;
+                    }
+                }
+                InputEvent::Mouse { device_id, event } => match event.type_ as u32 {
+                    EV_KEY if event.value == KeyPressState::Down as i32 => {
+                        match event.code as u32 {
+                            BTN_LEFT => self.counters.left_clicks += 1,
+                            BTN_RIGHT => self.counters.right_clicks += 1,
+                            BTN_MIDDLE => self.counters.middle_clicks += 1,
+                            _ => {}
+                        }
+                    }
+                    EV_REL => {
+                        let value = event.value as f64;
+                        match event.code as u32 {
+                            REL_X => self.pending_motion.entry(device_id).or_default().0 += value,
+                            REL_Y => self.pending_motion.entry(device_id).or_default().1 += value,
+                            REL_WHEEL => self.counters.scroll_vertical_steps += value.abs(),
+                            REL_HWHEEL => self.counters.scroll_horizontal_steps += value.abs(),
+                            _ => {}
+                        }
+                    }
+                    EV_SYN if event.code as u32 == SYN_REPORT => {
+                        if let Some((dx, dy)) = self.pending_motion.remove(&device_id) {
+                            self.counters.mouse_counts += dx.hypot(dy);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        /// Counters since the previous call; motion without its report yet waits for the next.
+        fn take(&mut self) -> InputCounters {
+            std::mem::take(&mut self.counters)
+        }
+    }
+
+    /// Gives up the group the helper was installed with, once the devices are open.
+    fn drop_group_privileges() -> Result<()> {
+        let gid = nix::unistd::getgid();
+        nix::unistd::setresgid(gid, gid, gid)
+            .with_context(|| "Failed to drop the helper's group privileges")?;
+        Ok(())
+    }
+
+    fn bind_socket(path: &Path, shared: bool) -> Result<UnixListener> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                bail!("{} exists and is not a socket", path.display());
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove the stale {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        // Other users' collectors are still checked against --allow-uid once they connect.
+        let mode = if shared { 0o666 } else { 0o600 };
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        Ok(listener)
+    }
+
+    /// One message per line.
+    fn encode(message: &HelperMessage) -> Result<Vec<u8>> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+
+    async fn serve_client(
+        mut stream: UnixStream,
+        devices: Arc<Vec<HelperDevice>>,
+        mut counters: broadcast::Receiver<InputCounters>,
+    ) -> Result<()> {
+        let devices = HelperMessage::Devices {
+            devices: devices.as_ref().clone(),
+        };
+        stream.write_all(&encode(&devices)?).await?;
+
+        loop {
+            let next = match counters.recv().await {
+                Ok(next) => next,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Collector fell behind, {missed} reports were not delivered");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            stream
+                .write_all(&encode(&HelperMessage::Counters(next))?)
+                .await?;
+        }
+    }
+
+    pub async fn run() -> Result<()> {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+            )
+            .init();
+
+        let args = Args::parse();
+        let socket = args
+            .socket
+            .or_else(helper_socket_path)
+            .with_context(|| "No socket given and XDG_RUNTIME_DIR is not set")?;
+        let shared = !args.allow_uids.is_empty();
+        let allowed_uids = if shared {
+            args.allow_uids
+        } else {
+            vec![nix::unistd::getuid().as_raw()]
+        };
+
+        let (events_tx, mut events_rx) = mpsc::channel::<Vec<InputEvent>>(256);
+        let filter = DeviceFilter {
+            include: args.include_devices,
+            exclude: args.exclude_devices,
+        };
+        let dropped = DroppedEvents::default();
+        let devices = spawn_input_listeners(events_tx, filter, dropped.clone()).await?;
+        drop_group_privileges()?;
+        let devices = Arc::new(
+            devices
+                .into_iter()
+                .map(|device| HelperDevice {
+                    kind: device.kind.to_string(),
+                    path: device.path,
+                    name: device.name,
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let listener = bind_socket(&socket, shared)?;
+        info!("Serving input counters on {}", socket.display());
+
+        let (counters_tx, _) = broadcast::channel::<InputCounters>(16);
+        let mut aggregator = Aggregator::default();
+        let mut report = tokio::time::interval(REPORT_INTERVAL);
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+
+        loop {
+            tokio::select! {
+                Some(events) = events_rx.recv() => {
+                    for event in events {
+                        aggregator.record(event);
+                    }
+                }
+                _ = report.tick() => {
+                    let mut counters = aggregator.take();
+                    counters.dropped_events = dropped.take();
+                    if !counters.is_empty() {
+                        // No receivers just means no collector is connected right now.
+                        let _ = counters_tx.send(counters);
+                    }
+                }
+                // don't leave a socket behind that collectors would keep trying
+                _ = terminate.recv() => break,
+                _ = interrupt.recv() => break,
+                accepted = listener.accept() => {
+                    // One failed connection must not stop counting for everyone else.
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(err) => {
+                            warn!("Failed to accept a connection: {err}");
+                            continue;
+                        }
+                    };
+                    let uid = match stream.peer_cred() {
+                        Ok(cred) => cred.uid(),
+                        Err(err) => {
+                            warn!("Refused a connection whose peer credentials could not be read: {err}");
+                            continue;
+                        }
+                    };
+                    if !allowed_uids.contains(&uid) {
+                        warn!("Refused a connection from uid {uid}");
+                        continue;
+                    }
+                    debug!("Collector of uid {uid} connected");
+                    let devices = devices.clone();
+                    let counters = counters_tx.subscribe();
+                    tokio::spawn(async move {
+                        if let Err(err) = serve_client(stream, devices, counters).await {
+                            debug!("Collector disconnected: {err}");
+                        }
+                    });
+                }
+            }
+        }
+
+        info!("Stopping, removing {}", socket.display());
+        if let Err(err) = fs::remove_file(&socket) {
+            warn!("Failed to remove {}: {err}", socket.display());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn event(type_: u32, code: u32, value: i32) -> input_event {
+            input_event {
+                time: timeval {
+                    tv_sec: 0 as _,
+                    tv_usec: 0 as _,
+                },
+                type_: type_ as u16,
+                code: code as u16,
+                value,
+            }
+        }
+
+        /// Verifies that only key presses are counted, without key codes, and that pointer
+        /// motion is measured per report so diagonal moves aren't counted twice.
+        #[test]
+        fn aggregator_counts_presses_and_reported_motion() {
+            let mut aggregator = Aggregator::default();
+            let keyboard = |type_, code, value| InputEvent::Keyboard {
+                event: event(type_, code, value),
+            };
+            let mouse = |type_, code, value| InputEvent::Mouse {
+                device_id: 0,
+                event: event(type_, code, value),
+            };
+            aggregator.record(keyboard(EV_KEY, 30, 1));
+            aggregator.record(keyboard(EV_KEY, 30, 2));
+            aggregator.record(keyboard(EV_KEY, 30, 0));
+            aggregator.record(mouse(EV_KEY, BTN_LEFT, 1));
+            aggregator.record(mouse(EV_REL, REL_X, 3));
+            aggregator.record(mouse(EV_REL, REL_Y, -4));
+            aggregator.record(mouse(EV_SYN, SYN_REPORT, 0));
+            aggregator.record(mouse(EV_REL, REL_WHEEL, -2));
+            aggregator.record(mouse(EV_REL, REL_X, 7));
+
+            assert_eq!(
+                aggregator.take(),
+                InputCounters {
+                    key_presses: 1,
+                    left_clicks: 1,
+                    mouse_counts: 5.0,
+                    scroll_vertical_steps: 2.0,
+                    ..InputCounters::default()
+                }
+            );
+            aggregator.record(mouse(EV_SYN, SYN_REPORT, 0));
+            assert_eq!(aggregator.take().mouse_counts, 7.0);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    helper::run().await
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("vigil-input-helper only exists for Linux");
+    std::process::exit(1);
+}
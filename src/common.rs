@@ -1,17 +1,32 @@
 //! Shared runtime types and helpers used across platforms.
 
+mod browser;
 mod buckets;
+#[cfg(target_os = "linux")]
+mod clock;
+mod domains;
 mod focus;
 mod input;
 mod motion;
 mod paths;
 mod process;
+mod project;
+mod project_buckets;
+mod terminal;
+mod terminal_buckets;
 #[cfg(target_os = "linux")]
 mod ticker;
 mod types;
 
+#[allow(unused_imports)]
+pub use browser::{is_browser_app, normalize_domain, BrowserTab};
 #[allow(unused_imports)]
 pub use buckets::{bucket_metadata, BucketMetadata};
+#[cfg(target_os = "linux")]
+#[allow(unused_imports)]
+pub use clock::{ClockGap, ClockSample, ClockWatch};
+#[allow(unused_imports)]
+pub use domains::DomainBucketBuffer;
 #[allow(unused_imports)]
 pub use focus::FocusBucketBuffer;
 pub use input::InputBucketBuffer;
@@ -24,11 +39,20 @@ pub use motion::{
 };
 pub use paths::program_data_dir;
 pub use process::ProcessTracker;
+#[allow(unused_imports)]
+pub use project::{find_git_root, resolve_project, Project};
+#[allow(unused_imports)]
+pub use project_buckets::ProjectBucketBuffer;
+#[allow(unused_imports)]
+pub use terminal::{is_terminal_app, TerminalContext};
+#[allow(unused_imports)]
+pub use terminal_buckets::TerminalBucketBuffer;
 #[cfg(target_os = "linux")]
 pub use ticker::spawn_ticker;
 #[allow(unused_imports)]
 pub use types::{
-    FocusBucketRecord, InputBucketRecord, InputLogger, Signals, SourceInfo, Window,
-    WindowsSpecific, ASSUMED_CM_PER_SCROLL_STEP, DEFAULT_BUCKET_MINUTES, DEFAULT_MOUSE_DPI,
-    DEFAULT_SOURCE_ID,
+    CollectorEventKind, CollectorEventRecord, DomainBucketRecord, FocusBucketRecord,
+    InputBucketRecord, InputLogger, ProjectBucketRecord, Signals, SourceInfo, SuspendDetection,
+    SuspendRecord, TerminalBucketRecord, Window, WindowsSpecific, ASSUMED_CM_PER_SCROLL_STEP,
+    DEFAULT_BUCKET_MINUTES, DEFAULT_MOUSE_DPI, DEFAULT_SOURCE_ID,
 };
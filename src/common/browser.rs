@@ -0,0 +1,60 @@
+/// Active browser tab as reported by the browser extension through `vigil browser-host`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BrowserTab {
+    pub domain: String,
+    /// Only present when the user opted in to full URL capture in the extension options.
+    pub url: Option<String>,
+}
+
+/// Returns true for app identifiers of browsers that can host the Vigil extension.
+pub fn is_browser_app(app_identifier: &str) -> bool {
+    matches!(
+        app_identifier,
+        "brave-browser"
+            | "brave.exe"
+            | "firefox"
+            | "firefox.exe"
+            | "chromium"
+            | "chromium-browser"
+            | "chrome"
+            | "chrome.exe"
+            | "google-chrome"
+    )
+}
+
+/// Normalizes a reported host name into the domain we store: lowercase, without port,
+/// trailing dot or leading `www.`. Returns `None` for anything that is not a plain host name.
+pub fn normalize_domain(raw: &str) -> Option<String> {
+    let host = raw.trim().trim_end_matches('.').to_ascii_lowercase();
+    let host = host.split(':').next().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+
+    let valid = !host.is_empty()
+        && host.len() <= 253
+        && host
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= 63)
+        && host
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '.');
+    valid.then(|| host.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verifies that reported hosts collapse to one stored domain by normalizing case, ports,
+    /// trailing dots and `www.` prefixes, while rejecting values that are not host names.
+    #[test]
+    fn normalize_domain_strips_noise_and_rejects_invalid_hosts() {
+        assert_eq!(
+            normalize_domain("WWW.GitHub.com:443"),
+            Some("github.com".to_string())
+        );
+        assert_eq!(normalize_domain("docs.rs."), Some("docs.rs".to_string()));
+        assert_eq!(normalize_domain(""), None);
+        assert_eq!(normalize_domain("bad host"), None);
+        assert_eq!(normalize_domain("a..b"), None);
+    }
+}
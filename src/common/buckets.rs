@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
 use chrono::{DateTime, Datelike, FixedOffset, Offset, TimeZone, Timelike, Utc};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,6 +52,76 @@ fn bucket_metadata_with_offset(
     }
 }
 
+/// Focus time per bucket and key, the buffer behind the focus, domain, terminal and project
+/// tables. Intervals are split at bucket boundaries, so every row only carries its own slice.
+#[derive(Debug)]
+pub struct KeyedBucketBuffer<K, R> {
+    source_id: i64,
+    granularity_minutes: u32,
+    buckets: HashMap<(DateTime<Utc>, K), R>,
+}
+
+impl<K, R> Default for KeyedBucketBuffer<K, R> {
+    fn default() -> Self {
+        Self {
+            source_id: 0,
+            granularity_minutes: 0,
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash + Ord, R> KeyedBucketBuffer<K, R> {
+    pub fn new(source_id: i64, granularity_minutes: u32) -> Self {
+        Self {
+            source_id,
+            granularity_minutes,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Adds `[start, end)` to the rows of `key`, one per bucket it touches. Missing rows are
+    /// made by `new_row` from the source id and the bucket, then `add` gets each row and the
+    /// seconds that fell into its bucket.
+    pub fn record_interval(
+        &mut self,
+        key: &K,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        new_row: impl Fn(i64, BucketMetadata) -> R,
+        mut add: impl FnMut(&mut R, u64),
+    ) {
+        let mut cursor = start;
+        while cursor < end {
+            let meta = bucket_metadata(cursor, self.granularity_minutes);
+            let segment_end = end.min(meta.bucket_end_utc);
+            let seconds = (segment_end - cursor).num_seconds() as u64;
+
+            if seconds > 0 {
+                let row = self
+                    .buckets
+                    .entry((meta.bucket_start_utc, key.clone()))
+                    .or_insert_with(|| new_row(self.source_id, meta));
+                add(row, seconds);
+            }
+
+            cursor = segment_end;
+        }
+    }
+
+    /// Number of bucket rows waiting to be written.
+    pub fn pending_rows(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Takes the buffered rows, ordered by bucket start and then key.
+    pub fn drain(&mut self) -> Vec<R> {
+        let mut rows = self.buckets.drain().collect::<Vec<_>>();
+        rows.sort_by(|(left, _), (right, _)| left.cmp(right));
+        rows.into_iter().map(|(_, row)| row).collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +148,36 @@ mod tests {
         assert_eq!(bucket.local_hour, 10);
         assert_eq!(bucket.timezone_offset_minutes, -180);
     }
+
+    /// Verifies that the keyed buffer adds up repeated intervals per bucket and key, lets the
+    /// caller update carried columns, and drains rows ordered by bucket and key.
+    #[test]
+    fn keyed_buffer_merges_per_bucket_and_key_and_drains_in_order() {
+        let mut buffer = KeyedBucketBuffer::<String, (String, u64, u32)>::new(1, 15);
+        let at = |minute, second| {
+            Utc.with_ymd_and_hms(2026, 4, 18, 12, minute, second)
+                .unwrap()
+        };
+        let mut record = |key: &str, start, end| {
+            buffer.record_interval(
+                &key.to_string(),
+                start,
+                end,
+                |_, meta| (key.to_string(), 0, meta.local_hour),
+                |row, seconds| row.1 += seconds,
+            )
+        };
+        record("b", at(14, 0), at(16, 0));
+        record("a", at(10, 0), at(10, 20));
+        record("a", at(11, 0), at(11, 10));
+        record("a", at(20, 0), at(20, 0));
+
+        let rows = buffer.drain();
+        let keys_and_seconds = rows
+            .iter()
+            .map(|(key, seconds, _)| (key.as_str(), *seconds))
+            .collect::<Vec<_>>();
+        assert_eq!(keys_and_seconds, [("a", 30), ("b", 60), ("b", 60)]);
+        assert_eq!(buffer.pending_rows(), 0);
+    }
 }
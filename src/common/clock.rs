@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use nix::time::{clock_gettime, ClockId};
+
+/// Divergence between the clocks that we attribute to a suspend or a clock change rather than
+/// to scheduling jitter.
+const GAP_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// The wall clock plus the two kernel clocks that tell a suspend from a clock change:
+/// `CLOCK_MONOTONIC` stops while the machine is suspended, `CLOCK_BOOTTIME` keeps counting,
+/// and neither follows NTP steps or manual changes of the wall clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    pub wall: DateTime<Utc>,
+    pub monotonic: Duration,
+    pub boottime: Duration,
+}
+
+impl ClockSample {
+    pub fn now() -> Self {
+        Self {
+            wall: Utc::now(),
+            monotonic: read_clock(ClockId::CLOCK_MONOTONIC),
+            boottime: read_clock(ClockId::CLOCK_BOOTTIME),
+        }
+    }
+}
+
+fn read_clock(clock: ClockId) -> Duration {
+    clock_gettime(clock).map(Duration::from).unwrap_or_default()
+}
+
+/// Time between two samples that must not count as focus time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockGap {
+    /// Wall-clock time up to which the machine was awake since the previous sample.
+    pub awake_until: DateTime<Utc>,
+    /// Wall-clock time of the sample that revealed the gap.
+    pub observed_at: DateTime<Utc>,
+    /// How long the machine was suspended in between; zero when only the wall clock jumped.
+    pub suspended: Duration,
+}
+
+impl ClockGap {
+    /// Whether the gap is a suspend rather than just a change of the wall clock.
+    pub fn is_suspend(&self) -> bool {
+        self.suspended >= GAP_THRESHOLD
+    }
+}
+
+/// Compares consecutive clock samples to catch suspends nobody announced and wall-clock jumps.
+#[derive(Debug)]
+pub struct ClockWatch {
+    last: ClockSample,
+}
+
+impl ClockWatch {
+    pub fn new(sample: ClockSample) -> Self {
+        Self { last: sample }
+    }
+
+    /// Starts over from `sample`, e.g. after a suspend that was already accounted for.
+    pub fn reset(&mut self, sample: ClockSample) {
+        self.last = sample;
+    }
+
+    pub fn observe(&mut self, sample: ClockSample) -> Option<ClockGap> {
+        let last = std::mem::replace(&mut self.last, sample);
+        let awake = sample.monotonic.saturating_sub(last.monotonic);
+        let suspended = sample
+            .boottime
+            .saturating_sub(last.boottime)
+            .saturating_sub(awake);
+
+        let awake_delta = chrono::Duration::from_std(awake).unwrap_or_default();
+        let suspended_delta = chrono::Duration::from_std(suspended).unwrap_or_default();
+        let jump = (sample.wall - last.wall) - awake_delta - suspended_delta;
+        let threshold = chrono::Duration::from_std(GAP_THRESHOLD).unwrap_or_default();
+        if suspended < GAP_THRESHOLD && jump.abs() < threshold {
+            return None;
+        }
+
+        Some(ClockGap {
+            awake_until: last.wall + awake_delta,
+            observed_at: sample.wall,
+            suspended,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(wall_secs: i64, monotonic_secs: u64, boottime_secs: u64) -> ClockSample {
+        ClockSample {
+            wall: Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap()
+                + chrono::Duration::seconds(wall_secs),
+            monotonic: Duration::from_secs(monotonic_secs),
+            boottime: Duration::from_secs(boottime_secs),
+        }
+    }
+
+    /// Verifies the three cases the watch tells apart: ordinary ticks, a suspend where only
+    /// the boot clock kept counting, and a wall-clock step while the machine stayed awake.
+    #[test]
+    fn clock_watch_separates_suspends_from_clock_jumps() {
+        let mut watch = ClockWatch::new(sample(0, 100, 100));
+        assert_eq!(watch.observe(sample(5, 105, 105)), None);
+
+        let suspend = watch.observe(sample(3605, 107, 3705)).unwrap();
+        assert_eq!(suspend.awake_until, sample(7, 0, 0).wall);
+        assert_eq!(suspend.observed_at, sample(3605, 0, 0).wall);
+        assert_eq!(suspend.suspended, Duration::from_secs(3598));
+        assert!(suspend.is_suspend());
+
+        let jump = watch.observe(sample(3000, 110, 3708)).unwrap();
+        assert_eq!(jump.awake_until, sample(3608, 0, 0).wall);
+        assert_eq!(jump.observed_at, sample(3000, 0, 0).wall);
+        assert_eq!(jump.suspended, Duration::ZERO);
+        assert!(!jump.is_suspend());
+
+        assert_eq!(watch.observe(sample(3001, 111, 3709)), None);
+    }
+}
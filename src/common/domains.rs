@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+
+use super::browser::BrowserTab;
+use super::buckets::KeyedBucketBuffer;
+use super::types::{DomainBucketRecord, Window};
+
+/// Browser focus rows keyed by app and domain.
+#[derive(Debug, Default)]
+pub struct DomainBucketBuffer {
+    buckets: KeyedBucketBuffer<(String, String), DomainBucketRecord>,
+}
+
+impl DomainBucketBuffer {
+    pub fn new(source_id: i64, granularity_minutes: u32) -> Self {
+        Self {
+            buckets: KeyedBucketBuffer::new(source_id, granularity_minutes),
+        }
+    }
+
+    pub fn record_interval(
+        &mut self,
+        window: &Window,
+        tab: &BrowserTab,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) {
+        let app_identifier = window.app_identifier();
+        self.buckets.record_interval(
+            &(app_identifier.clone(), tab.domain.clone()),
+            start,
+            end,
+            |source_id, meta| DomainBucketRecord {
+                source_id,
+                bucket_start_utc: meta.bucket_start_utc,
+                bucket_end_utc: meta.bucket_end_utc,
+                local_date: meta.local_date,
+                local_hour: meta.local_hour,
+                timezone_offset_minutes: meta.timezone_offset_minutes,
+                app_identifier: app_identifier.clone(),
+                domain: tab.domain.clone(),
+                last_url: None,
+                focus_seconds: 0,
+            },
+            |row, seconds| {
+                row.focus_seconds += seconds;
+                if tab.url.is_some() {
+                    row.last_url = tab.url.clone();
+                }
+            },
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn drain(&mut self) -> Vec<DomainBucketRecord> {
+        self.buckets.drain()
+    }
+}
@@ -1,85 +1,50 @@
-use std::collections::HashMap;
-
 use chrono::{DateTime, Utc};
 
-use super::buckets::bucket_metadata;
+use super::buckets::KeyedBucketBuffer;
 use super::types::{FocusBucketRecord, Window};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct FocusBucketKey {
-    source_id: i64,
-    bucket_start_utc: DateTime<Utc>,
-    window_title: String,
-    window_class: String,
-}
-
+/// Focus rows keyed by window class and title.
 #[derive(Debug, Default)]
 pub struct FocusBucketBuffer {
-    source_id: i64,
-    granularity_minutes: u32,
-    buckets: HashMap<FocusBucketKey, FocusBucketRecord>,
+    buckets: KeyedBucketBuffer<(String, String), FocusBucketRecord>,
 }
 
 impl FocusBucketBuffer {
     pub fn new(source_id: i64, granularity_minutes: u32) -> Self {
         Self {
-            source_id,
-            granularity_minutes,
-            buckets: HashMap::new(),
+            buckets: KeyedBucketBuffer::new(source_id, granularity_minutes),
         }
     }
 
     pub fn record_interval(&mut self, window: &Window, start: DateTime<Utc>, end: DateTime<Utc>) {
-        if end <= start {
-            return;
-        }
-
-        let mut cursor = start;
-        while cursor < end {
-            let meta = bucket_metadata(cursor, self.granularity_minutes);
-            let segment_end = end.min(meta.bucket_end_utc);
-            let seconds = (segment_end - cursor).num_seconds() as u64;
-
-            if seconds > 0 {
-                let key = FocusBucketKey {
-                    source_id: self.source_id,
-                    bucket_start_utc: meta.bucket_start_utc,
-                    window_title: window.name.clone(),
-                    window_class: window.class.clone(),
-                };
-
-                let record = self
-                    .buckets
-                    .entry(key)
-                    .or_insert_with(|| FocusBucketRecord {
-                        source_id: self.source_id,
-                        bucket_start_utc: meta.bucket_start_utc,
-                        bucket_end_utc: meta.bucket_end_utc,
-                        local_date: meta.local_date,
-                        local_hour: meta.local_hour,
-                        timezone_offset_minutes: meta.timezone_offset_minutes,
-                        app_identifier: window.app_identifier(),
-                        window_title: window.name.clone(),
-                        window_class: window.class.clone(),
-                        focus_seconds: 0,
-                    });
-
-                record.focus_seconds += seconds;
-            }
+        self.buckets.record_interval(
+            &(window.class.clone(), window.name.clone()),
+            start,
+            end,
+            |source_id, meta| FocusBucketRecord {
+                source_id,
+                bucket_start_utc: meta.bucket_start_utc,
+                bucket_end_utc: meta.bucket_end_utc,
+                local_date: meta.local_date,
+                local_hour: meta.local_hour,
+                timezone_offset_minutes: meta.timezone_offset_minutes,
+                app_identifier: window.app_identifier(),
+                window_title: window.name.clone(),
+                window_class: window.class.clone(),
+                focus_seconds: 0,
+            },
+            |row, seconds| row.focus_seconds += seconds,
+        );
+    }
 
-            cursor = segment_end;
-        }
+    /// Number of bucket rows waiting to be written.
+    #[allow(dead_code)]
+    pub fn pending_rows(&self) -> usize {
+        self.buckets.pending_rows()
     }
 
     pub fn drain(&mut self) -> Vec<FocusBucketRecord> {
-        let mut rows = self.buckets.drain().map(|(_, row)| row).collect::<Vec<_>>();
-        rows.sort_by(|left, right| {
-            left.bucket_start_utc
-                .cmp(&right.bucket_start_utc)
-                .then_with(|| left.window_class.cmp(&right.window_class))
-                .then_with(|| left.window_title.cmp(&right.window_title))
-        });
-        rows
+        self.buckets.drain()
     }
 }
 
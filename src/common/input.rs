@@ -51,6 +51,7 @@ impl InputBucketBuffer {
                 mouse_distance_cm: 0.0,
                 scroll_vertical_cm: 0.0,
                 scroll_horizontal_cm: 0.0,
+                dropped_events: 0,
             })
     }
 
@@ -70,6 +71,19 @@ impl InputBucketBuffer {
         self.bucket_mut(at).middle_clicks += 1;
     }
 
+    /// Adds presses counted elsewhere, e.g. by the input helper.
+    pub fn record_key_presses(&mut self, at: DateTime<Utc>, count: u64) {
+        self.bucket_mut(at).key_presses += count;
+    }
+
+    /// Adds clicks counted elsewhere, e.g. by the input helper.
+    pub fn record_clicks(&mut self, at: DateTime<Utc>, left: u64, right: u64, middle: u64) {
+        let bucket = self.bucket_mut(at);
+        bucket.left_clicks += left;
+        bucket.right_clicks += right;
+        bucket.middle_clicks += middle;
+    }
+
     pub fn record_mouse_distance_cm(&mut self, at: DateTime<Utc>, distance_cm: f64) {
         self.bucket_mut(at).mouse_distance_cm += distance_cm;
     }
@@ -82,6 +96,17 @@ impl InputBucketBuffer {
         self.bucket_mut(at).scroll_horizontal_cm += distance_cm;
     }
 
+    /// Marks the bucket as incomplete by `count` events that could not be processed.
+    pub fn record_dropped_events(&mut self, at: DateTime<Utc>, count: u64) {
+        self.bucket_mut(at).dropped_events += count;
+    }
+
+    /// Number of bucket rows waiting to be written.
+    #[allow(dead_code)]
+    pub fn pending_rows(&self) -> usize {
+        self.buckets.len()
+    }
+
     pub fn drain(&mut self) -> Vec<InputBucketRecord> {
         let mut rows = self.buckets.drain().map(|(_, row)| row).collect::<Vec<_>>();
         rows.sort_by_key(|row| row.bucket_start_utc);
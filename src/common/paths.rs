@@ -1,6 +1,6 @@
 use std::env;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Returns a platform-specific path for storing program-related files and ensures the directory exists.
 pub fn program_data_dir() -> io::Result<PathBuf> {
@@ -45,3 +45,21 @@ pub fn program_data_dir() -> io::Result<PathBuf> {
     std::fs::create_dir_all(&path)?;
     Ok(path)
 }
+
+/// Shows `path` relative to the home directory as `~/...`, leaving other paths untouched.
+pub fn abbreviate_home(path: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Reverses [`abbreviate_home`]: `~` and `~/...` are resolved against the home directory.
+pub fn expand_home_path(path: &str, home: Option<&Path>) -> PathBuf {
+    match (path, home) {
+        ("~", Some(home)) => home.to_path_buf(),
+        (path, Some(home)) if path.starts_with("~/") => home.join(&path[2..]),
+        (path, _) => PathBuf::from(path),
+    }
+}
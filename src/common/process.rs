@@ -1,21 +1,47 @@
+use std::path::PathBuf;
+
 use chrono::{DateTime, Utc};
 
+use super::browser::{is_browser_app, BrowserTab};
+use super::domains::DomainBucketBuffer;
 use super::focus::FocusBucketBuffer;
-use super::types::{FocusBucketRecord, Window};
+use super::project::{resolve_project, Project};
+use super::project_buckets::ProjectBucketBuffer;
+use super::terminal::{is_terminal_app, TerminalContext};
+use super::terminal_buckets::TerminalBucketBuffer;
+#[cfg(target_os = "linux")]
+use super::types::{DomainBucketRecord, TerminalBucketRecord};
+use super::types::{FocusBucketRecord, ProjectBucketRecord, Window};
 
 #[derive(Debug)]
 pub struct ProcessTracker {
     pending: FocusBucketBuffer,
+    pending_domains: DomainBucketBuffer,
+    pending_terminals: TerminalBucketBuffer,
+    pending_projects: ProjectBucketBuffer,
     active_window: Option<Window>,
     active_since_utc: Option<DateTime<Utc>>,
+    browser_tab: Option<BrowserTab>,
+    terminal_context: Option<TerminalContext>,
+    /// Project of the active window, re-resolved whenever the window or its terminal context
+    /// changes.
+    project: Option<Project>,
+    home_dir: Option<PathBuf>,
 }
 
 impl ProcessTracker {
     pub fn new(source_id: i64, granularity_minutes: u32) -> Self {
         Self {
             pending: FocusBucketBuffer::new(source_id, granularity_minutes),
+            pending_domains: DomainBucketBuffer::new(source_id, granularity_minutes),
+            pending_terminals: TerminalBucketBuffer::new(source_id, granularity_minutes),
+            pending_projects: ProjectBucketBuffer::new(source_id, granularity_minutes),
             active_window: None,
             active_since_utc: None,
+            browser_tab: None,
+            terminal_context: None,
+            project: None,
+            home_dir: std::env::var_os("HOME").map(PathBuf::from),
         }
     }
 
@@ -23,6 +49,9 @@ impl ProcessTracker {
         self.record_active_until(now);
         self.active_window = Some(window);
         self.active_since_utc = Some(now);
+        // The context belonged to the previous window; the caller resolves the new one.
+        self.terminal_context = None;
+        self.refresh_project();
     }
 
     pub fn pause(&mut self, now: DateTime<Utc>) {
@@ -30,6 +59,17 @@ impl ProcessTracker {
         self.active_since_utc = None;
     }
 
+    /// Ends the running interval at `until` and restarts it at `resumed_at`, so the time in
+    /// between (a suspend or a wall-clock jump) is not attributed to the window.
+    #[cfg(target_os = "linux")]
+    pub fn skip_gap(&mut self, until: DateTime<Utc>, resumed_at: DateTime<Utc>) {
+        if self.active_since_utc.is_none() {
+            return;
+        }
+        self.record_active_until(until);
+        self.active_since_utc = Some(resumed_at);
+    }
+
     pub fn resume(&mut self, now: DateTime<Utc>) {
         if self.active_window.is_some() && self.active_since_utc.is_none() {
             self.active_since_utc = Some(now);
@@ -43,32 +83,235 @@ impl ProcessTracker {
         let Some(start) = self.active_since_utc else {
             return;
         };
+        // Buckets count whole seconds; the rest carries over, so recording often (e.g. for the
+        // spool) doesn't shave time off.
+        let now = start + chrono::Duration::seconds((now - start).num_seconds());
 
         self.pending.record_interval(window, start, now);
+        if let Some(tab) = self.browser_tab.as_ref() {
+            if is_browser_app(&window.app_identifier()) {
+                self.pending_domains
+                    .record_interval(window, tab, start, now);
+            }
+        }
+        if let Some(context) = self.terminal_context.as_ref() {
+            if is_terminal_app(&window.app_identifier()) {
+                self.pending_terminals
+                    .record_interval(window, context, start, now);
+            }
+        }
+        if let Some(project) = self.project.as_ref() {
+            self.pending_projects
+                .record_interval(window, project, start, now);
+        }
         self.active_since_utc = Some(now);
     }
 
+    /// Switches the browser tab that browser focus time is attributed to.
+    #[cfg(target_os = "linux")]
+    pub fn set_browser_tab(&mut self, tab: Option<BrowserTab>, now: DateTime<Utc>) {
+        self.record_active_until(now);
+        self.browser_tab = tab;
+    }
+
+    /// Switches the foreground command that terminal focus time is attributed to.
+    #[cfg(target_os = "linux")]
+    pub fn set_terminal_context(&mut self, context: Option<TerminalContext>, now: DateTime<Utc>) {
+        if self.terminal_context == context {
+            return;
+        }
+        self.record_active_until(now);
+        self.terminal_context = context;
+        self.refresh_project();
+    }
+
+    fn refresh_project(&mut self) {
+        self.project = self.active_window.as_ref().and_then(|window| {
+            resolve_project(
+                window,
+                self.terminal_context.as_ref(),
+                self.home_dir.as_deref(),
+            )
+        });
+    }
+
     pub fn clear_focus(&mut self, now: DateTime<Utc>) {
         self.record_active_until(now);
         self.active_window = None;
         self.active_since_utc = None;
+        self.project = None;
     }
 
-    #[allow(dead_code)]
+    #[cfg(any(target_os = "windows", feature = "x11"))]
     pub fn current_window_name(&self) -> Option<&str> {
         self.active_window
             .as_ref()
             .map(|window| window.name.as_str())
     }
 
-    #[allow(dead_code)]
+    #[cfg(any(target_os = "windows", feature = "x11"))]
     pub fn current_window_class(&self) -> Option<&str> {
         self.active_window
             .as_ref()
             .map(|window| window.class.as_str())
     }
 
+    #[cfg(target_os = "linux")]
+    pub fn current_window(&self) -> Option<&Window> {
+        self.active_window.as_ref()
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn pending_rows(&self) -> usize {
+        self.pending.pending_rows()
+    }
+
     pub fn drain_pending(&mut self) -> Vec<FocusBucketRecord> {
         self.pending.drain()
     }
+
+    #[cfg(target_os = "linux")]
+    pub fn drain_pending_domains(&mut self) -> Vec<DomainBucketRecord> {
+        self.pending_domains.drain()
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn drain_pending_terminals(&mut self) -> Vec<TerminalBucketRecord> {
+        self.pending_terminals.drain()
+    }
+
+    pub fn drain_pending_projects(&mut self) -> Vec<ProjectBucketRecord> {
+        self.pending_projects.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::DEFAULT_SOURCE_ID;
+    use chrono::TimeZone;
+
+    /// Verifies that only browser focus time is attributed to the reported tab by tracking a
+    /// browser, switching tabs mid-interval, then switching to an editor with the tab still set.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn tracker_attributes_browser_time_to_reported_domains() {
+        let mut tracker = ProcessTracker::new(DEFAULT_SOURCE_ID, 15);
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        let browser = Window {
+            name: "Pull requests".to_string(),
+            class: "firefox".to_string(),
+        };
+        let editor = Window {
+            name: "main.rs".to_string(),
+            class: "code".to_string(),
+        };
+
+        tracker.set_browser_tab(
+            Some(BrowserTab {
+                domain: "github.com".to_string(),
+                url: None,
+            }),
+            start,
+        );
+        tracker.switch_window(browser, start);
+        tracker.set_browser_tab(
+            Some(BrowserTab {
+                domain: "docs.rs".to_string(),
+                url: Some("https://docs.rs/tokio".to_string()),
+            }),
+            start + chrono::Duration::seconds(60),
+        );
+        tracker.switch_window(editor, start + chrono::Duration::seconds(90));
+        tracker.record_active_until(start + chrono::Duration::seconds(300));
+
+        let domains = tracker.drain_pending_domains();
+        assert_eq!(domains.len(), 2);
+        assert_eq!(domains[0].domain, "docs.rs");
+        assert_eq!(domains[0].focus_seconds, 30);
+        assert_eq!(
+            domains[0].last_url.as_deref(),
+            Some("https://docs.rs/tokio")
+        );
+        assert_eq!(domains[1].domain, "github.com");
+        assert_eq!(domains[1].focus_seconds, 60);
+
+        let focus_seconds = tracker
+            .drain_pending()
+            .iter()
+            .map(|row| row.focus_seconds)
+            .sum::<u64>();
+        assert_eq!(focus_seconds, 300);
+    }
+
+    /// Verifies that a terminal context only covers the terminal window it was resolved for,
+    /// by switching commands mid-interval and then focusing an editor.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn tracker_drops_terminal_context_when_focus_moves() {
+        let mut tracker = ProcessTracker::new(DEFAULT_SOURCE_ID, 15);
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        let context = |command: &str| TerminalContext {
+            command: command.to_string(),
+            working_dir: "~/work/vigil".to_string(),
+        };
+
+        tracker.switch_window(
+            Window {
+                name: "~/work/vigil".to_string(),
+                class: "kitty".to_string(),
+            },
+            start,
+        );
+        tracker.set_terminal_context(Some(context("zsh")), start);
+        tracker.set_terminal_context(
+            Some(context("cargo")),
+            start + chrono::Duration::seconds(20),
+        );
+        tracker.switch_window(
+            Window {
+                name: "main.rs".to_string(),
+                class: "code".to_string(),
+            },
+            start + chrono::Duration::seconds(80),
+        );
+        tracker.record_active_until(start + chrono::Duration::seconds(200));
+
+        let terminals = tracker.drain_pending_terminals();
+        assert_eq!(terminals.len(), 2);
+        assert_eq!(terminals[0].command, "cargo");
+        assert_eq!(terminals[0].focus_seconds, 60);
+        assert_eq!(terminals[1].command, "zsh");
+        assert_eq!(terminals[1].focus_seconds, 20);
+    }
+
+    /// Verifies that editor time is attributed to the project named in its title and that
+    /// windows without a project add nothing, by focusing VS Code and then a browser.
+    #[test]
+    fn tracker_attributes_editor_time_to_projects() {
+        let mut tracker = ProcessTracker::new(DEFAULT_SOURCE_ID, 15);
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+
+        tracker.switch_window(
+            Window {
+                name: "main.rs - vigil - Visual Studio Code".to_string(),
+                class: "code".to_string(),
+            },
+            start,
+        );
+        tracker.switch_window(
+            Window {
+                name: "Pull requests".to_string(),
+                class: "firefox".to_string(),
+            },
+            start + chrono::Duration::seconds(45),
+        );
+        tracker.record_active_until(start + chrono::Duration::seconds(100));
+
+        let projects = tracker.drain_pending_projects();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].project, "vigil");
+        assert_eq!(projects[0].app_identifier, "code");
+        assert_eq!(projects[0].focus_seconds, 45);
+    }
 }
@@ -0,0 +1,235 @@
+use std::path::{Path, PathBuf};
+
+use super::paths::{abbreviate_home, expand_home_path};
+use super::terminal::{is_terminal_app, TerminalContext};
+use super::types::Window;
+
+/// Project a focus interval belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Project {
+    /// Repository or workspace folder name, e.g. `vigil`.
+    pub name: String,
+    /// Repository root with the home directory shown as `~`, when it could be located.
+    pub root: Option<String>,
+}
+
+/// Derives the project of the focused window from the signals we have: Neovim titles name the
+/// edited file's directory, terminals report their foreground job's working directory, and
+/// VS Code and JetBrains titles name the open workspace. Paths are resolved to their git
+/// repository root; a terminal outside a repository has no project.
+pub fn resolve_project(
+    window: &Window,
+    terminal: Option<&TerminalContext>,
+    home: Option<&Path>,
+) -> Option<Project> {
+    let app_identifier = window.app_identifier();
+    let title = window.name.trim();
+
+    if let Some(dir) = neovim_title_dir(title) {
+        return project_from_path(&expand_home_path(dir, home), home);
+    }
+    if is_terminal_app(&app_identifier) {
+        let working_dir = expand_home_path(&terminal?.working_dir, home);
+        return project_from_path(&working_dir, home);
+    }
+    if is_vscode_app(&app_identifier) {
+        return vscode_title_folder(title).map(|name| Project { name, root: None });
+    }
+    if is_jetbrains_app(&app_identifier) {
+        return jetbrains_title_project(title, home);
+    }
+    None
+}
+
+fn is_vscode_app(app_identifier: &str) -> bool {
+    matches!(
+        app_identifier,
+        "code"
+            | "code.exe"
+            | "code-oss"
+            | "codium"
+            | "codium.exe"
+            | "vscodium"
+            | "cursor"
+            | "cursor.exe"
+    )
+}
+
+fn is_jetbrains_app(app_identifier: &str) -> bool {
+    app_identifier.starts_with("jetbrains-")
+        || matches!(
+            app_identifier,
+            "rider" | "rider64.exe" | "clion" | "clion64.exe" | "idea" | "idea64.exe"
+        )
+}
+
+/// The folder VS Code shows before its product name: `main.rs - vigil - Visual Studio Code`.
+fn vscode_title_folder(title: &str) -> Option<String> {
+    let title = title.trim_start_matches('●').trim();
+    let parts = title
+        .split(" - ")
+        .flat_map(|part| part.split(" — "))
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+    // The last part is the product name. With a file open the folder is the part between
+    // them, whatever it is called. A lone part before the product is either a folder with no
+    // file open or a file opened without a folder, so a name with an extension is skipped.
+    let (folder, may_be_file) = match parts.as_slice() {
+        [.., _file, folder, _product] => (*folder, false),
+        [folder, _product] => (*folder, true),
+        _ => return None,
+    };
+    let folder = folder
+        .split_once(" [")
+        .map_or(folder, |(name, _)| name)
+        .trim();
+    if folder.is_empty() || (may_be_file && Path::new(folder).extension().is_some()) {
+        return None;
+    }
+    Some(folder.to_string())
+}
+
+/// JetBrains titles lead with the project: `vigil – main.rs` or `vigil [~/work/vigil] – main.rs`.
+fn jetbrains_title_project(title: &str, home: Option<&Path>) -> Option<Project> {
+    let (head, _) = title.split_once(" – ")?;
+    let head = head.trim();
+    if head.is_empty() {
+        return None;
+    }
+    match head.split_once(" [") {
+        Some((name, rest)) => {
+            let root = rest.trim_end_matches(']');
+            Some(Project {
+                name: name.trim().to_string(),
+                root: Some(abbreviate_home(&expand_home_path(root, home), home)),
+            })
+        }
+        None => Some(Project {
+            name: head.to_string(),
+            root: None,
+        }),
+    }
+}
+
+/// Neovim's default title is `main.rs (~/work/vigil/src) - NVIM`; returns the directory part.
+fn neovim_title_dir(title: &str) -> Option<&str> {
+    let lowered = title.to_ascii_lowercase();
+    if !lowered.ends_with("- nvim") && !lowered.ends_with("- vim") {
+        return None;
+    }
+    let open = title.rfind(" (")?;
+    let close = title[open..].find(')')? + open;
+    Some(&title[open + 2..close])
+}
+
+fn project_from_path(path: &Path, home: Option<&Path>) -> Option<Project> {
+    // Relative paths would be resolved against the collector's own working directory.
+    if !path.is_absolute() {
+        return None;
+    }
+    let root = find_git_root(path)?;
+    let name = root.file_name()?.to_string_lossy().into_owned();
+    Some(Project {
+        name,
+        root: Some(abbreviate_home(&root, home)),
+    })
+}
+
+/// Walks up from `path` to the closest directory containing `.git`.
+pub fn find_git_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn window(class: &str, title: &str) -> Window {
+        Window {
+            name: title.to_string(),
+            class: class.to_string(),
+        }
+    }
+
+    /// Verifies the editor title patterns we recognise, including VS Code folders with dots in
+    /// their name, and that unrelated titles or editors without an open folder yield no project.
+    #[test]
+    fn editor_titles_name_their_project() {
+        let vscode = resolve_project(
+            &window("Code", "● main.rs - vigil - Visual Studio Code"),
+            None,
+            None,
+        );
+        assert_eq!(vscode.unwrap().name, "vigil");
+        let dotted = resolve_project(
+            &window("code", "README.md - user.github.io - Visual Studio Code"),
+            None,
+            None,
+        );
+        assert_eq!(dotted.unwrap().name, "user.github.io");
+        let folder_only =
+            resolve_project(&window("code", "vigil - Visual Studio Code"), None, None);
+        assert_eq!(folder_only.unwrap().name, "vigil");
+        assert!(
+            resolve_project(&window("code", "main.rs - Visual Studio Code"), None, None).is_none()
+        );
+        assert!(resolve_project(&window("code", "Visual Studio Code"), None, None).is_none());
+
+        let home = Path::new("/home/ana");
+        let jetbrains = resolve_project(
+            &window("jetbrains-rustrover", "vigil [~/work/vigil] – main.rs"),
+            None,
+            Some(home),
+        )
+        .unwrap();
+        assert_eq!(jetbrains.name, "vigil");
+        assert_eq!(jetbrains.root.as_deref(), Some("~/work/vigil"));
+
+        assert!(resolve_project(&window("firefox", "vigil - GitHub"), None, None).is_none());
+    }
+
+    /// Verifies that terminal working directories and Neovim titles resolve to the enclosing
+    /// git repository, using a throwaway repository layout on disk.
+    #[test]
+    fn paths_resolve_to_their_git_root() -> std::io::Result<()> {
+        let home = std::env::temp_dir().join(format!("vigil-project-{}", Uuid::new_v4()));
+        let repo = home.join("work").join("vigil");
+        std::fs::create_dir_all(repo.join(".git"))?;
+        std::fs::create_dir_all(repo.join("src"))?;
+        std::fs::create_dir_all(home.join("scratch"))?;
+
+        let context = TerminalContext {
+            command: "cargo".to_string(),
+            working_dir: "~/work/vigil/src".to_string(),
+        };
+        let project = resolve_project(&window("kitty", "zsh"), Some(&context), Some(&home));
+        assert_eq!(
+            project,
+            Some(Project {
+                name: "vigil".to_string(),
+                root: Some("~/work/vigil".to_string()),
+            })
+        );
+
+        let outside = TerminalContext {
+            command: "zsh".to_string(),
+            working_dir: "~/scratch".to_string(),
+        };
+        assert!(resolve_project(&window("kitty", "zsh"), Some(&outside), Some(&home)).is_none());
+        // The edited file wins over the directory the terminal was started in.
+        let nvim = window("kitty", "main.rs (~/work/vigil/src) - NVIM");
+        assert_eq!(
+            resolve_project(&nvim, Some(&outside), Some(&home))
+                .unwrap()
+                .name,
+            "vigil"
+        );
+
+        std::fs::remove_dir_all(&home)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+
+use super::buckets::KeyedBucketBuffer;
+use super::project::Project;
+use super::types::{ProjectBucketRecord, Window};
+
+/// Project focus rows keyed by project and app.
+#[derive(Debug, Default)]
+pub struct ProjectBucketBuffer {
+    buckets: KeyedBucketBuffer<(String, String), ProjectBucketRecord>,
+}
+
+impl ProjectBucketBuffer {
+    pub fn new(source_id: i64, granularity_minutes: u32) -> Self {
+        Self {
+            buckets: KeyedBucketBuffer::new(source_id, granularity_minutes),
+        }
+    }
+
+    pub fn record_interval(
+        &mut self,
+        window: &Window,
+        project: &Project,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) {
+        let app_identifier = window.app_identifier();
+        self.buckets.record_interval(
+            &(project.name.clone(), app_identifier.clone()),
+            start,
+            end,
+            |source_id, meta| ProjectBucketRecord {
+                source_id,
+                bucket_start_utc: meta.bucket_start_utc,
+                bucket_end_utc: meta.bucket_end_utc,
+                local_date: meta.local_date,
+                local_hour: meta.local_hour,
+                timezone_offset_minutes: meta.timezone_offset_minutes,
+                project: project.name.clone(),
+                project_root: None,
+                app_identifier: app_identifier.clone(),
+                focus_seconds: 0,
+            },
+            |row, seconds| {
+                row.focus_seconds += seconds;
+                // Editors that only show a folder name don't know the root; keep one we learned.
+                if project.root.is_some() {
+                    row.project_root = project.root.clone();
+                }
+            },
+        );
+    }
+
+    pub fn drain(&mut self) -> Vec<ProjectBucketRecord> {
+        self.buckets.drain()
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use super::paths::abbreviate_home;
+
+/// What the focused terminal window is running, resolved from its shell's foreground job.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TerminalContext {
+    /// Name of the foreground process, e.g. `cargo` or `nvim`.
+    pub command: String,
+    /// Working directory of the foreground process, with the home directory shown as `~`.
+    pub working_dir: String,
+}
+
+impl TerminalContext {
+    pub fn new(command: impl Into<String>, working_dir: &Path, home: Option<&Path>) -> Self {
+        Self {
+            command: command.into(),
+            working_dir: abbreviate_home(working_dir, home),
+        }
+    }
+
+    /// Human readable form, e.g. `cargo in ~/work/vigil`.
+    #[allow(dead_code)]
+    pub fn label(&self) -> String {
+        format!("{} in {}", self.command, self.working_dir)
+    }
+}
+
+/// Returns true for app identifiers of terminal emulators whose shells we can inspect.
+pub fn is_terminal_app(app_identifier: &str) -> bool {
+    matches!(
+        app_identifier,
+        "com.mitchellh.ghostty"
+            | "ghostty"
+            | "kitty"
+            | "wezterm"
+            | "wezterm-gui"
+            | "org.wezfurlong.wezterm"
+            | "alacritty"
+            | "foot"
+            | "konsole"
+            | "org.kde.konsole"
+            | "xterm"
+            | "gnome-terminal-server"
+            | "gnome-terminal"
+            | "org.gnome.terminal"
+            // text sessions reported by the console/SSH backend
+            | "ssh"
+            | "tty"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verifies that working directories under the home directory are shortened to `~` while
+    /// other paths are kept as-is, and that the label reads like `cargo in ~/work/vigil`.
+    #[test]
+    fn terminal_context_abbreviates_home_directory() {
+        let home = Path::new("/home/ana");
+        let context = TerminalContext::new("cargo", Path::new("/home/ana/work/vigil"), Some(home));
+        assert_eq!(context.label(), "cargo in ~/work/vigil");
+        assert_eq!(
+            TerminalContext::new("zsh", home, Some(home)).working_dir,
+            "~"
+        );
+        assert_eq!(
+            TerminalContext::new("htop", Path::new("/etc"), Some(home)).working_dir,
+            "/etc"
+        );
+        assert_eq!(
+            TerminalContext::new("ls", Path::new("/home/anabel"), Some(home)).working_dir,
+            "/home/anabel"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+
+use super::buckets::KeyedBucketBuffer;
+use super::terminal::TerminalContext;
+use super::types::{TerminalBucketRecord, Window};
+
+/// Terminal focus rows keyed by app, command and working directory.
+#[derive(Debug, Default)]
+pub struct TerminalBucketBuffer {
+    buckets: KeyedBucketBuffer<(String, String, String), TerminalBucketRecord>,
+}
+
+impl TerminalBucketBuffer {
+    pub fn new(source_id: i64, granularity_minutes: u32) -> Self {
+        Self {
+            buckets: KeyedBucketBuffer::new(source_id, granularity_minutes),
+        }
+    }
+
+    pub fn record_interval(
+        &mut self,
+        window: &Window,
+        context: &TerminalContext,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) {
+        let app_identifier = window.app_identifier();
+        self.buckets.record_interval(
+            &(
+                app_identifier.clone(),
+                context.command.clone(),
+                context.working_dir.clone(),
+            ),
+            start,
+            end,
+            |source_id, meta| TerminalBucketRecord {
+                source_id,
+                bucket_start_utc: meta.bucket_start_utc,
+                bucket_end_utc: meta.bucket_end_utc,
+                local_date: meta.local_date,
+                local_hour: meta.local_hour,
+                timezone_offset_minutes: meta.timezone_offset_minutes,
+                app_identifier: app_identifier.clone(),
+                command: context.command.clone(),
+                working_dir: context.working_dir.clone(),
+                focus_seconds: 0,
+            },
+            |row, seconds| row.focus_seconds += seconds,
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn drain(&mut self) -> Vec<TerminalBucketRecord> {
+        self.buckets.drain()
+    }
+}
@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 pub const DEFAULT_SOURCE_ID: i64 = 1;
@@ -25,7 +26,7 @@ impl Window {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InputBucketRecord {
     pub source_id: i64,
     pub bucket_start_utc: DateTime<Utc>,
@@ -41,9 +42,12 @@ pub struct InputBucketRecord {
     pub mouse_distance_cm: f64,
     pub scroll_vertical_cm: f64,
     pub scroll_horizontal_cm: f64,
+    /// Events the collector had to drop because its channel was full; kept locally as a
+    /// sign that the counts of this bucket are incomplete.
+    pub dropped_events: u64,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FocusBucketRecord {
     pub source_id: i64,
     pub bucket_start_utc: DateTime<Utc>,
@@ -57,6 +61,115 @@ pub struct FocusBucketRecord {
     pub focus_seconds: u64,
 }
 
+/// Focus seconds a terminal window spent running one foreground command in one directory.
+/// Stored next to `focus_buckets`, which keep the whole terminal window time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerminalBucketRecord {
+    pub source_id: i64,
+    pub bucket_start_utc: DateTime<Utc>,
+    pub bucket_end_utc: DateTime<Utc>,
+    pub local_date: String,
+    pub local_hour: u32,
+    pub timezone_offset_minutes: i32,
+    pub app_identifier: String,
+    pub command: String,
+    pub working_dir: String,
+    pub focus_seconds: u64,
+}
+
+/// Focus seconds attributed to one project inside a bucket. Stored next to `focus_buckets`,
+/// which keep the whole window time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectBucketRecord {
+    pub source_id: i64,
+    pub bucket_start_utc: DateTime<Utc>,
+    pub bucket_end_utc: DateTime<Utc>,
+    pub local_date: String,
+    pub local_hour: u32,
+    pub timezone_offset_minutes: i32,
+    pub project: String,
+    pub project_root: Option<String>,
+    pub app_identifier: String,
+    pub focus_seconds: u64,
+}
+
+/// Focus seconds spent on one browser domain inside a bucket. Stored next to
+/// `focus_buckets`, which keep the whole browser window time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainBucketRecord {
+    pub source_id: i64,
+    pub bucket_start_utc: DateTime<Utc>,
+    pub bucket_end_utc: DateTime<Utc>,
+    pub local_date: String,
+    pub local_hour: u32,
+    pub timezone_offset_minutes: i32,
+    pub app_identifier: String,
+    pub domain: String,
+    pub last_url: Option<String>,
+    pub focus_seconds: u64,
+}
+
+/// How a suspend period was noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SuspendDetection {
+    /// logind announced it through `PrepareForSleep`.
+    Logind,
+    /// The boot clock kept counting while the monotonic clock stood still.
+    ClockCheck,
+}
+
+impl SuspendDetection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SuspendDetection::Logind => "logind",
+            SuspendDetection::ClockCheck => "clock",
+        }
+    }
+}
+
+/// A stretch of time the machine spent suspended, kept as a timeline next to the buckets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SuspendRecord {
+    pub source_id: i64,
+    pub started_at_utc: DateTime<Utc>,
+    pub ended_at_utc: DateTime<Utc>,
+    pub detected_by: SuspendDetection,
+}
+
+/// What happened to one of the collector's supervised tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollectorEventKind {
+    /// The task returned an error.
+    Error,
+    /// The task panicked.
+    Panic,
+    /// The task returned although it should run for as long as the collector.
+    Exited,
+    /// The supervisor started the task again after a backoff.
+    Restarted,
+}
+
+impl CollectorEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CollectorEventKind::Error => "error",
+            CollectorEventKind::Panic => "panic",
+            CollectorEventKind::Exited => "exited",
+            CollectorEventKind::Restarted => "restarted",
+        }
+    }
+}
+
+/// A failure or restart of a collector task, kept so the dashboard can show them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectorEventRecord {
+    pub source_id: i64,
+    pub occurred_at_utc: DateTime<Utc>,
+    pub task: String,
+    pub kind: CollectorEventKind,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceInfo {
     pub id: i64,
@@ -0,0 +1,10 @@
+//! Local control socket for a running collector.
+
+mod protocol;
+mod server;
+mod state;
+
+#[allow(unused_imports)]
+pub use protocol::{ControlDevice, ControlRequest, ControlResponse, ControlStatus, ControlWindow};
+pub use server::{control_socket_path, send_control_request, serve};
+pub use state::{next_signal, CollectorControl, ControlSignal};
@@ -0,0 +1,100 @@
+//! Wire format of the control socket.
+//!
+//! Every request and response is a single line of JSON. Requests name a `command`:
+//!
+//! ```text
+//! {"command":"status"}
+//! {"command":"flush-now"}
+//! {"command":"pause"}
+//! {"command":"resume"}
+//! {"command":"reload-config"}
+//! {"command":"shutdown"}
+//! {"command":"browser-tab","domain":"github.com","url":null}
+//! ```
+//!
+//! Responses always carry `ok`, plus either `data` or `error`:
+//!
+//! ```text
+//! {"ok":true,"data":{"paused":true}}
+//! {"ok":false,"error":"unknown command"}
+//! ```
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum ControlRequest {
+    Status,
+    FlushNow,
+    Pause,
+    Resume,
+    ReloadConfig,
+    Shutdown,
+    /// Sent by `vigil browser-host`. A missing domain means no tab is active anymore.
+    BrowserTab {
+        #[serde(default)]
+        domain: Option<String>,
+        #[serde(default)]
+        url: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ControlResponse {
+    pub fn success(data: Value) -> Self {
+        Self {
+            ok: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub fn failure(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlDevice {
+    /// `keyboard` or `mouse`.
+    pub kind: String,
+    pub path: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlWindow {
+    pub app_identifier: String,
+    pub title: String,
+    pub class: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlStatus {
+    pub pid: u32,
+    pub started_at_utc: String,
+    pub uptime_seconds: u64,
+    pub paused: bool,
+    pub locked: bool,
+    pub db_path: String,
+    pub devices: Vec<ControlDevice>,
+    pub current_window: Option<ControlWindow>,
+    pub browser_domain: Option<String>,
+    pub buffered_input_rows: usize,
+    pub buffered_focus_rows: usize,
+    pub database_reachable: bool,
+    /// Rows kept in the offline database until the database is reachable again.
+    pub offline_rows: u64,
+}
@@ -0,0 +1,269 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::*;
+
+use crate::common::{normalize_domain, program_data_dir, BrowserTab};
+use crate::utils::dpi::load_mouse_dpi;
+
+use super::protocol::{ControlRequest, ControlResponse};
+use super::state::CollectorControl;
+
+const CONTROL_SOCKET_NAME: &str = "control.sock";
+
+pub fn control_socket_path() -> Result<PathBuf> {
+    Ok(program_data_dir()
+        .with_context(|| "Could not determine directory for the control socket")?
+        .join(CONTROL_SOCKET_NAME))
+}
+
+/// Removes the socket file once the collector stops listening.
+struct SocketFileGuard {
+    path: PathBuf,
+}
+
+impl Drop for SocketFileGuard {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!(
+                    "Failed to remove control socket {}: {err}",
+                    self.path.display()
+                );
+            }
+        }
+    }
+}
+
+/// Listens on the control socket until the collector exits.
+///
+/// Callers must already hold the single-instance lock: any socket left at the path is assumed
+/// to be stale and is replaced.
+pub async fn serve(path: PathBuf, control: CollectorControl) -> Result<()> {
+    let listener = bind_control_socket(&path)?;
+    let _guard = SocketFileGuard { path: path.clone() };
+    info!("Control socket listening at {}", path.display());
+
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .with_context(|| "Failed to accept control socket connection")?;
+        let control = control.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, control).await {
+                warn!("Control connection failed: {err:#}");
+            }
+        });
+    }
+}
+
+fn bind_control_socket(path: &Path) -> Result<UnixListener> {
+    match std::fs::remove_file(path) {
+        Ok(()) => debug!("Removed stale control socket {}", path.display()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err).with_context(|| {
+                format!("Failed to remove stale control socket {}", path.display())
+            })
+        }
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket at {}", path.display()))?;
+    // Anyone who can talk to the socket can pause or stop collection, so keep it owner-only.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).with_context(|| {
+        format!(
+            "Failed to restrict permissions of control socket {}",
+            path.display()
+        )
+    })?;
+    Ok(listener)
+}
+
+async fn handle_connection(stream: UnixStream, control: CollectorControl) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => handle_request(request, &control).await,
+            Err(err) => ControlResponse::failure(format!("Invalid control request: {err}")),
+        };
+
+        let mut encoded = serde_json::to_vec(&response)?;
+        encoded.push(b'\n');
+        writer.write_all(&encoded).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn handle_request(
+    request: ControlRequest,
+    control: &CollectorControl,
+) -> ControlResponse {
+    debug!("Control request: {request:?}");
+    match request {
+        ControlRequest::Status => match serde_json::to_value(control.status()) {
+            Ok(status) => ControlResponse::success(status),
+            Err(err) => ControlResponse::failure(format!("Failed to encode status: {err}")),
+        },
+        ControlRequest::FlushNow => match control.flush_now().await {
+            Ok(tasks) => ControlResponse::success(json!({ "flushed_tasks": tasks })),
+            Err(err) => ControlResponse::failure(format!("{err:#}")),
+        },
+        ControlRequest::Pause => {
+            let changed = control.set_paused(true);
+            if changed {
+                info!("Collection paused through the control socket");
+            }
+            ControlResponse::success(json!({ "paused": true, "changed": changed }))
+        }
+        ControlRequest::Resume => {
+            let changed = control.set_paused(false);
+            if changed {
+                info!("Collection resumed through the control socket");
+            }
+            ControlResponse::success(json!({ "paused": false, "changed": changed }))
+        }
+        ControlRequest::ReloadConfig => match load_mouse_dpi() {
+            Ok(Some(mouse_dpi)) => {
+                info!("Reloaded collector settings: mouse DPI {mouse_dpi}");
+                let tasks = control.reload_config(mouse_dpi);
+                ControlResponse::success(json!({ "mouse_dpi": mouse_dpi, "applied_tasks": tasks }))
+            }
+            Ok(None) => ControlResponse::success(json!({ "mouse_dpi": null, "applied_tasks": 0 })),
+            Err(err) => ControlResponse::failure(format!("{err:#}")),
+        },
+        ControlRequest::Shutdown => {
+            info!("Shutdown requested through the control socket");
+            control.request_shutdown();
+            ControlResponse::success(json!({ "shutting_down": true }))
+        }
+        ControlRequest::BrowserTab { domain, url } => {
+            let tab = match domain
+                .as_deref()
+                .map(str::trim)
+                .filter(|raw| !raw.is_empty())
+            {
+                Some(raw) => match normalize_domain(raw) {
+                    Some(domain) => Some(BrowserTab { domain, url }),
+                    None => {
+                        return ControlResponse::failure(format!(
+                            "Invalid browser tab domain: {raw}"
+                        ))
+                    }
+                },
+                None => None,
+            };
+            let domain = tab.as_ref().map(|tab| tab.domain.clone());
+            control.set_browser_tab(tab);
+            ControlResponse::success(json!({ "domain": domain }))
+        }
+    }
+}
+
+/// Sends one request to a running collector and waits for its reply.
+pub async fn send_control_request(path: &Path, request: ControlRequest) -> Result<ControlResponse> {
+    let stream = UnixStream::connect(path).await.with_context(|| {
+        format!(
+            "Failed to connect to the collector control socket at {}. Is `vigil collector` running?",
+            path.display()
+        )
+    })?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut encoded = serde_json::to_vec(&request)?;
+    encoded.push(b'\n');
+    writer.write_all(&encoded).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .with_context(|| "Collector closed the control socket without replying")?;
+    serde_json::from_str(&line).with_context(|| format!("Invalid control response: {line}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Window;
+    use crate::control::{next_signal, ControlSignal};
+    use uuid::Uuid;
+
+    fn unique_socket_path() -> PathBuf {
+        std::env::temp_dir().join(format!("vigil-control-{}.sock", Uuid::new_v4()))
+    }
+
+    /// Verifies that requests use the documented kebab-case `command` tag by decoding raw
+    /// JSON lines and rejecting a command the protocol does not know.
+    #[test]
+    fn control_requests_decode_from_command_tag() {
+        let request: ControlRequest = serde_json::from_str(r#"{"command":"flush-now"}"#).unwrap();
+        assert_eq!(request, ControlRequest::FlushNow);
+        let request: ControlRequest =
+            serde_json::from_str(r#"{"command":"reload-config"}"#).unwrap();
+        assert_eq!(request, ControlRequest::ReloadConfig);
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command":"explode"}"#).is_err());
+    }
+
+    /// Verifies the socket round trip against a fake collector task by pausing, flushing and
+    /// reading status through a real Unix socket, then checking the socket file is cleaned up.
+    #[tokio::test]
+    async fn control_socket_drives_live_collector_state() -> anyhow::Result<()> {
+        let path = unique_socket_path();
+        let control = CollectorControl::new(PathBuf::from("/tmp/vigil-test.db"));
+        control.set_current_window(Some(&Window {
+            name: "Docs".to_string(),
+            class: "Firefox".to_string(),
+        }));
+        control.set_buffered_input_rows(3);
+
+        let mut signals = control.subscribe();
+        let task_control = control.clone();
+        tokio::spawn(async move {
+            loop {
+                if next_signal(&mut signals).await == ControlSignal::FlushNow {
+                    task_control.set_buffered_input_rows(0);
+                    task_control.ack_flush();
+                }
+            }
+        });
+
+        let server = tokio::spawn(serve(path.clone(), control.clone()));
+        for _ in 0..50 {
+            if path.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let paused = send_control_request(&path, ControlRequest::Pause).await?;
+        assert!(paused.ok);
+        assert!(control.is_paused());
+
+        let flushed = send_control_request(&path, ControlRequest::FlushNow).await?;
+        assert_eq!(flushed.data.unwrap()["flushed_tasks"], 1);
+
+        let status = send_control_request(&path, ControlRequest::Status).await?;
+        let status: crate::control::ControlStatus = serde_json::from_value(status.data.unwrap())?;
+        assert!(status.paused);
+        assert_eq!(status.buffered_input_rows, 0);
+        assert_eq!(status.current_window.unwrap().app_identifier, "firefox");
+
+        server.abort();
+        let _ = server.await;
+        assert!(!path.exists());
+        Ok(())
+    }
+}
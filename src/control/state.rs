@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use tokio::sync::{broadcast, watch, Notify};
+use tracing::warn;
+
+use crate::common::{BrowserTab, Window};
+
+use super::protocol::{ControlDevice, ControlStatus, ControlWindow};
+
+/// How long a `flush-now` request waits for the collector tasks to report back.
+const FLUSH_ACK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Commands fanned out from the control socket to every collector task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlSignal {
+    /// Drain in-memory buffers into the database right now.
+    FlushNow,
+    /// Apply freshly reloaded collector settings.
+    ReloadConfig { mouse_dpi: u32 },
+    /// The browser extension reported a new active tab, or none.
+    BrowserTab(Option<BrowserTab>),
+    /// The system is about to suspend (`sleeping`) or has just resumed, as of `at`.
+    PrepareForSleep { sleeping: bool, at: DateTime<Utc> },
+}
+
+/// Shared handle between the control socket and the collector tasks.
+///
+/// Tasks publish what they are doing (devices, focused window, buffered rows) and subscribe to
+/// control signals; the socket reads the published state and sends the signals.
+#[derive(Debug, Clone)]
+pub struct CollectorControl {
+    inner: Arc<ControlInner>,
+}
+
+#[derive(Debug)]
+struct ControlInner {
+    started_at: Instant,
+    started_at_utc: DateTime<Utc>,
+    db_path: PathBuf,
+    devices: Mutex<Vec<ControlDevice>>,
+    current_window: Mutex<Option<Window>>,
+    browser_tab: Mutex<Option<BrowserTab>>,
+    buffered_input_rows: AtomicUsize,
+    buffered_focus_rows: AtomicUsize,
+    database_reachable: AtomicBool,
+    offline_rows: AtomicU64,
+    flushes_completed: watch::Sender<u64>,
+    paused: watch::Sender<bool>,
+    locked: watch::Sender<bool>,
+    sleeping: watch::Sender<bool>,
+    signals: broadcast::Sender<ControlSignal>,
+    shutdown: Notify,
+    stopping: AtomicBool,
+}
+
+impl CollectorControl {
+    pub fn new(db_path: PathBuf) -> Self {
+        let (paused, _) = watch::channel(false);
+        let (locked, _) = watch::channel(false);
+        let (sleeping, _) = watch::channel(false);
+        let (signals, _) = broadcast::channel(16);
+        let (flushes_completed, _) = watch::channel(0);
+        Self {
+            inner: Arc::new(ControlInner {
+                started_at: Instant::now(),
+                started_at_utc: Utc::now(),
+                db_path,
+                devices: Mutex::new(Vec::new()),
+                current_window: Mutex::new(None),
+                browser_tab: Mutex::new(None),
+                buffered_input_rows: AtomicUsize::new(0),
+                buffered_focus_rows: AtomicUsize::new(0),
+                database_reachable: AtomicBool::new(true),
+                offline_rows: AtomicU64::new(0),
+                flushes_completed,
+                paused,
+                locked,
+                sleeping,
+                signals,
+                shutdown: Notify::new(),
+                stopping: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ControlSignal> {
+        self.inner.signals.subscribe()
+    }
+
+    pub fn watch_paused(&self) -> watch::Receiver<bool> {
+        self.inner.paused.subscribe()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.inner.paused.borrow()
+    }
+
+    /// Returns `true` when the state actually changed.
+    pub fn set_paused(&self, paused: bool) -> bool {
+        self.inner.paused.send_if_modified(|current| {
+            let changed = *current != paused;
+            *current = paused;
+            changed
+        })
+    }
+
+    pub fn watch_locked(&self) -> watch::Receiver<bool> {
+        self.inner.locked.subscribe()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        *self.inner.locked.borrow()
+    }
+
+    /// Records whether the session's screen is locked. Returns `true` when the state actually
+    /// changed.
+    pub fn set_locked(&self, locked: bool) -> bool {
+        self.inner.locked.send_if_modified(|current| {
+            let changed = *current != locked;
+            *current = locked;
+            changed
+        })
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        *self.inner.sleeping.borrow()
+    }
+
+    /// Records that the system is about to suspend or has resumed and tells the collector
+    /// tasks when that happened. Returns how many tasks received it.
+    pub fn prepare_for_sleep(&self, sleeping: bool, at: DateTime<Utc>) -> usize {
+        self.inner.sleeping.send_replace(sleeping);
+        self.inner
+            .signals
+            .send(ControlSignal::PrepareForSleep { sleeping, at })
+            .unwrap_or(0)
+    }
+
+    /// Activity counts only while collection is neither paused, behind a lock screen, on its
+    /// way into a suspend, nor shutting down.
+    pub fn is_recording(&self) -> bool {
+        !self.is_paused()
+            && !self.is_locked()
+            && !self.is_sleeping()
+            && !self.inner.stopping.load(Ordering::Relaxed)
+    }
+
+    /// Stops counting new activity for good, so the final flush is the last word.
+    pub fn stop_intake(&self) {
+        self.inner.stopping.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_devices(&self, devices: Vec<ControlDevice>) {
+        if let Ok(mut current) = self.inner.devices.lock() {
+            *current = devices;
+        }
+    }
+
+    pub fn set_current_window(&self, window: Option<&Window>) {
+        if let Ok(mut current) = self.inner.current_window.lock() {
+            *current = window.cloned();
+        }
+    }
+
+    pub fn set_buffered_input_rows(&self, rows: usize) {
+        self.inner
+            .buffered_input_rows
+            .store(rows, Ordering::Relaxed);
+    }
+
+    pub fn set_buffered_focus_rows(&self, rows: usize) {
+        self.inner
+            .buffered_focus_rows
+            .store(rows, Ordering::Relaxed);
+    }
+
+    /// Publishes whether the database is reachable and how many rows wait in the offline
+    /// database for it.
+    pub fn set_offline_rows(&self, reachable: bool, rows: u64) {
+        self.inner
+            .database_reachable
+            .store(reachable, Ordering::Relaxed);
+        self.inner.offline_rows.store(rows, Ordering::Relaxed);
+    }
+
+    /// Called by a collector task once it finished handling a [`ControlSignal::FlushNow`].
+    pub fn ack_flush(&self) {
+        self.inner
+            .flushes_completed
+            .send_modify(|completed| *completed += 1);
+    }
+
+    /// Asks every task to flush and waits until each subscriber acknowledged it.
+    /// Returns how many tasks flushed.
+    pub async fn flush_now(&self) -> Result<usize> {
+        let mut completed = self.inner.flushes_completed.subscribe();
+        let baseline = *completed.borrow_and_update();
+        let expected = self
+            .inner
+            .signals
+            .send(ControlSignal::FlushNow)
+            .unwrap_or(0) as u64;
+
+        tokio::time::timeout(
+            FLUSH_ACK_TIMEOUT,
+            completed.wait_for(|completed| completed - baseline >= expected),
+        )
+        .await
+        .map_err(|_| anyhow!("Timed out waiting for collector tasks to flush"))??;
+
+        Ok(expected as usize)
+    }
+
+    /// Broadcasts reloaded settings; returns how many tasks received them.
+    pub fn reload_config(&self, mouse_dpi: u32) -> usize {
+        self.inner
+            .signals
+            .send(ControlSignal::ReloadConfig { mouse_dpi })
+            .unwrap_or(0)
+    }
+
+    /// Records the active browser tab and forwards it to the focus tracker.
+    pub fn set_browser_tab(&self, tab: Option<BrowserTab>) -> usize {
+        if let Ok(mut current) = self.inner.browser_tab.lock() {
+            current.clone_from(&tab);
+        }
+        self.inner
+            .signals
+            .send(ControlSignal::BrowserTab(tab))
+            .unwrap_or(0)
+    }
+
+    pub fn request_shutdown(&self) {
+        self.inner.shutdown.notify_one();
+    }
+
+    pub async fn shutdown_requested(&self) {
+        self.inner.shutdown.notified().await;
+    }
+
+    pub fn status(&self) -> ControlStatus {
+        let devices = self
+            .inner
+            .devices
+            .lock()
+            .map(|devices| devices.clone())
+            .unwrap_or_default();
+        let current_window = self
+            .inner
+            .current_window
+            .lock()
+            .ok()
+            .and_then(|window| window.clone())
+            .map(|window| ControlWindow {
+                app_identifier: window.app_identifier(),
+                title: window.name,
+                class: window.class,
+            });
+
+        let browser_domain = self
+            .inner
+            .browser_tab
+            .lock()
+            .ok()
+            .and_then(|tab| tab.as_ref().map(|tab| tab.domain.clone()));
+
+        ControlStatus {
+            pid: std::process::id(),
+            started_at_utc: self.inner.started_at_utc.to_rfc3339(),
+            uptime_seconds: self.inner.started_at.elapsed().as_secs(),
+            paused: self.is_paused(),
+            locked: self.is_locked(),
+            db_path: self.inner.db_path.display().to_string(),
+            devices,
+            current_window,
+            browser_domain,
+            buffered_input_rows: self.inner.buffered_input_rows.load(Ordering::Relaxed),
+            buffered_focus_rows: self.inner.buffered_focus_rows.load(Ordering::Relaxed),
+            database_reachable: self.inner.database_reachable.load(Ordering::Relaxed),
+            offline_rows: self.inner.offline_rows.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Waits for the next control signal, skipping over any that were missed because the task
+/// was busy for too long.
+pub async fn next_signal(rx: &mut broadcast::Receiver<ControlSignal>) -> ControlSignal {
+    loop {
+        match rx.recv().await {
+            Ok(signal) => return signal,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {missed} control signals");
+            }
+            // The sender lives as long as the control handle, so this never resolves.
+            Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
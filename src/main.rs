@@ -12,27 +12,58 @@ use crate::platform::windows::systray;
 #[cfg(target_os = "linux")]
 use crate::platform::linux::common::*;
 
+#[cfg(target_os = "linux")]
+use crate::platform::linux::browser_host::run_browser_host;
 #[cfg(target_os = "linux")]
 use crate::platform::linux::process;
 
+#[cfg(target_os = "linux")]
+use crate::control::{control_socket_path, send_control_request, CollectorControl, ControlRequest};
 use crate::storage::backend::*;
-use crate::storage::localdb::{export_database, import_snapshot, plan_import, DbConfig};
-#[cfg(feature = "multi-sync")]
-use crate::storage::localdb::{open_con_at, setup_database};
+use crate::storage::localdb::{
+    check_database, database_passphrase, database_stats, export_database, export_tables,
+    find_source, forget, import_snapshot, list_sources, load_retention_policy, open_con_at,
+    open_for_inspection, plan_import, project_report, read_passphrase_file, recover_database,
+    rekey_database, render_project_report, render_source_list, render_table_export,
+    resolve_db_path, run_maintenance, save_retention_policy, setup_database, vacuum_database,
+    DbConfig, ExportTable, ForgetFilter, ReadPool, TableExportFilter, PASSPHRASE_ENV,
+    PASSPHRASE_FILE_ENV,
+};
+#[cfg(not(feature = "multi-sync"))]
+use crate::storage::localdb::{delete_source, merge_sources, rename_source};
+use crate::storage::offline::{
+    merge_offline_entries, offline_db_path, pending_offline_rows, run_reconnect,
+};
+use crate::storage::sink::SinkSpec;
+use crate::storage::spool::{replay_spools, spool_dir};
 #[cfg(feature = "multi-sync")]
 use crate::sync::{
-    record_sync_error, render_sync_status, resolve_sync_runtime_config, run_sync_cycle, sync_pull,
-    sync_push, sync_status_snapshot, SqldRemote,
+    apply_local_forget, apply_local_source_action, record_sync_error, render_sync_status,
+    resolve_sync_runtime_config, run_sync_cycle, sync_pull, sync_push, sync_status_snapshot,
+    SourceAction, SqldRemote,
 };
 use crate::tui::run_dashboard;
-use crate::utils::args::{parse_cli, Cli, CollectorCli, Command, DashboardCli};
+#[cfg(target_os = "linux")]
+use crate::utils::args::ControlCommand;
+use crate::utils::args::{
+    parse_cli, Cli, CollectorCli, Command, DashboardCli, DbCommand, DbFileCli, DbRekeyCli,
+    DbRepairCli, ExportCli, ForgetCli, MaintenanceCli, ProjectsCli, SourcesCommand,
+    SourcesDeleteCli, SourcesMergeCli, SourcesRenameCli,
+};
 #[cfg(feature = "multi-sync")]
 use crate::utils::args::{SyncCli, SyncCommand};
 use crate::utils::dpi::{log_mouse_dpi_resolution, resolve_mouse_dpi};
 use crate::utils::lock::*;
 use crate::utils::logger;
+#[cfg(target_os = "windows")]
+use crate::utils::shutdown::FinalFlush;
+use crate::utils::shutdown::{shut_down, ShutdownSignals};
+use crate::utils::supervisor::supervise;
+#[cfg(feature = "multi-sync")]
+use crate::{common::DEFAULT_SOURCE_ID, storage::localdb::get_source};
 
 use anyhow::{Context, Result};
+use std::path::PathBuf;
 
 use tokio::task::JoinSet;
 use tracing::*;
@@ -41,6 +72,8 @@ use tracing::*;
 mod input_bindings;
 
 mod common;
+#[cfg(target_os = "linux")]
+mod control;
 mod platform;
 mod storage;
 #[cfg(feature = "multi-sync")]
@@ -51,7 +84,7 @@ mod utils;
 #[tokio::main]
 async fn main() {
     let args = parse_cli();
-    logger::init(debug_enabled(&args));
+    logger::init(debug_enabled(&args), console_to_stderr(&args));
     logger::setup_panic_hook();
 
     if let Err(err) = run(args).await {
@@ -63,8 +96,18 @@ async fn run(args: Cli) -> Result<()> {
     match args.command {
         Command::Collector(args) => run_collector(args).await,
         Command::Dashboard(args) => run_dashboard_mode(args).await,
+        Command::Projects(args) => run_projects_report(args),
+        Command::Maintenance(args) => run_maintenance_command(args),
+        Command::Db { action } => run_db_command(action),
+        Command::Sources { action } => run_sources_command(action),
+        Command::Forget(args) => run_forget_command(args),
+        Command::Export(args) => run_export_command(args),
         #[cfg(feature = "multi-sync")]
         Command::Sync { action, args } => run_sync_command(action, args).await,
+        #[cfg(target_os = "linux")]
+        Command::Control { action } => run_control_command(action).await,
+        #[cfg(target_os = "linux")]
+        Command::BrowserHost(args) => run_browser_host(args).await,
     }
 }
 
@@ -72,8 +115,27 @@ fn debug_enabled(cli: &Cli) -> bool {
     match &cli.command {
         Command::Collector(args) => args.debug,
         Command::Dashboard(_) => false,
+        Command::Projects(_) => false,
+        Command::Maintenance(_) => false,
+        Command::Db { .. } => false,
+        Command::Sources { .. } => false,
+        Command::Forget(_) => false,
+        Command::Export(_) => false,
         #[cfg(feature = "multi-sync")]
         Command::Sync { .. } => false,
+        #[cfg(target_os = "linux")]
+        Command::Control { .. } => false,
+        #[cfg(target_os = "linux")]
+        Command::BrowserHost(_) => false,
+    }
+}
+
+/// The browser host speaks native messaging over stdout, so its logs must stay off it.
+fn console_to_stderr(cli: &Cli) -> bool {
+    match &cli.command {
+        #[cfg(target_os = "linux")]
+        Command::BrowserHost(args) => !args.install,
+        _ => false,
     }
 }
 
@@ -82,6 +144,390 @@ async fn run_dashboard_mode(_args: DashboardCli) -> Result<()> {
     run_dashboard(&db_config.db_path).with_context(|| "Failed to run terminal dashboard")
 }
 
+fn run_projects_report(args: ProjectsCli) -> Result<()> {
+    let db_config = DbConfig::from_cli_path(None)?;
+    let readers = ReadPool::new(&db_config.db_path);
+    let conn = readers.get()?;
+    let rows = project_report(&conn, args.days, args.project.as_deref())?;
+    println!("{}", render_project_report(&rows, args.project.as_deref()));
+    Ok(())
+}
+
+fn run_maintenance_command(args: MaintenanceCli) -> Result<()> {
+    let db_config = DbConfig::from_cli_path(args.db_path.clone())?;
+    let mut conn = open_con_at(&db_config.db_path)?;
+    setup_database(&conn)?;
+    let _op_lock = acquire_db_operation_lock(&db_config.db_path)?;
+
+    let mut policy = load_retention_policy(&conn)?;
+    policy.full_detail_days = args.full_days.unwrap_or(policy.full_detail_days);
+    policy.hourly_days = args.hourly_days.unwrap_or(policy.hourly_days);
+    policy.title_days = args.title_days.unwrap_or(policy.title_days);
+    if !args.dry_run {
+        save_retention_policy(&conn, &policy)?;
+    }
+
+    let report = run_maintenance(&mut conn, policy, chrono::Utc::now(), args.dry_run)
+        .with_context(|| "Failed to apply the retention rules")?;
+    println!("{}", report.render());
+    Ok(())
+}
+
+fn run_db_command(action: DbCommand) -> Result<()> {
+    match action {
+        DbCommand::Check(args) => run_db_check(args),
+        DbCommand::Stats(args) => run_db_stats(args),
+        DbCommand::Vacuum(args) => run_db_vacuum(args),
+        DbCommand::Repair(args) => run_db_repair(args),
+        DbCommand::Rekey(args) => run_db_rekey(args),
+    }
+}
+
+/// `vigil db` commands work on the database a `--db-path` names without remembering it, so
+/// checking a recovered copy doesn't switch the collector over to it.
+fn existing_db_path(path: Option<PathBuf>) -> Result<PathBuf> {
+    let db_path = match path {
+        Some(path) => resolve_db_path(Some(&path))?,
+        None => DbConfig::from_cli_path(None)?.db_path,
+    };
+    if !db_path.exists() {
+        anyhow::bail!("No vigil database at {}", db_path.display());
+    }
+    Ok(db_path)
+}
+
+fn run_db_check(args: DbFileCli) -> Result<()> {
+    let db_path = existing_db_path(args.db_path)?;
+    let conn = open_for_inspection(&db_path)?;
+    let report = check_database(&conn)?;
+    println!("{}", report.render());
+    if !report.is_healthy() {
+        anyhow::bail!("{} has problems", db_path.display());
+    }
+    Ok(())
+}
+
+fn run_db_stats(args: DbFileCli) -> Result<()> {
+    let db_path = existing_db_path(args.db_path)?;
+    let conn = open_for_inspection(&db_path)?;
+    println!("{}", database_stats(&conn, &db_path)?.render());
+    Ok(())
+}
+
+fn run_db_vacuum(args: DbFileCli) -> Result<()> {
+    let db_path = existing_db_path(args.db_path)?;
+    let conn = open_con_at(&db_path)?;
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    println!("{}", vacuum_database(&conn)?.render());
+    Ok(())
+}
+
+fn run_db_repair(args: DbRepairCli) -> Result<()> {
+    let db_path = existing_db_path(args.db_path)?;
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    let report = recover_database(&db_path, args.output.as_deref())
+        .with_context(|| "Failed to recover the database")?;
+    println!("{}", report.render());
+    println!(
+        "{} was left as it was. Stop the collector and move the recovered file into place once `vigil db check --db-path {}` passes.",
+        db_path.display(),
+        report.recovered_path.display()
+    );
+    Ok(())
+}
+
+fn run_db_rekey(args: DbRekeyCli) -> Result<()> {
+    let db_path = existing_db_path(args.db_path.clone())?;
+    let new_passphrase = if args.decrypt {
+        None
+    } else if let Some(path) = &args.new_passphrase_file {
+        Some(read_passphrase_file(path)?)
+    } else {
+        match std::env::var("VIGIL_DB_NEW_PASSPHRASE") {
+            Ok(passphrase) if !passphrase.is_empty() => Some(passphrase),
+            _ => anyhow::bail!(
+                "Give the new passphrase with --new-passphrase-file or VIGIL_DB_NEW_PASSPHRASE, or pass --decrypt"
+            ),
+        }
+    };
+    // The collector would keep writing to the file that is moved aside.
+    if !try_ensure_single_instance() {
+        anyhow::bail!("Stop the collector before changing the database passphrase");
+    }
+
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    let current_passphrase = database_passphrase()?;
+    let backup_path = rekey_database(
+        &db_path,
+        current_passphrase.as_deref(),
+        new_passphrase.as_deref(),
+    )
+    .with_context(|| "Failed to rekey the database")?;
+    // The offline fallback is opened with the same passphrase, so it has to follow.
+    let offline_path = offline_db_path()?;
+    let offline_backup = if offline_path.exists() {
+        Some(
+            rekey_database(
+                &offline_path,
+                current_passphrase.as_deref(),
+                new_passphrase.as_deref(),
+            )
+            .with_context(|| {
+                format!(
+                    "Rekeyed {} but not the offline database {}; its original is unchanged",
+                    db_path.display(),
+                    offline_path.display()
+                )
+            })?,
+        )
+    } else {
+        None
+    };
+    match new_passphrase {
+        Some(_) => println!(
+            "Encrypted {} with the new passphrase. Set {PASSPHRASE_ENV} or {PASSPHRASE_FILE_ENV} to it before starting Vigil again.",
+            db_path.display()
+        ),
+        None => println!(
+            "Decrypted {}. Unset {PASSPHRASE_ENV} and {PASSPHRASE_FILE_ENV} before starting Vigil again.",
+            db_path.display()
+        ),
+    }
+    println!(
+        "The original is kept at {} with the old passphrase; delete it once the database works.",
+        backup_path.display()
+    );
+    if let Some(offline_backup) = offline_backup {
+        println!(
+            "Rekeyed the offline database {} too; its original is kept at {}.",
+            offline_path.display(),
+            offline_backup.display()
+        );
+    }
+    println!("`--sink sqlite:` databases use the passphrase too; rekey each with `vigil db rekey --db-path <SINK>`.");
+    Ok(())
+}
+
+fn run_sources_command(action: SourcesCommand) -> Result<()> {
+    match action {
+        SourcesCommand::List(args) => run_sources_list(args),
+        SourcesCommand::Rename(args) => run_sources_rename(args),
+        SourcesCommand::Merge(args) => run_sources_merge(args),
+        SourcesCommand::Delete(args) => run_sources_delete(args),
+    }
+}
+
+fn open_database_for_edit(path: Option<PathBuf>) -> Result<(rusqlite::Connection, PathBuf)> {
+    let db_config = DbConfig::from_cli_path(path)?;
+    let conn = open_con_at(&db_config.db_path)?;
+    setup_database(&conn)?;
+    Ok((conn, db_config.db_path))
+}
+
+/// With sync, source changes go through the outbox so the other devices make them too.
+#[cfg(feature = "multi-sync")]
+fn own_source_uuid(conn: &rusqlite::Connection) -> Result<String> {
+    Ok(get_source(conn, DEFAULT_SOURCE_ID)?.source_uuid)
+}
+
+fn run_sources_list(args: DbFileCli) -> Result<()> {
+    let db_config = DbConfig::from_cli_path(args.db_path)?;
+    let readers = ReadPool::new(&db_config.db_path);
+    let conn = readers.get()?;
+    println!("{}", render_source_list(&list_sources(&conn)?));
+    Ok(())
+}
+
+fn run_sources_rename(args: SourcesRenameCli) -> Result<()> {
+    let (mut conn, db_path) = open_database_for_edit(args.db_path)?;
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    let source = find_source(&conn, &args.source)?;
+    let tx = conn.transaction()?;
+    #[cfg(feature = "multi-sync")]
+    apply_local_source_action(
+        &tx,
+        &own_source_uuid(&tx)?,
+        &source.source_uuid,
+        SourceAction::Rename {
+            source_name: args.name.clone(),
+        },
+    )?;
+    #[cfg(not(feature = "multi-sync"))]
+    rename_source(&tx, &source.source_uuid, &args.name)?;
+    tx.commit()?;
+    println!(
+        "Renamed {} ({}) to {}",
+        source.source_name, source.source_uuid, args.name
+    );
+    Ok(())
+}
+
+fn run_sources_merge(args: SourcesMergeCli) -> Result<()> {
+    let (mut conn, db_path) = open_database_for_edit(args.db_path)?;
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    let from = find_source(&conn, &args.from)?;
+    let into = find_source(&conn, &args.into)?;
+    let tx = conn.transaction()?;
+    #[cfg(feature = "multi-sync")]
+    apply_local_source_action(
+        &tx,
+        &own_source_uuid(&tx)?,
+        &from.source_uuid,
+        SourceAction::Merge {
+            merged_into_uuid: into.source_uuid.clone(),
+        },
+    )?;
+    #[cfg(not(feature = "multi-sync"))]
+    merge_sources(&tx, &from.source_uuid, &into.source_uuid)?;
+    tx.commit()?;
+    println!(
+        "Merged {} ({}) into {} ({})",
+        from.source_name, from.source_uuid, into.source_name, into.source_uuid
+    );
+    Ok(())
+}
+
+fn run_sources_delete(args: SourcesDeleteCli) -> Result<()> {
+    let (mut conn, db_path) = open_database_for_edit(args.db_path)?;
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    let source = find_source(&conn, &args.source)?;
+    let tx = conn.transaction()?;
+    #[cfg(feature = "multi-sync")]
+    apply_local_source_action(
+        &tx,
+        &own_source_uuid(&tx)?,
+        &source.source_uuid,
+        SourceAction::Delete,
+    )?;
+    #[cfg(not(feature = "multi-sync"))]
+    delete_source(&tx, &source.source_uuid)?;
+    tx.commit()?;
+    println!(
+        "Deleted {} ({}) and its activity",
+        source.source_name, source.source_uuid
+    );
+    Ok(())
+}
+
+fn run_forget_command(args: ForgetCli) -> Result<()> {
+    if let (Some(from), Some(until)) = (args.from, args.until) {
+        if from >= until {
+            anyhow::bail!("--from has to be before --until");
+        }
+    }
+    // Rows in the collector's buffers would be written back after the forget.
+    if !try_ensure_single_instance() {
+        anyhow::bail!("Stop the collector before forgetting, e.g. with `vigil control shutdown`");
+    }
+    let (mut conn, db_path) = open_database_for_edit(args.db_path)?;
+    let _op_lock = acquire_db_operation_lock(&db_path)?;
+    let filter = ForgetFilter {
+        source_uuid: args
+            .source
+            .map(|source| find_source(&conn, &source).map(|source| source.source_uuid))
+            .transpose()?,
+        app_identifier: args.app,
+        title_pattern: args.title_match,
+        from_utc: args.from.map(|time| time.to_rfc3339()),
+        until_utc: args.until.map(|time| time.to_rfc3339()),
+    };
+
+    // Leftover spools and rows kept in offline.db go in first, so the forget covers them too.
+    let offline_path = offline_db_path()?;
+    if args.dry_run {
+        let pending = pending_offline_rows(&offline_path, &db_path)?;
+        if pending > 0 {
+            println!(
+                "{pending} rows kept in offline.db are merged and forgotten too on a real run"
+            );
+        }
+    } else {
+        replay_spools(&mut conn, &spool_dir()?)?;
+        merge_offline_entries(&mut conn, &db_path, &offline_path)?;
+    }
+
+    // Every database is changed in a transaction that is only committed once all of them
+    // managed to forget, so a failing sink leaves them all as they were.
+    let mut sinks = Vec::new();
+    for sink in &args.sinks {
+        match sink {
+            SinkSpec::Sqlite(path) if !path.exists() => println!("{sink}: no database yet"),
+            SinkSpec::Sqlite(path) => {
+                let conn = open_con_at(path)?;
+                setup_database(&conn)?;
+                let op_lock = acquire_db_operation_lock(path)?;
+                sinks.push((sink, conn, op_lock));
+            }
+            SinkSpec::JsonLines(path) => println!(
+                "{sink}: left as it is, remove matching lines from {} by hand",
+                path.display()
+            ),
+        }
+    }
+
+    let tx = conn.transaction()?;
+    #[cfg(feature = "multi-sync")]
+    let report = apply_local_forget(&tx, &own_source_uuid(&tx)?, &filter)?;
+    #[cfg(not(feature = "multi-sync"))]
+    let report = forget(&tx, &filter)?;
+    println!("{}", report.render());
+    let mut sink_txs = Vec::new();
+    for (sink, conn, _) in &sinks {
+        let tx = conn.unchecked_transaction()?;
+        let report =
+            forget(&tx, &filter).with_context(|| format!("Failed to forget in sink {sink}"))?;
+        println!("{sink}:\n{}", report.render());
+        sink_txs.push((sink, tx));
+    }
+    if args.dry_run {
+        println!("dry run, nothing was changed");
+        return Ok(());
+    }
+
+    tx.commit()?;
+    let mut failed = Vec::new();
+    for (sink, tx) in sink_txs {
+        if let Err(err) = tx.commit() {
+            error!("Failed to commit the forget in sink {sink}: {err}");
+            failed.push(format!("--sink {sink}"));
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "The database and the other sinks forgot the rows, but not these sinks. Run the same forget again with only {}",
+            failed.join(" ")
+        );
+    }
+    Ok(())
+}
+
+fn run_export_command(args: ExportCli) -> Result<()> {
+    if let (Some(since), Some(until)) = (args.since, args.until) {
+        if since >= until {
+            anyhow::bail!("--since has to be before --until");
+        }
+    }
+    let db_config = DbConfig::from_cli_path(args.db_path)?;
+    let readers = ReadPool::new(&db_config.db_path);
+    let conn = readers.get()?;
+    let filter = TableExportFilter {
+        source_uuid: args
+            .source
+            .map(|source| find_source(&conn, &source).map(|source| source.source_uuid))
+            .transpose()?,
+        since_utc: args.since.map(|time| time.to_rfc3339()),
+        until_utc: args.until.map(|time| time.to_rfc3339()),
+    };
+    let tables = if args.tables.is_empty() {
+        ExportTable::ALL.to_vec()
+    } else {
+        args.tables
+    };
+    let exported = export_tables(&conn, &args.output, args.format, &tables, &filter)?;
+    println!("{}", render_table_export(&exported));
+    Ok(())
+}
+
 #[cfg(feature = "multi-sync")]
 async fn run_sync_command(action: SyncCommand, args: SyncCli) -> Result<()> {
     let db_config = DbConfig::from_cli_path(args.db_path.clone())?;
@@ -114,6 +560,29 @@ async fn run_sync_command(action: SyncCommand, args: SyncCli) -> Result<()> {
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+async fn run_control_command(action: ControlCommand) -> Result<()> {
+    let request = match action {
+        ControlCommand::Status => ControlRequest::Status,
+        ControlCommand::FlushNow => ControlRequest::FlushNow,
+        ControlCommand::Pause => ControlRequest::Pause,
+        ControlCommand::Resume => ControlRequest::Resume,
+        ControlCommand::ReloadConfig => ControlRequest::ReloadConfig,
+        ControlCommand::Shutdown => ControlRequest::Shutdown,
+    };
+    let response = send_control_request(&control_socket_path()?, request).await?;
+    if !response.ok {
+        anyhow::bail!(
+            "Collector rejected the request: {}",
+            response.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+    if let Some(data) = response.data {
+        println!("{}", serde_json::to_string_pretty(&data)?);
+    }
+    Ok(())
+}
+
 async fn run_collector(mut args: CollectorCli) -> Result<()> {
     let db_config = DbConfig::from_cli_path(args.db_path.clone())?;
 
@@ -182,97 +651,251 @@ async fn run_collector(mut args: CollectorCli) -> Result<()> {
     let mouse_dpi = resolve_mouse_dpi(args.dpi)?;
     log_mouse_dpi_resolution(mouse_dpi);
 
-    let storage_backend = StorageBackend::Local(
-        LocalDb::new(db_config, args.clear)
-            .with_context(|| "Failed to initialize SQLite backend")?,
-    );
+    #[cfg(target_os = "linux")]
+    let control = CollectorControl::new(db_config.db_path.clone());
+    #[cfg(target_os = "windows")]
+    let final_flush = FinalFlush::default();
+
+    if let Some(spec) = args
+        .sinks
+        .iter()
+        .find(|spec| spec.path() == db_config.db_path)
+    {
+        anyhow::bail!("Sink {spec} points at the database itself");
+    }
+    let local_db = LocalDb::new(db_config, args.clear, args.granularity)
+        .with_context(|| "Failed to initialize SQLite backend")?;
+    let storage_backend = if args.sinks.is_empty() {
+        StorageBackend::Local(local_db)
+    } else {
+        StorageBackend::FanOut(FanOut::new(local_db, &args.sinks))
+    };
 
     let mut tasks_set = JoinSet::new();
 
+    #[cfg(target_os = "linux")]
+    {
+        let socket_path = control_socket_path()?;
+        let control = control.clone();
+        tasks_set.spawn(supervise("control", storage_backend.clone(), move || {
+            crate::control::serve(socket_path.clone(), control.clone())
+        }));
+    }
+
     #[cfg(feature = "multi-sync")]
     {
-        let StorageBackend::Local(local_db) = &storage_backend;
+        let local_db = storage_backend.primary();
         let sync_config = {
             let conn = local_db.shared_connection();
             let conn = conn.lock().unwrap();
-            resolve_sync_runtime_config(
-                &conn,
-                args.sync_remote_url.as_deref(),
-                args.sync_auth_token.as_deref(),
-                args.sync_enable,
-                args.sync_interval,
-            )?
+            match conn.as_ref() {
+                Some(conn) => resolve_sync_runtime_config(
+                    conn,
+                    args.sync_remote_url.as_deref(),
+                    args.sync_auth_token.as_deref(),
+                    args.sync_enable,
+                    args.sync_interval,
+                )?,
+                None => {
+                    warn!("The database is out of reach, background sync stays off until the next start");
+                    None
+                }
+            }
         };
         if let Some(sync_config) = sync_config.filter(|config| config.sync_enabled) {
             let db_path = local_db.db_path().clone();
             let sync_config = sync_config.clone();
-            tasks_set.spawn(async move {
-                let mut tick = tokio::time::interval(std::time::Duration::from_secs(
-                    sync_config.sync_interval_seconds,
-                ));
-                let mut remote = None;
-                loop {
-                    tick.tick().await;
-                    let _op_lock = acquire_db_operation_lock(&db_path)?;
-                    // Sync must stay opportunistic. When the remote is down we only record the
-                    // failure in local sync state and retry later; collection keeps running.
-                    if remote.is_none() {
-                        match SqldRemote::new(&sync_config.remote_url, &sync_config.auth_token)
-                            .await
-                        {
-                            Ok(connected) => {
-                                info!("Connected background sync to {}", sync_config.remote_url);
-                                remote = Some(connected);
+            let start_sync = move || {
+                let db_path = db_path.clone();
+                let sync_config = sync_config.clone();
+                async move {
+                    let mut tick = tokio::time::interval(std::time::Duration::from_secs(
+                        sync_config.sync_interval_seconds,
+                    ));
+                    let mut remote = None;
+                    loop {
+                        tick.tick().await;
+                        let _op_lock = acquire_db_operation_lock(&db_path)?;
+                        // Sync must stay opportunistic. When the remote is down we only record the
+                        // failure in local sync state and retry later; collection keeps running.
+                        if remote.is_none() {
+                            match SqldRemote::new(&sync_config.remote_url, &sync_config.auth_token)
+                                .await
+                            {
+                                Ok(connected) => {
+                                    info!(
+                                        "Connected background sync to {}",
+                                        sync_config.remote_url
+                                    );
+                                    remote = Some(connected);
+                                }
+                                Err(err) => {
+                                    error!("Sync remote unavailable: {err:#}");
+                                    let conn = open_con_at(&db_path)?;
+                                    record_sync_error(
+                                        &conn,
+                                        &sync_config.own_source_uuid,
+                                        &sync_config.remote_url,
+                                        &err.to_string(),
+                                    )?;
+                                    continue;
+                                }
                             }
-                            Err(err) => {
-                                error!("Sync remote unavailable: {err:#}");
-                                let conn = open_con_at(&db_path)?;
-                                record_sync_error(
-                                    &conn,
-                                    &sync_config.own_source_uuid,
-                                    &sync_config.remote_url,
-                                    &err.to_string(),
-                                )?;
-                                continue;
+                        }
+
+                        if let Some(connected) = remote.as_ref() {
+                            if let Err(err) =
+                                run_sync_cycle(&db_path, connected, &sync_config).await
+                            {
+                                error!("Sync cycle failed: {err:#}");
+                                remote = None;
                             }
                         }
                     }
+                    #[allow(unreachable_code)]
+                    Ok::<(), anyhow::Error>(())
+                }
+            };
+            tasks_set.spawn(supervise("sync", storage_backend.clone(), start_sync));
+        }
+    }
+    // Lock and suspend detection are best effort, so they run outside the tasks that must
+    // stay alive.
+    #[cfg(target_os = "linux")]
+    {
+        tokio::spawn(crate::platform::linux::screen_lock::watch_screen_lock(
+            control.clone(),
+        ));
+        tokio::spawn(crate::platform::linux::suspend::watch_suspend(
+            control.clone(),
+        ));
+    }
 
-                    if let Some(connected) = remote.as_ref() {
-                        if let Err(err) = run_sync_cycle(&db_path, connected, &sync_config).await {
-                            error!("Sync cycle failed: {err:#}");
-                            remote = None;
+    {
+        let backend = storage_backend.clone();
+        #[cfg(target_os = "linux")]
+        let control = control.clone();
+        tasks_set.spawn(supervise("offline", storage_backend.clone(), move || {
+            run_reconnect(
+                backend.clone(),
+                #[cfg(target_os = "linux")]
+                control.clone(),
+            )
+        }));
+    }
+
+    if let Some(hours) = args.maintenance_interval {
+        let backend = storage_backend.clone();
+        tasks_set.spawn(supervise(
+            "maintenance",
+            storage_backend.clone(),
+            move || {
+                let backend = backend.clone();
+                async move {
+                    let mut tick =
+                        tokio::time::interval(std::time::Duration::from_secs(hours * 60 * 60));
+                    loop {
+                        tick.tick().await;
+                        if let Some(report) = backend.run_maintenance().await? {
+                            info!("Applied the retention rules:\n{}", report.render());
                         }
                     }
+                    #[allow(unreachable_code)]
+                    Ok::<(), anyhow::Error>(())
                 }
-                #[allow(unreachable_code)]
-                Ok::<(), anyhow::Error>(())
-            });
-        }
+            },
+        ));
     }
+
+    // Input and focus tracking restart with fresh state after a failure; whatever they had
+    // spooled is replayed when they start again.
     #[cfg(target_os = "linux")]
-    tasks_set.spawn(crate::platform::linux::inputs::run(
-        Some(mouse_dpi.dpi),
-        db_update_interval + 5,
-        storage_backend.clone(),
-    ));
+    {
+        let device_filter = crate::platform::linux::evdev::DeviceFilter {
+            include: args.include_devices.clone(),
+            exclude: args.exclude_devices.clone(),
+        };
+        let backend = storage_backend.clone();
+        let control = control.clone();
+        tasks_set.spawn(supervise("input", storage_backend.clone(), move || {
+            crate::platform::linux::inputs::run(
+                Some(mouse_dpi.dpi),
+                device_filter.clone(),
+                db_update_interval + 5,
+                backend.clone(),
+                control.clone(),
+            )
+        }));
+    }
 
     #[cfg(target_os = "windows")]
-    tasks_set.spawn(crate::platform::windows::inputs::run(
-        Some(mouse_dpi.dpi),
-        db_update_interval + 5,
-        storage_backend.clone(),
-    ));
+    {
+        let backend = storage_backend.clone();
+        let final_flush = final_flush.clone();
+        tasks_set.spawn(supervise("input", storage_backend.clone(), move || {
+            crate::platform::windows::inputs::run(
+                Some(mouse_dpi.dpi),
+                db_update_interval + 5,
+                backend.clone(),
+                final_flush.clone(),
+            )
+        }));
+    }
 
-    tasks_set.spawn(process::run(db_update_interval, storage_backend));
+    #[cfg(target_os = "linux")]
+    {
+        let backend = storage_backend.clone();
+        let control = control.clone();
+        tasks_set.spawn(supervise("focus", storage_backend.clone(), move || {
+            process::run(db_update_interval, backend.clone(), control.clone())
+        }));
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let backend = storage_backend.clone();
+        let final_flush = final_flush.clone();
+        tasks_set.spawn(supervise("focus", storage_backend.clone(), move || {
+            process::run(db_update_interval, backend.clone(), final_flush.clone())
+        }));
+    }
 
     #[cfg(target_os = "windows")]
     if !args.no_systray {
         tasks_set.spawn(systray::init_tray());
     }
 
+    #[cfg(target_os = "linux")]
+    let mut shutdown = std::pin::pin!(control.shutdown_requested());
+    let mut signals =
+        ShutdownSignals::new().with_context(|| "Failed to listen for shutdown signals")?;
+
     // Need to wait the tasks finish, which they shouldn't.
-    while let Some(res) = tasks_set.join_next().await {
+    loop {
+        #[cfg(target_os = "linux")]
+        let res = tokio::select! {
+            res = tasks_set.join_next() => res,
+            _ = &mut shutdown => {
+                shut_down("control-socket", &mut tasks_set, &control, &storage_backend).await;
+                return Ok(());
+            }
+            reason = signals.recv() => {
+                shut_down(reason, &mut tasks_set, &control, &storage_backend).await;
+                return Ok(());
+            }
+        };
+        #[cfg(target_os = "windows")]
+        let res = tokio::select! {
+            res = tasks_set.join_next() => res,
+            reason = signals.recv() => {
+                shut_down(reason, &mut tasks_set, &final_flush, &storage_backend).await;
+                return Ok(());
+            }
+        };
+
+        let Some(res) = res else {
+            break;
+        };
         // -> Option(Result(Result())))
         match res {
             Ok(Ok(())) => error!("Task exited cleanly but unexpectedly"),
@@ -323,6 +946,13 @@ mod tests {
             dry_run: false,
             import_notes: None,
             dpi: None,
+            granularity: None,
+            maintenance_interval: None,
+            sinks: Vec::new(),
+            #[cfg(target_os = "linux")]
+            include_devices: Vec::new(),
+            #[cfg(target_os = "linux")]
+            exclude_devices: Vec::new(),
             clear: false,
             enable_startup: false,
             disable_startup: false,
@@ -353,6 +983,7 @@ mod tests {
             mouse_distance_cm: 3.0,
             scroll_vertical_cm: 0.4,
             scroll_horizontal_cm: 0.0,
+            dropped_events: 0,
         }
     }
 
@@ -0,0 +1,231 @@
+//! Native messaging host that relays the active browser tab to the running collector.
+//!
+//! Browsers start the host through the wrapper script written by `--install` and talk to it
+//! over stdio: every message is a native-endian `u32` length followed by that many bytes of
+//! JSON. The extension sends `{"domain":"github.com","url":null}` whenever the active tab
+//! changes, and `{"domain":null}` when no tab is focused.
+use std::fs::{self, create_dir_all, write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::*;
+
+use crate::common::program_data_dir;
+use crate::control::{control_socket_path, send_control_request, ControlRequest, ControlResponse};
+use crate::platform::linux::common::{expand_home, user_config_dir};
+use crate::utils::args::BrowserHostCli;
+
+pub const BROWSER_HOST_NAME: &str = "vigil.browser_host";
+const FIREFOX_EXTENSION_ID: &str = "vigil-browser@vigil-rs";
+const WRAPPER_SCRIPT_NAME: &str = "vigil-browser-host";
+/// Browsers cap messages sent to a host at 64 MiB; tab updates are tiny, so refuse anything big.
+const MAX_MESSAGE_BYTES: u32 = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct TabMessage {
+    #[serde(default)]
+    domain: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+pub async fn run_browser_host(args: BrowserHostCli) -> Result<()> {
+    if args.install {
+        return install_browser_host(args.chrome_extension_id.as_deref());
+    }
+
+    debug!("Browser host started by {:?}", args.browser_args);
+    let socket_path = control_socket_path()?;
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(message) = read_message(&mut stdin).await? {
+        let response = match serde_json::from_slice::<TabMessage>(&message) {
+            Result::Ok(tab) => forward_tab(&socket_path, tab.domain, tab.url).await,
+            Err(err) => ControlResponse::failure(format!("Invalid browser message: {err}")),
+        };
+        write_message(&mut stdout, &serde_json::to_vec(&response)?).await?;
+    }
+
+    // The browser closed the pipe, so no tab is active anymore.
+    forward_tab(&socket_path, None, None).await;
+    Ok(())
+}
+
+async fn forward_tab(
+    socket_path: &Path,
+    domain: Option<String>,
+    url: Option<String>,
+) -> ControlResponse {
+    match send_control_request(socket_path, ControlRequest::BrowserTab { domain, url }).await {
+        Result::Ok(response) => response,
+        Err(err) => {
+            // The collector may simply not be running yet; keep serving the extension.
+            debug!("Could not forward browser tab: {err:#}");
+            ControlResponse::failure(format!("{err:#}"))
+        }
+    }
+}
+
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length).await {
+        Result::Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err).with_context(|| "Failed to read browser message length"),
+    }
+
+    let length = u32::from_ne_bytes(length);
+    if length > MAX_MESSAGE_BYTES {
+        bail!("Browser message of {length} bytes exceeds the {MAX_MESSAGE_BYTES} byte limit");
+    }
+
+    let mut message = vec![0u8; length as usize];
+    reader
+        .read_exact(&mut message)
+        .await
+        .with_context(|| "Failed to read browser message body")?;
+    Ok(Some(message))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &[u8]) -> Result<()> {
+    let length = u32::try_from(message.len()).with_context(|| "Host reply is too large")?;
+    writer.write_all(&length.to_ne_bytes()).await?;
+    writer.write_all(message).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn shell_single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn render_wrapper_script(executable: &Path) -> String {
+    format!(
+        "#!/bin/sh\n# Generated by `vigil browser-host --install`.\nexec {} browser-host \"$@\"\n",
+        shell_single_quote(&executable.to_string_lossy())
+    )
+}
+
+fn render_firefox_manifest(wrapper: &Path) -> String {
+    let manifest = json!({
+        "name": BROWSER_HOST_NAME,
+        "description": "Vigil browser tab tracking",
+        "path": wrapper,
+        "type": "stdio",
+        "allowed_extensions": [FIREFOX_EXTENSION_ID],
+    });
+    serde_json::to_string_pretty(&manifest).unwrap_or_default()
+}
+
+fn render_chromium_manifest(wrapper: &Path, extension_id: &str) -> String {
+    let manifest = json!({
+        "name": BROWSER_HOST_NAME,
+        "description": "Vigil browser tab tracking",
+        "path": wrapper,
+        "type": "stdio",
+        "allowed_origins": [format!("chrome-extension://{extension_id}/")],
+    });
+    serde_json::to_string_pretty(&manifest).unwrap_or_default()
+}
+
+/// Config directories of Chromium-based browsers, keyed by a display name.
+fn chromium_config_dirs() -> [(&'static str, PathBuf); 3] {
+    let config = user_config_dir();
+    [
+        ("Chromium", config.join("chromium")),
+        ("Google Chrome", config.join("google-chrome")),
+        ("Brave", config.join("BraveSoftware/Brave-Browser")),
+    ]
+}
+
+fn write_manifest(dir: &Path, contents: &str) -> Result<PathBuf> {
+    create_dir_all(dir).with_context(|| {
+        format!(
+            "Failed to create native messaging directory: {}",
+            dir.display()
+        )
+    })?;
+    let path = dir.join(format!("{BROWSER_HOST_NAME}.json"));
+    write(&path, contents).with_context(|| {
+        format!(
+            "Failed to write native messaging manifest to {}",
+            path.display()
+        )
+    })?;
+    Ok(path)
+}
+
+fn install_browser_host(chrome_extension_id: Option<&str>) -> Result<()> {
+    let current_exe = std::env::current_exe()
+        .with_context(|| "Could not determine the filesystem path of the application")?;
+    let wrapper = program_data_dir()
+        .with_context(|| "Could not determine directory for the browser host wrapper")?
+        .join(WRAPPER_SCRIPT_NAME);
+
+    write(&wrapper, render_wrapper_script(&current_exe)).with_context(|| {
+        format!(
+            "Failed to write browser host wrapper to {}",
+            wrapper.display()
+        )
+    })?;
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).with_context(|| {
+        format!(
+            "Failed to make browser host wrapper executable: {}",
+            wrapper.display()
+        )
+    })?;
+    info!("Wrote browser host wrapper to '{}'.", wrapper.display());
+
+    let firefox_dir = expand_home("~/.mozilla/native-messaging-hosts");
+    let path = write_manifest(&firefox_dir, &render_firefox_manifest(&wrapper))?;
+    info!("Registered the host with Firefox at '{}'.", path.display());
+
+    match chrome_extension_id {
+        Some(extension_id) => {
+            for (browser, config_dir) in chromium_config_dirs() {
+                if !config_dir.exists() {
+                    debug!("Skipping {browser}: {} does not exist", config_dir.display());
+                    continue;
+                }
+                let contents = render_chromium_manifest(&wrapper, extension_id);
+                let path = write_manifest(&config_dir.join("NativeMessagingHosts"), &contents)?;
+                info!("Registered the host with {browser} at '{}'.", path.display());
+            }
+        }
+        None => info!(
+            "Pass --chrome-extension-id <ID> to also register the host with Chromium-based browsers."
+        ),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verifies the stdio framing by writing a message with a native-endian length prefix,
+    /// reading it back, and treating a closed pipe as the end of the session.
+    #[tokio::test]
+    async fn native_messages_round_trip_through_length_prefixed_frames() -> Result<()> {
+        let mut encoded = Vec::new();
+        write_message(&mut encoded, br#"{"domain":"github.com"}"#).await?;
+        assert_eq!(&encoded[..4], &23u32.to_ne_bytes());
+
+        let mut reader = encoded.as_slice();
+        let message = read_message(&mut reader).await?.unwrap();
+        let tab: TabMessage = serde_json::from_slice(&message)?;
+        assert_eq!(tab.domain.as_deref(), Some("github.com"));
+        assert_eq!(tab.url, None);
+        assert!(read_message(&mut reader).await?.is_none());
+
+        let oversized = (MAX_MESSAGE_BYTES + 1).to_ne_bytes();
+        assert!(read_message(&mut oversized.as_slice()).await.is_err());
+        Ok(())
+    }
+}
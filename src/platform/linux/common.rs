@@ -167,7 +167,7 @@ pub fn detect_display_server() -> DisplayServer {
     )
 }
 
-fn expand_home(path: &str) -> PathBuf {
+pub(crate) fn expand_home(path: &str) -> PathBuf {
     if let Some(stripped) = path.strip_prefix("~/") {
         let home = std::env::var("HOME").unwrap_or_else(|_| {
             let username = std::env::var("USER").expect("Cannot determine home directory");
@@ -180,7 +180,7 @@ fn expand_home(path: &str) -> PathBuf {
     }
 }
 
-fn user_config_dir() -> PathBuf {
+pub(crate) fn user_config_dir() -> PathBuf {
     non_empty_env("XDG_CONFIG_HOME")
         .map(PathBuf::from)
         .unwrap_or_else(|| expand_home("~/.config"))
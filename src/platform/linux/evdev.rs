@@ -0,0 +1,588 @@
+//! Reading keyboards and mice straight from `/dev/input/event*`.
+//!
+//! Shared by the collector and `vigil-input-helper`, so it only depends on the generated input
+//! bindings (`crate::input_bindings` in both crates).
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::mem::MaybeUninit;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use nix::unistd::read;
+use nix::{ioctl_read, ioctl_read_buf};
+
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::*;
+
+use crate::input_bindings::*;
+
+/// Either a relative change for EV_REL, absolute new value for EV_ABS (joysticks ...), or 0 for EV_KEY for release, 1 for keypress and 2 for autorepeat
+/// https://docs.kernel.org/input/input.html
+#[derive(PartialEq, Eq)]
+pub enum KeyPressState {
+    _Up = 0,
+    Down = 1,
+    _Repeat = 2,
+}
+
+/// One event read from a device, tagged with the kind of device it came from.
+pub enum InputEvent {
+    Keyboard {
+        event: input_event,
+    },
+    Mouse {
+        device_id: usize,
+        event: input_event,
+    },
+}
+
+/// A device being listened to, as reported to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDevice {
+    /// `keyboard` or `mouse`.
+    pub kind: &'static str,
+    pub path: String,
+    pub name: String,
+}
+
+/// Bus types from `EVIOCGID` that device rules can refer to by name.
+const BUS_NAMES: [(&str, u32); 7] = [
+    ("pci", BUS_PCI),
+    ("usb", BUS_USB),
+    ("bluetooth", BUS_BLUETOOTH),
+    ("virtual", BUS_VIRTUAL),
+    ("i8042", BUS_I8042),
+    ("i2c", BUS_I2C),
+    ("host", BUS_HOST),
+];
+/// Devices created in software (uinput, e.g. ydotool or remote desktop tools) sit here in
+/// sysfs instead of under the controller they are plugged into.
+const SYSFS_VIRTUAL_DEVICES: &str = "/sys/devices/virtual/";
+/// Bluetooth LE keyboards and mice are HID over GATT, which the kernel runs through uhid, so
+/// real hardware sits here too. It reports its actual bus, unlike software devices.
+const SYSFS_UHID_DEVICES: &str = "/sys/devices/virtual/misc/uhid/";
+
+/// A rule matching input devices: `name:<text>` (part of the device name, any case),
+/// `bus:<usb|bluetooth|i8042|...|0x..>`, `virtual` or `physical`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceRule {
+    Name(String),
+    Bus(u16),
+    Virtual,
+    Physical,
+}
+
+impl FromStr for DeviceRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> std::result::Result<Self, Self::Err> {
+        match rule.split_once(':') {
+            Some(("name", name)) if !name.is_empty() => Ok(Self::Name(name.to_lowercase())),
+            Some(("bus", bus)) => {
+                let bus = bus.to_lowercase();
+                let id = match bus.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                    None => BUS_NAMES
+                        .iter()
+                        .find(|(name, _)| *name == bus)
+                        .map(|(_, id)| *id as u16),
+                };
+                id.map(Self::Bus).ok_or_else(|| {
+                    let known = BUS_NAMES.map(|(name, _)| name).join(", ");
+                    format!("unknown bus `{bus}`, expected one of {known} or a 0x.. id")
+                })
+            }
+            None if rule == "virtual" => Ok(Self::Virtual),
+            None if rule == "physical" => Ok(Self::Physical),
+            _ => Err(format!(
+                "invalid device rule `{rule}`, expected name:<text>, bus:<bus>, virtual or physical"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DeviceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "name:{name}"),
+            Self::Bus(bus) => write!(f, "bus:{}", bus_name(*bus)),
+            Self::Virtual => f.write_str("virtual"),
+            Self::Physical => f.write_str("physical"),
+        }
+    }
+}
+
+/// What the rules look at: the device name, its bus and where it lives in sysfs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DeviceIdentity {
+    pub(crate) name: String,
+    /// Unknown for XInput2 devices, which bus rules then never match.
+    pub(crate) bus: Option<u16>,
+    pub(crate) is_virtual: bool,
+}
+
+impl DeviceRule {
+    fn matches(&self, device: &DeviceIdentity) -> bool {
+        match self {
+            Self::Name(name) => device.name.to_lowercase().contains(name),
+            Self::Bus(bus) => device.bus == Some(*bus),
+            Self::Virtual => device.is_virtual,
+            Self::Physical => !device.is_virtual,
+        }
+    }
+}
+
+/// Decides which keyboards and mice are listened to. Include rules win over exclude rules,
+/// and without a matching rule virtual devices are ignored, so replayed or remote input
+/// doesn't count as the user's own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    pub include: Vec<DeviceRule>,
+    pub exclude: Vec<DeviceRule>,
+}
+
+impl DeviceFilter {
+    /// Whether to listen to the device, and why, for the startup log.
+    pub(crate) fn decide(&self, device: &DeviceIdentity) -> (bool, String) {
+        if let Some(rule) = self.include.iter().find(|rule| rule.matches(device)) {
+            return (true, format!("included by `{rule}`"));
+        }
+        if let Some(rule) = self.exclude.iter().find(|rule| rule.matches(device)) {
+            return (false, format!("excluded by `{rule}`"));
+        }
+        if device.is_virtual {
+            return (false, "virtual device, ignored by default".to_string());
+        }
+        (true, "physical device".to_string())
+    }
+}
+
+fn bus_name(bustype: u16) -> String {
+    BUS_NAMES
+        .iter()
+        .find(|(_, id)| *id == bustype as u32)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("0x{bustype:02x}"))
+}
+
+/// Name, bus and virtual/physical origin of an opened `/dev/input/event*` device.
+fn device_identity(path: &Path, fd: &File) -> DeviceIdentity {
+    let mut id = MaybeUninit::<input_id>::uninit();
+    let bustype = match unsafe { eviocgid(fd.as_raw_fd(), id.as_mut_ptr()) } {
+        Ok(_) => Some(unsafe { id.assume_init() }.bustype),
+        Err(_) => None,
+    };
+    let sysfs_device = path.file_name().and_then(|event| {
+        fs::canonicalize(Path::new("/sys/class/input").join(event).join("device")).ok()
+    });
+    DeviceIdentity {
+        name: get_device_name(fd).unwrap_or("N/A".to_string()),
+        bus: bustype,
+        is_virtual: is_virtual_device(sysfs_device.as_deref(), bustype.unwrap_or_default()),
+    }
+}
+
+/// A device is virtual when it says so through its bus, or when it lives among the software
+/// devices in sysfs without being a uhid device that reports a real bus.
+fn is_virtual_device(sysfs_device: Option<&Path>, bustype: u16) -> bool {
+    if bustype as u32 == BUS_VIRTUAL {
+        return true;
+    }
+    sysfs_device.is_some_and(|device| {
+        device.starts_with(SYSFS_VIRTUAL_DEVICES) && !device.starts_with(SYSFS_UHID_DEVICES)
+    })
+}
+
+struct DiscoveredDevices {
+    keyboards: Vec<File>,
+    mice: Vec<File>,
+    summaries: Vec<InputDevice>,
+}
+
+// ioctl defs
+ioctl_read!(eviocgid, b'E', 0x02, input_id);
+ioctl_read_buf!(eviocguniq, b'E', 0x08, u8);
+ioctl_read_buf!(eviocgprop, b'E', 0x09, u8);
+ioctl_read_buf!(eviocgmtslots, b'E', 0x0a, u8);
+ioctl_read_buf!(eviocgkey, b'E', 0x18, u8);
+ioctl_read_buf!(eviocgled, b'E', 0x19, u8);
+ioctl_read_buf!(eviocgsnd, b'E', 0x1a, u8);
+ioctl_read_buf!(eviocgsw, b'E', 0x1b, u8);
+ioctl_read_buf!(eviocgbit_all, b'E', 0x20, u8);
+ioctl_read_buf!(eviocgname, b'E', 0x06, u8);
+ioctl_read_buf!(eviocgphys, b'E', 0x07, u8);
+ioctl_read_buf!(eviocgbit_key, b'E', 0x20 + EV_KEY, u8); // key bitmask
+ioctl_read_buf!(eviocgbit_rel, b'E', 0x20 + EV_REL, u8); // relative movement
+ioctl_read_buf!(eviocgbit_abs, b'E', 0x20 + EV_ABS, u8); // absolute movement
+ioctl_read_buf!(eviocgbit_rep, b'E', 0x20 + EV_REP, u8); // repeat
+                                                         //
+
+fn discover_devices(filter: &DeviceFilter) -> Result<DiscoveredDevices> {
+    info!("Scanning /dev/input for devices...");
+    let entries = fs::read_dir("/dev/input")?;
+    let mut mice = Vec::new();
+    let mut keyboards = Vec::new();
+    let mut summaries = Vec::new();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if !file_type.is_char_device() {
+            continue;
+        }
+
+        // Opening device is fine here (discover phase)
+        let current_device = File::open(&path)?;
+        let kind = if is_keyboard(&current_device) {
+            "keyboard"
+        } else if is_mouse(&current_device) {
+            "mouse"
+        } else {
+            continue;
+        };
+
+        let identity = device_identity(&path, &current_device);
+        let (listen, reason) = filter.decide(&identity);
+        info!(
+            "Found {kind}: [{}] -> {} (bus {}): {}, {reason}",
+            path.display(),
+            identity.name,
+            identity.bus.map_or("unknown".to_string(), bus_name),
+            if listen { "counting" } else { "ignoring" }
+        );
+        if !listen {
+            continue;
+        }
+        summaries.push(InputDevice {
+            kind,
+            path: path.display().to_string(),
+            name: identity.name,
+        });
+        match kind {
+            "keyboard" => keyboards.push(current_device),
+            _ => mice.push(current_device),
+        }
+    }
+
+    if keyboards.is_empty() {
+        anyhow::bail!("No keyboard devices found (or all were ignored by the device rules). This is required to run.");
+    }
+    if mice.is_empty() {
+        anyhow::bail!("No mouse devices found (or all were ignored by the device rules). This is required to run.");
+    }
+
+    Ok(DiscoveredDevices {
+        keyboards,
+        mice,
+        summaries,
+    })
+}
+
+// Returns the device name of a fd from: /dev/input/event*
+fn get_device_name(fd: &File) -> Option<String> {
+    let mut buf = vec![0u8; 256];
+    match unsafe { eviocgname(fd.as_raw_fd(), buf.as_mut_slice()) } {
+        Ok(len) if (len as usize) > buf.len() => {
+            // should not happen with our buffer size, but guard anyway
+            None
+        }
+        Ok(len) if len > 0 => {
+            // ioctl returns length including trailing NUL; truncate to len and strip trailing zero
+            let mut slice = &buf[..len as usize];
+            if let Some(&0) = slice.last() {
+                slice = &slice[..slice.len() - 1];
+            }
+            String::from_utf8(slice.to_vec()).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Helper: is `bit` set in `bytes` bitmask.
+fn test_bit(bit: u32, bytes: &[u8]) -> bool {
+    let byte_index = (bit / 8) as usize;
+    let bit_in_byte = (bit % 8) as u8;
+    if byte_index < bytes.len() {
+        (bytes[byte_index] >> bit_in_byte) & 1 != 0
+    } else {
+        false
+    }
+}
+
+/// Detect keyboard capabilities
+fn is_keyboard(fd: &File) -> bool {
+    has_keyboard_capabilities(fd) && !has_mouse_capabilities(fd)
+}
+
+/// Detect mouse capabilities
+fn is_mouse(fd: &File) -> bool {
+    has_mouse_capabilities(fd) && !has_keyboard_capabilities(fd)
+}
+
+fn has_keyboard_capabilities(fd: &File) -> bool {
+    // (EV_MAX + 7) / 8
+    let mut ev_bitmask = vec![0u8; (EV_MAX as usize).div_ceil(8)];
+    unsafe {
+        if eviocgbit_all(fd.as_raw_fd(), ev_bitmask.as_mut_slice()).is_err() {
+            // debug!("ioctl EVIOCGBIT(ALL) failed with error code: [{ret}]");
+            return false;
+        }
+    }
+
+    if !test_bit(EV_KEY, &ev_bitmask) {
+        // debug!("Device is not a keyboard, EV_KEY test failed!");
+        return false;
+    }
+
+    if !test_bit(EV_REP, &ev_bitmask) {
+        // debug!("Device is not a keyboard, EV_REP test failed!");
+        return false;
+    }
+
+    let mut key_bitmask = vec![0u8; (EV_MAX as usize).div_ceil(8)];
+    unsafe {
+        if eviocgbit_key(fd.as_raw_fd(), key_bitmask.as_mut_slice()).is_err() {
+            // debug!("ioctl EVIOCGBIT(EV_KEYS) failed with error code: [{ret}]");
+            return false;
+        }
+    }
+
+    // check for alphabetic keys (Q..Y)
+    let has_qwerty_keys = (KEY_Q..=KEY_Y).all(|k| test_bit(k, &key_bitmask));
+    if !has_qwerty_keys {
+        // debug!("Device is not a keyboard, can't handle alphabetic keys!");
+        return false;
+    }
+
+    true
+}
+
+fn has_mouse_capabilities(fd: &File) -> bool {
+    let mut ev_types_bits = vec![0u8; (EV_MAX as usize).div_ceil(8)];
+    unsafe {
+        if eviocgbit_all(fd.as_raw_fd(), ev_types_bits.as_mut_slice()).is_err() {
+            // debug!("ioctl EVIOCGBIT(ALL) failed with error code: [{ret}]");
+            return false;
+        }
+    }
+
+    if !test_bit(EV_REL, &ev_types_bits) {
+        // debug!("Device can't handle relative movement, is not a mouse!");
+        return false;
+    }
+
+    let mut rel_bits = vec![0u8; (EV_MAX as usize).div_ceil(8)];
+    unsafe {
+        if eviocgbit_rel(fd.as_raw_fd(), rel_bits.as_mut_slice()).is_err() {
+            // debug!("ioctl EVIOCGBIT(EV_REL) failed with error code: [{ret}]");
+            return false;
+        }
+    }
+
+    if !test_bit(REL_X, &rel_bits) && !test_bit(REL_Y, &rel_bits) {
+        // debug!("Device can't handle relative axes (X/Y). Not a mouse!");
+        return false;
+    }
+
+    let mut ev_bitmask = vec![0u8; (EV_MAX as usize).div_ceil(8)];
+    unsafe {
+        if eviocgbit_all(fd.as_raw_fd(), ev_bitmask.as_mut_slice()).is_err() {
+            // debug!("ioctl EVIOCGBIT(ALL) failed with error code: [{ret}]");
+            return false;
+        }
+    }
+
+    if !test_bit(EV_KEY, &ev_bitmask) {
+        // debug!("Device can't handle EV_KEY events, not a mouse!");
+        return false;
+    }
+
+    true
+}
+
+/// Most events read from a device at once; a key press with its `SYN_REPORT` is a few, a fast
+/// mouse sends a few dozen per frame.
+const READ_BATCH: usize = 64;
+
+/// Counts events dropped because the channel to the input loop was full. Readers never wait for
+/// a slow input loop, as the kernel would start dropping events itself; they count what they
+/// could not deliver instead, so the bucket the events belonged to can be marked incomplete.
+#[derive(Debug, Clone, Default)]
+pub struct DroppedEvents(Arc<AtomicU64>);
+
+impl DroppedEvents {
+    pub fn add(&self, count: u64) {
+        self.0.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Events dropped since the previous call.
+    pub fn take(&self) -> u64 {
+        self.0.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Reads events from one device and sends every read as one batch, tagged by `tag`.
+async fn device_loop(
+    mut file: AsyncFd<File>,
+    tx: mpsc::Sender<Vec<InputEvent>>,
+    dropped: DroppedEvents,
+    tag: impl Fn(input_event) -> InputEvent,
+) -> Result<()> {
+    const EVENT_SIZE: usize = core::mem::size_of::<input_event>();
+    loop {
+        let mut guard = file.readable_mut().await?;
+        let mut events = [MaybeUninit::<input_event>::uninit(); READ_BATCH];
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(events.as_mut_ptr() as *mut u8, READ_BATCH * EVENT_SIZE)
+        };
+
+        match guard.try_io(|inner| {
+            read(inner, buf).map_err(|err| std::io::Error::from_raw_os_error(err as i32))
+        }) {
+            // the kernel only hands out whole events
+            Ok(Ok(n)) if n >= EVENT_SIZE => {
+                let batch = events[..n / EVENT_SIZE]
+                    .iter()
+                    .map(|event| tag(unsafe { event.assume_init() }))
+                    .collect::<Vec<_>>();
+                match tx.try_send(batch) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(batch)) => dropped.add(batch.len() as u64),
+                    // the input loop is gone, e.g. while it is being restarted
+                    Err(TrySendError::Closed(_)) => return Ok(()),
+                }
+            }
+            Ok(Ok(_)) => {} // partial read; ignore
+            Ok(Err(err)) => return Err(anyhow::Error::from(err)),
+            Err(_would_block) => continue, // fd not ready, await again
+        }
+    }
+}
+
+/// Spawn listeners on the devices `filter` lets through: devices will send batches of
+/// `InputEvent` to `tx`, and count in `dropped` what didn't fit. Returns a description of every
+/// device being listened to.
+pub async fn spawn_input_listeners(
+    tx: mpsc::Sender<Vec<InputEvent>>,
+    filter: DeviceFilter,
+    dropped: DroppedEvents,
+) -> Result<Vec<InputDevice>> {
+    let devices = tokio::task::spawn_blocking(move || discover_devices(&filter)).await??;
+
+    for file in devices.keyboards {
+        nix::fcntl::fcntl(
+            &file,
+            nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK),
+        )?;
+        let async_file = AsyncFd::new(file)?;
+        let tx_clone = tx.clone();
+        let dropped = dropped.clone();
+        tokio::spawn(async move {
+            let tag = |event| InputEvent::Keyboard { event };
+            if let Err(err) = device_loop(async_file, tx_clone, dropped, tag).await {
+                error!("Keyboard device task failed: {err:?}");
+            }
+        });
+    }
+
+    for (device_id, file) in devices.mice.into_iter().enumerate() {
+        nix::fcntl::fcntl(
+            &file,
+            nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK),
+        )?;
+        let async_file = AsyncFd::new(file)?;
+        let tx_clone = tx.clone();
+        let dropped = dropped.clone();
+        tokio::spawn(async move {
+            let tag = |event| InputEvent::Mouse { device_id, event };
+            if let Err(e) = device_loop(async_file, tx_clone, dropped, tag).await {
+                error!("Mouse device task failed: {}", e);
+            }
+        });
+    }
+
+    Ok(devices.summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str, bus: u32, is_virtual: bool) -> DeviceIdentity {
+        DeviceIdentity {
+            name: name.to_string(),
+            bus: Some(bus as u16),
+            is_virtual,
+        }
+    }
+
+    /// Verifies that virtual devices are ignored unless a rule includes them, that include
+    /// rules win over exclude rules, and that malformed rules are rejected.
+    #[test]
+    fn device_filter_ignores_virtual_devices_by_default() {
+        let keyboard = device("AT Translated Set 2 keyboard", BUS_I8042, false);
+        let ydotool = device("ydotoold virtual device", BUS_USB, true);
+        let barrier = device("Barrier Virtual Mouse", BUS_VIRTUAL, true);
+
+        let defaults = DeviceFilter::default();
+        assert!(defaults.decide(&keyboard).0);
+        assert!(!defaults.decide(&ydotool).0);
+
+        let filter = DeviceFilter {
+            include: vec!["name:barrier".parse().unwrap()],
+            exclude: vec!["bus:i8042".parse().unwrap(), "virtual".parse().unwrap()],
+        };
+        assert_eq!(
+            filter.decide(&barrier),
+            (true, "included by `name:barrier`".to_string())
+        );
+        assert_eq!(
+            filter.decide(&keyboard),
+            (false, "excluded by `bus:i8042`".to_string())
+        );
+
+        let custom = device("Tablet", 0x1f, false);
+        for rule in ["bus:0x1f", "bus:0x001f", "bus:0X1F"] {
+            assert!(
+                rule.parse::<DeviceRule>().unwrap().matches(&custom),
+                "{rule}"
+            );
+        }
+        assert_eq!(
+            "bus:0x03".parse::<DeviceRule>().unwrap().to_string(),
+            "bus:usb"
+        );
+
+        assert!("bus:firewire".parse::<DeviceRule>().is_err());
+        assert!("bus:0x".parse::<DeviceRule>().is_err());
+        assert!("bus:0x10000".parse::<DeviceRule>().is_err());
+        assert!("name:".parse::<DeviceRule>().is_err());
+        assert!("usb".parse::<DeviceRule>().is_err());
+    }
+
+    /// Verifies that a Bluetooth LE keyboard under uhid counts as physical, while uinput
+    /// devices and anything on the virtual bus stay virtual.
+    #[test]
+    fn uhid_devices_on_a_real_bus_are_physical() {
+        let uhid = Path::new("/sys/devices/virtual/misc/uhid/0005:046D:B35B.0004/input/input21");
+        assert!(!is_virtual_device(Some(uhid), BUS_BLUETOOTH as u16));
+        assert!(is_virtual_device(Some(uhid), BUS_VIRTUAL as u16));
+
+        let uinput = Path::new("/sys/devices/virtual/input/input30");
+        assert!(is_virtual_device(Some(uinput), BUS_USB as u16));
+
+        let usb = Path::new("/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/input/input5");
+        assert!(!is_virtual_device(Some(usb), BUS_USB as u16));
+        assert!(!is_virtual_device(None, BUS_I8042 as u16));
+    }
+}
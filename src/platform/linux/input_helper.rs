@@ -0,0 +1,132 @@
+//! Collector side of `vigil-input-helper`.
+//!
+//! The helper is the only process that needs access to `/dev/input` (through the `input`
+//! group or `CAP_DAC_READ_SEARCH`); it streams counters over a Unix socket, so the collector,
+//! its database and the sync client run without that access.
+pub mod protocol;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::*;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tracing::*;
+
+use crate::control::ControlDevice;
+use protocol::{HelperMessage, InputCounters};
+
+/// How long to wait before reconnecting after the helper went away, e.g. during an upgrade.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+async fn next_message<R: AsyncRead + Unpin>(
+    lines: &mut Lines<BufReader<R>>,
+) -> Result<HelperMessage> {
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow!("The input helper closed the connection"))?;
+    serde_json::from_str(&line).with_context(|| "Invalid message from the input helper")
+}
+
+/// Connects to the helper and reads the device list it sends first.
+async fn connect(path: &Path) -> Result<(Lines<BufReader<UnixStream>>, Vec<ControlDevice>)> {
+    let stream = UnixStream::connect(path).await.with_context(|| {
+        format!(
+            "Failed to connect to the input helper at {}",
+            path.display()
+        )
+    })?;
+    let mut lines = BufReader::new(stream).lines();
+
+    let HelperMessage::Devices { devices } = next_message(&mut lines).await? else {
+        bail!("The input helper did not start with its device list");
+    };
+    let devices = devices
+        .into_iter()
+        .map(|device| ControlDevice {
+            kind: device.kind,
+            path: device.path,
+            name: device.name,
+        })
+        .collect();
+    Ok((lines, devices))
+}
+
+/// Forwards the helper's counters to `tx`, reconnecting whenever the helper restarts.
+/// Returns the devices the helper listens to.
+pub async fn spawn_helper_listener(
+    path: PathBuf,
+    tx: mpsc::Sender<InputCounters>,
+) -> Result<Vec<ControlDevice>> {
+    let (mut lines, devices) = connect(&path).await?;
+    info!("Reading input through the helper at {}", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            match next_message(&mut lines).await {
+                Result::Ok(HelperMessage::Counters(counters)) => {
+                    if tx.send(counters).await.is_err() {
+                        return;
+                    }
+                }
+                Result::Ok(HelperMessage::Devices { .. }) => {}
+                Err(err) => {
+                    warn!("Lost the input helper: {err:#}");
+                    lines = loop {
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        if tx.is_closed() {
+                            return;
+                        }
+                        match connect(&path).await {
+                            Result::Ok((lines, _)) => {
+                                info!("Reconnected to the input helper");
+                                break lines;
+                            }
+                            Err(err) => debug!("{err:#}"),
+                        }
+                    };
+                }
+            }
+        }
+    });
+
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::HelperDevice;
+
+    /// Verifies the line format the helper writes: a device list, then counters, each read
+    /// back as one message per line.
+    #[tokio::test]
+    async fn helper_messages_are_read_one_per_line() -> Result<()> {
+        let devices = HelperMessage::Devices {
+            devices: vec![HelperDevice {
+                kind: "keyboard".to_string(),
+                path: "/dev/input/event3".to_string(),
+                name: "AT Translated Set 2 keyboard".to_string(),
+            }],
+        };
+        let counters = HelperMessage::Counters(InputCounters {
+            key_presses: 14,
+            mouse_counts: 812.5,
+            ..InputCounters::default()
+        });
+        let wire = format!(
+            "{}\n{}\n",
+            serde_json::to_string(&devices)?,
+            serde_json::to_string(&counters)?
+        );
+        assert!(wire.starts_with(r#"{"type":"devices","devices":[{"kind":"keyboard""#));
+
+        let mut lines = BufReader::new(wire.as_bytes()).lines();
+        assert_eq!(next_message(&mut lines).await?, devices);
+        assert_eq!(next_message(&mut lines).await?, counters);
+        assert!(next_message(&mut lines).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! Wire format between `vigil-input-helper` and the collector.
+//!
+//! The helper writes one JSON object per line: first the devices it listens to, then, about
+//! once a second while there is any, the activity counted since its previous message:
+//!
+//! ```text
+//! {"type":"devices","devices":[{"kind":"keyboard","path":"/dev/input/event3","name":"AT Translated Set 2 keyboard"}]}
+//! {"type":"counters","key_presses":14,"left_clicks":1,"right_clicks":0,"middle_clicks":0,"mouse_counts":812.5,"scroll_vertical_steps":3.0,"scroll_horizontal_steps":0.0,"dropped_events":0}
+//! ```
+//!
+//! Key codes never leave the helper, only how many keys were pressed. This file is compiled
+//! into the helper binary as well, so it must not depend on anything but std and serde.
+use std::env;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Overrides the socket the helper listens on and the collector connects to.
+pub const HELPER_SOCKET_ENV: &str = "VIGIL_INPUT_HELPER_SOCKET";
+const HELPER_SOCKET_NAME: &str = "vigil-input-helper.sock";
+
+/// `$VIGIL_INPUT_HELPER_SOCKET`, or `vigil-input-helper.sock` in the user's runtime directory.
+pub fn helper_socket_path() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    non_empty(HELPER_SOCKET_ENV).map(PathBuf::from).or_else(|| {
+        non_empty("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join(HELPER_SOCKET_NAME))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HelperDevice {
+    /// `keyboard` or `mouse`.
+    pub kind: String,
+    pub path: String,
+    pub name: String,
+}
+
+/// Activity since the previous message. Pointer travel stays in device counts, the collector
+/// converts it with its configured mouse DPI.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputCounters {
+    pub key_presses: u64,
+    pub left_clicks: u64,
+    pub right_clicks: u64,
+    pub middle_clicks: u64,
+    pub mouse_counts: f64,
+    pub scroll_vertical_steps: f64,
+    pub scroll_horizontal_steps: f64,
+    /// Events the helper had to drop because it fell behind reading its devices.
+    #[serde(default)]
+    pub dropped_events: u64,
+}
+
+impl InputCounters {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum HelperMessage {
+    Devices { devices: Vec<HelperDevice> },
+    Counters(InputCounters),
+}
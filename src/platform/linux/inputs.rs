@@ -1,53 +1,26 @@
 use std::collections::HashMap;
-use std::fs;
-use std::fs::File;
-use std::mem::MaybeUninit;
-use std::os::fd::AsRawFd;
-use std::os::unix::fs::FileTypeExt;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 
-use nix::ioctl_read_buf;
-use nix::unistd::read;
-
-use tokio::io::unix::AsyncFd;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::channel;
 use tokio::time::*;
 use tracing::*;
 
 use crate::common::*;
+use crate::control::{next_signal, CollectorControl, ControlDevice, ControlSignal};
 use crate::input_bindings::*;
-use crate::storage::backend::DataStore;
+use crate::platform::linux::evdev::{
+    spawn_input_listeners, DeviceFilter, DroppedEvents, InputEvent, KeyPressState,
+};
+use crate::platform::linux::input_helper::protocol::{helper_socket_path, InputCounters};
+use crate::platform::linux::input_helper::spawn_helper_listener;
 use crate::storage::backend::StorageBackend;
+use crate::storage::spool::{Spool, SpoolRows, SPOOL_INTERVAL};
 
 static mut IDLE_TIME: u64 = 0;
 
-/// Either a relative change for EV_REL, absolute new value for EV_ABS (joysticks ...), or 0 for EV_KEY for release, 1 for keypress and 2 for autorepeat
-/// https://docs.kernel.org/input/input.html
-#[derive(PartialEq, Eq)]
-enum KeyPressState {
-    _Up = 0,
-    Down = 1,
-    _Repeat = 2,
-}
-
-enum InputEvent {
-    Keyboard {
-        event: input_event,
-    },
-    Mouse {
-        device_id: usize,
-        event: input_event,
-    },
-}
-
-struct DiscoveredDevices {
-    keyboards: Vec<File>,
-    mice: Vec<File>,
-}
-
 #[derive(Debug, Default)]
 struct PendingMousePacket {
     dx_counts: f64,
@@ -97,288 +70,203 @@ impl PendingMousePacket {
     }
 }
 
-// ioctl defs
-ioctl_read_buf!(eviocguniq, b'E', 0x08, u8);
-ioctl_read_buf!(eviocgprop, b'E', 0x09, u8);
-ioctl_read_buf!(eviocgmtslots, b'E', 0x0a, u8);
-ioctl_read_buf!(eviocgkey, b'E', 0x18, u8);
-ioctl_read_buf!(eviocgled, b'E', 0x19, u8);
-ioctl_read_buf!(eviocgsnd, b'E', 0x1a, u8);
-ioctl_read_buf!(eviocgsw, b'E', 0x1b, u8);
-ioctl_read_buf!(eviocgbit_all, b'E', 0x20, u8);
-ioctl_read_buf!(eviocgname, b'E', 0x06, u8);
-ioctl_read_buf!(eviocgphys, b'E', 0x07, u8);
-ioctl_read_buf!(eviocgbit_key, b'E', 0x20 + EV_KEY, u8); // key bitmask
-ioctl_read_buf!(eviocgbit_rel, b'E', 0x20 + EV_REL, u8); // relative movement
-ioctl_read_buf!(eviocgbit_abs, b'E', 0x20 + EV_ABS, u8); // absolute movement
-ioctl_read_buf!(eviocgbit_rep, b'E', 0x20 + EV_REP, u8); // repeat
-                                                         //
-
-fn discover_devices() -> Result<DiscoveredDevices> {
-    info!("Scanning /dev/input for devices...");
-    let entries = fs::read_dir("/dev/input")?;
-    let mut mice = Vec::new();
-    let mut keyboards = Vec::new();
-
-    for entry in entries.flatten() {
-        let path = entry.path();
-        let file_type = entry.file_type()?;
-
-        if !file_type.is_char_device() {
-            continue;
-        }
-
-        // Opening device is fine here (discover phase)
-        let current_device = File::open(&path)?;
-        if is_keyboard(&current_device) {
-            info!(
-                "Found Keyboard: [{}] -> {}",
-                path.display(),
-                get_device_name(&current_device).unwrap_or("N/A".to_string())
-            );
-            keyboards.push(current_device);
-        } else if is_mouse(&current_device) {
-            info!(
-                "Found Mouse: [{}] -> {}",
-                path.display(),
-                get_device_name(&current_device).unwrap_or("N/A".to_string())
-            );
-            mice.push(current_device);
-        }
+/// Adds activity counted by `vigil-input-helper` to the buffer.
+fn record_helper_counters(
+    input_buffer: &mut InputBucketBuffer,
+    counters: &InputCounters,
+    now: chrono::DateTime<chrono::Utc>,
+    mouse_dpi: f64,
+) {
+    input_buffer.record_key_presses(now, counters.key_presses);
+    input_buffer.record_clicks(
+        now,
+        counters.left_clicks,
+        counters.right_clicks,
+        counters.middle_clicks,
+    );
+    if counters.mouse_counts != 0.0 {
+        input_buffer
+            .record_mouse_distance_cm(now, counts_to_centimeters(counters.mouse_counts, mouse_dpi));
     }
-
-    if keyboards.is_empty() {
-        anyhow::bail!("No keyboard devices found. This is required to run.");
+    if counters.scroll_vertical_steps != 0.0 {
+        input_buffer.record_vertical_scroll_cm(
+            now,
+            scroll_steps_to_centimeters(counters.scroll_vertical_steps),
+        );
     }
-    if mice.is_empty() {
-        anyhow::bail!("No mouse devices found. This is required to run.");
+    if counters.scroll_horizontal_steps != 0.0 {
+        input_buffer.record_horizontal_scroll_cm(
+            now,
+            scroll_steps_to_centimeters(counters.scroll_horizontal_steps),
+        );
+    }
+    if counters.dropped_events != 0 {
+        input_buffer.record_dropped_events(now, counters.dropped_events);
     }
-
-    Ok(DiscoveredDevices { keyboards, mice })
 }
 
-// Returns the device name of a fd from: /dev/input/event*
-fn get_device_name(fd: &File) -> Option<String> {
-    let mut buf = vec![0u8; 256];
-    match unsafe { eviocgname(fd.as_raw_fd(), buf.as_mut_slice()) } {
-        Ok(len) if (len as usize) > buf.len() => {
-            // should not happen with our buffer size, but guard anyway
-            None
-        }
-        Ok(len) if len > 0 => {
-            // ioctl returns length including trailing NUL; truncate to len and strip trailing zero
-            let mut slice = &buf[..len as usize];
-            if let Some(&0) = slice.last() {
-                slice = &slice[..slice.len() - 1];
+/// Adds one evdev event to the buffer.
+fn record_event(
+    input_buffer: &mut InputBucketBuffer,
+    pending_mouse_packets: &mut HashMap<usize, PendingMousePacket>,
+    input: InputEvent,
+    now: chrono::DateTime<chrono::Utc>,
+    mouse_dpi: f64,
+) {
+    match input {
+        InputEvent::Keyboard { event } => {
+            if event.value == KeyPressState::Down as i32 {
+                input_buffer.record_key_press(now);
             }
-            String::from_utf8(slice.to_vec()).ok()
         }
-        _ => None,
-    }
-}
-
-/// Helper: is `bit` set in `bytes` bitmask.
-fn test_bit(bit: u32, bytes: &[u8]) -> bool {
-    let byte_index = (bit / 8) as usize;
-    let bit_in_byte = (bit % 8) as u8;
-    if byte_index < bytes.len() {
-        (bytes[byte_index] >> bit_in_byte) & 1 != 0
-    } else {
-        false
+        InputEvent::Mouse { device_id, event } => match event.type_ as u32 {
+            EV_KEY if event.value == KeyPressState::Down as i32 => {
+                match event.code as u32 {
+                    BTN_LEFT => input_buffer.record_left_click(now),
+                    BTN_RIGHT => input_buffer.record_right_click(now),
+                    BTN_MIDDLE => input_buffer.record_middle_click(now),
+                    // BTN_SIDE => logger.side_clicks += 1,
+                    // BTN_EXTRA => logger.extra_clicks +=1,
+                    // BTN_FORWARD => logger.forward_clicks +=1,
+                    // BTN_BACK => logger.back_clicks +=1,
+                    // Other buttons are ignored for now.
+                    _ => {}
+                }
+            }
+            EV_REL => {
+                pending_mouse_packets
+                    .entry(device_id)
+                    .or_default()
+                    .record_relative_event(event.code as u32, event.value);
+            }
+            EV_SYN if event.code as u32 == SYN_REPORT => {
+                pending_mouse_packets.entry(device_id).or_default().flush(
+                    input_buffer,
+                    now,
+                    mouse_dpi,
+                );
+            }
+            _ => {}
+        },
     }
 }
 
-/// Detect keyboard capabilities
-fn is_keyboard(fd: &File) -> bool {
-    has_keyboard_capabilities(fd) && !has_mouse_capabilities(fd)
+/// Whether evdev failed because this user may not open `/dev/input` devices.
+#[cfg(feature = "x11")]
+fn is_permission_denied(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io| io.kind() == std::io::ErrorKind::PermissionDenied)
+    })
 }
 
-/// Detect mouse capabilities
-fn is_mouse(fd: &File) -> bool {
-    has_mouse_capabilities(fd) && !has_keyboard_capabilities(fd)
-}
-
-fn has_keyboard_capabilities(fd: &File) -> bool {
-    // (EV_MAX + 7) / 8
-    let mut ev_bitmask = vec![0u8; (EV_MAX as usize).div_ceil(8)];
-    unsafe {
-        if eviocgbit_all(fd.as_raw_fd(), ev_bitmask.as_mut_slice()).is_err() {
-            // debug!("ioctl EVIOCGBIT(ALL) failed with error code: [{ret}]");
-            return false;
+/// Reads input through `vigil-input-helper` when it is running, otherwise listens on evdev
+/// devices directly, falling back to XInput2 raw events in an X11 session where `/dev/input`
+/// is off limits (no `input` group membership).
+async fn spawn_input_sources(
+    tx: mpsc::Sender<Vec<InputEvent>>,
+    counters_tx: mpsc::Sender<InputCounters>,
+    device_filter: DeviceFilter,
+    dropped: DroppedEvents,
+) -> Result<Vec<ControlDevice>> {
+    if let Some(socket) = helper_socket_path().filter(|socket| socket.exists()) {
+        match spawn_helper_listener(socket, counters_tx).await {
+            Result::Ok(devices) => return Ok(devices),
+            Err(err) => warn!("Input helper unavailable, reading devices directly: {err:#}"),
         }
     }
 
-    if !test_bit(EV_KEY, &ev_bitmask) {
-        // debug!("Device is not a keyboard, EV_KEY test failed!");
-        return false;
-    }
+    let evdev = spawn_input_listeners(tx.clone(), device_filter.clone(), dropped.clone())
+        .await
+        .map(|devices| {
+            devices
+                .into_iter()
+                .map(|device| ControlDevice {
+                    kind: device.kind.to_string(),
+                    path: device.path,
+                    name: device.name,
+                })
+                .collect()
+        });
 
-    if !test_bit(EV_REP, &ev_bitmask) {
-        // debug!("Device is not a keyboard, EV_REP test failed!");
-        return false;
-    }
+    #[cfg(feature = "x11")]
+    if let Err(err) = &evdev {
+        use crate::platform::linux::common::{detect_display_server, DisplayServer};
 
-    let mut key_bitmask = vec![0u8; (EV_MAX as usize).div_ceil(8)];
-    unsafe {
-        if eviocgbit_key(fd.as_raw_fd(), key_bitmask.as_mut_slice()).is_err() {
-            // debug!("ioctl EVIOCGBIT(EV_KEYS) failed with error code: [{ret}]");
-            return false;
+        if is_permission_denied(err) && detect_display_server() == DisplayServer::X11 {
+            warn!("Cannot read input devices ({err:#}), using XInput2 raw events instead");
+            return crate::platform::linux::xinput::spawn_xinput_listener(
+                tx,
+                device_filter,
+                dropped,
+            );
         }
     }
 
-    // check for alphabetic keys (Q..Y)
-    let has_qwerty_keys = (KEY_Q..=KEY_Y).all(|k| test_bit(k, &key_bitmask));
-    if !has_qwerty_keys {
-        // debug!("Device is not a keyboard, can't handle alphabetic keys!");
-        return false;
-    }
-
-    true
+    evdev
 }
 
-fn has_mouse_capabilities(fd: &File) -> bool {
-    let mut ev_types_bits = vec![0u8; (EV_MAX as usize).div_ceil(8)];
-    unsafe {
-        if eviocgbit_all(fd.as_raw_fd(), ev_types_bits.as_mut_slice()).is_err() {
-            // debug!("ioctl EVIOCGBIT(ALL) failed with error code: [{ret}]");
-            return false;
-        }
-    }
-
-    if !test_bit(EV_REL, &ev_types_bits) {
-        // debug!("Device can't handle relative movement, is not a mouse!");
-        return false;
-    }
-
-    let mut rel_bits = vec![0u8; (EV_MAX as usize).div_ceil(8)];
-    unsafe {
-        if eviocgbit_rel(fd.as_raw_fd(), rel_bits.as_mut_slice()).is_err() {
-            // debug!("ioctl EVIOCGBIT(EV_REL) failed with error code: [{ret}]");
-            return false;
-        }
-    }
-
-    if !test_bit(REL_X, &rel_bits) && !test_bit(REL_Y, &rel_bits) {
-        // debug!("Device can't handle relative axes (X/Y). Not a mouse!");
-        return false;
-    }
-
-    let mut ev_bitmask = vec![0u8; (EV_MAX as usize).div_ceil(8)];
-    unsafe {
-        if eviocgbit_all(fd.as_raw_fd(), ev_bitmask.as_mut_slice()).is_err() {
-            // debug!("ioctl EVIOCGBIT(ALL) failed with error code: [{ret}]");
-            return false;
-        }
-    }
-
-    if !test_bit(EV_KEY, &ev_bitmask) {
-        // debug!("Device can't handle EV_KEY events, not a mouse!");
-        return false;
-    }
-
-    true
+fn unix_now() -> Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
 }
 
-async fn keyboard_device_loop(mut file: AsyncFd<File>, tx: mpsc::Sender<InputEvent>) -> Result<()> {
-    loop {
-        let mut guard = file.readable_mut().await?;
-        let mut event = MaybeUninit::<input_event>::uninit();
-        let buf = unsafe {
-            std::slice::from_raw_parts_mut(
-                &mut event as *mut _ as *mut u8,
-                core::mem::size_of::<input_event>(),
-            )
-        };
-
-        match guard.try_io(|inner| {
-            read(inner, buf).map_err(|err| std::io::Error::from_raw_os_error(err as i32))
-        }) {
-            Ok(Ok(n)) if n == core::mem::size_of::<input_event>() => {
-                let kbd_event = unsafe { event.assume_init() };
-                let _ = tx.try_send(InputEvent::Keyboard { event: kbd_event });
-            }
-            Ok(Ok(_)) => {} // partial read; ignore
-            Ok(Err(err)) => return Err(anyhow::Error::from(err)),
-            Err(_would_block) => continue, // fd not ready, await again
-        }
-    }
+fn event_time(event: &input_event) -> Duration {
+    Duration::new(event.time.tv_sec as u64, event.time.tv_usec as u32 * 1000)
 }
 
-async fn mouse_device_loop(
-    mut file: AsyncFd<File>,
-    device_id: usize,
-    tx: mpsc::Sender<InputEvent>,
-) -> Result<()> {
-    loop {
-        let mut guard = file.readable_mut().await?;
-        let mut event = MaybeUninit::<input_event>::uninit();
-        let buf = unsafe {
-            std::slice::from_raw_parts_mut(
-                &mut event as *mut _ as *mut u8,
-                core::mem::size_of::<input_event>(),
-            )
-        };
-
-        match guard.try_io(|inner| {
-            read(inner, buf).map_err(|err| std::io::Error::from_raw_os_error(err as i32))
-        }) {
-            Ok(Ok(n)) if n == core::mem::size_of::<input_event>() => {
-                let mouse_event = unsafe { event.assume_init() };
-                let _ = tx.try_send(InputEvent::Mouse {
-                    device_id,
-                    event: mouse_event,
-                });
-            }
-            Ok(Ok(_)) => {} // partial read; ignore
-            Ok(Err(err)) => return Err(anyhow::Error::from(err)),
-            Err(_would_block) => continue, // fd not ready, await again
-        }
+/// Moves the finished buckets into the spool, so a crash can't lose them.
+fn spool_input_buffer(input_buffer: &mut InputBucketBuffer, spool: &mut Spool) {
+    let rows = input_buffer.drain();
+    let dropped = rows.iter().map(|row| row.dropped_events).sum::<u64>();
+    if dropped > 0 {
+        warn!("{dropped} input events were dropped, counts are incomplete");
+    }
+    if let Err(err) = spool.append(SpoolRows::Input(rows)) {
+        error!("Failed to spool input rows: {err:#}");
     }
 }
 
-/// Spawn listeners: devices will send `InputEvent` to `tx`.
-async fn spawn_input_listeners(tx: mpsc::Sender<InputEvent>) -> Result<()> {
-    let devices = tokio::task::spawn_blocking(discover_devices).await??;
-
-    for file in devices.keyboards {
-        nix::fcntl::fcntl(
-            &file,
-            nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK),
-        )?;
-        let async_file = AsyncFd::new(file)?;
-        let tx_clone = tx.clone();
-        tokio::spawn(async move {
-            if let Err(err) = keyboard_device_loop(async_file, tx_clone).await {
-                error!("Keyboard device task failed: {err:?}");
-            }
-        });
+async fn flush_input_buffer(
+    pending_mouse_packets: &mut HashMap<usize, PendingMousePacket>,
+    input_buffer: &mut InputBucketBuffer,
+    spool: &mut Spool,
+    mouse_dpi: f64,
+    backend: &StorageBackend,
+) {
+    let now = chrono::Utc::now();
+    for packet in pending_mouse_packets.values_mut() {
+        packet.flush(input_buffer, now, mouse_dpi);
     }
-
-    for (device_id, file) in devices.mice.into_iter().enumerate() {
-        nix::fcntl::fcntl(
-            &file,
-            nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK),
-        )?;
-        let async_file = AsyncFd::new(file)?;
-        let tx_clone = tx.clone();
-        tokio::spawn(async move {
-            if let Err(e) = mouse_device_loop(async_file, device_id, tx_clone).await {
-                error!("Mouse device task failed: {}", e);
-            }
-        });
+    spool_input_buffer(input_buffer, spool);
+    if let Err(e) = spool.commit(backend).await {
+        error!("Failed to store keylogger data in backend: {:?}", e);
     }
-
-    Ok(())
 }
 
-pub async fn run(dpi: Option<u32>, update_interval: u32, backend: StorageBackend) -> Result<()> {
-    let mouse_dpi = dpi.unwrap_or(DEFAULT_MOUSE_DPI).max(1) as f64;
+pub async fn run(
+    dpi: Option<u32>,
+    device_filter: DeviceFilter,
+    update_interval: u32,
+    backend: StorageBackend,
+    control: CollectorControl,
+) -> Result<()> {
+    let mut mouse_dpi = dpi.unwrap_or(DEFAULT_MOUSE_DPI).max(1) as f64;
     let mut input_buffer =
         InputBucketBuffer::new(backend.source_id(), backend.bucket_granularity_minutes());
     let mut pending_mouse_packets = HashMap::<usize, PendingMousePacket>::new();
+    let mut spool = Spool::open(&backend, "input")
+        .await
+        .with_context(|| "Failed to open the input spool")?;
+    let mut spool_tick = interval(SPOOL_INTERVAL);
 
     let (tasks_tx, mut tasks_rx) = channel::<Signals>(32);
-    let (events_tx, mut events_rx) = channel::<InputEvent>(256);
+    // Readers send one batch per read and count what doesn't fit instead of waiting.
+    let (events_tx, mut events_rx) = channel::<Vec<InputEvent>>(256);
+    let dropped = DroppedEvents::default();
+    let (counters_tx, mut counters_rx) = channel::<InputCounters>(64);
 
     // database updates
     let ticker_handle = spawn_ticker(
@@ -395,86 +283,80 @@ pub async fn run(dpi: Option<u32>, update_interval: u32, backend: StorageBackend
         }
     });
 
-    spawn_input_listeners(events_tx)
+    let devices = spawn_input_sources(events_tx, counters_tx, device_filter, dropped.clone())
         .await
         .with_context(|| "Failed to spawn input listeners")?;
+    control.set_devices(devices);
+    let mut control_rx = control.subscribe();
     let idle = Duration::from_secs(20);
     let mut ticker = interval(idle);
-    let mut last_event: Option<input_event> = None;
+    // time since the Unix epoch of the last input, from any source
+    let mut last_event_at: Option<Duration> = None;
     loop {
         tokio::select! {
             _ = ticker.tick() => {
                 debug!("Checking the time of the last event");
-                if let Some(event) = last_event {
-                    let event_timeval = event.time;
-                    let now = std::time::SystemTime::now()
-                        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                        .unwrap();
-
-                    let delta = now.checked_sub(Duration::new(event_timeval.tv_sec as u64, event_timeval.tv_usec as u32)).unwrap_or_default();
+                if let Some(event_at) = last_event_at {
+                    let delta = unix_now().checked_sub(event_at).unwrap_or_default();
                     debug!("Time since last event: {:.6} seconds", delta.as_secs_f64());
                     unsafe {
                         IDLE_TIME = delta.as_secs()
                     }
                 }
             }
-            // An input event was received from a device.
-            Some(event) = events_rx.recv() => {
-                match event {
-                    InputEvent::Keyboard { event } => {
-                        last_event = Some(event);
-                        if event.value == KeyPressState::Down as i32 {
-                            input_buffer.record_key_press(chrono::Utc::now());
-                        }
-                    },
-                    InputEvent::Mouse { device_id, event } => {
-                        last_event = Some(event);
-                        let now = chrono::Utc::now();
-                        match event.type_ as u32 {
-                            EV_KEY => {
-                                if event.value == KeyPressState::Down as i32 {
-                                    match event.code as u32 {
-                                        BTN_LEFT => input_buffer.record_left_click(now),
-                                        BTN_RIGHT => input_buffer.record_right_click(now),
-                                        BTN_MIDDLE => input_buffer.record_middle_click(now),
-                                        // BTN_SIDE => logger.side_clicks += 1,
-                                        // BTN_EXTRA => logger.extra_clicks +=1,
-                                        // BTN_FORWARD => logger.forward_clicks +=1,
-                                        // BTN_BACK => logger.back_clicks +=1,
-                                        // Other buttons are ignored for now.
-                                        _ => {}
-                                    }
-                                }
-                            }
-                            EV_REL => {
-                                pending_mouse_packets
-                                    .entry(device_id)
-                                    .or_default()
-                                    .record_relative_event(event.code as u32, event.value);
-                            }
-                            EV_SYN if event.code as u32 == SYN_REPORT => {
-                                pending_mouse_packets
-                                    .entry(device_id)
-                                    .or_default()
-                                    .flush(&mut input_buffer, now, mouse_dpi);
-                            }
-                            _ => {}
-                        }
-                    },
+            // A batch of input events was received from a device.
+            Some(events) = events_rx.recv() => {
+                let dropped_events = dropped.take();
+                if let Some(InputEvent::Keyboard { event } | InputEvent::Mouse { event, .. }) = events.last() {
+                    last_event_at = Some(event_time(event));
+                }
+                // Paused collection still tracks the last event so idle detection stays accurate.
+                // Keys typed into a lock screen (its password included) are never counted.
+                if !control.is_recording() {
+                    continue;
+                }
+                let now = chrono::Utc::now();
+                if dropped_events > 0 {
+                    input_buffer.record_dropped_events(now, dropped_events);
+                }
+                for event in events {
+                    record_event(&mut input_buffer, &mut pending_mouse_packets, event, now, mouse_dpi);
+                }
+                control.set_buffered_input_rows(input_buffer.pending_rows());
+            }
+
+            // Activity counted by the input helper since its previous message.
+            Some(counters) = counters_rx.recv() => {
+                last_event_at = Some(unix_now());
+                if control.is_recording() && !counters.is_empty() {
+                    record_helper_counters(&mut input_buffer, &counters, chrono::Utc::now(), mouse_dpi);
+                    control.set_buffered_input_rows(input_buffer.pending_rows());
                 }
             }
 
+            _ = spool_tick.tick() => spool_input_buffer(&mut input_buffer, &mut spool),
+
             // A signal was received from another task.
             Some(signal) = tasks_rx.recv() => {
                 if matches!(signal, Signals::DbUpdate) {
-                    let now = chrono::Utc::now();
-                    for packet in pending_mouse_packets.values_mut() {
-                        packet.flush(&mut input_buffer, now, mouse_dpi);
+                    flush_input_buffer(&mut pending_mouse_packets, &mut input_buffer, &mut spool, mouse_dpi, &backend).await;
+                    control.set_buffered_input_rows(0);
+                }
+            }
+
+            // A command arrived through the control socket.
+            signal = next_signal(&mut control_rx) => {
+                match signal {
+                    ControlSignal::FlushNow => {
+                        flush_input_buffer(&mut pending_mouse_packets, &mut input_buffer, &mut spool, mouse_dpi, &backend).await;
+                        control.set_buffered_input_rows(0);
+                        control.ack_flush();
                     }
-                    let pending_rows = input_buffer.drain();
-                    if let Err(e) = backend.store_keys_data(&pending_rows).await {
-                        error!("Failed to store keylogger data in backend: {:?}", e);
+                    ControlSignal::ReloadConfig { mouse_dpi: reloaded } => {
+                        info!("Applying reloaded mouse DPI {reloaded}");
+                        mouse_dpi = reloaded.max(1) as f64;
                     }
+                    ControlSignal::BrowserTab(_) | ControlSignal::PrepareForSleep { .. } => {}
                 }
             }
 
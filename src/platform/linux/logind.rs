@@ -0,0 +1,15 @@
+//! Names shared by the watchers that talk to systemd-logind over the system bus.
+use anyhow::*;
+use zbus::{Connection, Proxy};
+
+pub const LOGIND_SERVICE: &str = "org.freedesktop.login1";
+pub const LOGIND_PATH: &str = "/org/freedesktop/login1";
+pub const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+pub const LOGIND_USER: &str = "org.freedesktop.login1.User";
+pub const LOGIND_SESSION: &str = "org.freedesktop.login1.Session";
+
+pub async fn logind_manager(connection: &Connection) -> Result<Proxy<'static>> {
+    Proxy::new(connection, LOGIND_SERVICE, LOGIND_PATH, LOGIND_MANAGER)
+        .await
+        .with_context(|| "Failed to reach logind")
+}
@@ -1,7 +1,17 @@
+pub mod browser_host;
 pub mod common;
+pub mod evdev;
+pub mod input_helper;
 pub mod inputs;
+pub mod logind;
 pub mod process;
+pub mod screen_lock;
+pub mod suspend;
+pub mod terminal;
+pub mod text_session;
 #[cfg(feature = "wayland")]
 pub mod wayland;
 #[cfg(feature = "x11")]
 pub mod x11;
+#[cfg(feature = "x11")]
+pub mod xinput;
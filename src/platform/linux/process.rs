@@ -1,45 +1,229 @@
 use crate::common::*;
+use crate::control::{next_signal, CollectorControl, ControlSignal};
 use crate::platform::linux::common::*;
 use crate::platform::linux::inputs::*;
+use crate::platform::linux::terminal::resolve_terminal_context;
+use crate::platform::linux::text_session::focused_text_session;
 use crate::storage::backend::{DataStore, StorageBackend};
+use crate::storage::spool::{Spool, SpoolRows, SPOOL_INTERVAL};
 
 use anyhow::*;
-use tokio::sync::mpsc::*;
+use chrono::{DateTime, Utc};
 use tokio::time::*;
 
 use tracing::*;
 
+/// How often the foreground command of a focused terminal is re-read from `/proc`.
+const TERMINAL_REFRESH_SECS: u64 = 5;
+
+/// Keeps suspends and wall-clock jumps out of focus time.
+///
+/// logind announces most suspends ahead of time; comparing clocks catches the ones it doesn't
+/// (no logind, or the announcement was missed) as soon as the loop wakes up again.
+struct SuspendGuard {
+    clock: ClockWatch,
+    suspended_since: Option<DateTime<Utc>>,
+}
+
+impl SuspendGuard {
+    fn new() -> Self {
+        Self {
+            clock: ClockWatch::new(ClockSample::now()),
+            suspended_since: None,
+        }
+    }
+
+    /// Must run before the tracker records anything, so an unannounced gap ends the running
+    /// interval where the machine was last awake instead of counting as focus time.
+    async fn check_clock(&mut self, proc_data: &mut ProcessTracker, backend: &StorageBackend) {
+        let gap = self.clock.observe(ClockSample::now());
+        // logind already paused the tracker and records the period on resume.
+        if self.suspended_since.is_some() {
+            return;
+        }
+        let Some(gap) = gap else {
+            return;
+        };
+
+        proc_data.skip_gap(gap.awake_until, gap.observed_at);
+        if gap.is_suspend() {
+            info!(
+                "Detected an unannounced suspend of {}s, leaving it out of focus time",
+                gap.suspended.as_secs()
+            );
+            let period = SuspendRecord {
+                source_id: backend.source_id(),
+                started_at_utc: gap.awake_until,
+                ended_at_utc: gap.observed_at,
+                detected_by: SuspendDetection::ClockCheck,
+            };
+            store_suspend_period(backend, &period).await;
+        } else {
+            warn!(
+                "Wall clock jumped from {} to {}, not counting the difference as focus time",
+                gap.awake_until, gap.observed_at
+            );
+        }
+    }
+
+    /// Handles logind's announcement that the system suspends (`sleeping`) or has resumed.
+    async fn prepare_for_sleep(
+        &mut self,
+        proc_data: &mut ProcessTracker,
+        backend: &StorageBackend,
+        sleeping: bool,
+        at: DateTime<Utc>,
+    ) {
+        if sleeping {
+            proc_data.pause(at);
+            self.suspended_since.get_or_insert(at);
+            return;
+        }
+
+        self.clock.reset(ClockSample::now());
+        if let Some(started_at_utc) = self.suspended_since.take() {
+            let period = SuspendRecord {
+                source_id: backend.source_id(),
+                started_at_utc,
+                ended_at_utc: at,
+                detected_by: SuspendDetection::Logind,
+            };
+            store_suspend_period(backend, &period).await;
+        }
+    }
+}
+
+async fn store_suspend_period(backend: &StorageBackend, period: &SuspendRecord) {
+    if let Err(err) = backend.store_suspend_period(period).await {
+        error!("Error sending data to suspend_periods table: {err:?}");
+    }
+}
+
+/// Moves the focus time recorded so far into the spool, so a crash can't lose it.
+async fn spool_focus_data(
+    proc_data: &mut ProcessTracker,
+    suspend: &mut SuspendGuard,
+    spool: &mut Spool,
+    backend: &StorageBackend,
+) {
+    suspend.check_clock(proc_data, backend).await;
+    proc_data.record_active_until(chrono::Utc::now());
+    let drained = [
+        SpoolRows::Focus(proc_data.drain_pending()),
+        SpoolRows::Domains(proc_data.drain_pending_domains()),
+        SpoolRows::Terminals(proc_data.drain_pending_terminals()),
+        SpoolRows::Projects(proc_data.drain_pending_projects()),
+    ];
+    for rows in drained {
+        if let Err(err) = spool.append(rows) {
+            error!("Failed to spool focus rows: {err:#}");
+        }
+    }
+}
+
+async fn flush_focus_data(
+    proc_data: &mut ProcessTracker,
+    suspend: &mut SuspendGuard,
+    spool: &mut Spool,
+    backend: &StorageBackend,
+    control: &CollectorControl,
+) {
+    spool_focus_data(proc_data, suspend, spool, backend).await;
+    if let Err(err) = spool.commit(backend).await {
+        error!("Error sending data to the focus tables: {err:?}");
+    }
+    control.set_buffered_focus_rows(0);
+}
+
+/// Re-reads what the focused terminal runs; clears the context for any other window.
+fn refresh_terminal_context(proc_data: &mut ProcessTracker, pid: Option<u32>) {
+    let context = proc_data
+        .current_window()
+        .filter(|window| is_terminal_app(&window.app_identifier()))
+        .and_then(|window| resolve_terminal_context(window, pid));
+    proc_data.set_terminal_context(context, chrono::Utc::now());
+}
+
+/// Publishes the tracker state that the control socket reports.
+fn publish_focus_state(proc_data: &ProcessTracker, control: &CollectorControl) {
+    control.set_current_window(proc_data.current_window());
+    control.set_buffered_focus_rows(proc_data.pending_rows());
+}
+
 #[cfg(feature = "x11")]
 pub async fn run_x11(
     mut proc_data: ProcessTracker,
+    mut spool: Spool,
     update_interval: u32,
     backend: StorageBackend,
+    control: CollectorControl,
 ) -> Result<()> {
     use crate::platform::linux::x11::*;
 
     let x11_ctx = X11Ctx::new()?;
 
     let mut tick = interval(Duration::from_secs(1));
+    let mut terminal_check = interval(Duration::from_secs(TERMINAL_REFRESH_SECS));
     let mut database_update = interval(Duration::from_secs(update_interval as u64));
+    let mut spool_tick = interval(SPOOL_INTERVAL);
+    let mut control_rx = control.subscribe();
+    let mut locked_rx = control.watch_locked();
+    let mut suspend = SuspendGuard::new();
 
     loop {
         tokio::select! {
             _ = tick.tick() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
                 // is_idle should be under common.rs since it can be used no matter if user is x11 or wayland
-                if !is_idle() {
-                    handle_active_window(&x11_ctx, &mut proc_data).await?;
+                if !is_idle() && control.is_recording() {
+                    if handle_active_window(&x11_ctx, &mut proc_data).await? {
+                        refresh_terminal_context(&mut proc_data, focused_window_pid(&x11_ctx));
+                    }
                 } else {
                     proc_data.pause(chrono::Utc::now());
                 }
+                publish_focus_state(&proc_data, &control);
             }
 
-            _ = database_update.tick() => {
-                proc_data.record_active_until(chrono::Utc::now());
-                let rows = proc_data.drain_pending();
-                if let Err(err) = backend.store_proc_data(&rows).await {
-                    error!("Error sending data to procs table: {err:?}");
+            _ = terminal_check.tick() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                refresh_terminal_context(&mut proc_data, focused_window_pid(&x11_ctx));
+            }
+
+            // stop the timer the moment the screen locks; the next tick resumes it after unlock
+            _ = locked_rx.changed() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                if *locked_rx.borrow_and_update() {
+                    proc_data.pause(chrono::Utc::now());
+                    publish_focus_state(&proc_data, &control);
                 }
+            }
+
+            _ = spool_tick.tick() => {
+                spool_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend).await;
+            }
 
+            _ = database_update.tick() => {
+                flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+            }
+
+            signal = next_signal(&mut control_rx) => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                match signal {
+                    ControlSignal::FlushNow => {
+                        flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+                        control.ack_flush();
+                    }
+                    ControlSignal::ReloadConfig { .. } => {}
+                    ControlSignal::BrowserTab(tab) => {
+                        proc_data.set_browser_tab(tab, chrono::Utc::now());
+                    }
+                    // the next tick picks the window up again after resume
+                    ControlSignal::PrepareForSleep { sleeping, at } => {
+                        suspend.prepare_for_sleep(&mut proc_data, &backend, sleeping, at).await;
+                        publish_focus_state(&proc_data, &control);
+                    }
+                }
             }
         }
     }
@@ -54,54 +238,94 @@ enum TrackingState {
     Idle(Window),
 }
 
+/// Pauses the timer when collection is paused or the screen locks, and resumes the active
+/// window once neither holds anymore.
+#[cfg(feature = "wayland")]
+fn apply_recording_state(
+    proc_data: &mut ProcessTracker,
+    state: &TrackingState,
+    control: &CollectorControl,
+) {
+    let now = chrono::Utc::now();
+    if !control.is_recording() {
+        proc_data.pause(now);
+    } else if matches!(state, TrackingState::Active(_)) {
+        proc_data.resume(now);
+    }
+    publish_focus_state(proc_data, control);
+}
+
 #[cfg(feature = "wayland")]
 pub async fn run_wayland(
     mut proc_data: ProcessTracker,
+    mut spool: Spool,
     update_interval: u32,
     backend: StorageBackend,
+    control: CollectorControl,
 ) -> Result<()> {
     use crate::platform::linux::wayland::*;
 
-    let (events_tx, mut events_rx) = channel::<FocusEvent>(240);
+    // only the latest focus matters, so changes we didn't get to yet are coalesced
+    let (focused_tx, mut focused_rx) = tokio::sync::watch::channel::<Option<Window>>(None);
 
-    // spawn Wayland listener
-    tokio::task::spawn_blocking(move || {
-        if let Err(e) = listen_for_wlevents(events_tx) {
-            error!("Wayland listener failed: {:?}", e);
-        }
-    });
+    // spawn Wayland listener; when the compositor goes away we return, and the supervisor
+    // connects again once it is back
+    let mut listener = tokio::task::spawn_blocking(move || listen_for_wlevents(focused_tx));
 
     let mut state = TrackingState::NoFocus;
     let mut idle_check = interval(Duration::from_secs(20));
+    let mut terminal_check = interval(Duration::from_secs(TERMINAL_REFRESH_SECS));
     let mut database_update = interval(Duration::from_secs(update_interval as u64));
+    let mut spool_tick = interval(SPOOL_INTERVAL);
+    let mut control_rx = control.subscribe();
+    let mut paused_rx = control.watch_paused();
+    let mut locked_rx = control.watch_locked();
+    let mut suspend = SuspendGuard::new();
 
     loop {
         tokio::select! {
-            Some(event) = events_rx.recv() => match event {
-                FocusEvent::FocusGained(new_window) => {
-                    // if a previous window was active, record its time before switching
-                    let now = chrono::Utc::now();
-                    proc_data.switch_window(new_window.clone(), now);
-                    // set the new window as being active to start its time
-                    state = TrackingState::Active(new_window);
-                }
-                FocusEvent::FocusLost(lost_window) => {
-                    // we only care about this event if the window that lost focus
-                    // is the one we are currently tracking as active.
-                    if let TrackingState::Active(ref active_window) = state {
-                        if active_window.name == lost_window.name {
-                            // The currently tracked window is the one that lost focus.
-                            // We take the state, record its time, and set the new state to NoFocus.
+            Result::Ok(()) = focused_rx.changed() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                let focused = focused_rx.borrow_and_update().clone();
+                match focused {
+                    Some(new_window) => {
+                        // if a previous window was active, record its time before switching
+                        let now = chrono::Utc::now();
+                        proc_data.switch_window(new_window.clone(), now);
+                        // foreign-toplevel does not expose PIDs, so match the emulator by name
+                        refresh_terminal_context(&mut proc_data, None);
+                        // keep following focus while paused or locked, but don't start its timer
+                        if !control.is_recording() {
+                            proc_data.pause(now);
+                        }
+                        // set the new window as being active to start its time
+                        state = TrackingState::Active(new_window);
+                    }
+                    // the tracked window lost focus and nothing else gained it (yet)
+                    None => {
+                        if !matches!(state, TrackingState::NoFocus) {
                             proc_data.clear_focus(chrono::Utc::now());
                             state = TrackingState::NoFocus;
                         }
-                        // if the windows do NOT match, we do nothing. This means a FocusGained
-                        // event for another window has already occurred and the state is correct.
                     }
                 }
-            },
+                publish_focus_state(&proc_data, &control);
+            }
 
-            _ = idle_check.tick() => {
+            listener_result = &mut listener => {
+                proc_data.clear_focus(chrono::Utc::now());
+                flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+                publish_focus_state(&proc_data, &control);
+                return match listener_result {
+                    Result::Ok(Result::Ok(())) => Err(anyhow!("Wayland listener stopped")),
+                    Result::Ok(Err(err)) => Err(err.context("Wayland listener failed")),
+                    Err(join_err) => Err(anyhow::Error::new(join_err).context("Wayland listener panicked")),
+                };
+            }
+
+            // pausing through the control socket or locking the screen overrides idle tracking
+            _ = idle_check.tick(), if control.is_recording() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
                 match state {
                     // the user was active, check if they've now become idle.
                     TrackingState::Active(ref window) => {
@@ -125,20 +349,123 @@ pub async fn run_wayland(
                 }
             }
 
+            _ = terminal_check.tick(), if matches!(state, TrackingState::Active(_)) => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                refresh_terminal_context(&mut proc_data, None);
+            }
+
+            _ = spool_tick.tick() => {
+                spool_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend).await;
+            }
+
             _ = database_update.tick() => {
-                proc_data.record_active_until(chrono::Utc::now());
-                let rows = proc_data.drain_pending();
-                if let Err(err) = backend.store_proc_data(&rows).await {
-                    error!("Error sending data to procs table: {err:?}");
+                flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+            }
+
+            _ = paused_rx.changed() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                paused_rx.borrow_and_update();
+                apply_recording_state(&mut proc_data, &state, &control);
+            }
+
+            _ = locked_rx.changed() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                locked_rx.borrow_and_update();
+                apply_recording_state(&mut proc_data, &state, &control);
+            }
+
+            signal = next_signal(&mut control_rx) => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                match signal {
+                    ControlSignal::FlushNow => {
+                        flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+                        control.ack_flush();
+                    }
+                    ControlSignal::ReloadConfig { .. } => {}
+                    ControlSignal::BrowserTab(tab) => {
+                        proc_data.set_browser_tab(tab, chrono::Utc::now());
+                    }
+                    ControlSignal::PrepareForSleep { sleeping, at } => {
+                        suspend.prepare_for_sleep(&mut proc_data, &backend, sleeping, at).await;
+                        apply_recording_state(&mut proc_data, &state, &control);
+                    }
                 }
             }
+        }
+    }
+}
 
+/// Follows consoles and SSH logins when there is no graphical session to ask for focus.
+pub async fn run_text_session(
+    mut proc_data: ProcessTracker,
+    mut spool: Spool,
+    update_interval: u32,
+    backend: StorageBackend,
+    control: CollectorControl,
+) -> Result<()> {
+    let mut poll = interval(Duration::from_secs(TERMINAL_REFRESH_SECS));
+    let mut database_update = interval(Duration::from_secs(update_interval as u64));
+    let mut spool_tick = interval(SPOOL_INTERVAL);
+    let mut control_rx = control.subscribe();
+    let mut suspend = SuspendGuard::new();
+
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                let now = chrono::Utc::now();
+                match focused_text_session() {
+                    Some(focus) if !focus.idle && control.is_recording() => {
+                        if proc_data.current_window() != Some(&focus.window) {
+                            debug!("Text session focus is now '{}'", focus.window.name);
+                            proc_data.switch_window(focus.window, now);
+                        } else {
+                            proc_data.resume(now);
+                        }
+                        proc_data.set_terminal_context(focus.context, now);
+                    }
+                    Some(_) => proc_data.pause(now),
+                    None => proc_data.clear_focus(now),
+                }
+                publish_focus_state(&proc_data, &control);
+            }
+
+            _ = spool_tick.tick() => {
+                spool_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend).await;
+            }
+
+            _ = database_update.tick() => {
+                flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+            }
+
+            signal = next_signal(&mut control_rx) => {
+                suspend.check_clock(&mut proc_data, &backend).await;
+                match signal {
+                    ControlSignal::FlushNow => {
+                        flush_focus_data(&mut proc_data, &mut suspend, &mut spool, &backend, &control).await;
+                        control.ack_flush();
+                    }
+                    ControlSignal::ReloadConfig { .. } | ControlSignal::BrowserTab(_) => {}
+                    // the next poll picks the session up again after resume
+                    ControlSignal::PrepareForSleep { sleeping, at } => {
+                        suspend.prepare_for_sleep(&mut proc_data, &backend, sleeping, at).await;
+                        publish_focus_state(&proc_data, &control);
+                    }
+                }
+            }
         }
     }
 }
 
-pub async fn run(update_interval: u32, backend: StorageBackend) -> Result<()> {
+pub async fn run(
+    update_interval: u32,
+    backend: StorageBackend,
+    control: CollectorControl,
+) -> Result<()> {
     let proc_data = ProcessTracker::new(backend.source_id(), backend.bucket_granularity_minutes());
+    let spool = Spool::open(&backend, "focus")
+        .await
+        .with_context(|| "Failed to open the focus spool")?;
     match detect_display_server() {
         DisplayServer::Wayland => {
             info!(
@@ -148,7 +475,7 @@ pub async fn run(update_interval: u32, backend: StorageBackend) -> Result<()> {
                 std::env::var("DISPLAY").ok(),
             );
             #[cfg(feature = "wayland")]
-            run_wayland(proc_data, update_interval, backend).await?;
+            run_wayland(proc_data, spool, update_interval, backend, control).await?;
 
             #[cfg(not(feature = "wayland"))]
             {
@@ -164,7 +491,7 @@ pub async fn run(update_interval: u32, backend: StorageBackend) -> Result<()> {
                 std::env::var("WAYLAND_DISPLAY").ok(),
             );
             #[cfg(feature = "x11")]
-            run_x11(proc_data, update_interval, backend).await?;
+            run_x11(proc_data, spool, update_interval, backend, control).await?;
 
             #[cfg(not(feature = "x11"))]
             {
@@ -173,16 +500,14 @@ pub async fn run(update_interval: u32, backend: StorageBackend) -> Result<()> {
             }
         }
         DisplayServer::Unknown => {
-            error!(
-                "Could not determine graphical session type from environment. WAYLAND_DISPLAY={:?}, WAYLAND_SOCKET={:?}, XDG_SESSION_TYPE={:?}, DISPLAY={:?}",
+            info!(
+                "No graphical session detected, following console and SSH sessions instead. WAYLAND_DISPLAY={:?}, WAYLAND_SOCKET={:?}, XDG_SESSION_TYPE={:?}, DISPLAY={:?}",
                 std::env::var("WAYLAND_DISPLAY").ok(),
                 std::env::var("WAYLAND_SOCKET").ok(),
                 std::env::var("XDG_SESSION_TYPE").ok(),
                 std::env::var("DISPLAY").ok(),
             );
-            return Err(anyhow!(
-                "Failed to detect whether the session is Wayland or X11"
-            ));
+            run_text_session(proc_data, spool, update_interval, backend, control).await?;
         }
     }
 
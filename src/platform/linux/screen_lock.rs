@@ -0,0 +1,221 @@
+//! Follows the screen lock state so focus and input stop counting while the session is locked.
+//!
+//! logind is asked first: screen lockers set the session's `LockedHint` property, and the
+//! session emits `Lock`/`Unlock` when a lock is requested (e.g. `loginctl lock-session`).
+//! Lockers that don't update the hint are usually covered by the screensaver interface on the
+//! session bus, which announces `ActiveChanged` when the lock screen comes up or goes away.
+use std::os::unix::fs::MetadataExt;
+
+use anyhow::*;
+use futures_util::StreamExt;
+use tokio::sync::mpsc::{channel, Sender};
+use tracing::*;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+use crate::control::CollectorControl;
+use crate::platform::linux::logind::{logind_manager, LOGIND_SERVICE, LOGIND_SESSION, LOGIND_USER};
+
+/// Screensaver services that report lock screen activation: (service, path, interface).
+const SCREENSAVERS: [(&str, &str, &str); 2] = [
+    (
+        "org.freedesktop.ScreenSaver",
+        "/org/freedesktop/ScreenSaver",
+        "org.freedesktop.ScreenSaver",
+    ),
+    (
+        "org.gnome.ScreenSaver",
+        "/org/gnome/ScreenSaver",
+        "org.gnome.ScreenSaver",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockSource {
+    Logind,
+    /// Index into [`SCREENSAVERS`].
+    ScreenSaver(usize),
+}
+
+/// Last state reported by each source. Any one reporting a lock is enough, since a locker
+/// may only talk to one of them.
+#[derive(Debug, Default)]
+struct LockState {
+    logind: bool,
+    screensavers: [bool; SCREENSAVERS.len()],
+}
+
+impl LockState {
+    fn update(&mut self, source: LockSource, locked: bool) -> bool {
+        match source {
+            LockSource::Logind => self.logind = locked,
+            LockSource::ScreenSaver(index) => self.screensavers[index] = locked,
+        }
+        self.logind || self.screensavers.contains(&true)
+    }
+}
+
+/// Publishes the lock state on `control` until every source is gone. Sources that are not
+/// reachable (no system bus, no screensaver service) are skipped.
+pub async fn watch_screen_lock(control: CollectorControl) {
+    let (updates_tx, mut updates_rx) = channel::<(LockSource, bool)>(8);
+
+    let tx = updates_tx.clone();
+    tokio::spawn(async move {
+        if let Err(err) = watch_logind(tx).await {
+            debug!("logind lock tracking unavailable: {err:#}");
+        }
+    });
+    for (index, (service, path, interface)) in SCREENSAVERS.into_iter().enumerate() {
+        let tx = updates_tx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = watch_screensaver(index, service, path, interface, tx).await {
+                debug!("{service} lock tracking unavailable: {err:#}");
+            }
+        });
+    }
+    drop(updates_tx);
+
+    let mut state = LockState::default();
+    let mut reported = false;
+    while let Some((source, locked)) = updates_rx.recv().await {
+        reported = true;
+        let locked = state.update(source, locked);
+        if control.set_locked(locked) {
+            if locked {
+                info!("Screen locked, pausing focus and input accounting");
+            } else {
+                info!("Screen unlocked, resuming focus and input accounting");
+            }
+        }
+    }
+
+    if !reported {
+        warn!("Screen lock detection is unavailable; a locked screen counts as activity until the idle timeout");
+    }
+}
+
+/// Finds the logind session whose lock state applies to this user's desktop.
+async fn logind_session(connection: &Connection) -> Result<OwnedObjectPath> {
+    let manager = logind_manager(connection).await?;
+
+    if let Some(session_id) = std::env::var("XDG_SESSION_ID")
+        .ok()
+        .filter(|id| !id.is_empty())
+    {
+        if let Result::Ok(path) = manager.call("GetSession", &(session_id.as_str(),)).await {
+            return Ok(path);
+        }
+    }
+    if let Result::Ok(path) = manager
+        .call("GetSessionByPID", &(std::process::id(),))
+        .await
+    {
+        return Ok(path);
+    }
+
+    // A systemd user service runs outside any session; follow the user's graphical one.
+    let uid = std::fs::metadata("/proc/self")
+        .with_context(|| "Failed to determine the current user")?
+        .uid();
+    let user_path: OwnedObjectPath = manager.call("GetUser", &(uid,)).await?;
+    let user = Proxy::new(connection, LOGIND_SERVICE, user_path, LOGIND_USER).await?;
+    let (session_id, path) = user
+        .get_property::<(String, OwnedObjectPath)>("Display")
+        .await?;
+    ensure!(
+        !session_id.is_empty(),
+        "User {uid} has no graphical session"
+    );
+    Ok(path)
+}
+
+async fn watch_logind(updates: Sender<(LockSource, bool)>) -> Result<()> {
+    let connection = Connection::system()
+        .await
+        .with_context(|| "Failed to connect to the system bus")?;
+    let path = logind_session(&connection)
+        .await
+        .with_context(|| "Failed to find the logind session")?;
+    let session = Proxy::new(&connection, LOGIND_SERVICE, path, LOGIND_SESSION).await?;
+
+    let mut lock = session.receive_signal("Lock").await?;
+    let mut unlock = session.receive_signal("Unlock").await?;
+    let mut hint = session.receive_property_changed::<bool>("LockedHint").await;
+    info!(
+        "Following the screen lock state of logind session {}",
+        session.path()
+    );
+
+    let mut locked = session.get_property::<bool>("LockedHint").await?;
+    loop {
+        if updates.send((LockSource::Logind, locked)).await.is_err() {
+            return Ok(());
+        }
+        locked = tokio::select! {
+            Some(_) = lock.next() => true,
+            Some(_) = unlock.next() => false,
+            Some(change) = hint.next() => change.get().await?,
+            else => bail!("logind closed the session signal streams"),
+        };
+    }
+}
+
+async fn watch_screensaver(
+    index: usize,
+    service: &'static str,
+    path: &'static str,
+    interface: &'static str,
+    updates: Sender<(LockSource, bool)>,
+) -> Result<()> {
+    let connection = Connection::session()
+        .await
+        .with_context(|| "Failed to connect to the session bus")?;
+    let screensaver = Proxy::new(&connection, service, path, interface).await?;
+
+    let mut changes = screensaver.receive_signal("ActiveChanged").await?;
+    // Fails when the service isn't running, which is how we tell which desktop we're on.
+    let mut active: bool = screensaver.call("GetActive", &()).await?;
+    info!("Following the screen lock state reported by {service}");
+
+    loop {
+        if updates
+            .send((LockSource::ScreenSaver(index), active))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+        let Some(message) = changes.next().await else {
+            bail!("{service} closed the ActiveChanged signal stream");
+        };
+        active = message.body().deserialize::<bool>()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verifies that the session counts as locked while any source reports a lock, so a
+    /// locker that only talks to the screensaver interface is not undone by logind's hint.
+    #[test]
+    fn lock_state_is_held_while_any_source_reports_a_lock() {
+        let mut state = LockState::default();
+        assert!(state.update(LockSource::ScreenSaver(0), true));
+        assert!(state.update(LockSource::Logind, false));
+        assert!(state.update(LockSource::Logind, true));
+        assert!(state.update(LockSource::ScreenSaver(0), false));
+        assert!(!state.update(LockSource::Logind, false));
+    }
+
+    /// Verifies that each screensaver service keeps its own state, so one that stays inactive
+    /// while the other shows the lock screen does not unlock the session.
+    #[test]
+    fn screensaver_services_do_not_overwrite_each_other() {
+        let mut state = LockState::default();
+        assert!(state.update(LockSource::ScreenSaver(1), true));
+        assert!(state.update(LockSource::ScreenSaver(0), false));
+        assert!(!state.update(LockSource::ScreenSaver(1), false));
+    }
+}
@@ -0,0 +1,76 @@
+//! Follows logind's sleep announcements so focus time stops at suspend and restarts at resume.
+//!
+//! logind emits `PrepareForSleep(true)` before the system suspends or hibernates and
+//! `PrepareForSleep(false)` once it is back. We hold a delay inhibitor so there is time to close
+//! the running interval and flush it before the machine goes down; releasing the inhibitor
+//! tells logind we're done. Without logind, the collector tasks still notice suspends by
+//! comparing clocks (see [`crate::common::ClockWatch`]).
+use anyhow::*;
+use chrono::Utc;
+use futures_util::StreamExt;
+use tracing::*;
+use zbus::zvariant::OwnedFd;
+use zbus::{Connection, Proxy};
+
+use crate::control::CollectorControl;
+use crate::platform::linux::logind::logind_manager;
+
+pub async fn watch_suspend(control: CollectorControl) {
+    if let Err(err) = follow_prepare_for_sleep(&control).await {
+        warn!("Suspend notifications are unavailable, relying on clock checks instead: {err:#}");
+    }
+}
+
+/// Asks logind to wait for us before suspending. Without it the announcement still arrives,
+/// but the suspend may begin before the last interval is written.
+async fn take_sleep_inhibitor(manager: &Proxy<'_>) -> Option<OwnedFd> {
+    match manager
+        .call(
+            "Inhibit",
+            &(
+                "sleep",
+                "vigil",
+                "Saving focus time before suspend",
+                "delay",
+            ),
+        )
+        .await
+    {
+        Result::Ok(fd) => Some(fd),
+        Err(err) => {
+            debug!("Could not take a sleep delay inhibitor: {err}");
+            None
+        }
+    }
+}
+
+async fn follow_prepare_for_sleep(control: &CollectorControl) -> Result<()> {
+    let connection = Connection::system()
+        .await
+        .with_context(|| "Failed to connect to the system bus")?;
+    let manager = logind_manager(&connection).await?;
+
+    let mut announcements = manager.receive_signal("PrepareForSleep").await?;
+    let mut inhibitor = take_sleep_inhibitor(&manager).await;
+    info!("Following suspend and resume notifications from logind");
+
+    while let Some(message) = announcements.next().await {
+        let sleeping = message.body().deserialize::<bool>()?;
+        control.prepare_for_sleep(sleeping, Utc::now());
+
+        if sleeping {
+            info!("System is suspending, closing the current focus interval");
+            if let Err(err) = control.flush_now().await {
+                warn!("Failed to flush before suspend: {err:#}");
+            }
+            inhibitor = None;
+        } else {
+            info!("System resumed from suspend");
+            if inhibitor.is_none() {
+                inhibitor = take_sleep_inhibitor(&manager).await;
+            }
+        }
+    }
+
+    bail!("logind closed the PrepareForSleep signal stream")
+}
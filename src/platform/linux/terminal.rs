@@ -0,0 +1,232 @@
+//! Resolves what a focused terminal window is running by walking `/proc`.
+//!
+//! Starting from the terminal emulator's processes, we look at the shells below them and
+//! follow each TTY's foreground process group (`tpgid` in `/proc/<pid>/stat`) to the job the
+//! user is looking at. Its name and working directory become the window's terminal context.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::*;
+
+use crate::common::{TerminalContext, Window};
+
+pub const PROC_ROOT: &str = "/proc";
+/// The kernel truncates `comm` to 15 bytes.
+const COMM_MAX_LEN: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcStat {
+    pub pid: u32,
+    pub ppid: u32,
+    pub comm: String,
+    pub tty_nr: i32,
+    pub tpgid: i32,
+    pub start_time: u64,
+}
+
+/// Parses `/proc/<pid>/stat`. The command name sits in parentheses and may itself contain
+/// spaces or parentheses, so fields are counted from the last `)`.
+fn parse_stat(raw: &str) -> Option<ProcStat> {
+    let (head, rest) = raw.rsplit_once(')')?;
+    let (pid, comm) = head.split_once(" (")?;
+    let fields = rest.split_whitespace().collect::<Vec<_>>();
+    Some(ProcStat {
+        pid: pid.trim().parse().ok()?,
+        comm: comm.to_string(),
+        ppid: fields.get(1)?.parse().ok()?,
+        tty_nr: fields.get(4)?.parse().ok()?,
+        tpgid: fields.get(5)?.parse().ok()?,
+        start_time: fields.get(19)?.parse().ok()?,
+    })
+}
+
+pub fn read_process_table(root: &Path) -> Vec<ProcStat> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path().join("stat")).ok())
+        .filter_map(|raw| parse_stat(&raw))
+        .collect()
+}
+
+/// Process names a terminal emulator may run under, derived from its app identifier,
+/// e.g. `com.mitchellh.ghostty` -> `ghostty`, `gnome-terminal-server` as-is.
+fn terminal_process_names(app_identifier: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut candidates = vec![app_identifier.to_string()];
+    if let Some((_, suffix)) = app_identifier.rsplit_once('.') {
+        candidates.push(suffix.to_string());
+    }
+    if let Some(stripped) = app_identifier.strip_suffix("-gui") {
+        candidates.push(stripped.to_string());
+    }
+    for candidate in candidates {
+        names.insert(candidate.chars().take(COMM_MAX_LEN).collect());
+        // wezterm and gnome-terminal run their windows from a separate GUI/server process.
+        names.insert(
+            format!("{candidate}-gui")
+                .chars()
+                .take(COMM_MAX_LEN)
+                .collect(),
+        );
+        names.insert(
+            format!("{candidate}-server")
+                .chars()
+                .take(COMM_MAX_LEN)
+                .collect(),
+        );
+    }
+    names
+}
+
+/// Picks the foreground job the user most likely sees in the focused terminal window.
+///
+/// Every TTY below the terminal contributes its foreground process group leader. When the
+/// terminal hosts several TTYs (tabs, windows), the one mentioned in the window title wins,
+/// falling back to the most recently started job.
+fn pick_foreground(
+    table: &[ProcStat],
+    roots: &HashSet<u32>,
+    title: &str,
+    cwd_of: impl Fn(u32) -> Option<PathBuf>,
+) -> Option<(ProcStat, PathBuf)> {
+    let by_pid = table
+        .iter()
+        .map(|stat| (stat.pid, stat))
+        .collect::<HashMap<_, _>>();
+    let mut children = HashMap::<u32, Vec<&ProcStat>>::new();
+    for stat in table {
+        children.entry(stat.ppid).or_default().push(stat);
+    }
+
+    let mut foreground_groups = HashSet::new();
+    let mut stack = roots.iter().copied().collect::<Vec<_>>();
+    let mut visited = HashSet::new();
+    while let Some(pid) = stack.pop() {
+        if !visited.insert(pid) {
+            continue;
+        }
+        for child in children.get(&pid).into_iter().flatten() {
+            if child.tty_nr != 0 && child.tpgid > 0 {
+                foreground_groups.insert(child.tpgid as u32);
+            }
+            stack.push(child.pid);
+        }
+    }
+
+    let title = title.to_ascii_lowercase();
+    foreground_groups
+        .into_iter()
+        .filter_map(|pgid| by_pid.get(&pgid).copied())
+        .filter_map(|stat| cwd_of(stat.pid).map(|cwd| (stat.clone(), cwd)))
+        .max_by_key(|(stat, cwd)| {
+            let dir_name = cwd
+                .file_name()
+                .map(|name| name.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            let mentioned = title.contains(&stat.comm.to_ascii_lowercase())
+                || (!dir_name.is_empty() && title.contains(&dir_name));
+            (mentioned, stat.start_time)
+        })
+}
+
+/// Resolves the terminal context of `window`. `pid` is the emulator's PID when the display
+/// server reports it (X11 `_NET_WM_PID`); otherwise processes are matched by name.
+pub fn resolve_terminal_context(window: &Window, pid: Option<u32>) -> Option<TerminalContext> {
+    let root = Path::new(PROC_ROOT);
+    let table = read_process_table(root);
+    let roots = match pid {
+        Some(pid) => HashSet::from([pid]),
+        None => {
+            let names = terminal_process_names(&window.app_identifier());
+            table
+                .iter()
+                .filter(|stat| names.contains(&stat.comm))
+                .map(|stat| stat.pid)
+                .collect()
+        }
+    };
+    if roots.is_empty() {
+        debug!("No process found for terminal '{}'", window.class);
+        return None;
+    }
+
+    let (stat, cwd) = pick_foreground(&table, &roots, &window.name, |pid| {
+        fs::read_link(root.join(pid.to_string()).join("cwd")).ok()
+    })?;
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    Some(TerminalContext::new(stat.comm, &cwd, home.as_deref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(pid: u32, ppid: u32, comm: &str, tty_nr: i32, tpgid: i32, start: u64) -> ProcStat {
+        ProcStat {
+            pid,
+            ppid,
+            comm: comm.to_string(),
+            tty_nr,
+            tpgid,
+            start_time: start,
+        }
+    }
+
+    /// Verifies that stat lines are parsed from the last parenthesis, so command names with
+    /// spaces or parentheses keep the following fields aligned.
+    #[test]
+    fn parse_stat_handles_parentheses_in_command_names() {
+        let raw = "4242 (tmux: (server)) S 1 4242 4242 34817 5000 4194560 0 0 0 0 1 2 0 0 20 0 1 0 987654 0 0";
+        let parsed = parse_stat(raw).unwrap();
+        assert_eq!(parsed.pid, 4242);
+        assert_eq!(parsed.comm, "tmux: (server)");
+        assert_eq!(parsed.ppid, 1);
+        assert_eq!(parsed.tty_nr, 34817);
+        assert_eq!(parsed.tpgid, 5000);
+        assert_eq!(parsed.start_time, 987654);
+    }
+
+    /// Verifies foreground job selection across two terminal tabs: the tab named in the window
+    /// title wins, and without a hint the most recently started job is used.
+    #[test]
+    fn pick_foreground_prefers_the_job_named_in_the_title() {
+        let table = vec![
+            stat(100, 1, "kitty", 0, -1, 10),
+            stat(200, 100, "zsh", 34816, 300, 20),
+            stat(300, 200, "cargo", 34816, 300, 30),
+            stat(400, 100, "zsh", 34817, 500, 40),
+            stat(500, 400, "nvim", 34817, 500, 50),
+            stat(900, 1, "nvim", 34818, 900, 99),
+        ];
+        let roots = HashSet::from([100]);
+        let cwd_of = |pid: u32| Some(PathBuf::from(format!("/home/ana/project-{pid}")));
+
+        let (picked, cwd) = pick_foreground(&table, &roots, "cargo build", cwd_of).unwrap();
+        assert_eq!(picked.comm, "cargo");
+        assert_eq!(cwd, PathBuf::from("/home/ana/project-300"));
+
+        let (picked, _) = pick_foreground(&table, &roots, "kitty", cwd_of).unwrap();
+        assert_eq!(picked.pid, 500);
+
+        assert!(pick_foreground(&table, &HashSet::from([42]), "", cwd_of).is_none());
+    }
+
+    /// Verifies that Wayland app identifiers map onto the truncated process names terminals
+    /// actually run under.
+    #[test]
+    fn terminal_process_names_cover_reverse_dns_and_server_processes() {
+        assert!(terminal_process_names("com.mitchellh.ghostty").contains("ghostty"));
+        assert!(terminal_process_names("gnome-terminal").contains("gnome-terminal-"));
+        assert!(terminal_process_names("org.wezfurlong.wezterm").contains("wezterm-gui"));
+    }
+}
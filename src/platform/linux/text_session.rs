@@ -0,0 +1,311 @@
+//! Focus tracking for machines without a graphical session: virtual consoles and SSH logins.
+//!
+//! Login sessions come from utmp. A console session counts only while its VT is the active one
+//! (`/sys/class/tty/tty0/active`); among the remaining sessions the one whose TTY was read most
+//! recently is where the user is typing, since the kernel bumps a TTY's access time on input.
+//! The focused session's foreground job is followed through `/proc`, descending into tmux or
+//! screen panes, and reported as a window such as `ssh: tmux / nvim`.
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tracing::*;
+
+use crate::common::{TerminalContext, Window};
+use crate::platform::linux::terminal::{read_process_table, ProcStat, PROC_ROOT};
+
+const UTMP_PATH: &str = "/var/run/utmp";
+const ACTIVE_VT_PATH: &str = "/sys/class/tty/tty0/active";
+/// `struct utmp` on Linux (glibc, all 64-bit and most 32-bit targets).
+const UTMP_RECORD_LEN: usize = 384;
+const UTMP_USER_PROCESS: i16 = 7;
+/// Only keystrokes reach a text session, so allow longer reading pauses than the desktop
+/// idle check does.
+const TEXT_IDLE_SECS: u64 = 60;
+/// Multiplexers nested inside each other more deeply than this are not followed.
+const MAX_PANE_DEPTH: usize = 4;
+/// Multiplexers whose client only relays panes run by a detached server:
+/// (client name prefix, server name prefix).
+const MULTIPLEXERS: [(&str, &str); 2] = [("tmux", "tmux: server"), ("screen", "SCREEN")];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UtmpEntry {
+    pid: u32,
+    line: String,
+    user: String,
+    host: String,
+}
+
+fn utmp_field(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).trim().to_string()
+}
+
+/// Parses the login records (`USER_PROCESS`) out of a utmp file.
+fn parse_utmp(raw: &[u8]) -> Vec<UtmpEntry> {
+    raw.chunks_exact(UTMP_RECORD_LEN)
+        .filter(|record| i16::from_ne_bytes([record[0], record[1]]) == UTMP_USER_PROCESS)
+        .map(|record| UtmpEntry {
+            pid: u32::from_ne_bytes([record[4], record[5], record[6], record[7]]),
+            line: utmp_field(&record[8..40]),
+            user: utmp_field(&record[44..76]),
+            host: utmp_field(&record[76..332]),
+        })
+        .filter(|entry| !entry.line.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TextSession {
+    /// `ssh` for remote logins, `tty` for virtual consoles.
+    kind: &'static str,
+    device: u64,
+    last_input: SystemTime,
+}
+
+/// This user's live login sessions that can currently receive input.
+fn read_text_sessions() -> Vec<TextSession> {
+    let Ok(raw) = fs::read(UTMP_PATH) else {
+        debug!("Could not read {UTMP_PATH}");
+        return Vec::new();
+    };
+    let Ok(uid) = fs::metadata(Path::new(PROC_ROOT).join("self")).map(|own| own.uid()) else {
+        return Vec::new();
+    };
+    let active_vt = fs::read_to_string(ACTIVE_VT_PATH)
+        .map(|vt| vt.trim().to_string())
+        .unwrap_or_default();
+
+    let mut sessions = HashMap::new();
+    for entry in parse_utmp(&raw) {
+        let is_console = entry.line.starts_with("tty");
+        if is_console && entry.line != active_vt {
+            continue;
+        }
+        // utmp keeps records of sessions that died without logging out.
+        if !Path::new(PROC_ROOT).join(entry.pid.to_string()).exists() {
+            continue;
+        }
+        let Ok(metadata) = fs::metadata(Path::new("/dev").join(&entry.line)) else {
+            continue;
+        };
+        if metadata.uid() != uid || !metadata.file_type().is_char_device() {
+            continue;
+        }
+        let Ok(last_input) = metadata.accessed() else {
+            continue;
+        };
+        let kind = if !is_console && !entry.host.is_empty() {
+            "ssh"
+        } else {
+            "tty"
+        };
+        trace!("Text session of {} on {}", entry.user, entry.line);
+        sessions.insert(
+            entry.line,
+            TextSession {
+                kind,
+                device: metadata.rdev(),
+                last_input,
+            },
+        );
+    }
+    sessions.into_values().collect()
+}
+
+fn tty_device(stat: &ProcStat) -> Option<u64> {
+    u64::try_from(stat.tty_nr)
+        .ok()
+        .filter(|&device| device != 0)
+}
+
+/// Follows the foreground job on `device` and, when it is a multiplexer client, the job in the
+/// pane typed into last. Returns the program names from the outside in, e.g. `[tmux, nvim]`,
+/// and the innermost process.
+fn foreground_chain(
+    table: &[ProcStat],
+    device: u64,
+    last_input: impl Fn(&ProcStat) -> Option<SystemTime>,
+) -> Option<(Vec<String>, ProcStat)> {
+    let by_pid = table
+        .iter()
+        .map(|stat| (stat.pid, stat))
+        .collect::<HashMap<_, _>>();
+
+    let mut chain = Vec::new();
+    let mut innermost = None;
+    let mut device = device;
+    for _ in 0..MAX_PANE_DEPTH {
+        let Some(leader) = table
+            .iter()
+            .find(|stat| tty_device(stat) == Some(device) && stat.tpgid > 0)
+            .and_then(|stat| by_pid.get(&(stat.tpgid as u32)).copied())
+        else {
+            break;
+        };
+        innermost = Some(leader.clone());
+
+        let Some((client, server)) = MULTIPLEXERS
+            .into_iter()
+            .find(|(client, _)| leader.comm.starts_with(client))
+        else {
+            chain.push(leader.comm.clone());
+            break;
+        };
+        chain.push(client.to_string());
+
+        let Some(pane) = table
+            .iter()
+            .filter(|stat| tty_device(stat).is_some())
+            .filter(|stat| {
+                by_pid
+                    .get(&stat.ppid)
+                    .is_some_and(|parent| parent.comm.starts_with(server))
+            })
+            .filter_map(|stat| last_input(stat).map(|time| (time, stat)))
+            .max_by_key(|(time, _)| *time)
+            .and_then(|(_, stat)| tty_device(stat))
+        else {
+            break;
+        };
+        device = pane;
+    }
+
+    innermost.map(|stat| (chain, stat))
+}
+
+/// Last input on the TTY a process reads from.
+fn tty_last_input(stat: &ProcStat) -> Option<SystemTime> {
+    let tty = fs::read_link(Path::new(PROC_ROOT).join(stat.pid.to_string()).join("fd/0")).ok()?;
+    fs::metadata(tty).ok()?.accessed().ok()
+}
+
+/// What the focused text session shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFocus {
+    pub window: Window,
+    pub context: Option<TerminalContext>,
+    /// Nobody typed into the session for a while.
+    pub idle: bool,
+}
+
+/// Finds the text session the user last typed into and what runs in its foreground.
+pub fn focused_text_session() -> Option<TextFocus> {
+    let session = read_text_sessions()
+        .into_iter()
+        .max_by_key(|session| session.last_input)?;
+    let table = read_process_table(Path::new(PROC_ROOT));
+    let (chain, innermost) = foreground_chain(&table, session.device, tty_last_input)?;
+
+    let idle = session
+        .last_input
+        .elapsed()
+        .is_ok_and(|elapsed| elapsed > Duration::from_secs(TEXT_IDLE_SECS));
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let context = fs::read_link(
+        Path::new(PROC_ROOT)
+            .join(innermost.pid.to_string())
+            .join("cwd"),
+    )
+    .ok()
+    .map(|cwd| TerminalContext::new(innermost.comm.clone(), &cwd, home.as_deref()));
+
+    Some(TextFocus {
+        window: Window {
+            name: format!("{}: {}", session.kind, chain.join(" / ")),
+            class: session.kind.to_string(),
+        },
+        context,
+        idle,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utmp_record(kind: i16, pid: u32, line: &str, user: &str, host: &str) -> Vec<u8> {
+        let mut record = vec![0u8; UTMP_RECORD_LEN];
+        record[0..2].copy_from_slice(&kind.to_ne_bytes());
+        record[4..8].copy_from_slice(&pid.to_ne_bytes());
+        record[8..8 + line.len()].copy_from_slice(line.as_bytes());
+        record[44..44 + user.len()].copy_from_slice(user.as_bytes());
+        record[76..76 + host.len()].copy_from_slice(host.as_bytes());
+        record
+    }
+
+    fn stat(pid: u32, ppid: u32, comm: &str, tty_nr: i32, tpgid: i32) -> ProcStat {
+        ProcStat {
+            pid,
+            ppid,
+            comm: comm.to_string(),
+            tty_nr,
+            tpgid,
+            start_time: pid as u64,
+        }
+    }
+
+    /// Verifies that only login records are taken from utmp and that the fixed-size,
+    /// NUL-padded fields are cut at their terminator.
+    #[test]
+    fn parse_utmp_keeps_login_records() {
+        let mut raw = utmp_record(2, 0, "~", "reboot", "6.18.0");
+        raw.extend(utmp_record(
+            UTMP_USER_PROCESS,
+            812,
+            "pts/3",
+            "ana",
+            "10.0.0.7",
+        ));
+        raw.extend(utmp_record(UTMP_USER_PROCESS, 640, "tty2", "ana", ""));
+        raw.extend(utmp_record(8, 500, "pts/1", "", ""));
+
+        let entries = parse_utmp(&raw);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            UtmpEntry {
+                pid: 812,
+                line: "pts/3".to_string(),
+                user: "ana".to_string(),
+                host: "10.0.0.7".to_string(),
+            }
+        );
+        assert_eq!(entries[1].line, "tty2");
+        assert_eq!(entries[1].host, "");
+    }
+
+    /// Verifies that the foreground job of an SSH session is followed into the tmux pane that
+    /// saw input last, and that a plain shell job is reported on its own.
+    #[test]
+    fn foreground_chain_descends_into_the_last_used_tmux_pane() {
+        let ssh_pts = 34819;
+        let (pane_a, pane_b) = (34820, 34821);
+        let table = vec![
+            stat(700, 1, "sshd", 0, -1),
+            stat(710, 700, "bash", ssh_pts, 720),
+            stat(720, 710, "tmux: client", ssh_pts, 720),
+            stat(800, 1, "tmux: server", 0, -1),
+            stat(810, 800, "zsh", pane_a, 830),
+            stat(830, 810, "cargo", pane_a, 830),
+            stat(820, 800, "zsh", pane_b, 840),
+            stat(840, 820, "nvim", pane_b, 840),
+        ];
+        let last_input = |stat: &ProcStat| {
+            let secs = if stat.tty_nr == pane_b { 200 } else { 100 };
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+        };
+
+        let (chain, innermost) = foreground_chain(&table, ssh_pts as u64, last_input).unwrap();
+        assert_eq!(chain, ["tmux", "nvim"]);
+        assert_eq!(innermost.pid, 840);
+
+        let (chain, innermost) = foreground_chain(&table, pane_a as u64, last_input).unwrap();
+        assert_eq!(chain, ["cargo"]);
+        assert_eq!(innermost.pid, 830);
+
+        assert!(foreground_chain(&table, 1, last_input).is_none());
+    }
+}
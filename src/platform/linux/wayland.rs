@@ -1,4 +1,4 @@
-use tokio::sync::mpsc::*;
+use tokio::sync::watch;
 
 use crate::platform::common::*;
 
@@ -17,7 +17,9 @@ use wayland_protocols_wlr::foreign_toplevel::v1::client::{
 struct WaylandData {
     manager: Option<ZwlrForeignToplevelManagerV1>,
     windows: Vec<WaylandWindow>,
-    channel_sender: Sender<FocusEvent>,
+    /// The activated toplevel and how it looked when it got activated.
+    focused: Option<(ZwlrForeignToplevelHandleV1, Window)>,
+    focus_sender: watch::Sender<Option<Window>>,
 }
 
 #[derive(Debug, Clone)]
@@ -27,10 +29,30 @@ pub struct WaylandWindow {
     pub w_class: String,
 }
 
-#[derive(Debug, Clone)]
-pub enum FocusEvent {
-    FocusGained(Window),
-    FocusLost(Window),
+impl WaylandData {
+    /// Hands the window focused after a dispatch to the tracker. Only the latest focus is
+    /// kept, so a burst of changes the tracker didn't get to yet never piles up; it sees
+    /// where focus ended up.
+    fn publish_focus(&self) {
+        let focused = self.focused.as_ref().map(|(_, window)| window.clone());
+        self.focus_sender.send_if_modified(|current| {
+            if *current == focused {
+                return false;
+            }
+            *current = focused;
+            true
+        });
+    }
+
+    fn lose_focus(&mut self, handle: &ZwlrForeignToplevelHandleV1) {
+        if self
+            .focused
+            .as_ref()
+            .is_some_and(|(focused, _)| focused == handle)
+        {
+            self.focused = None;
+        }
+    }
 }
 
 impl Dispatch<wl_registry::WlRegistry, ()> for WaylandData {
@@ -131,23 +153,10 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandData {
                             class: focused_window.w_class.clone(),
                         };
 
-                        state
-                            .channel_sender
-                            .try_send(FocusEvent::FocusGained(window))
-                            .unwrap();
+                        state.focused = Some((handle.clone(), window));
                     }
-                } else if let Some(unfocused_window) =
-                    state.windows.iter().find(|t| t.wl_handle == *handle)
-                {
-                    let window = Window {
-                        name: unfocused_window.w_name.clone(),
-                        class: unfocused_window.w_class.clone(),
-                    };
-
-                    state
-                        .channel_sender
-                        .try_send(FocusEvent::FocusLost(window))
-                        .unwrap();
+                } else {
+                    state.lose_focus(handle);
                 }
             }
 
@@ -155,6 +164,7 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandData {
                 if let Some(closed_window) = state.windows.iter().find(|t| t.wl_handle == *handle) {
                     debug!("Window [{}] was closed", closed_window.w_class);
                 }
+                state.lose_focus(handle);
             }
 
             Event::OutputEnter { output } => {
@@ -174,7 +184,8 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandData {
     }
 }
 
-pub fn listen_for_wlevents(sender: Sender<FocusEvent>) -> Result<()> {
+/// Follows the activated toplevel and publishes it to `sender`, `None` while nothing is focused.
+pub fn listen_for_wlevents(sender: watch::Sender<Option<Window>>) -> Result<()> {
     let conn = Connection::connect_to_env()
         .with_context(|| "Failed to open a connection with wayland server")?;
     let wl_display = conn.display();
@@ -184,7 +195,8 @@ pub fn listen_for_wlevents(sender: Sender<FocusEvent>) -> Result<()> {
     let mut wl_data = WaylandData {
         manager: None,
         windows: Vec::with_capacity(300),
-        channel_sender: sender,
+        focused: None,
+        focus_sender: sender,
     };
 
     // Flush pending requests from wayland server.
@@ -204,10 +216,14 @@ pub fn listen_for_wlevents(sender: Sender<FocusEvent>) -> Result<()> {
         "Interface 'zwlr_foreign_toplevel_manager_v1' is available but we failed when requesting it"
     })?;
 
-    loop {
+    wl_data.publish_focus();
+    // The tracker went away (e.g. it is being restarted), so nobody needs our events anymore.
+    while !wl_data.focus_sender.is_closed() {
         // We block the thread to receive events from the toplevel handle :D
         event_q
             .blocking_dispatch(&mut wl_data)
-            .with_context(|| "Failed when handling events from wayland interface: {err}")?;
+            .with_context(|| "Failed when handling events from wayland interface")?;
+        wl_data.publish_focus();
     }
+    Ok(())
 }
@@ -22,8 +22,8 @@ impl X11Ctx {
 }
 
 /// This function upload the time for the entry in the vector only if we change window to reduce the
-/// overload by not going through the vector every second.
-pub async fn handle_active_window(x11: &X11Ctx, procs_data: &mut ProcessTracker) -> Result<()> {
+/// overload by not going through the vector every second. Returns whether focus moved.
+pub async fn handle_active_window(x11: &X11Ctx, procs_data: &mut ProcessTracker) -> Result<bool> {
     let (w_name, w_class) = get_focused_window(x11)?;
     let now = chrono::Utc::now();
     let window = Window {
@@ -34,7 +34,7 @@ pub async fn handle_active_window(x11: &X11Ctx, procs_data: &mut ProcessTracker)
     if procs_data.current_window_name().is_none() {
         debug!("First run, recording initial window: '{}'", window.name);
         procs_data.switch_window(window, now);
-        return Ok(());
+        return Ok(true);
     }
 
     if procs_data.current_window_name() != Some(window.name.as_str()) {
@@ -44,11 +44,30 @@ pub async fn handle_active_window(x11: &X11Ctx, procs_data: &mut ProcessTracker)
             window.name
         );
         procs_data.switch_window(window, now);
-    } else {
-        procs_data.resume(now);
+        return Ok(true);
     }
 
-    Ok(())
+    procs_data.resume(now);
+    Ok(false)
+}
+
+/// PID of the client owning the focused window, from `_NET_WM_PID`. Not every client sets it.
+pub fn focused_window_pid(x11: &X11Ctx) -> Option<u32> {
+    let root = x11.conn.setup().roots[x11.screen_num].root;
+    let net_active_window = get_or_intern_atom(&x11.conn, b"_NET_ACTIVE_WINDOW");
+    let net_wm_pid = get_or_intern_atom(&x11.conn, b"_NET_WM_PID");
+    let focus = find_active_window(&x11.conn, root, net_active_window).ok()?;
+
+    let reply = x11
+        .conn
+        .get_property(false, focus, net_wm_pid, AtomEnum::CARDINAL, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?;
+    reply
+        .value32()
+        .and_then(|mut values| values.next())
+        .filter(|pid| *pid > 0)
 }
 
 // https://www.reddit.com/r/rust/comments/f7yrle/get_information_about_current_w_xorg/
@@ -0,0 +1,318 @@
+//! XInput2 raw events as an input source for X11 sessions that may not read `/dev/input`.
+//!
+//! Since XI 2.1 the server delivers raw events selected on the root window no matter which
+//! window has focus, with the unaccelerated device deltas and without any special permission.
+//! They are translated into the evdev events the input loop already understands, so both
+//! sources produce the same bucket rows.
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use anyhow::*;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::*;
+use x11rb::connection::Connection;
+use x11rb::protocol::xinput::{self, ConnectionExt as _, Device, DeviceType, EventMask, Fp3232};
+use x11rb::protocol::Event;
+
+use crate::control::ControlDevice;
+use crate::input_bindings::*;
+use crate::platform::linux::evdev::{DeviceFilter, DeviceIdentity, DroppedEvents, InputEvent};
+
+/// X keycodes are evdev codes shifted by 8, the range below is reserved by the protocol.
+const X_KEYCODE_OFFSET: u32 = 8;
+/// Core pointer buttons 4-7 are the wheel: up, down, left, right.
+const X_WHEEL_BUTTONS: [(u32, u32, i32); 4] = [
+    (4, REL_WHEEL, 1),
+    (5, REL_WHEEL, -1),
+    (6, REL_HWHEEL, -1),
+    (7, REL_HWHEEL, 1),
+];
+
+fn evdev_event(type_: u32, code: u32, value: i32) -> input_event {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    input_event {
+        time: timeval {
+            tv_sec: now.as_secs() as _,
+            tv_usec: now.subsec_micros() as _,
+        },
+        type_: type_ as u16,
+        code: code as u16,
+        value,
+    }
+}
+
+/// A key press; auto-repeated presses keep evdev's repeat value so they aren't counted.
+fn translate_key(keycode: u32, repeat: bool) -> Option<InputEvent> {
+    let code = keycode.checked_sub(X_KEYCODE_OFFSET)?;
+    let value = if repeat { 2 } else { 1 };
+    Some(InputEvent::Keyboard {
+        event: evdev_event(EV_KEY, code, value),
+    })
+}
+
+fn translate_button(device_id: usize, button: u32) -> Vec<InputEvent> {
+    let mouse = |event| InputEvent::Mouse { device_id, event };
+    let code = match button {
+        1 => BTN_LEFT,
+        2 => BTN_MIDDLE,
+        3 => BTN_RIGHT,
+        8 => BTN_SIDE,
+        9 => BTN_EXTRA,
+        _ => {
+            return X_WHEEL_BUTTONS
+                .iter()
+                .filter(|(wheel, _, _)| *wheel == button)
+                .flat_map(|&(_, axis, steps)| {
+                    [
+                        mouse(evdev_event(EV_REL, axis, steps)),
+                        mouse(evdev_event(EV_SYN, SYN_REPORT, 0)),
+                    ]
+                })
+                .collect();
+        }
+    };
+    vec![mouse(evdev_event(EV_KEY, code, 1))]
+}
+
+fn fp3232_to_f64(value: &Fp3232) -> f64 {
+    value.integral as f64 + value.frac as f64 / (1u64 << 32) as f64
+}
+
+/// Raw X/Y deltas (valuators 0 and 1) of a motion event. `values` holds one entry per bit set
+/// in `mask`, in bit order.
+fn motion_deltas(mask: &[u32], values: &[Fp3232]) -> (f64, f64) {
+    let mut deltas = (0.0, 0.0);
+    let set_bits = mask
+        .iter()
+        .enumerate()
+        .flat_map(|(word, bits)| {
+            (0..32)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word * 32 + bit)
+        })
+        .take_while(|&axis| axis <= 1);
+    for (axis, value) in set_bits.zip(values) {
+        match axis {
+            0 => deltas.0 = fp3232_to_f64(value),
+            _ => deltas.1 = fp3232_to_f64(value),
+        }
+    }
+    deltas
+}
+
+/// Turns fractional raw deltas into whole evdev counts, carrying the remainder per device so
+/// slow, sub-count movement still adds up.
+#[derive(Debug, Default)]
+struct MotionRemainder {
+    per_device: HashMap<usize, (f64, f64)>,
+}
+
+impl MotionRemainder {
+    fn translate(&mut self, device_id: usize, dx: f64, dy: f64) -> Vec<InputEvent> {
+        let remainder = self.per_device.entry(device_id).or_default();
+        remainder.0 += dx;
+        remainder.1 += dy;
+        let counts = (remainder.0.trunc(), remainder.1.trunc());
+        remainder.0 -= counts.0;
+        remainder.1 -= counts.1;
+
+        let mouse = |event| InputEvent::Mouse { device_id, event };
+        let mut events = Vec::new();
+        if counts.0 != 0.0 {
+            events.push(mouse(evdev_event(EV_REL, REL_X, counts.0 as i32)));
+        }
+        if counts.1 != 0.0 {
+            events.push(mouse(evdev_event(EV_REL, REL_Y, counts.1 as i32)));
+        }
+        if !events.is_empty() {
+            events.push(mouse(evdev_event(EV_SYN, SYN_REPORT, 0)));
+        }
+        events
+    }
+}
+
+/// The keyboards and mice the server reports that `filter` lets count, by device id. The
+/// server's own XTEST devices replay synthetic input, so they count as virtual.
+fn list_devices(
+    conn: &impl Connection,
+    filter: &DeviceFilter,
+) -> Result<Vec<(u16, ControlDevice)>> {
+    let reply = conn.xinput_xi_query_device(Device::ALL)?.reply()?;
+    Ok(reply
+        .infos
+        .into_iter()
+        .filter(|info| info.enabled)
+        .filter_map(|info| {
+            let kind = match info.type_ {
+                DeviceType::SLAVE_KEYBOARD => "keyboard",
+                DeviceType::SLAVE_POINTER => "mouse",
+                _ => return None,
+            };
+            let name = String::from_utf8_lossy(&info.name).to_string();
+            let (listen, reason) = filter.decide(&DeviceIdentity {
+                is_virtual: name.contains("XTEST"),
+                name: name.clone(),
+                bus: None,
+            });
+            info!(
+                "Found {kind} over XInput2: [xinput:{}] -> {name}: {}, {reason}",
+                info.deviceid,
+                if listen { "counting" } else { "ignoring" }
+            );
+            listen.then(|| {
+                (
+                    info.deviceid,
+                    ControlDevice {
+                        kind: kind.to_string(),
+                        path: format!("xinput:{}", info.deviceid),
+                        name,
+                    },
+                )
+            })
+        })
+        .collect())
+}
+
+/// Starts listening for raw input on the X server from `DISPLAY`, counting in `dropped` what
+/// the channel had no room for. Only devices `filter` lets through count, matched by name.
+/// Returns those devices.
+pub fn spawn_xinput_listener(
+    tx: mpsc::Sender<Vec<InputEvent>>,
+    filter: DeviceFilter,
+    dropped: DroppedEvents,
+) -> Result<Vec<ControlDevice>> {
+    let (conn, screen_num) =
+        x11rb::connect(None).with_context(|| "Failed to connect to the X server")?;
+    let version = conn
+        .xinput_xi_query_version(2, 2)?
+        .reply()
+        .with_context(|| "The X server does not support XInput2")?;
+    ensure!(
+        (version.major_version, version.minor_version) >= (2, 1),
+        "Raw events on the root window need XInput 2.1, the server has {}.{}",
+        version.major_version,
+        version.minor_version
+    );
+
+    let root = conn.setup().roots[screen_num].root;
+    conn.xinput_xi_select_events(
+        root,
+        &[EventMask {
+            deviceid: Device::ALL_MASTER.into(),
+            mask: vec![
+                xinput::XIEventMask::RAW_KEY_PRESS
+                    | xinput::XIEventMask::RAW_BUTTON_PRESS
+                    | xinput::XIEventMask::RAW_MOTION,
+            ],
+        }],
+    )?
+    .check()
+    .with_context(|| "Failed to select XInput2 raw events")?;
+
+    let (counted, devices): (HashSet<u16>, Vec<ControlDevice>) =
+        list_devices(&conn, &filter)?.into_iter().unzip();
+
+    tokio::task::spawn_blocking(move || {
+        let mut motion = MotionRemainder::default();
+        loop {
+            let event = match conn.wait_for_event() {
+                Result::Ok(event) => event,
+                Err(err) => {
+                    error!("XInput2 listener lost the X connection: {err}");
+                    return;
+                }
+            };
+            let events = match event {
+                Event::XinputRawKeyPress(key) if counted.contains(&key.sourceid) => translate_key(
+                    key.detail,
+                    key.flags.contains(xinput::KeyEventFlags::KEY_REPEAT),
+                )
+                .into_iter()
+                .collect(),
+                Event::XinputRawButtonPress(button) if counted.contains(&button.sourceid) => {
+                    translate_button(button.sourceid as usize, button.detail)
+                }
+                Event::XinputRawMotion(moved) if counted.contains(&moved.sourceid) => {
+                    let (dx, dy) = motion_deltas(&moved.valuator_mask, &moved.axisvalues_raw);
+                    motion.translate(moved.sourceid as usize, dx, dy)
+                }
+                _ => Vec::new(),
+            };
+            if events.is_empty() {
+                continue;
+            }
+            match tx.try_send(events) {
+                Result::Ok(()) => {}
+                Err(TrySendError::Full(events)) => dropped.add(events.len() as u64),
+                Err(TrySendError::Closed(_)) => return,
+            }
+        }
+    });
+
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fp(value: f64) -> Fp3232 {
+        Fp3232 {
+            integral: value.floor() as i32,
+            frac: ((value - value.floor()) * (1u64 << 32) as f64) as u32,
+        }
+    }
+
+    fn mouse_events(events: &[InputEvent]) -> Vec<(u32, u32, i32)> {
+        events
+            .iter()
+            .map(|event| match event {
+                InputEvent::Mouse { event, .. } | InputEvent::Keyboard { event } => {
+                    (event.type_ as u32, event.code as u32, event.value)
+                }
+            })
+            .collect()
+    }
+
+    /// Verifies that core buttons map onto evdev buttons and wheel buttons onto scroll steps
+    /// closed by a report, the way a mouse reports them through evdev.
+    #[test]
+    fn raw_buttons_translate_to_evdev_events() {
+        assert_eq!(
+            mouse_events(&translate_button(7, 3)),
+            [(EV_KEY, BTN_RIGHT, 1)]
+        );
+        assert_eq!(
+            mouse_events(&translate_button(7, 5)),
+            [(EV_REL, REL_WHEEL, -1), (EV_SYN, SYN_REPORT, 0)]
+        );
+        assert!(translate_button(7, 12).is_empty());
+
+        let key = translate_key(38, false).unwrap();
+        assert_eq!(mouse_events(&[key]), [(EV_KEY, 30, 1)]);
+        assert!(translate_key(3, false).is_none());
+    }
+
+    /// Verifies that X/Y deltas are picked from the valuator mask and that fractional motion
+    /// accumulates until it amounts to whole counts.
+    #[test]
+    fn raw_motion_accumulates_fractional_counts() {
+        // valuators 1 and 3 set: only Y is a pointer axis
+        assert_eq!(motion_deltas(&[0b1010], &[fp(2.5), fp(9.0)]), (0.0, 2.5));
+        assert_eq!(motion_deltas(&[0b11], &[fp(-1.5), fp(0.25)]), (-1.5, 0.25));
+
+        let mut remainder = MotionRemainder::default();
+        assert!(remainder.translate(4, 0.6, 0.0).is_empty());
+        assert_eq!(
+            mouse_events(&remainder.translate(4, 0.6, 3.0)),
+            [
+                (EV_REL, REL_X, 1),
+                (EV_REL, REL_Y, 3),
+                (EV_SYN, SYN_REPORT, 0)
+            ]
+        );
+    }
+}
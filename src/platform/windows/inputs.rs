@@ -1,10 +1,13 @@
 use crate::common::*;
 use crate::storage::backend::*;
+use crate::storage::spool::{Spool, SpoolRows, SPOOL_INTERVAL};
+use crate::utils::shutdown::FinalFlush;
 use crate::StorageBackend;
 
 use anyhow::{Context, Result};
 use std::ffi::c_void;
 use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use tokio::{sync::mpsc::*, sync::*, time::*};
 
@@ -25,6 +28,10 @@ use windows::Win32::UI::Input::{
 };
 use windows::Win32::UI::WindowsAndMessaging::*;
 
+/// Raw input events the window procedure could not queue because the channel was full. The
+/// message loop must never wait on the input loop, so it counts them instead.
+static DROPPED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
 /// List all raw input devices
 fn list_raw_input_devices() -> Result<Vec<RAWINPUTDEVICELIST>> {
     unsafe {
@@ -101,6 +108,11 @@ impl InputCollector {
         }
     }
 
+    fn record_dropped_events(&mut self, count: u64) {
+        self.input_buffer
+            .record_dropped_events(chrono::Utc::now(), count);
+    }
+
     fn drain_rows(&mut self) -> Vec<InputBucketRecord> {
         self.input_buffer.drain()
     }
@@ -167,7 +179,12 @@ unsafe fn get_human_readable_name(device_handle: HANDLE) -> Result<String> {
     Ok(name)
 }
 
-pub async fn run(dpi: Option<u32>, update_interval: u32, backend: StorageBackend) -> Result<()> {
+pub async fn run(
+    dpi: Option<u32>,
+    update_interval: u32,
+    backend: StorageBackend,
+    final_flush: FinalFlush,
+) -> Result<()> {
     let mouse_dpi = dpi.unwrap_or(DEFAULT_MOUSE_DPI).max(1) as f64;
     let mut collector = InputCollector::new(
         backend.source_id(),
@@ -176,7 +193,12 @@ pub async fn run(dpi: Option<u32>, update_interval: u32, backend: StorageBackend
     );
 
     let (events_tx, mut events_rx) = channel::<RawInputMessage>(256);
+    let mut spool = Spool::open(&backend, "input")
+        .await
+        .with_context(|| "Failed to open the input spool")?;
+    let mut spool_tick = interval(SPOOL_INTERVAL);
     let mut db_updates = interval(Duration::from_secs(update_interval as u64));
+    let mut flush_requested = final_flush.subscribe();
 
     // Log devices
     for (i, dev) in list_raw_input_devices()?.into_iter().enumerate() {
@@ -211,16 +233,40 @@ pub async fn run(dpi: Option<u32>, update_interval: u32, backend: StorageBackend
     loop {
         tokio::select! {
             Some(message) = events_rx.recv() => {
+                let dropped = DROPPED_EVENTS.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    collector.record_dropped_events(dropped);
+                }
                 collector.handle_message(message);
             }
 
+            _ = spool_tick.tick() => {
+                if let Err(e) = spool.append(SpoolRows::Input(collector.drain_rows())) {
+                    error!("Failed to spool input rows: {e:#}");
+                }
+            }
+
             _ = db_updates.tick() => {
-                let pending_rows = collector.drain_rows();
-                if let Err(e) = backend.store_keys_data(&pending_rows).await {
+                if let Err(e) = spool.append(SpoolRows::Input(collector.drain_rows())) {
+                    error!("Failed to spool input rows: {e:#}");
+                }
+                if let Err(e) = spool.commit(&backend).await {
                     error!("Failed to store inputs data in backend: {:?}", e);
                 }
             }
 
+            Ok(()) = flush_requested.changed() => {
+                if let Err(e) = spool.append(SpoolRows::Input(collector.drain_rows())) {
+                    error!("Failed to spool input rows: {e:#}");
+                }
+                if let Err(e) = spool.commit(&backend).await {
+                    error!("Failed to store inputs data in backend: {:?}", e);
+                }
+                final_flush.ack();
+                // Counting stops here; shutdown aborts the task next.
+                return std::future::pending().await;
+            }
+
             else => {
                 error!("Event source channel closed. Shutting down.");
                 break;
@@ -666,11 +712,8 @@ unsafe fn handle_raw_input(handle: HRAWINPUT, tx: &mpsc::Sender<RawInputMessage>
     };
 
     if let Some(e) = event {
-        if let Err(send_err) = tx.try_send(RawInputMessage::Input(e)) {
-            warn!(
-                "Failed to send raw input event, channel may be full: {}",
-                send_err
-            );
+        if let Err(mpsc::error::TrySendError::Full(_)) = tx.try_send(RawInputMessage::Input(e)) {
+            DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
         }
     }
 
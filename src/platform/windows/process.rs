@@ -1,4 +1,10 @@
-use crate::{common::*, platform::windows::common::*, storage::backend::*};
+use crate::{
+    common::*,
+    platform::windows::common::*,
+    storage::backend::*,
+    storage::spool::{Spool, SpoolRows, SPOOL_INTERVAL},
+    utils::shutdown::FinalFlush,
+};
 
 use anyhow::{Context, Result};
 use tokio::time::*;
@@ -14,6 +20,20 @@ fn read_focused_window(idle: bool) -> Result<Option<Window>> {
     }
 }
 
+/// Moves the focus time recorded so far into the spool.
+fn spool_focus_data(tracker: &mut ProcessTracker, spool: &mut Spool) {
+    tracker.record_active_until(chrono::Utc::now());
+    let rows = [
+        SpoolRows::Focus(tracker.drain_pending()),
+        SpoolRows::Projects(tracker.drain_pending_projects()),
+    ];
+    for rows in rows {
+        if let Err(err) = spool.append(rows) {
+            error!("Failed to spool focus rows: {err:#}");
+        }
+    }
+}
+
 fn update_focus_tracker(
     tracker: &mut ProcessTracker,
     now: chrono::DateTime<chrono::Utc>,
@@ -34,12 +54,21 @@ fn update_focus_tracker(
     }
 }
 
-pub async fn run(update_interval: u32, backend: StorageBackend) -> Result<()> {
+pub async fn run(
+    update_interval: u32,
+    backend: StorageBackend,
+    final_flush: FinalFlush,
+) -> Result<()> {
     let mut procs_data =
         ProcessTracker::new(backend.source_id(), backend.bucket_granularity_minutes());
 
+    let mut spool = Spool::open(&backend, "focus")
+        .await
+        .with_context(|| "Failed to open the focus spool")?;
     let mut tick = interval(Duration::from_secs(1));
+    let mut spool_tick = interval(SPOOL_INTERVAL);
     let mut database_update = interval(Duration::from_secs(update_interval as u64));
+    let mut flush_requested = final_flush.subscribe();
 
     loop {
         tokio::select! {
@@ -50,13 +79,23 @@ pub async fn run(update_interval: u32, backend: StorageBackend) -> Result<()> {
                 update_focus_tracker(&mut procs_data, now, idle, focused_window);
             }
 
+            _ = spool_tick.tick() => spool_focus_data(&mut procs_data, &mut spool),
+
             _ = database_update.tick() => {
-                procs_data.record_active_until(chrono::Utc::now());
-                let rows = procs_data.drain_pending();
-                if let Err(err) = backend.store_proc_data(&rows).await {
-                    error!("Error sending data to procs table: {err:?}");
+                spool_focus_data(&mut procs_data, &mut spool);
+                if let Err(err) = spool.commit(&backend).await {
+                    error!("Error sending focus data to the database: {err:?}");
                 }
+            }
 
+            Ok(()) = flush_requested.changed() => {
+                spool_focus_data(&mut procs_data, &mut spool);
+                if let Err(err) = spool.commit(&backend).await {
+                    error!("Error sending focus data to the database: {err:?}");
+                }
+                final_flush.ack();
+                // Counting stops here; shutdown aborts the task next.
+                return std::future::pending().await;
             }
         }
     }
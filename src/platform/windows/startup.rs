@@ -176,6 +176,9 @@ mod tests {
             dry_run: false,
             import_notes: None,
             dpi: None,
+            granularity: None,
+            maintenance_interval: None,
+            sinks: Vec::new(),
             clear: false,
             enable_startup: false,
             disable_startup: false,
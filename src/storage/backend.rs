@@ -1,14 +1,21 @@
 //! Storage backend abstraction.
 use crate::common::*;
 use crate::storage::localdb::*;
+use crate::storage::offline::{
+    is_unreachable_error, merge_offline_entries, offline_db_path, open_offline_db,
+    pending_offline_rows, store_offline_entries,
+};
+use crate::storage::sink::{SinkBatch, SinkHandle, SinkSpec};
+use crate::storage::spool::{apply_spool_entries, replay_spools, spool_dir, SpoolEntry, SpoolRows};
 #[cfg(feature = "multi-sync")]
-use crate::sync::{apply_local_focus_rows, apply_local_input_rows, apply_local_source};
+use crate::sync::apply_local_source;
 use crate::utils::lock::acquire_db_operation_lock;
 
+use chrono::{DateTime, Utc};
 use rusqlite::Connection;
 
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use anyhow::*;
 
@@ -16,65 +23,97 @@ use tracing::*;
 
 #[allow(async_fn_in_trait)]
 pub trait DataStore {
-    async fn store_keys_data(&self, rows: &[InputBucketRecord]) -> Result<()>;
-    async fn store_proc_data(&self, rows: &[FocusBucketRecord]) -> Result<()>;
+    async fn store_suspend_period(&self, period: &SuspendRecord) -> Result<()>;
+    async fn store_collector_event(&self, event: &CollectorEventRecord) -> Result<()>;
+    async fn store_spool_entries(&self, spool: &str, entries: &[SpoolEntry]) -> Result<()>;
 }
 
 #[derive(Debug, Clone)]
 pub struct LocalDb {
-    con: Arc<Mutex<Connection>>,
+    /// `None` while the database is out of reach; rows then go to `offline_path`.
+    con: Arc<Mutex<Option<Connection>>>,
     source_id: i64,
+    granularity_minutes: u32,
     db_path: PathBuf,
-    _session: Arc<SessionLifecycle>,
+    spool_dir: PathBuf,
+    offline_path: PathBuf,
+    session: Arc<SessionLifecycle>,
 }
 
 #[derive(Debug)]
 struct SessionLifecycle {
-    con: Arc<Mutex<Connection>>,
-    session_uuid: String,
+    con: Arc<Mutex<Option<Connection>>>,
+    /// Begun as soon as the database is reached, which is later than startup when it was
+    /// out of reach then.
+    session_uuid: Mutex<Option<String>>,
 }
 
 impl LocalDb {
-    pub fn new(config: DbConfig, should_clear: bool) -> Result<Self> {
+    /// `granularity_minutes` changes the bucket granularity remembered in the database.
+    pub fn new(
+        config: DbConfig,
+        should_clear: bool,
+        granularity_minutes: Option<u32>,
+    ) -> Result<Self> {
         if should_clear {
             info!("Clean argument provided, cleaning database!");
             clear_database(&config.db_path).context("Failed to clear database")?;
         };
 
-        let conn = open_con_at(&config.db_path).with_context(|| {
-            if config.source == DbPathSource::Remembered {
-                format_remembered_db_open_error(&config.db_path)
-            } else {
-                format!(
-                    "Failed to open connection with sqlite database at '{}'",
-                    config.db_path.display()
-                )
+        let spool_dir = spool_dir()?;
+        let offline_path = offline_db_path()?;
+        let conn = match open_database(&config.db_path).and_then(|mut conn| {
+            replay_spools(&mut conn, &spool_dir)
+                .context("Failed to replay unflushed activity from the spool")?;
+            Ok(conn)
+        }) {
+            Result::Ok(conn) => Some(conn),
+            // Only a database the user pointed Vigil at can live on a share that is gone.
+            Err(err) if config.source != DbPathSource::Default => {
+                warn!(
+                    "{}\nUntil it is reachable again, collected activity is kept in '{}'. Cause: {err:#}",
+                    format_db_open_error(&config),
+                    offline_path.display()
+                );
+                None
             }
-        })?;
-        setup_database(&conn).context("Failed to properly setup sqlite database")?;
-        let session_uuid = begin_session(&conn, DEFAULT_SOURCE_ID, std::env::consts::OS)
-            .with_context(|| "Failed to record the startup of the current collection session")?;
-        #[cfg(feature = "multi-sync")]
-        {
-            let source = get_source(&conn, DEFAULT_SOURCE_ID)?;
-            apply_local_source(&conn, &source)
-                .with_context(|| "Failed to seed the local source row into the sync outbox")?;
-        }
+            Err(err) => return Err(err).with_context(|| format_db_open_error(&config)),
+        };
+
+        let granularity_minutes = match (&conn, granularity_minutes) {
+            (Some(conn), Some(granularity)) => {
+                set_bucket_granularity(conn, granularity)?;
+                granularity
+            }
+            (Some(conn), None) => {
+                bucket_granularity(conn)?.unwrap_or(DEFAULT_BUCKET_MINUTES as u32)
+            }
+            (None, granularity) => granularity.unwrap_or(DEFAULT_BUCKET_MINUTES as u32),
+        };
+        info!("Recording activity in {granularity_minutes}-minute buckets");
 
-        info!(
-            "Backend using SQLite successfully initialized at {}.",
-            config.db_path.display()
-        );
         let shared_con = Arc::new(Mutex::new(conn));
-        Ok(Self {
+        let db = Self {
             con: shared_con.clone(),
             source_id: DEFAULT_SOURCE_ID,
+            granularity_minutes,
             db_path: config.db_path,
-            _session: Arc::new(SessionLifecycle {
+            spool_dir,
+            offline_path,
+            session: Arc::new(SessionLifecycle {
                 con: shared_con,
-                session_uuid,
+                session_uuid: Mutex::new(None),
             }),
-        })
+        };
+        if let Some(conn) = db.lock_con()?.as_mut() {
+            db.merge_offline(conn)?;
+            db.session.begin(conn)?;
+            info!(
+                "Backend using SQLite successfully initialized at {}.",
+                db.db_path.display()
+            );
+        }
+        Ok(db)
     }
 
     pub fn source_id(&self) -> i64 {
@@ -82,11 +121,137 @@ impl LocalDb {
     }
 
     pub fn bucket_granularity_minutes(&self) -> u32 {
-        DEFAULT_BUCKET_MINUTES as u32
+        self.granularity_minutes
+    }
+
+    pub fn spool_dir(&self) -> &Path {
+        &self.spool_dir
+    }
+
+    /// Whether the database can be written to right now.
+    pub fn is_reachable(&self) -> bool {
+        self.con.lock().is_ok_and(|con| con.is_some())
+    }
+
+    /// This collector's own source row, while the database is reachable.
+    pub fn source_info(&self) -> Option<SourceInfo> {
+        let con = self.lock_con().ok()?;
+        get_source(con.as_ref()?, self.source_id).ok()
+    }
+
+    fn lock_con(&self) -> Result<MutexGuard<'_, Option<Connection>>> {
+        self.con
+            .lock()
+            .map_err(|_| anyhow!("database connection lock was poisoned"))
+    }
+
+    fn merge_offline(&self, conn: &mut Connection) -> Result<()> {
+        let _op_lock = acquire_db_operation_lock(&self.db_path)?;
+        let merged = merge_offline_entries(conn, &self.db_path, &self.offline_path)?;
+        if merged > 0 {
+            info!(
+                "Merged {merged} rows kept in '{}' into {}",
+                self.offline_path.display(),
+                self.db_path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Opens the database again if it was out of reach and merges the rows kept offline into
+    /// it. Returns how many rows are still kept offline.
+    pub async fn reconnect(&self) -> Result<u64> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut con = db.lock_con()?;
+            if con.is_none() {
+                match open_database(&db.db_path) {
+                    Result::Ok(conn) => {
+                        info!(
+                            "The database at {} is reachable again",
+                            db.db_path.display()
+                        );
+                        *con = Some(conn);
+                    }
+                    Err(err) => {
+                        debug!("The database is still out of reach: {err:#}");
+                        return pending_offline_rows(&db.offline_path, &db.db_path);
+                    }
+                }
+            }
+            if let Some(conn) = con.as_mut() {
+                db.merge_offline(conn)?;
+                db.session.begin(conn)?;
+            }
+            pending_offline_rows(&db.offline_path, &db.db_path)
+        })
+        .await?
+    }
+
+    /// Writes `entries` of spool `spool` to the database. While the database is out of reach,
+    /// or the moment it can't be reached, they are kept offline instead. Entries it refuses
+    /// are kept offline too, and the next reconnect tick merges them one by one.
+    fn store_entries_blocking(&self, spool: &str, entries: &[SpoolEntry]) -> Result<()> {
+        let mut con = self.lock_con()?;
+        if let Some(conn) = con.as_mut() {
+            let stored = acquire_db_operation_lock(&self.db_path)
+                .and_then(|_op_lock| apply_spool_entries(conn, spool, entries));
+            match stored {
+                Result::Ok(_) => return Ok(()),
+                Err(err) if is_unreachable_error(&err) => {
+                    warn!(
+                        "Failed to write to the database at {}, keeping activity in '{}' until it is reachable again: {err:#}",
+                        self.db_path.display(),
+                        self.offline_path.display()
+                    );
+                    *con = None;
+                }
+                Err(err) => warn!(
+                    "The database at {} refused rows of {spool}, keeping them in '{}' to merge one by one: {err:#}",
+                    self.db_path.display(),
+                    self.offline_path.display()
+                ),
+            }
+        }
+        let mut offline = open_offline_db(&self.offline_path)?;
+        store_offline_entries(&mut offline, &self.db_path, spool, entries)
+    }
+
+    /// Closes the collection session as of `ended_at`; see [`end_session`].
+    pub async fn end_session(&self, ended_at: DateTime<Utc>, reason: &'static str) -> Result<()> {
+        let db = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let Some(session_uuid) = db.session.uuid() else {
+                return Ok(());
+            };
+            let con = db.lock_con()?;
+            let conn = con.as_ref().ok_or_else(|| {
+                anyhow!("the database at {} is out of reach", db.db_path.display())
+            })?;
+            let _op_lock = acquire_db_operation_lock(&db.db_path)?;
+            end_session(conn, &session_uuid, ended_at, reason)
+        })
+        .await?
+    }
+
+    /// Applies the remembered retention rules. Does nothing while the database is out of reach.
+    pub async fn run_maintenance(&self) -> Result<Option<MaintenanceReport>> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut con = db.lock_con()?;
+            let Some(conn) = con.as_mut() else {
+                return Ok(None);
+            };
+            let _op_lock = acquire_db_operation_lock(&db.db_path)?;
+            let policy = load_retention_policy(conn)?;
+            run_maintenance(conn, policy, Utc::now(), false).map(Some)
+        })
+        .await?
     }
 
     #[cfg(feature = "multi-sync")]
-    pub fn shared_connection(&self) -> Arc<Mutex<Connection>> {
+    pub fn shared_connection(&self) -> Arc<Mutex<Option<Connection>>> {
         self.con.clone()
     }
 
@@ -96,6 +261,30 @@ impl LocalDb {
     }
 }
 
+/// Opens the database at `db_path` and brings its schema up to date.
+fn open_database(db_path: &Path) -> Result<Connection> {
+    let conn = open_con_at(db_path)?;
+    setup_database(&conn).context("Failed to properly setup sqlite database")?;
+    #[cfg(feature = "multi-sync")]
+    {
+        let source = get_source(&conn, DEFAULT_SOURCE_ID)?;
+        apply_local_source(&conn, &source)
+            .with_context(|| "Failed to seed the local source row into the sync outbox")?;
+    }
+    Ok(conn)
+}
+
+fn format_db_open_error(config: &DbConfig) -> String {
+    if config.source == DbPathSource::Remembered {
+        format_remembered_db_open_error(&config.db_path)
+    } else {
+        format!(
+            "Failed to open connection with sqlite database at '{}'",
+            config.db_path.display()
+        )
+    }
+}
+
 fn format_remembered_db_open_error(path: &std::path::Path) -> String {
     format!(
         "Failed to open the remembered database path '{}'.\nThis usually means the path is no longer available, such as an unmounted or disconnected network share.\nWhat you can do:\n- mount or reconnect the share again so Vigil can access it\n- run Vigil with --db-path <NEW_PATH> to switch to another database now\n- later, import the old database or a snapshot once it is available again",
@@ -104,93 +293,210 @@ fn format_remembered_db_open_error(path: &std::path::Path) -> String {
 }
 
 impl DataStore for LocalDb {
-    async fn store_keys_data(&self, rows: &[InputBucketRecord]) -> Result<()> {
-        let rows = rows.to_vec();
-        let con = self.con.clone();
-        let db_path = self.db_path.clone();
+    /// Suspend periods describe this machine only and are not synced.
+    async fn store_suspend_period(&self, period: &SuspendRecord) -> Result<()> {
+        let entry = SpoolEntry::standalone(SpoolRows::Suspends(vec![period.clone()]));
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || db.store_entries_blocking("suspend", &[entry]))
+            .await?
+            .context("Failed to insert suspend period into sqlite database")
+    }
 
-        tokio::task::spawn_blocking(move || {
-            let _op_lock = acquire_db_operation_lock(&db_path)?;
-            let mut con = con
-                .lock()
-                .map_err(|_| anyhow!("database connection lock was poisoned"))?;
-            let tx = con.transaction()?;
-            #[cfg(feature = "multi-sync")]
-            apply_local_input_rows(&tx, &rows)
-                .context("Failed to insert input bucket rows into sqlite database")?;
-            #[cfg(not(feature = "multi-sync"))]
-            insert_input_buckets(&tx, &rows)
-                .context("Failed to insert input bucket rows into sqlite database")?;
-            tx.commit().context("Failed to commit input bucket rows")
-        })
-        .await?
+    /// Task failures describe this collector only and are not synced.
+    async fn store_collector_event(&self, event: &CollectorEventRecord) -> Result<()> {
+        let entry = SpoolEntry::standalone(SpoolRows::Events(vec![event.clone()]));
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || db.store_entries_blocking("events", &[entry]))
+            .await?
+            .context("Failed to insert collector event into sqlite database")
     }
 
-    async fn store_proc_data(&self, rows: &[FocusBucketRecord]) -> Result<()> {
-        let rows = rows.to_vec();
-        let con = self.con.clone();
-        let db_path = self.db_path.clone();
+    /// Spooled rows go through the same tables (and sync outbox) as flushed ones.
+    async fn store_spool_entries(&self, spool: &str, entries: &[SpoolEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let spool = spool.to_string();
+        let entries = entries.to_vec();
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || db.store_entries_blocking(&spool, &entries)).await?
+    }
+}
 
-        tokio::task::spawn_blocking(move || {
-            let _op_lock = acquire_db_operation_lock(&db_path)?;
-            let mut con = con
-                .lock()
-                .map_err(|_| anyhow!("database connection lock was poisoned"))?;
-            let tx = con.transaction()?;
-            #[cfg(feature = "multi-sync")]
-            apply_local_focus_rows(&tx, &rows)
-                .context("Failed to insert focus bucket rows into sqlite database")?;
-            #[cfg(not(feature = "multi-sync"))]
-            insert_focus_buckets(&tx, &rows)
-                .context("Failed to insert focus bucket rows into sqlite database")?;
-            tx.commit().context("Failed to commit focus bucket rows")
-        })
-        .await?
+impl SessionLifecycle {
+    fn uuid(&self) -> Option<String> {
+        self.session_uuid.lock().ok().and_then(|uuid| uuid.clone())
+    }
+
+    /// Begins the collection session unless it already is.
+    fn begin(&self, conn: &Connection) -> Result<()> {
+        let mut session_uuid = self
+            .session_uuid
+            .lock()
+            .map_err(|_| anyhow!("session lock was poisoned"))?;
+        if session_uuid.is_none() {
+            *session_uuid = Some(
+                begin_session(conn, DEFAULT_SOURCE_ID, std::env::consts::OS).with_context(
+                    || "Failed to record the startup of the current collection session",
+                )?,
+            );
+        }
+        Ok(())
     }
 }
 
 impl Drop for SessionLifecycle {
     fn drop(&mut self) {
-        if let Some(conn) = self.con.lock().ok() {
-            if let Err(err) = end_session(&conn, &self.session_uuid) {
-                error!(
-                    "Failed to finalize collection session {}: {err:#}",
-                    self.session_uuid
-                );
+        let Some(session_uuid) = self.uuid() else {
+            return;
+        };
+        if let Some(Some(conn)) = self.con.lock().ok().as_deref() {
+            // a graceful shutdown already closed the session with its own time and reason
+            if let Err(err) = end_session(conn, &session_uuid, Utc::now(), "exit") {
+                error!("Failed to finalize collection session {session_uuid}: {err:#}");
             }
         }
     }
 }
 
+/// The primary database plus secondary sinks that get a copy of every row written to it.
+#[derive(Debug, Clone)]
+pub struct FanOut {
+    primary: LocalDb,
+    sinks: Arc<[SinkHandle]>,
+}
+
+impl FanOut {
+    /// Starts a task for each sink in `specs`. Must run inside the Tokio runtime.
+    pub fn new(primary: LocalDb, specs: &[SinkSpec]) -> Self {
+        let source = primary.source_info();
+        let sinks = specs
+            .iter()
+            .map(|spec| {
+                info!("Also writing activity to sink {spec}");
+                SinkHandle::spawn(spec.clone(), source.clone())
+            })
+            .collect();
+        Self { primary, sinks }
+    }
+
+    /// Hands rows the primary accepted to every sink. Sinks write in the background, so
+    /// this never waits on them.
+    fn forward(&self, spool: &str, entries: &[SpoolEntry]) {
+        for sink in self.sinks.iter() {
+            sink.send(SinkBatch {
+                spool: spool.to_string(),
+                entries: entries.to_vec(),
+            });
+        }
+    }
+
+    /// Gives every sink one more chance to write what it has queued.
+    pub async fn flush_sinks(&self) {
+        // Each sink writes in its own task, so waiting on them one by one loses no time.
+        for sink in self.sinks.iter() {
+            sink.flush().await;
+        }
+    }
+}
+
+impl DataStore for FanOut {
+    async fn store_suspend_period(&self, period: &SuspendRecord) -> Result<()> {
+        self.primary.store_suspend_period(period).await?;
+        let entry = SpoolEntry::standalone(SpoolRows::Suspends(vec![period.clone()]));
+        self.forward("suspend", &[entry]);
+        Ok(())
+    }
+
+    async fn store_collector_event(&self, event: &CollectorEventRecord) -> Result<()> {
+        self.primary.store_collector_event(event).await?;
+        let entry = SpoolEntry::standalone(SpoolRows::Events(vec![event.clone()]));
+        self.forward("events", &[entry]);
+        Ok(())
+    }
+
+    /// Rows the primary refused stay in the caller's spool and come back with the next
+    /// commit, so they are only forwarded once the primary has them.
+    async fn store_spool_entries(&self, spool: &str, entries: &[SpoolEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.primary.store_spool_entries(spool, entries).await?;
+        self.forward(spool, entries);
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum StorageBackend {
     Local(LocalDb),
+    FanOut(FanOut),
 }
 
 impl StorageBackend {
-    pub fn source_id(&self) -> i64 {
+    /// The database sessions, maintenance and sync work on.
+    pub fn primary(&self) -> &LocalDb {
         match self {
-            StorageBackend::Local(db) => db.source_id(),
+            StorageBackend::Local(db) => db,
+            StorageBackend::FanOut(fan_out) => &fan_out.primary,
         }
     }
 
+    pub fn source_id(&self) -> i64 {
+        self.primary().source_id()
+    }
+
     pub fn bucket_granularity_minutes(&self) -> u32 {
-        match self {
-            StorageBackend::Local(db) => db.bucket_granularity_minutes(),
+        self.primary().bucket_granularity_minutes()
+    }
+
+    pub fn spool_dir(&self) -> &Path {
+        self.primary().spool_dir()
+    }
+
+    pub fn is_reachable(&self) -> bool {
+        self.primary().is_reachable()
+    }
+
+    pub async fn reconnect(&self) -> Result<u64> {
+        self.primary().reconnect().await
+    }
+
+    pub async fn end_session(&self, ended_at: DateTime<Utc>, reason: &'static str) -> Result<()> {
+        self.primary().end_session(ended_at, reason).await
+    }
+
+    pub async fn run_maintenance(&self) -> Result<Option<MaintenanceReport>> {
+        self.primary().run_maintenance().await
+    }
+
+    /// Waits for the sinks to write what they have queued; nothing to do without sinks.
+    pub async fn flush_sinks(&self) {
+        if let StorageBackend::FanOut(fan_out) = self {
+            fan_out.flush_sinks().await;
         }
     }
 }
 
 impl DataStore for StorageBackend {
-    async fn store_keys_data(&self, rows: &[InputBucketRecord]) -> Result<()> {
+    async fn store_suspend_period(&self, period: &SuspendRecord) -> Result<()> {
+        match self {
+            StorageBackend::Local(db) => db.store_suspend_period(period).await,
+            StorageBackend::FanOut(fan_out) => fan_out.store_suspend_period(period).await,
+        }
+    }
+
+    async fn store_collector_event(&self, event: &CollectorEventRecord) -> Result<()> {
         match self {
-            StorageBackend::Local(db) => db.store_keys_data(rows).await,
+            StorageBackend::Local(db) => db.store_collector_event(event).await,
+            StorageBackend::FanOut(fan_out) => fan_out.store_collector_event(event).await,
         }
     }
 
-    async fn store_proc_data(&self, rows: &[FocusBucketRecord]) -> Result<()> {
+    async fn store_spool_entries(&self, spool: &str, entries: &[SpoolEntry]) -> Result<()> {
         match self {
-            StorageBackend::Local(db) => db.store_proc_data(rows).await,
+            StorageBackend::Local(db) => db.store_spool_entries(spool, entries).await,
+            StorageBackend::FanOut(fan_out) => fan_out.store_spool_entries(spool, entries).await,
         }
     }
 }
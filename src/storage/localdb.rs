@@ -1,30 +1,84 @@
 mod analytics;
 mod config;
+mod encryption;
 mod export;
+mod forget;
+mod health;
 mod import;
 mod integrity;
+mod journal;
+mod migrations;
+mod pool;
+mod retention;
+mod rollups;
 mod rows;
 mod schema;
+mod sources;
+mod table_export;
 
 #[allow(unused_imports)]
-pub use analytics::{begin_session, daily_activity_report, end_session, DailyActivityRow};
+pub use analytics::{
+    begin_session, daily_activity_report, end_session, project_report, render_project_report,
+    DailyActivityRow, ProjectDayRow,
+};
 #[allow(unused_imports)]
 pub use config::{default_db_path, resolve_db_path, DbConfig, DbPathSource};
 #[allow(unused_imports)]
+pub use encryption::{
+    database_passphrase, read_passphrase_file, rekey_database, PASSPHRASE_ENV, PASSPHRASE_FILE_ENV,
+};
+#[allow(unused_imports)]
 pub use export::{export_database, ExportMetadata, ExportResult};
 #[allow(unused_imports)]
+pub use forget::{forget, ForgetFilter, ForgetMatcher, ForgetReport};
+#[allow(unused_imports)]
+pub use health::{
+    check_database, database_stats, open_for_inspection, recover_database, vacuum_database,
+    CheckReport, DatabaseStats, RecoveredTable, RecoveryReport, SourceStats, VacuumReport,
+};
+#[allow(unused_imports)]
 pub use import::{import_snapshot, plan_import, ImportPlan, ImportResult};
 #[allow(unused_imports)]
+pub use migrations::{stored_schema_version, Migration, MIGRATIONS};
+#[allow(unused_imports)]
+pub use pool::{PooledReader, ReadPool};
+#[allow(unused_imports)]
+#[cfg(feature = "multi-sync")]
+pub use retention::{compact_late_focus_row, compact_late_input_row};
+#[allow(unused_imports)]
+pub use retention::{
+    load_retention_policy, run_maintenance, save_retention_policy, CompactionRecord,
+    MaintenanceReport, RetentionPolicy,
+};
+#[allow(unused_imports)]
+pub use rollups::{rebuild_rollups, rollup_for_series, ROLLUP_GRANULARITIES};
+#[allow(unused_imports)]
 pub use rows::{
-    get_source, get_source_by_uuid, insert_focus_buckets, insert_input_buckets, open_con_at,
+    get_source, get_source_by_uuid, insert_collector_event, insert_domain_buckets,
+    insert_focus_buckets, insert_input_buckets, insert_project_buckets, insert_suspend_period,
+    insert_terminal_buckets, open_con_at, set_spool_checkpoint, spool_checkpoint,
     upsert_source_by_uuid,
 };
 #[allow(unused_imports)]
-pub use schema::{clear_database, setup_database, SCHEMA_VERSION};
+pub use schema::{
+    bucket_granularity, clear_database, set_bucket_granularity, setup_database, SCHEMA_VERSION,
+};
+#[allow(unused_imports)]
+pub use sources::{
+    delete_source, find_source, list_sources, merge_sources, rename_source, render_source_list,
+    retired_source, RetiredSource, SourceSummary,
+};
+#[allow(unused_imports)]
+pub use table_export::{
+    export_tables, render_table_export, ExportFormat, ExportTable, ExportedTable, TableExportFilter,
+};
 
+pub(crate) use encryption::attach_database;
 #[cfg(test)]
 pub(crate) use export::latest_export_metadata;
 #[cfg(test)]
+pub(crate) use health::pragma_u64;
+#[cfg(test)]
 pub(crate) use integrity::{file_sha256, scalar_query_u64};
 
 #[cfg(test)]
@@ -70,6 +124,7 @@ mod tests {
             mouse_distance_cm: 3.0,
             scroll_vertical_cm: 0.4,
             scroll_horizontal_cm: 0.0,
+            dropped_events: 0,
         }
     }
 
@@ -249,8 +304,826 @@ mod tests {
         Ok(())
     }
 
+    /// Verifies that a database left at schema version 3 is backed up and upgraded step by
+    /// step, rollups included, and that one written by a newer vigil is refused.
+    #[test]
+    fn setup_database_upgrades_older_schema_and_refuses_newer() -> anyhow::Result<()> {
+        let path = unique_temp_db("migrate");
+        let conn = build_test_db(&path)?;
+        insert_input_buckets(&conn, &[sample_input_row()])?;
+        conn.execute_batch(
+            "
+            DROP TABLE input_rollups;
+            DROP TABLE focus_rollups;
+            DROP TABLE compactions;
+            UPDATE schema_meta SET value = '3' WHERE key = 'schema_version';
+            ",
+        )?;
+
+        setup_database(&conn)?;
+        assert_eq!(stored_schema_version(&conn)?, Some(SCHEMA_VERSION));
+        assert_eq!(
+            scalar_query_u64(&conn, "SELECT SUM(key_presses) FROM input_rollups")?,
+            10
+        );
+        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+        let backups = fs::read_dir(path.parent().unwrap())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|backup| {
+                backup.file_name().is_some_and(|name| {
+                    name.to_string_lossy()
+                        .starts_with(&format!("{stem}.pre-migration-v3-"))
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(backups.len(), 1);
+        let backup = open_con_at(&backups[0])?;
+        assert_eq!(stored_schema_version(&backup)?, Some(3));
+
+        conn.execute(
+            "UPDATE schema_meta SET value = ?1 WHERE key = 'schema_version'",
+            [(SCHEMA_VERSION + 1).to_string()],
+        )?;
+        let err = setup_database(&conn).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+
+        drop(backup);
+        drop(conn);
+        fs::remove_file(&backups[0])?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that databases created before `dropped_events` existed gain the column, and
+    /// that dropped events add up when a bucket is written again.
+    #[test]
+    fn setup_database_adds_dropped_events_to_existing_input_buckets() -> anyhow::Result<()> {
+        let path = unique_temp_db("dropped-events");
+        let conn = open_con_at(&path)?;
+        conn.execute_batch(
+            "
+            CREATE TABLE input_buckets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source_id INTEGER NOT NULL,
+                bucket_start_utc TEXT NOT NULL,
+                bucket_end_utc TEXT NOT NULL,
+                local_date TEXT NOT NULL,
+                local_hour INTEGER NOT NULL,
+                timezone_offset_minutes INTEGER NOT NULL,
+                granularity_minutes INTEGER NOT NULL,
+                left_clicks INTEGER NOT NULL,
+                right_clicks INTEGER NOT NULL,
+                middle_clicks INTEGER NOT NULL,
+                key_presses INTEGER NOT NULL,
+                mouse_distance_cm REAL NOT NULL,
+                scroll_vertical_cm REAL NOT NULL,
+                scroll_horizontal_cm REAL NOT NULL,
+                UNIQUE(source_id, bucket_start_utc, granularity_minutes)
+            );
+            ",
+        )?;
+        setup_database(&conn)?;
+        setup_database(&conn)?;
+
+        let row = InputBucketRecord {
+            dropped_events: 3,
+            ..sample_input_row()
+        };
+        insert_input_buckets(&conn, &[row.clone(), row])?;
+        assert_eq!(
+            scalar_query_u64(&conn, "SELECT dropped_events FROM input_buckets")?,
+            6
+        );
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that rollups kept on write match a rebuild from the buckets when rows of
+    /// different granularities land in the same hour and day.
+    #[test]
+    fn rollups_on_write_match_rebuild_across_granularities() -> anyhow::Result<()> {
+        let path = unique_temp_db("rollups");
+        let conn = build_test_db(&path)?;
+        let one_minute = InputBucketRecord {
+            bucket_start_utc: Utc.with_ymd_and_hms(2026, 4, 18, 12, 40, 0).unwrap(),
+            bucket_end_utc: Utc.with_ymd_and_hms(2026, 4, 18, 12, 41, 0).unwrap(),
+            granularity_minutes: 1,
+            key_presses: 11,
+            ..sample_input_row()
+        };
+        insert_input_buckets(&conn, &[sample_input_row(), one_minute])?;
+        insert_focus_buckets(&conn, &[sample_focus_row(), sample_second_focus_row()])?;
+
+        let rollup_totals = |conn: &rusqlite::Connection| -> anyhow::Result<(u64, u64)> {
+            Ok((
+                scalar_query_u64(
+                    conn,
+                    "SELECT key_presses FROM input_rollups
+                     WHERE granularity_minutes = 60 AND bucket_start_utc = '2026-04-18T12:00:00+00:00'",
+                )?,
+                scalar_query_u64(
+                    conn,
+                    "SELECT focus_seconds FROM focus_rollups
+                     WHERE granularity_minutes = 1440 AND bucket_start_utc = '2026-04-18T00:00:00+00:00'",
+                )?,
+            ))
+        };
+        assert_eq!(rollup_totals(&conn)?, (16, 165));
+        rebuild_rollups(&conn)?;
+        assert_eq!(rollup_totals(&conn)?, (16, 165));
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that maintenance merges old buckets into hourly ones and drops old window
+    /// titles without changing totals, records what it did, and that a dry run changes nothing.
+    #[test]
+    fn run_maintenance_downsamples_old_buckets_and_keeps_totals() -> anyhow::Result<()> {
+        let path = unique_temp_db("maintenance");
+        let mut conn = build_test_db(&path)?;
+        insert_input_buckets(&conn, &[sample_input_row(), sample_second_input_row()])?;
+        insert_focus_buckets(&conn, &[sample_focus_row(), sample_second_focus_row()])?;
+        let policy = RetentionPolicy {
+            full_detail_days: 30,
+            hourly_days: 0,
+            title_days: 60,
+        };
+        let now = Utc.with_ymd_and_hms(2026, 7, 1, 0, 0, 0).unwrap();
+
+        let preview = run_maintenance(&mut conn, policy, now, true)?;
+        assert_eq!(preview.compactions.len(), 3);
+        assert_eq!(
+            scalar_query_u64(&conn, "SELECT COUNT(*) FROM input_buckets")?,
+            2
+        );
+        assert_eq!(
+            scalar_query_u64(&conn, "SELECT COUNT(*) FROM compactions")?,
+            0
+        );
+
+        run_maintenance(&mut conn, policy, now, false)?;
+        let hourly: (String, String, u32, u64) = conn.query_row(
+            "SELECT bucket_start_utc, bucket_end_utc, granularity_minutes, key_presses FROM input_buckets",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        assert_eq!(
+            hourly,
+            (
+                "2026-04-18T12:00:00+00:00".to_string(),
+                "2026-04-18T13:00:00+00:00".to_string(),
+                60,
+                12
+            )
+        );
+        let focus: (String, u64) = conn.query_row(
+            "SELECT window_title, focus_seconds FROM focus_buckets",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(focus, (String::new(), 165));
+        assert_eq!(
+            scalar_query_u64(
+                &conn,
+                "SELECT COUNT(*) FROM compactions WHERE action = 'drop_titles'"
+            )?,
+            1
+        );
+        assert!(run_maintenance(&mut conn, policy, now, false)?
+            .compactions
+            .is_empty());
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that daily compaction merges local days rather than UTC ones, so buckets keep
+    /// the `local_date` they were recorded on in a timezone east of UTC.
+    #[test]
+    fn run_maintenance_compacts_days_by_local_date() -> anyhow::Result<()> {
+        let path = unique_temp_db("maintenance-local-days");
+        let mut conn = build_test_db(&path)?;
+        let at = |day: u32, hour: u32, local_date: &str, local_hour: u32, key_presses: u64| {
+            InputBucketRecord {
+                bucket_start_utc: Utc.with_ymd_and_hms(2026, 4, day, hour, 0, 0).unwrap(),
+                bucket_end_utc: Utc.with_ymd_and_hms(2026, 4, day, hour, 15, 0).unwrap(),
+                local_date: local_date.to_string(),
+                local_hour,
+                timezone_offset_minutes: 120,
+                key_presses,
+                ..sample_input_row()
+            }
+        };
+        insert_input_buckets(
+            &conn,
+            &[
+                at(18, 20, "2026-04-18", 22, 1),
+                at(18, 23, "2026-04-19", 1, 2),
+                at(19, 10, "2026-04-19", 12, 4),
+            ],
+        )?;
+        let policy = RetentionPolicy {
+            full_detail_days: 0,
+            hourly_days: 30,
+            title_days: 0,
+        };
+        run_maintenance(
+            &mut conn,
+            policy,
+            Utc.with_ymd_and_hms(2026, 7, 1, 0, 0, 0).unwrap(),
+            false,
+        )?;
+
+        let mut statement = conn.prepare(
+            "SELECT bucket_start_utc, bucket_end_utc, local_date, key_presses
+             FROM input_buckets ORDER BY bucket_start_utc",
+        )?;
+        let days = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<rusqlite::Result<Vec<(String, String, String, u64)>>>()?;
+        assert_eq!(
+            days,
+            vec![
+                (
+                    "2026-04-17T22:00:00+00:00".to_string(),
+                    "2026-04-18T22:00:00+00:00".to_string(),
+                    "2026-04-18".to_string(),
+                    1
+                ),
+                (
+                    "2026-04-18T22:00:00+00:00".to_string(),
+                    "2026-04-19T22:00:00+00:00".to_string(),
+                    "2026-04-19".to_string(),
+                    6
+                ),
+            ]
+        );
+
+        drop(statement);
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that rekeying encrypts a plain database, moves it to another passphrase and
+    /// decrypts it again, keeping its rows and locking out every other passphrase on the way.
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn rekey_database_encrypts_changes_and_removes_the_passphrase() -> anyhow::Result<()> {
+        use super::rows::open_con_with_passphrase;
+
+        let path = unique_temp_db("rekey");
+        let conn = open_con_with_passphrase(&path, None)?;
+        setup_database(&conn)?;
+        insert_input_buckets(&conn, &[sample_input_row()])?;
+        drop(conn);
+        let key_presses = |passphrase: Option<&str>| -> anyhow::Result<u64> {
+            let conn = open_con_with_passphrase(&path, passphrase)?;
+            scalar_query_u64(&conn, "SELECT SUM(key_presses) FROM input_buckets")
+        };
+
+        let mut backups = vec![rekey_database(&path, None, Some("first"))?];
+        assert!(key_presses(None).is_err());
+        assert_eq!(key_presses(Some("first"))?, 5);
+
+        backups.push(rekey_database(&path, Some("first"), Some("second"))?);
+        assert!(key_presses(Some("first")).is_err());
+        assert_eq!(key_presses(Some("second"))?, 5);
+
+        backups.push(rekey_database(&path, Some("second"), None)?);
+        assert_eq!(key_presses(None)?, 5);
+
+        for backup in backups {
+            fs::remove_file(backup)?;
+        }
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that pooled readers see committed rows while a write transaction is open, cannot
+    /// write, and refuse databases that are missing or not yet upgraded.
+    #[test]
+    fn read_pool_reads_during_writes_and_refuses_unready_databases() -> anyhow::Result<()> {
+        let path = unique_temp_db("read-pool");
+        assert!(ReadPool::new(&path).get().is_err());
+
+        let conn = build_test_db(&path)?;
+        let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        assert_eq!(journal_mode, "wal");
+        insert_input_buckets(&conn, &[sample_input_row()])?;
+
+        let readers = ReadPool::new(&path);
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        insert_input_buckets(&conn, &[sample_second_input_row()])?;
+        {
+            let reader = readers.get()?;
+            assert_eq!(
+                scalar_query_u64(&reader, "SELECT COUNT(*) FROM input_buckets")?,
+                1
+            );
+            assert!(reader.execute("DELETE FROM input_buckets", []).is_err());
+        }
+        conn.execute_batch("COMMIT")?;
+        assert_eq!(
+            scalar_query_u64(&*readers.get()?, "SELECT COUNT(*) FROM input_buckets")?,
+            2
+        );
+
+        conn.execute(
+            "UPDATE schema_meta SET value = '3' WHERE key = 'schema_version'",
+            [],
+        )?;
+        let error = ReadPool::new(&path).get().err().unwrap();
+        assert!(error.to_string().contains("upgrade"));
+
+        drop(readers);
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that stats count rows per table and source, report the covered range, and count
+    /// unsent outbox rows of other sources as orphaned while leaving the own source's alone.
+    #[test]
+    fn database_stats_reports_rows_per_source_and_orphaned_outbox_rows() -> anyhow::Result<()> {
+        let path = unique_temp_db("db-stats");
+        let conn = build_test_db(&path)?;
+        insert_input_buckets(&conn, &[sample_input_row(), sample_second_input_row()])?;
+        let laptop = upsert_source_by_uuid(
+            &conn,
+            "laptop-uuid",
+            "laptop",
+            "linux",
+            "2026-04-01T00:00:00+00:00",
+        )?;
+        let mut laptop_focus = sample_focus_row();
+        laptop_focus.source_id = laptop;
+        insert_focus_buckets(&conn, &[laptop_focus])?;
+        let own_uuid = get_source(&conn, DEFAULT_SOURCE_ID)?.source_uuid;
+        for source_uuid in [own_uuid.as_str(), "laptop-uuid", "gone-uuid"] {
+            conn.execute(
+                "
+                INSERT INTO sync_outbox_sources (source_uuid, source_name, platform, created_at_utc)
+                VALUES (?1, 'name', 'linux', '2026-04-18T12:00:00+00:00')
+                ",
+                [source_uuid],
+            )?;
+        }
+
+        let stats = database_stats(&conn, &path)?;
+        let table_rows = |name: &str| {
+            stats
+                .tables
+                .iter()
+                .find(|(table, _)| table == name)
+                .map(|(_, rows)| *rows)
+        };
+        assert_eq!(table_rows("input_buckets"), Some(2));
+        assert_eq!(table_rows("focus_buckets"), Some(1));
+        assert_eq!(table_rows("sqlite_sequence"), None);
+        assert_eq!(stats.orphaned_outbox_rows, 2);
+        assert_eq!(stats.sources.len(), 2);
+        assert_eq!(stats.sources[0].bucket_rows, 2);
+        assert_eq!(stats.sources[1].source_name, "laptop");
+        assert_eq!(stats.sources[1].bucket_rows, 1);
+        assert_eq!(
+            stats.first_bucket_utc.as_deref(),
+            Some(sample_input_row().bucket_start_utc.to_rfc3339().as_str())
+        );
+        assert_eq!(
+            stats.last_bucket_utc.as_deref(),
+            Some(
+                sample_second_input_row()
+                    .bucket_end_utc
+                    .to_rfc3339()
+                    .as_str()
+            )
+        );
+        assert!(stats.file_size_bytes > 0);
+        assert!(check_database(&conn)?.is_healthy());
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that merging adds up the buckets both sources have and moves the rest, and that
+    /// deleting drops a source's rows while sources merged into it follow it into retirement.
+    #[test]
+    fn merge_sources_adds_up_shared_buckets_and_delete_retires_the_chain() -> anyhow::Result<()> {
+        let path = unique_temp_db("sources");
+        let conn = build_test_db(&path)?;
+        let old = upsert_source_by_uuid(
+            &conn,
+            "old-laptop-uuid",
+            "old-laptop",
+            "linux",
+            "2026-04-01T00:00:00+00:00",
+        )?;
+        let new = upsert_source_by_uuid(
+            &conn,
+            "new-laptop-uuid",
+            "laptop",
+            "linux",
+            "2026-04-10T00:00:00+00:00",
+        )?;
+        let mut old_input = sample_input_row();
+        old_input.source_id = old;
+        let mut new_input = sample_input_row();
+        new_input.source_id = new;
+        let mut old_focus = sample_focus_row();
+        old_focus.source_id = old;
+        insert_input_buckets(&conn, &[old_input.clone(), new_input.clone()])?;
+        insert_focus_buckets(&conn, &[old_focus])?;
+
+        assert_eq!(find_source(&conn, "laptop")?.id, new);
+        assert_eq!(find_source(&conn, "old-lap")?.id, old);
+        assert!(find_source(&conn, "nowhere").is_err());
+        let own_uuid = get_source(&conn, DEFAULT_SOURCE_ID)?.source_uuid;
+        assert!(merge_sources(&conn, &own_uuid, "new-laptop-uuid").is_err());
+
+        merge_sources(&conn, "old-laptop-uuid", "new-laptop-uuid")?;
+        let (input_rows, left_clicks): (u64, u64) = conn.query_row(
+            "SELECT COUNT(*), SUM(left_clicks) FROM input_buckets WHERE source_id = ?1",
+            [new],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(input_rows, 1);
+        assert_eq!(left_clicks, old_input.left_clicks + new_input.left_clicks);
+        let rollup_clicks: u64 = conn.query_row(
+            "SELECT left_clicks FROM input_rollups WHERE source_id = ?1 AND granularity_minutes = 60",
+            [new],
+            |row| row.get(0),
+        )?;
+        assert_eq!(rollup_clicks, left_clicks);
+        let focus_sources: Vec<i64> = conn
+            .prepare("SELECT source_id FROM focus_buckets")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(focus_sources, vec![new]);
+        assert!(get_source_by_uuid(&conn, "old-laptop-uuid")?.is_none());
+        assert_eq!(
+            retired_source(&conn, "old-laptop-uuid")?,
+            Some(RetiredSource::MergedInto("new-laptop-uuid".to_string()))
+        );
+        assert_eq!(list_sources(&conn)?.len(), 2);
+
+        assert!(delete_source(&conn, &own_uuid).is_err());
+        delete_source(&conn, "new-laptop-uuid")?;
+        let remaining: u64 = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM input_buckets WHERE source_id = ?1)
+                  + (SELECT COUNT(*) FROM focus_buckets WHERE source_id = ?1)
+                  + (SELECT COUNT(*) FROM input_rollups WHERE source_id = ?1)",
+            [new],
+            |row| row.get(0),
+        )?;
+        assert_eq!(remaining, 0);
+        assert_eq!(
+            retired_source(&conn, "new-laptop-uuid")?,
+            Some(RetiredSource::Deleted)
+        );
+        assert_eq!(
+            retired_source(&conn, "old-laptop-uuid")?,
+            Some(RetiredSource::Deleted)
+        );
+        assert_eq!(list_sources(&conn)?.len(), 1);
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that forgetting blanks matching titles while keeping their focus time, deletes
+    /// an app's rows and a time range's input, and scrubs the changes waiting to be pushed.
+    #[test]
+    fn forget_redacts_titles_and_deletes_apps_and_ranges() -> anyhow::Result<()> {
+        let path = unique_temp_db("forget");
+        let conn = build_test_db(&path)?;
+        let bank = FocusBucketRecord {
+            window_title: "Bank of Somewhere - Firefox".to_string(),
+            focus_seconds: 180,
+            ..sample_focus_row()
+        };
+        let editor = FocusBucketRecord {
+            app_identifier: "code".to_string(),
+            window_title: "main.rs".to_string(),
+            window_class: "code".to_string(),
+            ..sample_second_focus_row()
+        };
+        insert_input_buckets(&conn, &[sample_input_row(), sample_second_input_row()])?;
+        insert_focus_buckets(
+            &conn,
+            &[sample_focus_row(), sample_second_focus_row(), bank, editor],
+        )?;
+        let own_uuid = get_source(&conn, DEFAULT_SOURCE_ID)?.source_uuid;
+        conn.execute(
+            "
+            INSERT INTO sync_outbox_focus_buckets (
+                source_uuid, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+                timezone_offset_minutes, app_identifier, window_title, window_class,
+                focus_seconds, created_at_utc
+            ) VALUES (?1, '2026-04-18T12:00:00+00:00', '2026-04-18T12:15:00+00:00',
+                      '2026-04-18', 9, -180, 'firefox', 'Bank of Somewhere - Firefox',
+                      'firefox', 180, '2026-04-18T12:15:00+00:00')
+            ",
+            [&own_uuid],
+        )?;
+        let focus = |conn: &rusqlite::Connection| -> anyhow::Result<Vec<(String, u64)>> {
+            Ok(conn
+                .prepare(
+                    "SELECT window_title, focus_seconds FROM focus_buckets
+                     ORDER BY bucket_start_utc, window_title",
+                )?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()?)
+        };
+
+        assert!(forget(&conn, &ForgetFilter::default()).is_err());
+        let report = forget(
+            &conn,
+            &ForgetFilter {
+                title_pattern: Some("(?i)bank".to_string()),
+                ..ForgetFilter::default()
+            },
+        )?;
+        assert_eq!((report.redacted_titles, report.outbox_rows), (1, 1));
+        assert_eq!(
+            focus(&conn)?,
+            vec![
+                (String::new(), 180),
+                ("Docs".to_string(), 120),
+                ("Mail".to_string(), 45),
+                ("main.rs".to_string(), 45),
+            ]
+        );
+        let pending_title: String = conn.query_row(
+            "SELECT window_title FROM sync_outbox_focus_buckets",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(pending_title, "");
+
+        let report = forget(
+            &conn,
+            &ForgetFilter {
+                app_identifier: Some("code".to_string()),
+                ..ForgetFilter::default()
+            },
+        )?;
+        assert_eq!((report.focus_rows, report.input_rows), (1, 0));
+
+        let report = forget(
+            &conn,
+            &ForgetFilter {
+                from_utc: Some("2026-04-18T12:15:00+00:00".to_string()),
+                until_utc: Some("2026-04-18T12:30:00+00:00".to_string()),
+                ..ForgetFilter::default()
+            },
+        )?;
+        assert_eq!((report.focus_rows, report.input_rows), (1, 1));
+        assert_eq!(
+            focus(&conn)?,
+            vec![(String::new(), 180), ("Docs".to_string(), 120)]
+        );
+        let rollup_clicks: u64 = conn.query_row(
+            "SELECT left_clicks FROM input_rollups WHERE granularity_minutes = 60",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(rollup_clicks, sample_input_row().left_clicks);
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Verifies that table exports write the documented columns, keep only the rows the time
+    /// range and source select, and refuse to overwrite an earlier export.
+    #[test]
+    fn export_tables_writes_filtered_csv_and_json_lines() -> anyhow::Result<()> {
+        let path = unique_temp_db("table-export");
+        let dir = unique_temp_db("table-export-dir");
+        let conn = build_test_db(&path)?;
+        let other = upsert_source_by_uuid(
+            &conn,
+            "other-uuid",
+            "other",
+            "linux",
+            "2026-04-01T00:00:00+00:00",
+        )?;
+        let other_focus = FocusBucketRecord {
+            source_id: other,
+            ..sample_focus_row()
+        };
+        insert_input_buckets(&conn, &[sample_input_row(), sample_second_input_row()])?;
+        insert_focus_buckets(
+            &conn,
+            &[
+                sample_focus_row(),
+                sample_second_focus_row(),
+                FocusBucketRecord {
+                    window_title: "Say \"hi\", then leave".to_string(),
+                    ..sample_focus_row()
+                },
+                other_focus,
+            ],
+        )?;
+        conn.execute(
+            "
+            INSERT INTO sessions (source_id, started_at_utc, ended_at_utc, session_uuid, platform)
+            VALUES (?1, '2026-04-18T08:00:00+00:00', '2026-04-18T11:00:00+00:00', 'early', 'linux'),
+                   (?1, '2026-04-18T11:30:00+00:00', NULL, 'open', 'linux')
+            ",
+            [DEFAULT_SOURCE_ID],
+        )?;
+        let own_uuid = get_source(&conn, DEFAULT_SOURCE_ID)?.source_uuid;
+        let filter = TableExportFilter {
+            source_uuid: Some(own_uuid.clone()),
+            since_utc: Some("2026-04-18T12:00:00+00:00".to_string()),
+            until_utc: Some("2026-04-18T12:15:00+00:00".to_string()),
+        };
+
+        let exported = export_tables(&conn, &dir, ExportFormat::Csv, &ExportTable::ALL, &filter)?;
+        let rows: Vec<u64> = exported.iter().map(|table| table.rows).collect();
+        assert_eq!(rows, vec![1, 1, 1, 2]);
+        let focus_csv = fs::read_to_string(dir.join("focus_buckets.csv"))?;
+        let mut lines = focus_csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "source_uuid,bucket_start_utc,bucket_end_utc,local_date,local_hour,\
+                 timezone_offset_minutes,app_identifier,window_title,window_class,focus_seconds"
+            )
+        );
+        assert!(focus_csv.contains("\"Say \"\"hi\"\", then leave\""));
+        assert!(!focus_csv.contains("other-uuid"));
+        let sessions_csv = fs::read_to_string(dir.join("sessions.csv"))?;
+        assert!(sessions_csv.contains(&format!(
+            "open,{own_uuid},linux,2026-04-18T11:30:00+00:00,,\n"
+        )));
+        assert!(export_tables(&conn, &dir, ExportFormat::Csv, &ExportTable::ALL, &filter).is_err());
+
+        let exported = export_tables(
+            &conn,
+            &dir,
+            ExportFormat::JsonLines,
+            &[ExportTable::InputBuckets],
+            &TableExportFilter::default(),
+        )?;
+        assert_eq!(exported[0].rows, 2);
+        let input_jsonl = fs::read_to_string(dir.join("input_buckets.jsonl"))?;
+        let first: serde_json::Value =
+            serde_json::from_str(input_jsonl.lines().next().unwrap_or_default())?;
+        assert_eq!(first["key_presses"], 5);
+        assert_eq!(first["mouse_distance_cm"], 3.0);
+        assert_eq!(first["bucket_start_utc"], "2026-04-18T12:00:00+00:00");
+        assert_eq!(first.as_object().map(|row| row.len()), Some(15));
+
+        drop(conn);
+        fs::remove_file(path)?;
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    /// Verifies that a Parquet export reads back with the documented schema, NULLs included,
+    /// and one row per exported row.
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn export_tables_writes_parquet_with_the_documented_schema() -> anyhow::Result<()> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let path = unique_temp_db("parquet-export");
+        let dir = unique_temp_db("parquet-export-dir");
+        let conn = build_test_db(&path)?;
+        insert_focus_buckets(&conn, &[sample_focus_row(), sample_second_focus_row()])?;
+        conn.execute(
+            "
+            INSERT INTO sessions (source_id, started_at_utc, ended_at_utc, session_uuid, platform)
+            VALUES (?1, '2026-04-18T11:30:00+00:00', NULL, 'open', 'linux')
+            ",
+            [DEFAULT_SOURCE_ID],
+        )?;
+
+        export_tables(
+            &conn,
+            &dir,
+            ExportFormat::Parquet,
+            &[ExportTable::Sessions, ExportTable::FocusBuckets],
+            &TableExportFilter::default(),
+        )?;
+        let focus = SerializedFileReader::new(fs::File::open(dir.join("focus_buckets.parquet"))?)?;
+        let schema = focus.metadata().file_metadata().schema_descr();
+        let names: Vec<&str> = schema
+            .columns()
+            .iter()
+            .map(|column| column.name())
+            .collect();
+        assert_eq!(names[0], "source_uuid");
+        assert_eq!(names.len(), 10);
+        assert_eq!(focus.metadata().file_metadata().num_rows(), 2);
+        let sessions = SerializedFileReader::new(fs::File::open(dir.join("sessions.parquet"))?)?;
+        let row = sessions.get_row_iter(None)?.next().transpose()?;
+        assert!(row.is_some_and(|row| row.to_string().contains("ended_at_utc: null")));
+
+        drop(conn);
+        fs::remove_file(path)?;
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    /// Verifies that repair copies the rows around a damaged table page into a fresh database
+    /// that passes the checks, losing only the rows on that page, while the check flags the
+    /// damaged original.
+    #[test]
+    fn recover_database_skips_a_damaged_page_and_keeps_the_rest() -> anyhow::Result<()> {
+        let path = unique_temp_db("db-repair");
+        let recovered_path = unique_temp_db("db-repaired");
+        let total_rows = 2000;
+        let (page, page_size, rows_on_page) = {
+            let conn = build_test_db(&path)?;
+            insert_input_buckets(&conn, &[sample_input_row()])?;
+            let rows: Vec<_> = (0..total_rows)
+                .map(|index| {
+                    let mut row = sample_focus_row();
+                    row.window_title = format!("{index:05} {}", "x".repeat(200));
+                    row
+                })
+                .collect();
+            insert_focus_buckets(&conn, &rows)?;
+            let leaves = scalar_query_u64(
+                &conn,
+                "SELECT COUNT(*) FROM dbstat WHERE name = 'focus_buckets' AND pagetype = 'leaf'",
+            )?;
+            let page: u64 = conn.query_row(
+                "
+                SELECT pageno FROM dbstat
+                WHERE name = 'focus_buckets' AND pagetype = 'leaf'
+                ORDER BY pageno LIMIT 1 OFFSET ?1
+                ",
+                [leaves / 2],
+                |row| row.get(0),
+            )?;
+            let rows_on_page: u64 = conn.query_row(
+                "SELECT ncell FROM dbstat WHERE pageno = ?1",
+                [page],
+                |row| row.get(0),
+            )?;
+            (page, pragma_u64(&conn, "page_size")?, rows_on_page)
+        };
+        // Closing the last connection folds the write-ahead log into the file.
+        let mut bytes = fs::read(&path)?;
+        let start = ((page - 1) * page_size) as usize;
+        bytes[start..start + page_size as usize].fill(0xA5);
+        fs::write(&path, &bytes)?;
+
+        assert!(!check_database(&open_for_inspection(&path)?)?.is_healthy());
+        assert_eq!(fs::read(&path)?, bytes);
+
+        let report = recover_database(&path, Some(&recovered_path))?;
+        let focus = report
+            .tables
+            .iter()
+            .find(|table| table.name == "focus_buckets")
+            .unwrap();
+        assert_eq!(focus.unreadable_ranges, 1);
+        assert_eq!(focus.rows, total_rows - rows_on_page);
+
+        let recovered = open_con_at(&recovered_path)?;
+        assert!(check_database(&recovered)?.is_healthy());
+        assert_eq!(
+            scalar_query_u64(&recovered, "SELECT COUNT(*) FROM input_buckets")?,
+            1
+        );
+        assert_eq!(
+            scalar_query_u64(&recovered, "SELECT COUNT(*) FROM focus_buckets")?,
+            focus.rows
+        );
+        assert_eq!(
+            scalar_query_u64(
+                &recovered,
+                "SELECT SUM(focus_seconds) FROM focus_rollups WHERE granularity_minutes = 60"
+            )?,
+            focus.rows * sample_focus_row().focus_seconds
+        );
+
+        drop(recovered);
+        fs::remove_file(path)?;
+        fs::remove_file(recovered_path)?;
+        Ok(())
+    }
+
     /// Verifies that snapshot export writes both copied data and export metadata by exporting
-    /// a seeded database and asserting on the snapshot's bucket rows and export record.
+    /// a seeded database and asserting on the snapshot's bucket rows and export record, and
+    /// that the snapshot is left in the rollback journal mode rather than the source's WAL.
     #[test]
     fn export_database_creates_snapshot_with_export_metadata() -> anyhow::Result<()> {
         let source_path = unique_temp_db("export-source");
@@ -259,6 +1132,8 @@ mod tests {
         insert_input_buckets(&conn, &[sample_input_row()])?;
 
         let export = export_database(&source_path, &export_path)?;
+        // Byte 18 of the header is 1 for the rollback journal and 2 for WAL.
+        assert_eq!(fs::read(&export_path)?[18], 1);
         let snapshot = open_con_at(&export_path)?;
         let metadata = latest_export_metadata(&snapshot)?;
 
@@ -380,6 +1255,46 @@ mod tests {
         Ok(())
     }
 
+    /// Verifies that a snapshot exported at an older schema version is upgraded before it is
+    /// merged, leaving the snapshot file itself as it was.
+    #[test]
+    fn import_snapshot_upgrades_older_snapshot() -> anyhow::Result<()> {
+        let destination_path = unique_temp_db("upgrade-dest");
+        let source_path = unique_temp_db("upgrade-source");
+        let export_path = unique_temp_db("upgrade-export");
+
+        build_test_db(&destination_path)?;
+        let source = build_test_db(&source_path)?;
+        insert_input_buckets(&source, &[sample_input_row()])?;
+        export_database(&source_path, &export_path)?;
+        let export_conn = open_con_at(&export_path)?;
+        export_conn.execute_batch(
+            "
+            DROP TABLE input_rollups;
+            DROP TABLE focus_rollups;
+            DROP TABLE compactions;
+            UPDATE schema_meta SET value = '3' WHERE key = 'schema_version';
+            ",
+        )?;
+        drop(export_conn);
+
+        let result = import_snapshot(&destination_path, &export_path, None)?;
+        let merged = open_con_at(&destination_path)?;
+        assert_eq!(
+            scalar_query_u64(&merged, "SELECT SUM(key_presses) FROM input_buckets")?,
+            5
+        );
+        assert_eq!(stored_schema_version(&open_con_at(&export_path)?)?, Some(3));
+
+        drop(merged);
+        drop(source);
+        fs::remove_file(destination_path)?;
+        fs::remove_file(source_path)?;
+        fs::remove_file(export_path)?;
+        fs::remove_file(result.destination_backup_path)?;
+        Ok(())
+    }
+
     /// Verifies that rows from a different source UUID stay separate after import by importing
     /// the same bucket shape from another source and asserting the destination keeps two rows.
     #[test]
@@ -586,7 +1501,7 @@ mod tests {
         let conn = build_test_db(&path)?;
 
         let first = begin_session(&conn, DEFAULT_SOURCE_ID, "linux")?;
-        end_session(&conn, &first)?;
+        end_session(&conn, &first, Utc::now(), "sigterm")?;
         let second = begin_session(&conn, DEFAULT_SOURCE_ID, "linux")?;
 
         let count: u64 = conn.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))?;
@@ -805,31 +1720,30 @@ mod tests {
         Ok(())
     }
 
-    /// Verifies that end_session does not overwrite ended_at_utc when a session is already
-    /// closed, which is the COALESCE guard that prevents double-closing a session.
+    /// Verifies that end_session does not overwrite ended_at_utc or end_reason when a session
+    /// is already closed, which is the COALESCE guard that prevents double-closing a session.
     #[test]
     fn end_session_does_not_update_already_ended_session() -> anyhow::Result<()> {
         let path = unique_temp_db("end-session-idempotent");
         let conn = build_test_db(&path)?;
 
         let uuid = begin_session(&conn, DEFAULT_SOURCE_ID, "linux")?;
-        end_session(&conn, &uuid)?;
-
-        let first_end: String = conn.query_row(
-            "SELECT ended_at_utc FROM sessions WHERE session_uuid = ?1",
-            [&uuid],
-            |row| row.get(0),
-        )?;
-
-        // Calling end_session again must not change the recorded end time.
-        end_session(&conn, &uuid)?;
-        let second_end: String = conn.query_row(
-            "SELECT ended_at_utc FROM sessions WHERE session_uuid = ?1",
-            [&uuid],
-            |row| row.get(0),
-        )?;
+        let stopped_at = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        end_session(&conn, &uuid, stopped_at, "sigterm")?;
+
+        let ended = |conn: &rusqlite::Connection| {
+            conn.query_row(
+                "SELECT ended_at_utc, end_reason FROM sessions WHERE session_uuid = ?1",
+                [&uuid],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+        };
+        let first_end = ended(&conn)?;
+        assert_eq!(first_end, (stopped_at.to_rfc3339(), "sigterm".to_string()));
 
-        assert_eq!(first_end, second_end);
+        // Calling end_session again, as dropping the backend does, must not change the record.
+        end_session(&conn, &uuid, Utc::now(), "exit")?;
+        assert_eq!(ended(&conn)?, first_end);
 
         drop(conn);
         fs::remove_file(path)?;
@@ -861,6 +1775,60 @@ mod tests {
         Ok(())
     }
 
+    /// Verifies that project rows merge on upsert without losing a known root, and that the
+    /// report ranks projects overall but lists days when filtered to one project.
+    #[test]
+    fn project_report_ranks_projects_and_filters_by_name() -> anyhow::Result<()> {
+        let path = unique_temp_db("project-report");
+        let conn = build_test_db(&path)?;
+        let now = Utc::now();
+        let row = |project: &str, root: Option<&str>, days_ago: i64, focus_seconds: u64| {
+            let bucket_start_utc = now - Duration::days(days_ago);
+            crate::common::ProjectBucketRecord {
+                source_id: DEFAULT_SOURCE_ID,
+                bucket_start_utc,
+                bucket_end_utc: bucket_start_utc + Duration::minutes(15),
+                local_date: bucket_start_utc.format("%Y-%m-%d").to_string(),
+                local_hour: 9,
+                timezone_offset_minutes: 0,
+                project: project.to_string(),
+                project_root: root.map(str::to_string),
+                app_identifier: "code".to_string(),
+                focus_seconds,
+            }
+        };
+
+        insert_project_buckets(
+            &conn,
+            &[
+                row("vigil", Some("~/work/vigil"), 0, 3600),
+                row("notes", None, 1, 600),
+                row("vigil", None, 2, 1800),
+                row("vigil", None, 40, 9000),
+            ],
+        )?;
+        // A second flush for the same bucket adds time but must not forget the root.
+        insert_project_buckets(&conn, &[row("vigil", None, 0, 60)])?;
+
+        let rows = project_report(&conn, 7, None)?;
+        let rendered = render_project_report(&rows, None);
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("vigil"));
+        assert!(lines[0].contains("1h 31m"));
+        assert!(lines[0].ends_with("~/work/vigil"));
+        assert!(lines[1].starts_with("notes"));
+        assert_eq!(lines[2], "total 1h 41m");
+
+        let rows = project_report(&conn, 0, Some("VIGIL"))?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].focus_seconds, 3660);
+        assert!(render_project_report(&rows, Some("VIGIL")).starts_with("vigil (~/work/vigil)"));
+
+        drop(conn);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
     /// Verifies that file_sha256 produces the same hash when called twice on the same file,
     /// since the hash is used as an import dedup key and must be deterministic.
     #[test]
@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rusqlite::{params, Connection};
 use std::collections::BTreeMap;
 use uuid::Uuid;
@@ -20,12 +20,21 @@ pub struct DailyActivityRow {
     pub focus_seconds: u64,
 }
 
+/// Focus seconds one project received on one local day, summed across sources and apps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectDayRow {
+    pub local_date: String,
+    pub project: String,
+    pub project_root: Option<String>,
+    pub focus_seconds: u64,
+}
+
 pub fn begin_session(conn: &Connection, source_id: i64, platform: &str) -> Result<String> {
     let now = Utc::now().to_rfc3339();
     conn.execute(
         "
         UPDATE sessions
-        SET ended_at_utc = ?1
+        SET ended_at_utc = ?1, end_reason = 'unclean'
         WHERE source_id = ?2 AND ended_at_utc IS NULL
         ",
         params![now, source_id],
@@ -45,14 +54,22 @@ pub fn begin_session(conn: &Connection, source_id: i64, platform: &str) -> Resul
     Ok(session_uuid)
 }
 
-pub fn end_session(conn: &Connection, session_uuid: &str) -> Result<()> {
+/// Closes the session as of `ended_at`, recording why the collector stopped. A session that
+/// is already closed keeps its end time and reason.
+pub fn end_session(
+    conn: &Connection,
+    session_uuid: &str,
+    ended_at: DateTime<Utc>,
+    reason: &str,
+) -> Result<()> {
     conn.execute(
         "
         UPDATE sessions
-        SET ended_at_utc = COALESCE(ended_at_utc, ?1)
-        WHERE session_uuid = ?2
+        SET ended_at_utc = COALESCE(ended_at_utc, ?1),
+            end_reason = COALESCE(end_reason, ?2)
+        WHERE session_uuid = ?3
         ",
-        params![Utc::now().to_rfc3339(), session_uuid],
+        params![ended_at.to_rfc3339(), reason, session_uuid],
     )
     .with_context(|| format!("Failed to finalize session {session_uuid}"))?;
     Ok(())
@@ -146,3 +163,101 @@ pub fn daily_activity_report(conn: &Connection, days: u32) -> Result<Vec<DailyAc
     });
     Ok(rows)
 }
+
+/// Loads per-day project totals for the last `days` days (all history when 0), optionally
+/// limited to one project matched case-insensitively. Rows are ordered by date, newest first.
+pub fn project_report(
+    conn: &Connection,
+    days: u32,
+    project: Option<&str>,
+) -> Result<Vec<ProjectDayRow>> {
+    let since = (days > 0).then(|| (Utc::now() - Duration::days(days as i64)).to_rfc3339());
+    let mut stmt = conn.prepare(
+        "
+        SELECT local_date, project, MAX(project_root), SUM(focus_seconds)
+        FROM project_buckets
+        WHERE (?1 IS NULL OR bucket_start_utc >= ?1)
+          AND (?2 IS NULL OR project = ?2 COLLATE NOCASE)
+        GROUP BY local_date, project
+        ORDER BY local_date DESC, SUM(focus_seconds) DESC, project ASC
+        ",
+    )?;
+    let rows = stmt.query_map(params![since, project], |row| {
+        Ok(ProjectDayRow {
+            local_date: row.get(0)?,
+            project: row.get(1)?,
+            project_root: row.get(2)?,
+            focus_seconds: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+        })
+    })?;
+
+    rows.collect::<rusqlite::Result<Vec<_>>>()
+        .with_context(|| "Failed to load project report")
+}
+
+/// Renders a project report as text: totals per project, or per-day totals when the report
+/// was filtered to a single project.
+pub fn render_project_report(rows: &[ProjectDayRow], project: Option<&str>) -> String {
+    if rows.is_empty() {
+        return match project {
+            Some(project) => format!("no focus time recorded for project '{project}'"),
+            None => "no project activity recorded".to_string(),
+        };
+    }
+
+    let total = rows.iter().map(|row| row.focus_seconds).sum::<u64>();
+    let mut lines = Vec::new();
+    if project.is_some() {
+        let name = &rows[0].project;
+        match rows.iter().find_map(|row| row.project_root.as_deref()) {
+            Some(root) => lines.push(format!("{name} ({root})")),
+            None => lines.push(name.clone()),
+        }
+        for row in rows {
+            lines.push(format!(
+                "  {}  {:>8}",
+                row.local_date,
+                format_focus_time(row.focus_seconds)
+            ));
+        }
+    } else {
+        let mut totals = BTreeMap::<&str, (u64, usize, Option<&str>)>::new();
+        for row in rows {
+            let entry = totals.entry(row.project.as_str()).or_default();
+            entry.0 += row.focus_seconds;
+            entry.1 += 1;
+            entry.2 = entry.2.or(row.project_root.as_deref());
+        }
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort_by(|left, right| right.1 .0.cmp(&left.1 .0).then(left.0.cmp(right.0)));
+
+        let width = totals
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (name, (focus_seconds, active_days, root)) in totals {
+            let mut line = format!(
+                "{name:<width$}  {:>8}  {:>3}%  {active_days:>3}d",
+                format_focus_time(focus_seconds),
+                (focus_seconds * 100 + total / 2) / total.max(1),
+            );
+            if let Some(root) = root {
+                line.push_str(&format!("  {root}"));
+            }
+            lines.push(line);
+        }
+    }
+    lines.push(format!("total {}", format_focus_time(total)));
+    lines.join("\n")
+}
+
+fn format_focus_time(seconds: u64) -> String {
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::common::program_data_dir;
 
@@ -35,12 +35,20 @@ impl DbConfig {
         }
 
         if let Some(path) = load_remembered_db_path()? {
-            let db_path = resolve_db_path(Some(path.as_path())).with_context(|| {
-                format_remembered_path_error(
-                    &path,
-                    "the remembered database path could not be prepared",
-                )
-            })?;
+            let db_path = match resolve_db_path(Some(path.as_path())) {
+                Ok(db_path) => db_path,
+                // The share may only be unmounted right now; opening the database reports it.
+                Err(err) => {
+                    warn!(
+                        "{}\nCause: {err:#}",
+                        format_remembered_path_error(
+                            &path,
+                            "the remembered database path could not be prepared",
+                        )
+                    );
+                    path
+                }
+            };
             info!("Using remembered database path: {}", db_path.display());
             return Ok(Self {
                 db_path,
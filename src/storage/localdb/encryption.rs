@@ -0,0 +1,174 @@
+//! Encryption at rest with SQLCipher.
+//!
+//! Vigil built with the `encryption` feature links SQLCipher instead of plain SQLite. Every
+//! connection opened through [`super::open_con_at`] is keyed with the passphrase from
+//! `VIGIL_DB_PASSPHRASE`, or from the file `VIGIL_DB_PASSPHRASE_FILE` names, so the whole file
+//! is encrypted: the collector's database, the offline fallback, the dashboard's readers, and the
+//! snapshots `--export-db` writes. Databases attached to a keyed connection use the same key.
+//!
+//! Without a passphrase the database stays plain SQLite. `vigil db rekey` moves a database
+//! between passphrases, or from plain to encrypted and back, by exporting it into a new file.
+use anyhow::{bail, Context, Result};
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::integrity::{backup_path_next_to, run_integrity_check};
+use super::rows::open_con_with_passphrase;
+
+pub const PASSPHRASE_ENV: &str = "VIGIL_DB_PASSPHRASE";
+pub const PASSPHRASE_FILE_ENV: &str = "VIGIL_DB_PASSPHRASE_FILE";
+
+/// The passphrase databases are opened with, if one is configured.
+pub fn database_passphrase() -> Result<Option<String>> {
+    if let Some(passphrase) = std::env::var_os(PASSPHRASE_ENV) {
+        let passphrase = passphrase
+            .into_string()
+            .map_err(|_| anyhow::anyhow!("{PASSPHRASE_ENV} is not valid UTF-8"))?;
+        if passphrase.is_empty() {
+            bail!("{PASSPHRASE_ENV} is set but empty");
+        }
+        return Ok(Some(passphrase));
+    }
+    match std::env::var_os(PASSPHRASE_FILE_ENV) {
+        Some(path) => read_passphrase_file(Path::new(&path)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Reads a passphrase from the first line of `path`.
+pub fn read_passphrase_file(path: &Path) -> Result<String> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read passphrase file {}", path.display()))?;
+    let passphrase = contents.lines().next().unwrap_or_default();
+    if passphrase.is_empty() {
+        bail!("Passphrase file {} is empty", path.display());
+    }
+    Ok(passphrase.to_string())
+}
+
+/// Keys `conn` with `passphrase`. Must run before anything reads the database.
+pub(crate) fn apply_passphrase(conn: &Connection, passphrase: Option<&str>) -> Result<()> {
+    let Some(passphrase) = passphrase else {
+        return Ok(());
+    };
+    #[cfg(feature = "encryption")]
+    {
+        conn.pragma_update(None, "key", passphrase)
+            .with_context(|| "Failed to set the database passphrase")
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = (conn, passphrase);
+        bail!("A database passphrase is set, but this vigil was built without the `encryption` feature")
+    }
+}
+
+/// Fails with a hint about the passphrase when `conn` cannot read its database.
+pub(crate) fn ensure_readable(conn: &Connection, db_path: &Path) -> Result<()> {
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|_| ())
+    .with_context(|| {
+        format!(
+            "Failed to read the database at {}. If it is encrypted, check {PASSPHRASE_ENV} or {PASSPHRASE_FILE_ENV}",
+            db_path.display()
+        )
+    })
+}
+
+/// Attaches `path` as `alias`, keyed with the configured passphrase like every other database.
+pub(crate) fn attach_database(conn: &Connection, path: &Path, alias: &str) -> Result<()> {
+    let path = path.to_string_lossy().to_string();
+    match database_passphrase()? {
+        Some(passphrase) => conn.execute(
+            &format!("ATTACH DATABASE ?1 AS {alias} KEY ?2"),
+            [path, passphrase],
+        )?,
+        None => conn.execute(&format!("ATTACH DATABASE ?1 AS {alias}"), [path])?,
+    };
+    Ok(())
+}
+
+/// Rewrites the database at `db_path`, readable with `current`, so it is encrypted with `new`
+/// instead, or not at all when `new` is `None`. The original file is kept next to it, still
+/// readable with `current`, and its path returned.
+///
+/// No other connection may have the database open while this runs.
+pub fn rekey_database(db_path: &Path, current: Option<&str>, new: Option<&str>) -> Result<PathBuf> {
+    if !db_path.exists() {
+        bail!("No database at {}", db_path.display());
+    }
+    // Opening checks the current passphrase and folds the write-ahead log back into the file.
+    drop(open_con_with_passphrase(db_path, current)?);
+
+    let rekeyed_path = db_path.with_extension("rekey.sqlite");
+    if rekeyed_path.exists() {
+        fs::remove_file(&rekeyed_path).with_context(|| {
+            format!("Failed to remove leftover file {}", rekeyed_path.display())
+        })?;
+    }
+    export_rekeyed_copy(db_path, current, &rekeyed_path, new)?;
+    {
+        let rekeyed = open_con_with_passphrase(&rekeyed_path, new)?;
+        run_integrity_check(&rekeyed, "rekeyed database")?;
+    }
+
+    let backup_path = backup_path_next_to(db_path, "pre-rekey");
+    fs::rename(db_path, &backup_path).with_context(|| {
+        format!(
+            "Failed to move {} aside to {}",
+            db_path.display(),
+            backup_path.display()
+        )
+    })?;
+    fs::rename(&rekeyed_path, db_path).with_context(|| {
+        format!(
+            "Failed to move the rekeyed database into place; the original is at {}",
+            backup_path.display()
+        )
+    })?;
+    Ok(backup_path)
+}
+
+/// Copies every table of `source` into a new file at `destination` with SQLCipher's
+/// `sqlcipher_export`, which, unlike the backup API, can change the key on the way.
+#[cfg(feature = "encryption")]
+pub(crate) fn export_rekeyed_copy(
+    source: &Path,
+    source_passphrase: Option<&str>,
+    destination: &Path,
+    destination_passphrase: Option<&str>,
+) -> Result<()> {
+    let conn = Connection::open(destination)
+        .with_context(|| format!("Failed to create rekeyed copy at {}", destination.display()))?;
+    apply_passphrase(&conn, destination_passphrase)?;
+    // An empty key attaches the source as plain SQLite instead of with the main key.
+    conn.execute(
+        "ATTACH DATABASE ?1 AS rekey_source KEY ?2",
+        [
+            source.to_string_lossy().to_string(),
+            source_passphrase.unwrap_or_default().to_string(),
+        ],
+    )
+    .with_context(|| format!("Failed to attach {}", source.display()))?;
+    conn.query_row(
+        "SELECT sqlcipher_export('main', 'rekey_source')",
+        [],
+        |_| Ok(()),
+    )
+    .with_context(|| format!("Failed to export {} with the new key", source.display()))?;
+    conn.execute_batch("DETACH DATABASE rekey_source")?;
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn export_rekeyed_copy(
+    _source: &Path,
+    _source_passphrase: Option<&str>,
+    _destination: &Path,
+    _destination_passphrase: Option<&str>,
+) -> Result<()> {
+    bail!("Changing the database passphrase needs a vigil built with the `encryption` feature")
+}
@@ -10,6 +10,7 @@ use crate::common::DEFAULT_SOURCE_ID;
 use crate::utils::lock::acquire_db_operation_lock;
 
 use super::integrity::{backup_database_contents, run_integrity_check};
+use super::journal::use_rollback_journal;
 use super::rows::{get_source, open_con_at};
 use super::schema::{setup_database, SCHEMA_VERSION};
 
@@ -71,6 +72,7 @@ pub fn export_database(source_db_path: &Path, export_path: &Path) -> Result<Expo
             SCHEMA_VERSION,
         ],
     )?;
+    use_rollback_journal(&snapshot, export_path)?;
 
     Ok(ExportResult {
         export_path: export_path.to_path_buf(),
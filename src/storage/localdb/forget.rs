@@ -0,0 +1,394 @@
+//! `vigil forget`: removing activity that should never have been recorded.
+//!
+//! Rows of an app and whole time ranges are deleted. Window titles matching a pattern are
+//! blanked instead, keeping the focus time per app the way maintenance drops old titles.
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use rusqlite::{params, Connection, Row};
+
+use super::retention::BUCKET_TABLES;
+use super::rollups::rebuild_rollups;
+
+/// Which rows to forget. Every field that is set narrows the selection. Times are UTC RFC 3339
+/// and select the buckets starting in `[from_utc, until_utc)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForgetFilter {
+    pub source_uuid: Option<String>,
+    pub app_identifier: Option<String>,
+    pub title_pattern: Option<String>,
+    pub from_utc: Option<String>,
+    pub until_utc: Option<String>,
+}
+
+impl ForgetFilter {
+    pub fn matcher(&self) -> Result<ForgetMatcher> {
+        if self.app_identifier.is_none()
+            && self.title_pattern.is_none()
+            && self.from_utc.is_none()
+            && self.until_utc.is_none()
+        {
+            bail!("Say what to forget: an app, a title pattern or a time range");
+        }
+        let title = self
+            .title_pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(|| "Invalid title pattern")?;
+        Ok(ForgetMatcher {
+            filter: self.clone(),
+            title,
+        })
+    }
+
+    /// Without an app, matching titles are blanked and their focus time is kept.
+    pub fn redacts_titles(&self) -> bool {
+        self.app_identifier.is_none() && self.title_pattern.is_some()
+    }
+
+    /// Input buckets have no app or title, so only a bare time range reaches them.
+    pub fn covers_input(&self) -> bool {
+        self.app_identifier.is_none() && self.title_pattern.is_none()
+    }
+}
+
+/// A [`ForgetFilter`] with its title pattern compiled, for testing rows one by one.
+pub struct ForgetMatcher {
+    filter: ForgetFilter,
+    title: Option<Regex>,
+}
+
+impl ForgetMatcher {
+    pub fn matches_input(&self, source_uuid: &str, bucket_start_utc: &str) -> bool {
+        self.filter.covers_input() && self.in_scope(source_uuid, bucket_start_utc)
+    }
+
+    /// Blank titles are left alone when titles are only redacted.
+    pub fn matches_focus(
+        &self,
+        source_uuid: &str,
+        bucket_start_utc: &str,
+        app_identifier: &str,
+        window_title: &str,
+    ) -> bool {
+        !(self.filter.redacts_titles() && window_title.is_empty())
+            && self.matches_texts(
+                source_uuid,
+                bucket_start_utc,
+                app_identifier,
+                &[window_title],
+            )
+    }
+
+    fn matches_texts(
+        &self,
+        source_uuid: &str,
+        bucket_start_utc: &str,
+        app_identifier: &str,
+        texts: &[&str],
+    ) -> bool {
+        self.in_scope(source_uuid, bucket_start_utc)
+            && self
+                .filter
+                .app_identifier
+                .as_deref()
+                .is_none_or(|app| app == app_identifier)
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|title| texts.iter().any(|text| title.is_match(text)))
+    }
+
+    fn in_scope(&self, source_uuid: &str, bucket_start_utc: &str) -> bool {
+        self.filter
+            .source_uuid
+            .as_deref()
+            .is_none_or(|uuid| uuid == source_uuid)
+            && self
+                .filter
+                .from_utc
+                .as_deref()
+                .is_none_or(|from| bucket_start_utc >= from)
+            && self
+                .filter
+                .until_utc
+                .as_deref()
+                .is_none_or(|until| bucket_start_utc < until)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForgetReport {
+    pub input_rows: u64,
+    pub focus_rows: u64,
+    pub redacted_titles: u64,
+    /// Browser domain, terminal and project rows.
+    pub context_rows: u64,
+    /// Changes that were waiting to be pushed.
+    pub outbox_rows: u64,
+}
+
+impl ForgetReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn render(&self) -> String {
+        if self.is_empty() {
+            return "nothing matched".to_string();
+        }
+        [
+            (self.input_rows, "input buckets deleted"),
+            (self.focus_rows, "focus buckets deleted"),
+            (self.redacted_titles, "window titles blanked"),
+            (
+                self.context_rows,
+                "domain, terminal and project buckets deleted",
+            ),
+            (self.outbox_rows, "unsent sync changes dropped"),
+        ]
+        .iter()
+        .filter(|(rows, _)| *rows > 0)
+        .map(|(rows, what)| format!("  {rows:>8}  {what}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
+
+/// Deletes or redacts the rows `filter` selects in every source, and the matching changes
+/// still waiting in the sync outbox. Run it in a transaction.
+pub fn forget(conn: &Connection, filter: &ForgetFilter) -> Result<ForgetReport> {
+    let matcher = filter.matcher()?;
+    let mut report = ForgetReport::default();
+
+    if filter.covers_input() {
+        let ids = matching_ids(
+            conn,
+            "SELECT t.id, s.source_uuid, t.bucket_start_utc FROM input_buckets t",
+            filter,
+            |row| Ok(matcher.matches_input(&row.get::<_, String>(1)?, &row.get::<_, String>(2)?)),
+        )?;
+        report.input_rows = delete_ids(conn, "input_buckets", &ids)?;
+    }
+
+    let ids = matching_ids(
+        conn,
+        "
+        SELECT t.id, s.source_uuid, t.bucket_start_utc, t.app_identifier, t.window_title
+        FROM focus_buckets t
+        ",
+        filter,
+        |row| {
+            Ok(matcher.matches_focus(
+                &row.get::<_, String>(1)?,
+                &row.get::<_, String>(2)?,
+                &row.get::<_, String>(3)?,
+                &row.get::<_, String>(4)?,
+            ))
+        },
+    )?;
+    if filter.redacts_titles() {
+        report.redacted_titles = redact_focus_titles(conn, &ids)?;
+    } else {
+        report.focus_rows = delete_ids(conn, "focus_buckets", &ids)?;
+    }
+
+    for table in BUCKET_TABLES
+        .iter()
+        .filter(|table| !matches!(table.name, "input_buckets" | "focus_buckets"))
+    {
+        let texts: Vec<&str> = table
+            .keys
+            .iter()
+            .chain(table.carried)
+            .copied()
+            .filter(|column| *column != "app_identifier")
+            .collect();
+        let ids = matching_ids(
+            conn,
+            &format!(
+                "SELECT t.id, s.source_uuid, t.bucket_start_utc, t.app_identifier, {} FROM {} t",
+                texts
+                    .iter()
+                    .map(|column| format!("COALESCE(t.{column}, '')"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                table.name
+            ),
+            filter,
+            |row| {
+                let values = (0..texts.len())
+                    .map(|index| row.get::<_, String>(index + 4))
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(matcher.matches_texts(
+                    &row.get::<_, String>(1)?,
+                    &row.get::<_, String>(2)?,
+                    &row.get::<_, String>(3)?,
+                    &values.iter().map(String::as_str).collect::<Vec<_>>(),
+                ))
+            },
+        )?;
+        report.context_rows += delete_ids(conn, table.name, &ids)?;
+    }
+
+    report.outbox_rows = forget_pending_changes(conn, &matcher)?;
+    if report.input_rows + report.focus_rows + report.redacted_titles > 0 {
+        rebuild_rollups(conn)?;
+    }
+    Ok(report)
+}
+
+/// Ids of the rows `query` returns that `keep` accepts. The query selects from a bucket table
+/// aliased `t`; the source and time range are narrowed in SQL first.
+fn matching_ids(
+    conn: &Connection,
+    query: &str,
+    filter: &ForgetFilter,
+    keep: impl Fn(&Row) -> rusqlite::Result<bool>,
+) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(&format!(
+        "
+        {query}
+        JOIN sources s ON s.id = t.source_id
+        WHERE (?1 IS NULL OR s.source_uuid = ?1)
+          AND (?2 IS NULL OR t.bucket_start_utc >= ?2)
+          AND (?3 IS NULL OR t.bucket_start_utc < ?3)
+        "
+    ))?;
+    let mut ids = Vec::new();
+    let mut rows = stmt.query(params![
+        filter.source_uuid,
+        filter.from_utc,
+        filter.until_utc
+    ])?;
+    while let Some(row) = rows.next()? {
+        if keep(row)? {
+            ids.push(row.get(0)?);
+        }
+    }
+    Ok(ids)
+}
+
+fn delete_ids(conn: &Connection, table: &str, ids: &[i64]) -> Result<u64> {
+    let mut stmt = conn.prepare(&format!("DELETE FROM {table} WHERE id = ?1"))?;
+    for id in ids {
+        stmt.execute([id])
+            .with_context(|| format!("Failed to delete from {table}"))?;
+    }
+    Ok(ids.len() as u64)
+}
+
+/// Folds the rows into the untitled row of their window class, like maintenance does with
+/// titles older than `--title-days`.
+fn redact_focus_titles(conn: &Connection, ids: &[i64]) -> Result<u64> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    conn.execute_batch("CREATE TEMP TABLE forget_ids (id INTEGER PRIMARY KEY)")?;
+    {
+        let mut stmt = conn.prepare("INSERT INTO forget_ids (id) VALUES (?1)")?;
+        for id in ids {
+            stmt.execute([id])?;
+        }
+    }
+    conn.execute_batch(
+        "
+        CREATE TEMP TABLE forget_untitled AS
+        SELECT
+            source_id, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+            timezone_offset_minutes, app_identifier, window_class,
+            SUM(focus_seconds) AS focus_seconds
+        FROM focus_buckets WHERE id IN (SELECT id FROM forget_ids)
+        GROUP BY source_id, bucket_start_utc, window_class;
+
+        DELETE FROM focus_buckets WHERE id IN (SELECT id FROM forget_ids);
+
+        INSERT INTO focus_buckets (
+            source_id, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+            timezone_offset_minutes, app_identifier, window_title, window_class, focus_seconds
+        )
+        SELECT
+            source_id, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+            timezone_offset_minutes, app_identifier, '', window_class, focus_seconds
+        FROM forget_untitled WHERE true
+        ON CONFLICT(source_id, bucket_start_utc, window_title, window_class) DO UPDATE SET
+            focus_seconds = focus_buckets.focus_seconds + excluded.focus_seconds;
+
+        DROP TABLE forget_untitled;
+        DROP TABLE forget_ids;
+        ",
+    )
+    .with_context(|| "Failed to blank window titles")?;
+    Ok(ids.len() as u64)
+}
+
+/// Unsent bucket changes are dropped, or for redacted titles folded into the untitled change
+/// of the same bucket, so the forgotten detail is never pushed.
+fn forget_pending_changes(conn: &Connection, matcher: &ForgetMatcher) -> Result<u64> {
+    let mut forgotten = 0;
+    if matcher.filter.covers_input() {
+        let mut ids = Vec::new();
+        let mut stmt = conn.prepare(
+            "SELECT id, source_uuid, bucket_start_utc FROM sync_outbox_input_buckets WHERE sent_at_utc IS NULL",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if matcher.matches_input(&row.get::<_, String>(1)?, &row.get::<_, String>(2)?) {
+                ids.push(row.get(0)?);
+            }
+        }
+        forgotten += delete_ids(conn, "sync_outbox_input_buckets", &ids)?;
+    }
+
+    let mut changes = Vec::new();
+    let mut stmt = conn.prepare(
+        "
+        SELECT id, source_uuid, bucket_start_utc, app_identifier, window_title, window_class,
+               focus_seconds
+        FROM sync_outbox_focus_buckets
+        WHERE sent_at_utc IS NULL
+        ",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let (source_uuid, bucket_start_utc): (String, String) = (row.get(1)?, row.get(2)?);
+        if matcher.matches_focus(
+            &source_uuid,
+            &bucket_start_utc,
+            &row.get::<_, String>(3)?,
+            &row.get::<_, String>(4)?,
+        ) {
+            changes.push((
+                row.get::<_, i64>(0)?,
+                source_uuid,
+                bucket_start_utc,
+                row.get::<_, String>(5)?,
+                row.get::<_, i64>(6)?,
+            ));
+        }
+    }
+    if !matcher.filter.redacts_titles() {
+        let ids: Vec<i64> = changes.iter().map(|change| change.0).collect();
+        return Ok(forgotten + delete_ids(conn, "sync_outbox_focus_buckets", &ids)?);
+    }
+    for (id, source_uuid, bucket_start_utc, window_class, focus_seconds) in changes {
+        let folded = conn.execute(
+            "
+            UPDATE sync_outbox_focus_buckets SET focus_seconds = focus_seconds + ?4
+            WHERE sent_at_utc IS NULL AND source_uuid = ?1 AND bucket_start_utc = ?2
+              AND window_title = '' AND window_class = ?3
+            ",
+            params![source_uuid, bucket_start_utc, window_class, focus_seconds],
+        )?;
+        if folded > 0 {
+            conn.execute("DELETE FROM sync_outbox_focus_buckets WHERE id = ?1", [id])?;
+        } else {
+            conn.execute(
+                "UPDATE sync_outbox_focus_buckets SET window_title = '' WHERE id = ?1",
+                [id],
+            )?;
+        }
+        forgotten += 1;
+    }
+    Ok(forgotten)
+}
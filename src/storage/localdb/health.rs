@@ -0,0 +1,570 @@
+//! Inspecting and fixing a database file: `vigil db check|stats|vacuum|repair`.
+use anyhow::{bail, Context, Result};
+use rusqlite::{params_from_iter, Connection, OpenFlags, OptionalExtension};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::DEFAULT_SOURCE_ID;
+
+use super::encryption::{apply_passphrase, database_passphrase};
+use super::integrity::{backup_path_next_to, run_integrity_check, scalar_query_u64};
+use super::migrations::stored_schema_version;
+use super::rollups::rebuild_rollups;
+use super::rows::open_con_at;
+use super::schema::{setup_database, SCHEMA_VERSION};
+
+/// Tables holding activity rows, each with `source_id` and `bucket_start_utc`/`bucket_end_utc`.
+const BUCKET_TABLES: [&str; 5] = [
+    "input_buckets",
+    "focus_buckets",
+    "browser_domain_buckets",
+    "terminal_context_buckets",
+    "project_buckets",
+];
+
+const OUTBOX_TABLES: [&str; 5] = [
+    "sync_outbox_sources",
+    "sync_outbox_input_buckets",
+    "sync_outbox_focus_buckets",
+    "sync_outbox_source_actions",
+    "sync_outbox_forgets",
+];
+
+/// `PRAGMA integrity_check` stops after this many problems.
+const MAX_INTEGRITY_ERRORS: u32 = 100;
+
+/// How far a recovery scan jumps past rows it cannot read before giving up on a table.
+const MAX_RECOVERY_SKIP: i64 = 1 << 40;
+
+#[derive(Debug, Clone)]
+pub struct CheckReport {
+    pub schema_version: Option<i64>,
+    pub integrity_errors: Vec<String>,
+    pub foreign_key_violations: u64,
+}
+
+impl CheckReport {
+    pub fn is_healthy(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.foreign_key_violations == 0
+            && self
+                .schema_version
+                .is_some_and(|version| version <= SCHEMA_VERSION)
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![match self.schema_version {
+            Some(version) if version == SCHEMA_VERSION => format!("schema version {version}"),
+            Some(version) if version < SCHEMA_VERSION => format!(
+                "schema version {version}, upgraded to {SCHEMA_VERSION} when the collector next starts"
+            ),
+            Some(version) => format!(
+                "schema version {version}, newer than the {SCHEMA_VERSION} this vigil supports"
+            ),
+            None => "schema version missing".to_string(),
+        }];
+        if self.integrity_errors.is_empty() {
+            lines.push("integrity check: ok".to_string());
+        } else {
+            lines.push(format!(
+                "integrity check: {} problem(s)",
+                self.integrity_errors.len()
+            ));
+            lines.extend(
+                self.integrity_errors
+                    .iter()
+                    .map(|error| format!("  {error}")),
+            );
+        }
+        lines.push(format!(
+            "foreign key violations: {}",
+            self.foreign_key_violations
+        ));
+        if !self.integrity_errors.is_empty() {
+            lines.push(
+                "run `vigil db repair` to recover what is readable into a new file".to_string(),
+            );
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceStats {
+    pub source_uuid: String,
+    pub source_name: String,
+    pub platform: String,
+    /// Rows across all bucket tables.
+    pub bucket_rows: u64,
+    pub first_bucket_utc: Option<String>,
+    pub last_bucket_utc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseStats {
+    pub file_size_bytes: u64,
+    pub wal_size_bytes: u64,
+    pub page_size: u64,
+    pub page_count: u64,
+    pub free_pages: u64,
+    /// Row count per table, by name.
+    pub tables: Vec<(String, u64)>,
+    pub sources: Vec<SourceStats>,
+    pub first_bucket_utc: Option<String>,
+    pub last_bucket_utc: Option<String>,
+    /// Unsent sync outbox rows for a source other than this database's own, which no push
+    /// ever picks up.
+    pub orphaned_outbox_rows: u64,
+}
+
+impl DatabaseStats {
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "file size: {} ({} write-ahead log)",
+                format_size(self.file_size_bytes),
+                format_size(self.wal_size_bytes)
+            ),
+            format!(
+                "pages: {} of {} bytes, {} free ({})",
+                self.page_count,
+                self.page_size,
+                self.free_pages,
+                format_size(self.free_pages * self.page_size)
+            ),
+            match (&self.first_bucket_utc, &self.last_bucket_utc) {
+                (Some(first), Some(last)) => format!("coverage: {first} to {last}"),
+                _ => "coverage: no activity recorded".to_string(),
+            },
+            format!("orphaned sync outbox rows: {}", self.orphaned_outbox_rows),
+            String::new(),
+            "rows per table:".to_string(),
+        ];
+        lines.extend(
+            self.tables
+                .iter()
+                .map(|(table, rows)| format!("  {table:<28} {rows:>10}")),
+        );
+        lines.push(String::new());
+        lines.push("rows per source:".to_string());
+        for source in &self.sources {
+            let coverage = match (&source.first_bucket_utc, &source.last_bucket_utc) {
+                (Some(first), Some(last)) => format!("{first} to {last}"),
+                _ => "no activity".to_string(),
+            };
+            lines.push(format!(
+                "  {} ({}, {})  {:>10}  {coverage}",
+                source.source_name, source.platform, source.source_uuid, source.bucket_rows
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VacuumReport {
+    pub size_before_bytes: u64,
+    pub size_after_bytes: u64,
+}
+
+impl VacuumReport {
+    pub fn render(&self) -> String {
+        format!(
+            "reindexed and vacuumed: {} -> {}",
+            format_size(self.size_before_bytes),
+            format_size(self.size_after_bytes)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecoveryReport {
+    pub recovered_path: PathBuf,
+    /// Rows copied per table, and how many could not be read.
+    pub tables: Vec<RecoveredTable>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecoveredTable {
+    pub name: String,
+    pub rows: u64,
+    pub unreadable_ranges: u64,
+    /// The scan gave up before reaching the end of the table.
+    pub incomplete: bool,
+}
+
+impl RecoveryReport {
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("recovered into {}", self.recovered_path.display())];
+        for table in &self.tables {
+            let mut line = format!("  {:<28} {:>10} rows", table.name, table.rows);
+            if table.unreadable_ranges > 0 {
+                line.push_str(&format!(
+                    ", skipped {} unreadable range(s)",
+                    table.unreadable_ranges
+                ));
+            }
+            if table.incomplete {
+                line.push_str(", stopped early");
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+/// Opens the database read-only and as it is, without switching its journal mode, so
+/// inspecting a damaged file never writes to it.
+pub fn open_for_inspection(db_path: &Path) -> Result<Connection> {
+    if !db_path.exists() {
+        bail!("No database at {}", db_path.display());
+    }
+    let conn = Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("Failed to open {}", db_path.display()))?;
+    apply_passphrase(&conn, database_passphrase()?.as_deref())?;
+    Ok(conn)
+}
+
+/// Runs SQLite's integrity and foreign key checks and reads the schema version. A check that
+/// SQLite aborts because the file is too damaged to walk is reported as a problem, not an error.
+pub fn check_database(conn: &Connection) -> Result<CheckReport> {
+    let pragma_rows = |sql: &str| -> rusqlite::Result<Vec<String>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect()
+    };
+
+    let mut integrity_errors = Vec::new();
+    let schema_version = stored_schema_version(conn).unwrap_or_else(|err| {
+        integrity_errors.push(format!("{err:#}"));
+        None
+    });
+    match pragma_rows(&format!("PRAGMA integrity_check({MAX_INTEGRITY_ERRORS})")) {
+        Ok(messages) => {
+            integrity_errors.extend(messages.into_iter().filter(|message| message != "ok"))
+        }
+        Err(err) => integrity_errors.push(format!("integrity_check stopped: {err}")),
+    }
+    let foreign_key_violations = match pragma_rows("PRAGMA foreign_key_check") {
+        Ok(violations) => violations.len() as u64,
+        Err(err) => {
+            integrity_errors.push(format!("foreign_key_check stopped: {err}"));
+            0
+        }
+    };
+    Ok(CheckReport {
+        schema_version,
+        integrity_errors,
+        foreign_key_violations,
+    })
+}
+
+pub fn database_stats(conn: &Connection, db_path: &Path) -> Result<DatabaseStats> {
+    let tables = table_names(conn)?
+        .into_iter()
+        .map(|table| {
+            let rows = scalar_query_u64(conn, &format!("SELECT COUNT(*) FROM \"{table}\""))?;
+            Ok((table, rows))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let present = |name: &str| tables.iter().any(|(table, _)| table == name);
+
+    let bucket_tables: Vec<&str> = BUCKET_TABLES
+        .into_iter()
+        .filter(|table| present(table))
+        .collect();
+    let buckets = bucket_tables
+        .iter()
+        .map(|table| format!("SELECT source_id, bucket_start_utc, bucket_end_utc FROM {table}"))
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+
+    let mut sources = Vec::new();
+    let (mut first_bucket_utc, mut last_bucket_utc) = (None, None);
+    if !buckets.is_empty() {
+        let mut stmt = conn.prepare(&format!(
+            "
+            SELECT s.source_uuid, s.source_name, s.platform, COUNT(b.source_id),
+                   MIN(b.bucket_start_utc), MAX(b.bucket_end_utc)
+            FROM sources s
+            LEFT JOIN ({buckets}) b ON b.source_id = s.id
+            GROUP BY s.id
+            ORDER BY s.id
+            "
+        ))?;
+        sources = stmt
+            .query_map([], |row| {
+                Ok(SourceStats {
+                    source_uuid: row.get(0)?,
+                    source_name: row.get(1)?,
+                    platform: row.get(2)?,
+                    bucket_rows: row.get(3)?,
+                    first_bucket_utc: row.get(4)?,
+                    last_bucket_utc: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        (first_bucket_utc, last_bucket_utc) = conn.query_row(
+            &format!("SELECT MIN(bucket_start_utc), MAX(bucket_end_utc) FROM ({buckets})"),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+    }
+
+    let own_source_uuid: Option<String> = conn
+        .query_row(
+            "SELECT source_uuid FROM sources WHERE id = ?1",
+            [DEFAULT_SOURCE_ID],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let mut orphaned_outbox_rows = 0;
+    for table in OUTBOX_TABLES.into_iter().filter(|table| present(table)) {
+        orphaned_outbox_rows += conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM {table} WHERE sent_at_utc IS NULL AND source_uuid IS NOT ?1"
+            ),
+            [&own_source_uuid],
+            |row| row.get::<_, u64>(0),
+        )?;
+    }
+
+    let file_size = |path: &Path| fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+    let mut wal_path = db_path.as_os_str().to_owned();
+    wal_path.push("-wal");
+    Ok(DatabaseStats {
+        file_size_bytes: file_size(db_path),
+        wal_size_bytes: file_size(Path::new(&wal_path)),
+        page_size: pragma_u64(conn, "page_size")?,
+        page_count: pragma_u64(conn, "page_count")?,
+        free_pages: pragma_u64(conn, "freelist_count")?,
+        tables,
+        sources,
+        first_bucket_utc,
+        last_bucket_utc,
+        orphaned_outbox_rows,
+    })
+}
+
+/// Rebuilds every index and the file itself, returning free pages to the filesystem.
+pub fn vacuum_database(conn: &Connection) -> Result<VacuumReport> {
+    let size = |conn: &Connection| -> Result<u64> {
+        Ok(pragma_u64(conn, "page_count")? * pragma_u64(conn, "page_size")?)
+    };
+    let size_before_bytes = size(conn)?;
+    conn.execute_batch("REINDEX")
+        .with_context(|| "Failed to rebuild the indexes")?;
+    conn.execute_batch("VACUUM")
+        .with_context(|| "Failed to vacuum the database")?;
+    // Folds the rewritten pages back into the file so its size on disk drops right away.
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .with_context(|| "Failed to checkpoint the write-ahead log")?;
+    Ok(VacuumReport {
+        size_before_bytes,
+        size_after_bytes: size(conn)?,
+    })
+}
+
+/// Copies every row that can still be read through SQLite from the database at `db_path` into
+/// a fresh database and returns where it went.
+///
+/// Each table is scanned in rowid order. When SQLite fails on a damaged page, the scan probes
+/// further and further ahead for a rowid it can read from, then narrows back to the first one
+/// after the failure and goes on from there. Unlike the sqlite3 shell's `.recover`, this does
+/// not read pages directly: rows below a damaged interior page are lost with it, and a probe
+/// that jumps over a second damaged page skips the readable rows between the two. The
+/// original file is never written to.
+pub fn recover_database(db_path: &Path, destination: Option<&Path>) -> Result<RecoveryReport> {
+    let source = open_for_inspection(db_path)?;
+    let source_tables = table_names(&source).with_context(|| {
+        format!(
+            "The schema of {} is unreadable, nothing can be recovered",
+            db_path.display()
+        )
+    })?;
+
+    let recovered_path = destination
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| backup_path_next_to(db_path, "recovered"));
+    if recovered_path.exists() {
+        bail!("{} already exists", recovered_path.display());
+    }
+    let mut recovered = open_con_at(&recovered_path)?;
+    setup_database(&recovered)?;
+    // Rows arrive table by table, so references are checked afterwards by `vigil db check`.
+    recovered.execute_batch("PRAGMA foreign_keys = OFF")?;
+    let recovered_tables: BTreeSet<String> = table_names(&recovered)?.into_iter().collect();
+
+    let tx = recovered.transaction()?;
+    let mut tables = Vec::new();
+    for table in source_tables
+        .iter()
+        .filter(|table| recovered_tables.contains(*table))
+    {
+        let source_columns = column_names(&source, table)?;
+        let columns: Vec<String> = column_names(&tx, table)?
+            .into_iter()
+            .filter(|column| source_columns.contains(column))
+            .collect();
+        tables.push(copy_readable_rows(&source, &tx, table, &columns)?);
+    }
+    // Rollups only summarise buckets, so rebuilding them also drops any for lost buckets.
+    rebuild_rollups(&tx)?;
+    tx.commit()?;
+    recovered.execute_batch("PRAGMA foreign_keys = ON")?;
+    run_integrity_check(&recovered, "recovered database")?;
+
+    Ok(RecoveryReport {
+        recovered_path,
+        tables,
+    })
+}
+
+fn copy_readable_rows(
+    source: &Connection,
+    destination: &Connection,
+    table: &str,
+    columns: &[String],
+) -> Result<RecoveredTable> {
+    let column_list = columns
+        .iter()
+        .map(|column| format!("\"{column}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = vec!["?"; columns.len() + 1].join(", ");
+    // The schema version stays the one `setup_database` wrote, which matches the copied columns.
+    let filter = if table == "schema_meta" {
+        " AND key <> 'schema_version'"
+    } else {
+        ""
+    };
+    let select = format!(
+        "SELECT rowid, {column_list} FROM \"{table}\" WHERE rowid >= ?1{filter} ORDER BY rowid"
+    );
+    let mut insert = destination.prepare(&format!(
+        "INSERT OR REPLACE INTO \"{table}\" (rowid, {column_list}) VALUES ({placeholders})"
+    ))?;
+
+    let mut report = RecoveredTable {
+        name: table.to_string(),
+        rows: 0,
+        unreadable_ranges: 0,
+        incomplete: false,
+    };
+    // Reads the first row from `start` on, which fails when the page holding it is damaged.
+    let readable_from = |start: i64| -> bool {
+        let outcome = (|| -> rusqlite::Result<()> {
+            let mut stmt = source.prepare(&select)?;
+            let mut rows = stmt.query([start])?;
+            if let Some(row) = rows.next()? {
+                for index in 0..=columns.len() {
+                    row.get::<_, rusqlite::types::Value>(index)?;
+                }
+            }
+            Ok(())
+        })();
+        outcome.is_ok()
+    };
+
+    let mut next_rowid = i64::MIN;
+    loop {
+        let outcome = (|| -> rusqlite::Result<()> {
+            let mut stmt = source.prepare(&select)?;
+            let mut rows = stmt.query([next_rowid])?;
+            while let Some(row) = rows.next()? {
+                let rowid: i64 = row.get(0)?;
+                let values = (0..=columns.len())
+                    .map(|index| row.get::<_, rusqlite::types::Value>(index))
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                insert.execute(params_from_iter(values))?;
+                report.rows += 1;
+                next_rowid = rowid.saturating_add(1);
+            }
+            Ok(())
+        })();
+        if outcome.is_ok() {
+            return Ok(report);
+        }
+        report.unreadable_ranges += 1;
+
+        // Probes ahead, further each time, until a scan can start again.
+        let failed = next_rowid;
+        let mut skip = 1;
+        let readable = loop {
+            if skip > MAX_RECOVERY_SKIP {
+                report.incomplete = true;
+                return Ok(report);
+            }
+            let probe = failed.saturating_add(skip);
+            if readable_from(probe) {
+                break probe;
+            }
+            skip *= 2;
+        };
+        // The probe may have landed well past the damaged page, so bisect back to the first
+        // rowid a scan can start from.
+        let (mut unreadable, mut readable) = (failed, readable);
+        while readable - unreadable > 1 {
+            let middle = unreadable + (readable - unreadable) / 2;
+            if readable_from(middle) {
+                readable = middle;
+            } else {
+                unreadable = middle;
+            }
+        }
+        next_rowid = readable;
+    }
+}
+
+/// Reads a numeric pragma. SQLCipher answers `page_size` with text on a keyed connection.
+pub(crate) fn pragma_u64(conn: &Connection, pragma: &str) -> Result<u64> {
+    let value = conn
+        .query_row(&format!("PRAGMA {pragma}"), [], |row| {
+            row.get::<_, rusqlite::types::Value>(0)
+        })
+        .with_context(|| format!("Failed to read PRAGMA {pragma}"))?;
+    match value {
+        rusqlite::types::Value::Integer(value) => Ok(value as u64),
+        rusqlite::types::Value::Text(text) => text
+            .parse()
+            .with_context(|| format!("Invalid PRAGMA {pragma} value: {text}")),
+        other => bail!("Invalid PRAGMA {pragma} value: {other:?}"),
+    }
+}
+
+fn table_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(names)
+}
+
+fn column_names(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{table}\")"))?;
+    let names = stmt
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(names)
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
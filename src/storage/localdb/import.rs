@@ -1,19 +1,25 @@
 use anyhow::{bail, Context, Result};
 use chrono::Utc;
 use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::info;
 use uuid::Uuid;
 
 use crate::utils::lock::acquire_db_operation_lock;
 
+use super::encryption::{
+    apply_passphrase, database_passphrase, ensure_readable, export_rekeyed_copy,
+};
 use super::export::{latest_export_metadata, latest_export_metadata_from_attached, ExportMetadata};
 use super::integrity::{
-    attach_source, backup_database_contents, default_pre_import_backup_path, detach_source,
-    file_sha256, run_integrity_check, scalar_query_u64, validate_schema_version,
+    attach_source, attached_table_exists, backup_database_contents, default_pre_import_backup_path,
+    detach_source, file_sha256, run_integrity_check, scalar_query_u64, validate_schema_version,
 };
+use super::migrations::migrate_copy;
+use super::rollups::rebuild_rollups;
 use super::rows::open_con_at;
-use super::schema::setup_database;
+use super::schema::{setup_database, SCHEMA_VERSION};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ImportPlan {
@@ -88,34 +94,100 @@ pub fn plan_import(destination_db_path: &Path, source_snapshot_path: &Path) -> R
         source_snapshot_path.display(),
         destination_db_path.display()
     );
-    plan_import_locked(destination_db_path, source_snapshot_path)
+    let source = ImportSource::open(source_snapshot_path)?;
+    plan_import_locked(destination_db_path, source_snapshot_path, &source)
+}
+
+/// The snapshot an import reads from: the file itself, or a copy when the file needs changes
+/// first. An older vigil's snapshot is upgraded to the current schema, and a plain snapshot is
+/// encrypted when the database is. The copy is removed once the import is done.
+struct ImportSource {
+    path: PathBuf,
+    temporary_copy: bool,
+}
+
+impl ImportSource {
+    fn open(snapshot_path: &Path) -> Result<Self> {
+        let passphrase = database_passphrase()?;
+        let mut source = Self {
+            path: snapshot_path.to_path_buf(),
+            temporary_copy: false,
+        };
+        let mut snapshot = open_snapshot(&source.path, passphrase.as_deref())?;
+        if passphrase.is_some() && ensure_readable(&snapshot, &source.path).is_err() {
+            let plain = open_snapshot(&source.path, None)?;
+            ensure_readable(&plain, &source.path)
+                .with_context(|| "Failed to read the source snapshot")?;
+            info!("The snapshot is not encrypted, importing an encrypted copy of it.");
+            drop((plain, snapshot));
+            source = Self::temporary();
+            export_rekeyed_copy(snapshot_path, None, &source.path, passphrase.as_deref())
+                .with_context(|| "Failed to encrypt a copy of the snapshot")?;
+            snapshot = open_snapshot(&source.path, passphrase.as_deref())?;
+        }
+        run_integrity_check(&snapshot, "source snapshot")?;
+        let version = validate_schema_version(&snapshot, "source snapshot")?;
+        if version == SCHEMA_VERSION {
+            return Ok(source);
+        }
+
+        info!("Upgrading a copy of the snapshot from schema version {version} to {SCHEMA_VERSION} before importing it.");
+        let upgraded = Self::temporary();
+        backup_database_contents(&snapshot, &upgraded.path)
+            .with_context(|| "Failed to copy the snapshot for upgrading")?;
+        migrate_copy(&open_con_at(&upgraded.path)?)
+            .with_context(|| "Failed to upgrade the snapshot to the current schema")?;
+        drop(snapshot);
+        Ok(upgraded)
+    }
+
+    fn temporary() -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("vigil-import-{}.sqlite", Uuid::new_v4())),
+            temporary_copy: true,
+        }
+    }
 }
 
+impl Drop for ImportSource {
+    fn drop(&mut self) {
+        if self.temporary_copy {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn open_snapshot(path: &Path, passphrase: Option<&str>) -> Result<Connection> {
+    let snapshot = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| {
+            format!(
+                "Failed to open source snapshot for import planning: {}",
+                path.display()
+            )
+        })?;
+    apply_passphrase(&snapshot, passphrase)?;
+    Ok(snapshot)
+}
+
+/// `source_snapshot_path` is the file the user gave, which duplicate detection hashes, and
+/// `source` what is actually read.
 fn plan_import_locked(
     destination_db_path: &Path,
     source_snapshot_path: &Path,
+    source: &ImportSource,
 ) -> Result<ImportPlan> {
     let destination = open_con_at(destination_db_path)?;
     setup_database(&destination)?;
-    let source =
-        Connection::open_with_flags(source_snapshot_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
-            .with_context(|| {
-                format!(
-                    "Failed to open source snapshot for import planning: {}",
-                    source_snapshot_path.display()
-                )
-            })?;
+    let snapshot = open_snapshot(&source.path, database_passphrase()?.as_deref())?;
 
     run_integrity_check(&destination, "destination database")?;
-    run_integrity_check(&source, "source snapshot")?;
     validate_schema_version(&destination, "destination database")?;
-    validate_schema_version(&source, "source snapshot")?;
 
-    let metadata = latest_export_metadata(&source)?;
+    let metadata = latest_export_metadata(&snapshot)?;
     let file_hash = file_sha256(source_snapshot_path)?;
     let duplicate_reason = existing_import_reason(&destination, &metadata.export_uuid, &file_hash)?;
 
-    attach_source(&destination, source_snapshot_path)?;
+    attach_source(&destination, &source.path)?;
     let plan = query_import_plan(&destination, &metadata, &file_hash, duplicate_reason)?;
     detach_source(&destination)?;
     Ok(plan)
@@ -132,7 +204,8 @@ pub fn import_snapshot(
         source_snapshot_path.display(),
         destination_db_path.display()
     );
-    let plan = plan_import_locked(destination_db_path, source_snapshot_path)?;
+    let source = ImportSource::open(source_snapshot_path)?;
+    let plan = plan_import_locked(destination_db_path, source_snapshot_path, &source)?;
     if plan.duplicate_import {
         bail!(
             "Import refused because this snapshot was already imported: {}",
@@ -152,7 +225,7 @@ pub fn import_snapshot(
         .with_context(|| "Failed to create automatic destination backup before import")?;
 
     let destination = open_con_at(destination_db_path)?;
-    attach_source(&destination, source_snapshot_path)?;
+    attach_source(&destination, &source.path)?;
     let metadata = latest_export_metadata_from_attached(&destination)?;
 
     let tx = destination.unchecked_transaction()?;
@@ -248,6 +321,156 @@ pub fn import_snapshot(
         ",
     )?;
 
+    if attached_table_exists(&tx, "browser_domain_buckets")? {
+        tx.execute_batch(
+            "
+            UPDATE browser_domain_buckets
+            SET
+                bucket_end_utc          = db.bucket_end_utc,
+                local_date              = db.local_date,
+                local_hour              = db.local_hour,
+                timezone_offset_minutes = db.timezone_offset_minutes,
+                last_url                = COALESCE(db.last_url, browser_domain_buckets.last_url),
+                focus_seconds           = browser_domain_buckets.focus_seconds + db.focus_seconds
+            FROM import_src.browser_domain_buckets db
+            JOIN import_src.sources src_src ON src_src.id = db.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE browser_domain_buckets.source_id        = dest_src.id
+              AND browser_domain_buckets.bucket_start_utc = db.bucket_start_utc
+              AND browser_domain_buckets.app_identifier   = db.app_identifier
+              AND browser_domain_buckets.domain           = db.domain;
+
+            INSERT INTO browser_domain_buckets (
+                source_id, bucket_start_utc, bucket_end_utc,
+                local_date, local_hour, timezone_offset_minutes,
+                app_identifier, domain, last_url, focus_seconds
+            )
+            SELECT
+                dest_src.id, db.bucket_start_utc, db.bucket_end_utc,
+                db.local_date, db.local_hour, db.timezone_offset_minutes,
+                db.app_identifier, db.domain, db.last_url, db.focus_seconds
+            FROM import_src.browser_domain_buckets db
+            JOIN import_src.sources src_src ON src_src.id = db.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE NOT EXISTS (
+                SELECT 1 FROM browser_domain_buckets dest
+                WHERE dest.source_id        = dest_src.id
+                  AND dest.bucket_start_utc = db.bucket_start_utc
+                  AND dest.app_identifier   = db.app_identifier
+                  AND dest.domain           = db.domain
+            );
+            ",
+        )?;
+    }
+
+    if attached_table_exists(&tx, "terminal_context_buckets")? {
+        tx.execute_batch(
+            "
+            UPDATE terminal_context_buckets
+            SET
+                bucket_end_utc          = tb.bucket_end_utc,
+                local_date              = tb.local_date,
+                local_hour              = tb.local_hour,
+                timezone_offset_minutes = tb.timezone_offset_minutes,
+                focus_seconds           = terminal_context_buckets.focus_seconds + tb.focus_seconds
+            FROM import_src.terminal_context_buckets tb
+            JOIN import_src.sources src_src ON src_src.id = tb.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE terminal_context_buckets.source_id        = dest_src.id
+              AND terminal_context_buckets.bucket_start_utc = tb.bucket_start_utc
+              AND terminal_context_buckets.app_identifier   = tb.app_identifier
+              AND terminal_context_buckets.command          = tb.command
+              AND terminal_context_buckets.working_dir      = tb.working_dir;
+
+            INSERT INTO terminal_context_buckets (
+                source_id, bucket_start_utc, bucket_end_utc,
+                local_date, local_hour, timezone_offset_minutes,
+                app_identifier, command, working_dir, focus_seconds
+            )
+            SELECT
+                dest_src.id, tb.bucket_start_utc, tb.bucket_end_utc,
+                tb.local_date, tb.local_hour, tb.timezone_offset_minutes,
+                tb.app_identifier, tb.command, tb.working_dir, tb.focus_seconds
+            FROM import_src.terminal_context_buckets tb
+            JOIN import_src.sources src_src ON src_src.id = tb.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE NOT EXISTS (
+                SELECT 1 FROM terminal_context_buckets dest
+                WHERE dest.source_id        = dest_src.id
+                  AND dest.bucket_start_utc = tb.bucket_start_utc
+                  AND dest.app_identifier   = tb.app_identifier
+                  AND dest.command          = tb.command
+                  AND dest.working_dir      = tb.working_dir
+            );
+            ",
+        )?;
+    }
+
+    if attached_table_exists(&tx, "suspend_periods")? {
+        tx.execute_batch(
+            "
+            INSERT INTO suspend_periods (
+                source_id, started_at_utc, ended_at_utc, duration_seconds, detected_by
+            )
+            SELECT
+                dest_src.id, sp.started_at_utc, sp.ended_at_utc, sp.duration_seconds,
+                sp.detected_by
+            FROM import_src.suspend_periods sp
+            JOIN import_src.sources src_src ON src_src.id = sp.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE NOT EXISTS (
+                SELECT 1 FROM suspend_periods dest
+                WHERE dest.source_id      = dest_src.id
+                  AND dest.started_at_utc = sp.started_at_utc
+            );
+            ",
+        )?;
+    }
+
+    if attached_table_exists(&tx, "project_buckets")? {
+        tx.execute_batch(
+            "
+            UPDATE project_buckets
+            SET
+                bucket_end_utc          = pb.bucket_end_utc,
+                local_date              = pb.local_date,
+                local_hour              = pb.local_hour,
+                timezone_offset_minutes = pb.timezone_offset_minutes,
+                project_root            = COALESCE(pb.project_root, project_buckets.project_root),
+                focus_seconds           = project_buckets.focus_seconds + pb.focus_seconds
+            FROM import_src.project_buckets pb
+            JOIN import_src.sources src_src ON src_src.id = pb.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE project_buckets.source_id        = dest_src.id
+              AND project_buckets.bucket_start_utc = pb.bucket_start_utc
+              AND project_buckets.project          = pb.project
+              AND project_buckets.app_identifier   = pb.app_identifier;
+
+            INSERT INTO project_buckets (
+                source_id, bucket_start_utc, bucket_end_utc,
+                local_date, local_hour, timezone_offset_minutes,
+                project, project_root, app_identifier, focus_seconds
+            )
+            SELECT
+                dest_src.id, pb.bucket_start_utc, pb.bucket_end_utc,
+                pb.local_date, pb.local_hour, pb.timezone_offset_minutes,
+                pb.project, pb.project_root, pb.app_identifier, pb.focus_seconds
+            FROM import_src.project_buckets pb
+            JOIN import_src.sources src_src ON src_src.id = pb.source_id
+            JOIN sources dest_src ON dest_src.source_uuid = src_src.source_uuid
+            WHERE NOT EXISTS (
+                SELECT 1 FROM project_buckets dest
+                WHERE dest.source_id        = dest_src.id
+                  AND dest.bucket_start_utc = pb.bucket_start_utc
+                  AND dest.project          = pb.project
+                  AND dest.app_identifier   = pb.app_identifier
+            );
+            ",
+        )?;
+    }
+
+    rebuild_rollups(&tx)?;
+
     tx.execute(
         "
         INSERT INTO imports (
@@ -8,6 +8,8 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use super::encryption::attach_database;
+use super::journal::use_rollback_journal;
 use super::rows::open_con_at;
 use super::schema::SCHEMA_VERSION;
 
@@ -18,6 +20,7 @@ pub(crate) fn scalar_query_u64(conn: &Connection, sql: &str) -> Result<u64> {
         .unwrap_or(0))
 }
 
+/// Returns the schema version of `conn`, refusing one written by a newer vigil.
 pub(crate) fn validate_schema_version(conn: &Connection, label: &str) -> Result<i64> {
     let version: String = conn
         .query_row(
@@ -30,9 +33,9 @@ pub(crate) fn validate_schema_version(conn: &Connection, label: &str) -> Result<
         .parse::<i64>()
         .with_context(|| format!("Invalid schema_version value in {label}: {version}"))?;
 
-    if version != SCHEMA_VERSION {
+    if version > SCHEMA_VERSION {
         bail!(
-            "{label} uses schema version {version}, but vigil expects {}",
+            "{label} uses schema version {version}, but this vigil only supports up to {}",
             SCHEMA_VERSION
         );
     }
@@ -50,11 +53,7 @@ pub(crate) fn run_integrity_check(conn: &Connection, label: &str) -> Result<()>
 }
 
 pub(crate) fn attach_source(conn: &Connection, source_snapshot_path: &Path) -> Result<()> {
-    conn.execute(
-        "ATTACH DATABASE ?1 AS import_src",
-        [source_snapshot_path.to_string_lossy().to_string()],
-    )
-    .with_context(|| {
+    attach_database(conn, source_snapshot_path, "import_src").with_context(|| {
         format!(
             "Failed to ATTACH source snapshot at {}",
             source_snapshot_path.display()
@@ -63,6 +62,18 @@ pub(crate) fn attach_source(conn: &Connection, source_snapshot_path: &Path) -> R
     Ok(())
 }
 
+/// Snapshots exported before a table was introduced simply do not have it.
+pub(crate) fn attached_table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    let count: u64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM import_src.sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )
+        .with_context(|| format!("Failed to look up table {table} in attached snapshot"))?;
+    Ok(count > 0)
+}
+
 pub(crate) fn detach_source(conn: &Connection) -> Result<()> {
     conn.execute_batch("DETACH DATABASE import_src")
         .with_context(|| "Failed to DETACH imported source database")
@@ -103,18 +114,27 @@ pub(crate) fn backup_database_contents(source: &Connection, backup_path: &Path)
     backup
         .run_to_completion(32, Duration::from_millis(50), None)
         .with_context(|| "Failed to complete SQLite backup operation")?;
-    Ok(())
+    drop(backup);
+    use_rollback_journal(&destination, backup_path)
 }
 
 pub(crate) fn default_pre_import_backup_path(destination_db_path: &Path) -> PathBuf {
+    backup_path_next_to(destination_db_path, "pre-import")
+}
+
+pub(crate) fn default_pre_migration_backup_path(db_path: &Path, from_version: i64) -> PathBuf {
+    backup_path_next_to(db_path, &format!("pre-migration-v{from_version}"))
+}
+
+pub(crate) fn backup_path_next_to(db_path: &Path, label: &str) -> PathBuf {
     let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ");
-    let stem = destination_db_path
+    let stem = db_path
         .file_stem()
         .and_then(|value| value.to_str())
         .unwrap_or("data");
-    let file_name = format!("{stem}.pre-import-{timestamp}.sqlite");
+    let file_name = format!("{stem}.{label}-{timestamp}.sqlite");
 
-    destination_db_path
+    db_path
         .parent()
         .map(|parent| parent.join(&file_name))
         .unwrap_or_else(|| PathBuf::from(file_name))
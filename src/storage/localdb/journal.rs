@@ -0,0 +1,99 @@
+//! Journal mode selection for database files.
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Puts a local database into WAL mode so readers never wait on the collector's
+/// writes. WAL needs a shared-memory index that network filesystems cannot provide,
+/// so databases on a network share keep the rollback journal.
+pub(crate) fn configure_journal_mode(conn: &Connection, db_path: &Path) -> Result<()> {
+    let current: String = conn
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .with_context(|| "Failed to read the sqlite journal mode")?;
+    let wanted = if is_network_filesystem(db_path) {
+        "delete"
+    } else {
+        "wal"
+    };
+    if current.eq_ignore_ascii_case(wanted) {
+        return Ok(());
+    }
+
+    // Switching modes needs an exclusive lock; when another process holds the file
+    // the next connection tries again, so a failure here only costs concurrency.
+    match conn.query_row(&format!("PRAGMA journal_mode = {wanted}"), [], |row| {
+        row.get::<_, String>(0)
+    }) {
+        Ok(mode) if mode.eq_ignore_ascii_case(wanted) => {
+            if wanted == "delete" {
+                info!(
+                    "Database at {} is on a network filesystem; using the rollback journal instead of WAL",
+                    db_path.display()
+                );
+            }
+        }
+        Ok(mode) => warn!(
+            "SQLite kept journal mode {mode} for {} instead of {wanted}",
+            db_path.display()
+        ),
+        Err(err) => warn!(
+            "Failed to switch {} to journal mode {wanted}: {err}",
+            db_path.display()
+        ),
+    }
+    Ok(())
+}
+
+/// Leaves a snapshot or backup in the rollback journal mode, so it is one self-contained
+/// file that can be copied or opened read-only without its `-wal` and `-shm` companions.
+pub(crate) fn use_rollback_journal(conn: &Connection, db_path: &Path) -> Result<()> {
+    let mode: String = conn
+        .query_row("PRAGMA journal_mode = DELETE", [], |row| row.get(0))
+        .with_context(|| format!("Failed to set the journal mode of {}", db_path.display()))?;
+    if !mode.eq_ignore_ascii_case("delete") {
+        anyhow::bail!(
+            "SQLite kept journal mode {mode} for {} instead of delete",
+            db_path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn is_network_filesystem(db_path: &Path) -> bool {
+    use nix::sys::statfs::statfs;
+
+    // Magic numbers from linux/magic.h and the cifs client: NFS, SMB, CIFS and SMB2.
+    const NETWORK_FS_MAGIC: [u32; 4] = [0x6969, 0x517B, 0xFF53_4D42, 0xFE53_4D42];
+
+    let Some(probe) = db_path.ancestors().find(|path| path.exists()) else {
+        return false;
+    };
+    match statfs(probe) {
+        Ok(stat) => NETWORK_FS_MAGIC.contains(&(stat.filesystem_type().0 as u32)),
+        Err(_) => false,
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn is_network_filesystem(db_path: &Path) -> bool {
+    use std::path::{Component, Prefix};
+    use windows::core::HSTRING;
+    use windows::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+
+    let absolute = std::path::absolute(db_path).unwrap_or_else(|_| db_path.to_path_buf());
+    let Some(Component::Prefix(prefix)) = absolute.components().next() else {
+        return false;
+    };
+    match prefix.kind() {
+        Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+            let root = HSTRING::from(format!("{}:\\", letter as char));
+            unsafe { GetDriveTypeW(&root) == DRIVE_REMOTE }
+        }
+        _ => false,
+    }
+}
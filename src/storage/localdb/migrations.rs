@@ -0,0 +1,568 @@
+//! Ordered schema migrations.
+//!
+//! Each [`Migration`] upgrades a database from the previous version to its own and runs in a
+//! transaction together with the `schema_version` bump, so an interrupted upgrade resumes at
+//! the step that failed. Databases are backed up before their first pending step, and ones
+//! written by a newer Vigil are refused. Every step is idempotent, so it also upgrades
+//! databases created before this registry that already have some of its tables.
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+use std::path::Path;
+use tracing::info;
+
+use super::integrity::{backup_database_contents, default_pre_migration_backup_path};
+use super::rollups::rebuild_rollups;
+use super::schema::SCHEMA_VERSION;
+
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+/// Every schema step in order. Append new ones; never change one that shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 3,
+        description: "sources, buckets, sessions, import and export history, sync outbox",
+        apply: create_baseline_schema,
+    },
+    Migration {
+        version: 4,
+        description: "browser domain, terminal context and project buckets",
+        apply: add_context_buckets,
+    },
+    Migration {
+        version: 5,
+        description: "suspend periods, collector events, dropped events and session end reasons",
+        apply: add_collector_health,
+    },
+    Migration {
+        version: 6,
+        description: "spool checkpoints",
+        apply: add_spool_checkpoints,
+    },
+    Migration {
+        version: 7,
+        description: "hourly and daily rollups",
+        apply: add_rollups,
+    },
+    Migration {
+        version: 8,
+        description: "compaction history",
+        apply: add_compactions,
+    },
+    Migration {
+        version: 9,
+        description: "retired sources and source actions in the sync outbox",
+        apply: add_source_management,
+    },
+    Migration {
+        version: 10,
+        description: "forget requests in the sync outbox",
+        apply: add_forget_outbox,
+    },
+    Migration {
+        version: 11,
+        description: "forgets kept for screening later sync changes",
+        apply: add_sync_forgets,
+    },
+];
+
+/// The version a database is at, or `None` for one that was never set up.
+pub fn stored_schema_version(conn: &Connection) -> Result<Option<i64>> {
+    let version: Option<String> = conn
+        .query_row(
+            "SELECT value FROM schema_meta WHERE key = 'schema_version'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .with_context(|| "Failed to read the schema version")?;
+    version
+        .map(|version| {
+            version
+                .parse::<i64>()
+                .with_context(|| format!("Invalid schema_version value: {version}"))
+        })
+        .transpose()
+}
+
+/// Brings the database up to [`SCHEMA_VERSION`].
+pub fn migrate(conn: &Connection) -> Result<()> {
+    apply_migrations(conn, true)
+}
+
+/// Like [`migrate`], without the backup, for copies that are thrown away afterwards.
+pub(crate) fn migrate_copy(conn: &Connection) -> Result<()> {
+    apply_migrations(conn, false)
+}
+
+fn apply_migrations(conn: &Connection, backup: bool) -> Result<()> {
+    let stored = stored_schema_version(conn)?;
+    if let Some(version) = stored.filter(|version| *version > SCHEMA_VERSION) {
+        bail!(
+            "The database uses schema version {version}, which is newer than the {SCHEMA_VERSION} this version of vigil supports. Upgrade vigil to open it."
+        );
+    }
+
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > stored.unwrap_or(0))
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // A database that was never set up has nothing worth keeping a copy of.
+    let db_path = conn.path().filter(|path| backup && !path.is_empty());
+    if let (Some(version), Some(db_path)) = (stored, db_path) {
+        let backup_path = default_pre_migration_backup_path(Path::new(db_path), version);
+        info!(
+            "Upgrading the database from schema version {version} to {SCHEMA_VERSION}, backing it up to '{}' first.",
+            backup_path.display()
+        );
+        backup_database_contents(conn, &backup_path)
+            .with_context(|| "Failed to back up the database before upgrading its schema")?;
+    }
+
+    for migration in pending {
+        let tx = conn.unchecked_transaction()?;
+        (migration.apply)(&tx).with_context(|| {
+            format!(
+                "Failed to upgrade the database to schema version {} ({})",
+                migration.version, migration.description
+            )
+        })?;
+        tx.execute(
+            "
+            INSERT INTO schema_meta (key, value)
+            VALUES ('schema_version', ?1)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value
+            ",
+            [migration.version.to_string()],
+        )?;
+        tx.commit()?;
+        if stored.is_some() {
+            info!(
+                "Upgraded the database to schema version {}: {}",
+                migration.version, migration.description
+            );
+        }
+    }
+    Ok(())
+}
+
+fn create_baseline_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS sources (
+            id INTEGER PRIMARY KEY,
+            source_uuid TEXT NOT NULL UNIQUE,
+            source_name TEXT NOT NULL,
+            platform TEXT NOT NULL,
+            created_at_utc TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS input_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            granularity_minutes INTEGER NOT NULL,
+            left_clicks INTEGER NOT NULL,
+            right_clicks INTEGER NOT NULL,
+            middle_clicks INTEGER NOT NULL,
+            key_presses INTEGER NOT NULL,
+            mouse_distance_cm REAL NOT NULL,
+            scroll_vertical_cm REAL NOT NULL,
+            scroll_horizontal_cm REAL NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id),
+            UNIQUE(source_id, bucket_start_utc, granularity_minutes)
+        );
+
+        CREATE TABLE IF NOT EXISTS focus_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            app_identifier TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            window_class TEXT NOT NULL,
+            focus_seconds INTEGER NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id),
+            UNIQUE(source_id, bucket_start_utc, window_title, window_class)
+        );
+
+        CREATE TABLE IF NOT EXISTS exports (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            export_uuid TEXT NOT NULL UNIQUE,
+            primary_source_uuid TEXT NOT NULL,
+            exported_at_utc TEXT NOT NULL,
+            schema_version INTEGER NOT NULL,
+            notes TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS imports (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            import_uuid TEXT NOT NULL UNIQUE,
+            source_export_uuid TEXT NOT NULL UNIQUE,
+            source_source_uuid TEXT NOT NULL,
+            exported_at_utc TEXT NOT NULL,
+            imported_at_utc TEXT NOT NULL,
+            file_hash TEXT NOT NULL,
+            schema_version INTEGER NOT NULL,
+            notes TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            started_at_utc TEXT NOT NULL,
+            ended_at_utc TEXT,
+            session_uuid TEXT NOT NULL UNIQUE,
+            platform TEXT NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS sync_state (
+            own_source_uuid TEXT NOT NULL,
+            remote_url TEXT NOT NULL,
+            last_pulled_revision INTEGER NOT NULL DEFAULT 0,
+            last_pushed_batch_uuid TEXT,
+            last_push_at_utc TEXT,
+            last_pull_at_utc TEXT,
+            last_sync_error TEXT,
+            last_sync_error_at_utc TEXT,
+            remote_head_revision INTEGER,
+            sync_enabled INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (own_source_uuid, remote_url)
+        );
+
+        CREATE TABLE IF NOT EXISTS sync_outbox_sources (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_uuid TEXT,
+            source_uuid TEXT NOT NULL,
+            source_name TEXT NOT NULL,
+            platform TEXT NOT NULL,
+            created_at_utc TEXT NOT NULL,
+            sent_at_utc TEXT,
+            attempt_count INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE UNIQUE INDEX IF NOT EXISTS sync_outbox_sources_pending_idx
+        ON sync_outbox_sources (source_uuid)
+        WHERE sent_at_utc IS NULL;
+
+        CREATE TABLE IF NOT EXISTS sync_outbox_input_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_uuid TEXT,
+            source_uuid TEXT NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            granularity_minutes INTEGER NOT NULL,
+            left_clicks INTEGER NOT NULL,
+            right_clicks INTEGER NOT NULL,
+            middle_clicks INTEGER NOT NULL,
+            key_presses INTEGER NOT NULL,
+            mouse_distance_cm REAL NOT NULL,
+            scroll_vertical_cm REAL NOT NULL,
+            scroll_horizontal_cm REAL NOT NULL,
+            created_at_utc TEXT NOT NULL,
+            sent_at_utc TEXT,
+            attempt_count INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE UNIQUE INDEX IF NOT EXISTS sync_outbox_input_pending_idx
+        ON sync_outbox_input_buckets (source_uuid, bucket_start_utc, granularity_minutes)
+        WHERE sent_at_utc IS NULL;
+
+        CREATE TABLE IF NOT EXISTS sync_outbox_focus_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_uuid TEXT,
+            source_uuid TEXT NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            app_identifier TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            window_class TEXT NOT NULL,
+            focus_seconds INTEGER NOT NULL,
+            created_at_utc TEXT NOT NULL,
+            sent_at_utc TEXT,
+            attempt_count INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE UNIQUE INDEX IF NOT EXISTS sync_outbox_focus_pending_idx
+        ON sync_outbox_focus_buckets (source_uuid, bucket_start_utc, window_title, window_class)
+        WHERE sent_at_utc IS NULL;
+        ",
+    )?;
+    Ok(())
+}
+
+fn add_context_buckets(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS browser_domain_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            app_identifier TEXT NOT NULL,
+            domain TEXT NOT NULL,
+            last_url TEXT,
+            focus_seconds INTEGER NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id),
+            UNIQUE(source_id, bucket_start_utc, app_identifier, domain)
+        );
+
+        CREATE TABLE IF NOT EXISTS terminal_context_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            app_identifier TEXT NOT NULL,
+            command TEXT NOT NULL,
+            working_dir TEXT NOT NULL,
+            focus_seconds INTEGER NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id),
+            UNIQUE(source_id, bucket_start_utc, app_identifier, command, working_dir)
+        );
+
+        CREATE TABLE IF NOT EXISTS project_buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            bucket_end_utc TEXT NOT NULL,
+            local_date TEXT NOT NULL,
+            local_hour INTEGER NOT NULL,
+            timezone_offset_minutes INTEGER NOT NULL,
+            project TEXT NOT NULL,
+            project_root TEXT,
+            app_identifier TEXT NOT NULL,
+            focus_seconds INTEGER NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id),
+            UNIQUE(source_id, bucket_start_utc, project, app_identifier)
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+fn add_collector_health(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS suspend_periods (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            started_at_utc TEXT NOT NULL,
+            ended_at_utc TEXT NOT NULL,
+            duration_seconds INTEGER NOT NULL,
+            detected_by TEXT NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id),
+            UNIQUE(source_id, started_at_utc)
+        );
+
+        CREATE TABLE IF NOT EXISTS collector_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            occurred_at_utc TEXT NOT NULL,
+            task TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            message TEXT NOT NULL,
+            FOREIGN KEY(source_id) REFERENCES sources(id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_collector_events_occurred
+            ON collector_events(occurred_at_utc);
+        ",
+    )?;
+    add_missing_column(
+        conn,
+        "input_buckets",
+        "dropped_events",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_missing_column(conn, "sessions", "end_reason", "TEXT")
+}
+
+fn add_spool_checkpoints(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS spool_checkpoints (
+            spool TEXT PRIMARY KEY,
+            generation TEXT NOT NULL,
+            last_seq INTEGER NOT NULL,
+            applied_at_utc TEXT NOT NULL
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+/// Databases that already had buckets get their rollups computed from them.
+fn add_rollups(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS input_rollups (
+            source_id INTEGER NOT NULL,
+            granularity_minutes INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            left_clicks INTEGER NOT NULL,
+            right_clicks INTEGER NOT NULL,
+            middle_clicks INTEGER NOT NULL,
+            key_presses INTEGER NOT NULL,
+            mouse_distance_cm REAL NOT NULL,
+            scroll_vertical_cm REAL NOT NULL,
+            scroll_horizontal_cm REAL NOT NULL,
+            dropped_events INTEGER NOT NULL,
+            PRIMARY KEY (source_id, granularity_minutes, bucket_start_utc)
+        );
+
+        CREATE TABLE IF NOT EXISTS focus_rollups (
+            source_id INTEGER NOT NULL,
+            granularity_minutes INTEGER NOT NULL,
+            bucket_start_utc TEXT NOT NULL,
+            app_identifier TEXT NOT NULL,
+            focus_seconds INTEGER NOT NULL,
+            PRIMARY KEY (source_id, granularity_minutes, bucket_start_utc, app_identifier)
+        );
+        ",
+    )?;
+    rebuild_rollups(conn)
+}
+
+fn add_compactions(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS compactions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            ran_at_utc TEXT NOT NULL,
+            table_name TEXT NOT NULL,
+            action TEXT NOT NULL,
+            cutoff_utc TEXT NOT NULL,
+            rows_before INTEGER NOT NULL,
+            rows_after INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_compactions_table
+            ON compactions(table_name, cutoff_utc);
+        ",
+    )?;
+    Ok(())
+}
+
+fn add_source_management(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS retired_sources (
+            source_uuid TEXT PRIMARY KEY,
+            merged_into_uuid TEXT,
+            retired_at_utc TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS sync_outbox_source_actions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_uuid TEXT,
+            source_uuid TEXT NOT NULL,
+            target_source_uuid TEXT NOT NULL,
+            action TEXT NOT NULL,
+            source_name TEXT,
+            merged_into_uuid TEXT,
+            created_at_utc TEXT NOT NULL,
+            sent_at_utc TEXT,
+            attempt_count INTEGER NOT NULL DEFAULT 0
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+fn add_forget_outbox(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS sync_outbox_forgets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch_uuid TEXT,
+            source_uuid TEXT NOT NULL,
+            target_source_uuid TEXT,
+            app_identifier TEXT,
+            title_pattern TEXT,
+            from_utc TEXT,
+            until_utc TEXT,
+            created_at_utc TEXT NOT NULL,
+            sent_at_utc TEXT,
+            attempt_count INTEGER NOT NULL DEFAULT 0
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+/// Forgets queued before this step are this device's own, so they seed the list.
+fn add_sync_forgets(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS sync_forgets (
+            source_uuid TEXT NOT NULL,
+            target_source_uuid TEXT,
+            app_identifier TEXT,
+            title_pattern TEXT,
+            from_utc TEXT,
+            until_utc TEXT,
+            created_at_utc TEXT NOT NULL,
+            PRIMARY KEY (source_uuid, created_at_utc)
+        );
+
+        INSERT OR IGNORE INTO sync_forgets (
+            source_uuid, target_source_uuid, app_identifier, title_pattern, from_utc, until_utc,
+            created_at_utc
+        )
+        SELECT
+            source_uuid, target_source_uuid, app_identifier, title_pattern, from_utc, until_utc,
+            created_at_utc
+        FROM sync_outbox_forgets;
+        ",
+    )?;
+    Ok(())
+}
+
+/// Adds a column that databases created before it existed don't have yet.
+fn add_missing_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let exists = conn
+        .prepare(&format!("PRAGMA table_info({table})"))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))
+        .with_context(|| format!("Failed to add column {column} to {table}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! Read-only connections for the dashboard and report commands.
+//!
+//! Readers open the database with `SQLITE_OPEN_READ_ONLY` and never run `setup_database`, so
+//! they need no write access and, with the collector's connections in WAL mode, never wait on
+//! its writes. Creating and upgrading the database is left to the collector.
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use super::encryption::{apply_passphrase, database_passphrase, ensure_readable};
+use super::integrity::validate_schema_version;
+use super::schema::SCHEMA_VERSION;
+
+/// Idle connections kept for reuse; further readers are opened on demand and closed after use.
+const MAX_IDLE_READERS: usize = 4;
+
+/// A small pool of read-only connections to one database.
+pub struct ReadPool {
+    db_path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl ReadPool {
+    /// Creates an empty pool; connections are opened and checked on first use.
+    pub fn new(db_path: &Path) -> Self {
+        Self {
+            db_path: db_path.to_path_buf(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Hands out an idle connection, or opens a new one.
+    pub fn get(&self) -> Result<PooledReader<'_>> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => open_reader(&self.db_path)?,
+        };
+        Ok(PooledReader {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+}
+
+/// A connection borrowed from a [`ReadPool`], returned to it on drop.
+pub struct PooledReader<'a> {
+    pool: &'a ReadPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledReader<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("pooled connection is present until drop")
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        let mut idle = self
+            .pool
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE_READERS {
+            idle.push(conn);
+        }
+    }
+}
+
+fn open_reader(db_path: &Path) -> Result<Connection> {
+    if !db_path.exists() {
+        bail!(
+            "No vigil database at {}; start the collector to create it",
+            db_path.display()
+        );
+    }
+
+    let conn = Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| {
+        format!(
+            "Failed to open read-only connection to sqlite database at: {}",
+            db_path.display()
+        )
+    })?;
+    apply_passphrase(&conn, database_passphrase()?.as_deref())?;
+    // Readers only wait here while a rollback-journal database is being written.
+    conn.busy_timeout(Duration::from_secs(5))
+        .with_context(|| "Failed to configure sqlite busy timeout")?;
+
+    ensure_readable(&conn, db_path)?;
+
+    let label = db_path.display().to_string();
+    let version = validate_schema_version(&conn, &label)?;
+    if version < SCHEMA_VERSION {
+        bail!(
+            "{label} uses schema version {version}; start the collector once to upgrade it to {SCHEMA_VERSION}"
+        );
+    }
+    Ok(conn)
+}
@@ -0,0 +1,561 @@
+//! Retention rules that keep old history coarse.
+//!
+//! Buckets older than [`RetentionPolicy::full_detail_days`] are merged into hourly buckets, and
+//! those older than [`RetentionPolicy::hourly_days`] into daily ones. Window titles older than
+//! [`RetentionPolicy::title_days`] are dropped, which merges the focus time of each app and
+//! window class. Totals never change, so the rollups stay valid. Every compaction is recorded
+//! in `compactions`, whose cutoffs also tell sync which ranges no longer have their detail.
+use anyhow::{Context, Result};
+#[cfg(feature = "multi-sync")]
+use chrono::NaiveDate;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+#[cfg(feature = "multi-sync")]
+use crate::common::{FocusBucketRecord, InputBucketRecord};
+
+use super::rollups::rollup_start;
+
+pub const DEFAULT_FULL_DETAIL_DAYS: u32 = 90;
+pub const DEFAULT_HOURLY_DAYS: u32 = 730;
+
+/// How long each level of detail is kept, in days. `0` keeps it forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub full_detail_days: u32,
+    pub hourly_days: u32,
+    pub title_days: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            full_detail_days: DEFAULT_FULL_DETAIL_DAYS,
+            hourly_days: DEFAULT_HOURLY_DAYS,
+            title_days: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionRecord {
+    pub table_name: String,
+    pub action: String,
+    pub cutoff_utc: String,
+    pub rows_before: u64,
+    pub rows_after: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceReport {
+    pub policy: RetentionPolicy,
+    pub dry_run: bool,
+    pub compactions: Vec<CompactionRecord>,
+}
+
+/// A bucket table and the columns its rows are told apart by, besides source and start.
+pub(super) struct BucketTable {
+    pub(super) name: &'static str,
+    pub(super) keys: &'static [&'static str],
+    /// Taken from the earliest row of the merged ones.
+    pub(super) carried: &'static [&'static str],
+    pub(super) sums: &'static [&'static str],
+    pub(super) has_granularity: bool,
+}
+
+const FOCUS_SUMS: &[&str] = &["focus_seconds"];
+
+pub(super) const BUCKET_TABLES: [BucketTable; 5] = [
+    BucketTable {
+        name: "input_buckets",
+        keys: &[],
+        carried: &[],
+        sums: &[
+            "left_clicks",
+            "right_clicks",
+            "middle_clicks",
+            "key_presses",
+            "mouse_distance_cm",
+            "scroll_vertical_cm",
+            "scroll_horizontal_cm",
+            "dropped_events",
+        ],
+        has_granularity: true,
+    },
+    BucketTable {
+        name: "focus_buckets",
+        keys: &["window_title", "window_class"],
+        carried: &["app_identifier"],
+        sums: FOCUS_SUMS,
+        has_granularity: false,
+    },
+    BucketTable {
+        name: "browser_domain_buckets",
+        keys: &["app_identifier", "domain"],
+        carried: &["last_url"],
+        sums: FOCUS_SUMS,
+        has_granularity: false,
+    },
+    BucketTable {
+        name: "terminal_context_buckets",
+        keys: &["app_identifier", "command", "working_dir"],
+        carried: &[],
+        sums: FOCUS_SUMS,
+        has_granularity: false,
+    },
+    BucketTable {
+        name: "project_buckets",
+        keys: &["project", "app_identifier"],
+        carried: &["project_root"],
+        sums: FOCUS_SUMS,
+        has_granularity: false,
+    },
+];
+
+pub fn load_retention_policy(conn: &Connection) -> Result<RetentionPolicy> {
+    let defaults = RetentionPolicy::default();
+    Ok(RetentionPolicy {
+        full_detail_days: meta_days(conn, "retention_full_detail_days")?
+            .unwrap_or(defaults.full_detail_days),
+        hourly_days: meta_days(conn, "retention_hourly_days")?.unwrap_or(defaults.hourly_days),
+        title_days: meta_days(conn, "retention_title_days")?.unwrap_or(defaults.title_days),
+    })
+}
+
+pub fn save_retention_policy(conn: &Connection, policy: &RetentionPolicy) -> Result<()> {
+    for (key, days) in [
+        ("retention_full_detail_days", policy.full_detail_days),
+        ("retention_hourly_days", policy.hourly_days),
+        ("retention_title_days", policy.title_days),
+    ] {
+        conn.execute(
+            "
+            INSERT INTO schema_meta (key, value)
+            VALUES (?1, ?2)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value
+            ",
+            params![key, days.to_string()],
+        )
+        .with_context(|| "Failed to store the retention policy")?;
+    }
+    Ok(())
+}
+
+fn meta_days(conn: &Connection, key: &str) -> Result<Option<u32>> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM schema_meta WHERE key = ?1",
+            [key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.and_then(|value| value.parse().ok()))
+}
+
+/// Applies `policy` as of `now`. A dry run reports what would be compacted and leaves the
+/// database as it was.
+pub fn run_maintenance(
+    conn: &mut Connection,
+    policy: RetentionPolicy,
+    now: DateTime<Utc>,
+    dry_run: bool,
+) -> Result<MaintenanceReport> {
+    let tx = conn.transaction()?;
+    let mut compactions = Vec::new();
+
+    if policy.full_detail_days > 0 {
+        let cutoff = rollup_start(now - Duration::days(policy.full_detail_days.into()), 60);
+        for table in &BUCKET_TABLES {
+            compactions.extend(downsample(&tx, table, 60, cutoff)?);
+        }
+    }
+    if policy.hourly_days > 0 {
+        let cutoff = rollup_start(now - Duration::days(policy.hourly_days.into()), 1440);
+        for table in &BUCKET_TABLES {
+            compactions.extend(downsample(&tx, table, 1440, cutoff)?);
+        }
+        compactions.extend(drop_hourly_rollups(&tx, cutoff)?);
+    }
+    if policy.title_days > 0 {
+        let cutoff = now - Duration::days(policy.title_days.into());
+        compactions.extend(drop_window_titles(&tx, cutoff)?);
+    }
+
+    if !dry_run {
+        let ran_at = now.to_rfc3339();
+        for compaction in &compactions {
+            tx.execute(
+                "
+                INSERT INTO compactions (
+                    ran_at_utc, table_name, action, cutoff_utc, rows_before, rows_after
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ",
+                params![
+                    ran_at,
+                    compaction.table_name,
+                    compaction.action,
+                    compaction.cutoff_utc,
+                    compaction.rows_before as i64,
+                    compaction.rows_after as i64,
+                ],
+            )?;
+        }
+        tx.commit()
+            .with_context(|| "Failed to commit the compacted history")?;
+    }
+
+    Ok(MaintenanceReport {
+        policy,
+        dry_run,
+        compactions,
+    })
+}
+
+/// Merges the rows of `table` that start before `cutoff` and are shorter than
+/// `target_minutes` into one row per `target_minutes` period.
+fn downsample(
+    conn: &Connection,
+    table: &BucketTable,
+    target_minutes: u32,
+    cutoff: DateTime<Utc>,
+) -> Result<Option<CompactionRecord>> {
+    let selection = format!(
+        "FROM {} WHERE bucket_start_utc < ?1
+            AND strftime('%s', bucket_end_utc) - strftime('%s', bucket_start_utc) < {}",
+        table.name,
+        target_minutes * 60
+    );
+    let cutoff_utc = cutoff.to_rfc3339();
+    let rows_before: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) {selection}"),
+        [&cutoff_utc],
+        |row| row.get(0),
+    )?;
+    if rows_before == 0 {
+        return Ok(None);
+    }
+
+    // Days are local ones, so per-day and hour-of-day reports keep their dates. A day starts
+    // at midnight in the offset of its earliest merged row.
+    let (period_start, period) = match target_minutes {
+        60 => (
+            "substr(bucket_start_utc, 1, 13) || ':00:00+00:00'",
+            "period_start",
+        ),
+        _ => (
+            "strftime('%Y-%m-%dT%H:%M:%S+00:00', local_date, -timezone_offset_minutes || ' minutes')",
+            "local_date",
+        ),
+    };
+    let group_columns = table.keys.join(", ");
+    let key_columns = prefixed(table.keys);
+    let carried_columns = prefixed(table.carried);
+    let sum_columns = table
+        .sums
+        .iter()
+        .map(|column| format!(", SUM({column}) AS {column}"))
+        .collect::<String>();
+    // With a single MIN() in the query, SQLite takes the bare local columns from the earliest
+    // of the merged rows.
+    conn.execute(
+        &format!(
+            "
+            CREATE TEMP TABLE retention_merged AS
+            SELECT
+                source_id,
+                {period_start} AS period_start,
+                MIN(bucket_start_utc) AS first_start,
+                local_date,
+                local_hour,
+                timezone_offset_minutes
+                {key_columns}{carried_columns}{sum_columns}
+            {selection}
+            GROUP BY source_id, {period}{group_by}
+            ",
+            group_by = if group_columns.is_empty() {
+                String::new()
+            } else {
+                format!(", {group_columns}")
+            },
+        ),
+        [&cutoff_utc],
+    )?;
+    conn.execute(&format!("DELETE {selection}"), [&cutoff_utc])?;
+    let rows_after: i64 = conn.query_row("SELECT COUNT(*) FROM retention_merged", [], |row| {
+        row.get(0)
+    })?;
+
+    let mut insert_columns = vec![
+        "source_id",
+        "bucket_start_utc",
+        "bucket_end_utc",
+        "local_date",
+        "local_hour",
+        "timezone_offset_minutes",
+    ];
+    let mut select_columns = vec![
+        "source_id".to_string(),
+        "period_start".to_string(),
+        format!("strftime('%Y-%m-%dT%H:%M:%S+00:00', period_start, '+{target_minutes} minutes')"),
+        "local_date".to_string(),
+        "local_hour".to_string(),
+        "timezone_offset_minutes".to_string(),
+    ];
+    let mut conflict_columns = vec!["source_id", "bucket_start_utc"];
+    if table.has_granularity {
+        insert_columns.push("granularity_minutes");
+        select_columns.push(target_minutes.to_string());
+        conflict_columns.push("granularity_minutes");
+    }
+    conflict_columns.extend(table.keys);
+    for column in table.keys.iter().chain(table.carried).chain(table.sums) {
+        insert_columns.push(column);
+        select_columns.push(column.to_string());
+    }
+    let updates = table
+        .sums
+        .iter()
+        .map(|column| {
+            format!(
+                "{column} = {table}.{column} + excluded.{column}",
+                table = table.name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    // `WHERE true` keeps SQLite from reading ON CONFLICT as a join constraint.
+    conn.execute_batch(&format!(
+        "
+        INSERT INTO {table} ({insert_columns})
+        SELECT {select_columns} FROM retention_merged WHERE true
+        ON CONFLICT({conflict_columns}) DO UPDATE SET {updates};
+        DROP TABLE retention_merged;
+        ",
+        table = table.name,
+        insert_columns = insert_columns.join(", "),
+        select_columns = select_columns.join(", "),
+        conflict_columns = conflict_columns.join(", "),
+    ))
+    .with_context(|| format!("Failed to downsample {}", table.name))?;
+
+    Ok(Some(CompactionRecord {
+        table_name: table.name.to_string(),
+        action: match target_minutes {
+            60 => "hourly",
+            _ => "daily",
+        }
+        .to_string(),
+        cutoff_utc,
+        rows_before: rows_before as u64,
+        rows_after: rows_after as u64,
+    }))
+}
+
+/// Hourly rollups of history that only has daily buckets left would not add anything.
+fn drop_hourly_rollups(conn: &Connection, cutoff: DateTime<Utc>) -> Result<Vec<CompactionRecord>> {
+    let cutoff_utc = cutoff.to_rfc3339();
+    let mut compactions = Vec::new();
+    for table in ["input_rollups", "focus_rollups"] {
+        let deleted = conn.execute(
+            &format!(
+                "DELETE FROM {table} WHERE granularity_minutes = 60 AND bucket_start_utc < ?1"
+            ),
+            [&cutoff_utc],
+        )?;
+        if deleted > 0 {
+            compactions.push(CompactionRecord {
+                table_name: table.to_string(),
+                action: "drop_hourly".to_string(),
+                cutoff_utc: cutoff_utc.clone(),
+                rows_before: deleted as u64,
+                rows_after: 0,
+            });
+        }
+    }
+    Ok(compactions)
+}
+
+/// Clears the window titles of focus rows that start before `cutoff`, merging the rows of
+/// each app and window class within a bucket.
+fn drop_window_titles(
+    conn: &Connection,
+    cutoff: DateTime<Utc>,
+) -> Result<Option<CompactionRecord>> {
+    let cutoff_utc = cutoff.to_rfc3339();
+    let selection = "FROM focus_buckets WHERE bucket_start_utc < ?1 AND window_title != ''";
+    let rows_before: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) {selection}"),
+        [&cutoff_utc],
+        |row| row.get(0),
+    )?;
+    if rows_before == 0 {
+        return Ok(None);
+    }
+
+    conn.execute(
+        &format!(
+            "
+            CREATE TEMP TABLE retention_untitled AS
+            SELECT
+                source_id, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+                timezone_offset_minutes, app_identifier, window_class,
+                SUM(focus_seconds) AS focus_seconds
+            {selection}
+            GROUP BY source_id, bucket_start_utc, window_class
+            "
+        ),
+        [&cutoff_utc],
+    )?;
+    conn.execute(&format!("DELETE {selection}"), [&cutoff_utc])?;
+    let rows_after: i64 = conn.query_row("SELECT COUNT(*) FROM retention_untitled", [], |row| {
+        row.get(0)
+    })?;
+    conn.execute_batch(
+        "
+        INSERT INTO focus_buckets (
+            source_id, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+            timezone_offset_minutes, app_identifier, window_title, window_class, focus_seconds
+        )
+        SELECT
+            source_id, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+            timezone_offset_minutes, app_identifier, '', window_class, focus_seconds
+        FROM retention_untitled WHERE true
+        ON CONFLICT(source_id, bucket_start_utc, window_title, window_class) DO UPDATE SET
+            focus_seconds = focus_buckets.focus_seconds + excluded.focus_seconds;
+        DROP TABLE retention_untitled;
+        ",
+    )
+    .with_context(|| "Failed to drop old window titles")?;
+
+    Ok(Some(CompactionRecord {
+        table_name: "focus_buckets".to_string(),
+        action: "drop_titles".to_string(),
+        cutoff_utc,
+        rows_before: rows_before as u64,
+        rows_after: rows_after as u64,
+    }))
+}
+
+/// The latest cutoff `action` compacted `table` at. Rows starting before it only exist in
+/// their compacted form.
+#[cfg(feature = "multi-sync")]
+fn compacted_before(conn: &Connection, table: &str, action: &str) -> Result<Option<String>> {
+    Ok(conn.query_row(
+        "SELECT MAX(cutoff_utc) FROM compactions WHERE table_name = ?1 AND action = ?2",
+        [table, action],
+        |row| row.get(0),
+    )?)
+}
+
+/// The period a bucket of `table` starting at `bucket_start` was merged into, if any.
+#[cfg(feature = "multi-sync")]
+fn compacted_minutes(
+    conn: &Connection,
+    table: &str,
+    bucket_start: DateTime<Utc>,
+) -> Result<Option<u32>> {
+    let bucket_start_utc = bucket_start.to_rfc3339();
+    for (action, minutes) in [("daily", 1440), ("hourly", 60)] {
+        if compacted_before(conn, table, action)?.is_some_and(|cutoff| bucket_start_utc < cutoff) {
+            return Ok(Some(minutes));
+        }
+    }
+    Ok(None)
+}
+
+/// The start of the hour, or of the local day, that [`downsample`] merges a bucket into.
+#[cfg(feature = "multi-sync")]
+fn period_start(
+    bucket_start: DateTime<Utc>,
+    local_date: &str,
+    timezone_offset_minutes: i32,
+    minutes: u32,
+) -> DateTime<Utc> {
+    let local_midnight = NaiveDate::parse_from_str(local_date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0));
+    match (minutes, local_midnight) {
+        (1440, Some(midnight)) => {
+            midnight.and_utc() - Duration::minutes(timezone_offset_minutes.into())
+        }
+        _ => rollup_start(bucket_start, minutes),
+    }
+}
+
+/// Moves a bucket that arrives after maintenance compacted its range into the period it would
+/// have been merged into, so inserting it adds to that bucket's totals.
+#[cfg(feature = "multi-sync")]
+pub fn compact_late_input_row(conn: &Connection, row: &mut InputBucketRecord) -> Result<()> {
+    if let Some(minutes) = compacted_minutes(conn, "input_buckets", row.bucket_start_utc)? {
+        if row.granularity_minutes < minutes {
+            row.bucket_start_utc = period_start(
+                row.bucket_start_utc,
+                &row.local_date,
+                row.timezone_offset_minutes,
+                minutes,
+            );
+            row.bucket_end_utc = row.bucket_start_utc + Duration::minutes(minutes.into());
+            row.granularity_minutes = minutes;
+        }
+    }
+    Ok(())
+}
+
+/// Like [`compact_late_input_row`], and also drops the title where maintenance dropped titles.
+#[cfg(feature = "multi-sync")]
+pub fn compact_late_focus_row(conn: &Connection, row: &mut FocusBucketRecord) -> Result<()> {
+    if compacted_before(conn, "focus_buckets", "drop_titles")?
+        .is_some_and(|cutoff| row.bucket_start_utc.to_rfc3339() < cutoff)
+    {
+        row.window_title.clear();
+    }
+    if let Some(minutes) = compacted_minutes(conn, "focus_buckets", row.bucket_start_utc)? {
+        let period = Duration::minutes(minutes.into());
+        if row.bucket_end_utc - row.bucket_start_utc < period {
+            row.bucket_start_utc = period_start(
+                row.bucket_start_utc,
+                &row.local_date,
+                row.timezone_offset_minutes,
+                minutes,
+            );
+            row.bucket_end_utc = row.bucket_start_utc + period;
+        }
+    }
+    Ok(())
+}
+
+impl MaintenanceReport {
+    pub fn render(&self) -> String {
+        let days = |days: u32| match days {
+            0 => "forever".to_string(),
+            days => format!("{days} days"),
+        };
+        let mut lines = vec![format!(
+            "retention: full detail {}, hourly {}, window titles {}",
+            days(self.policy.full_detail_days),
+            days(self.policy.hourly_days),
+            days(self.policy.title_days),
+        )];
+        if self.compactions.is_empty() {
+            lines.push("nothing to compact".to_string());
+        }
+        for compaction in &self.compactions {
+            lines.push(format!(
+                "  {:<24}  {:<11}  before {}  {:>8} -> {} rows",
+                compaction.table_name,
+                compaction.action,
+                compaction.cutoff_utc,
+                compaction.rows_before,
+                compaction.rows_after,
+            ));
+        }
+        if self.dry_run {
+            lines.push("dry run, nothing was changed".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+fn prefixed(columns: &[&str]) -> String {
+    columns.iter().map(|column| format!(", {column}")).collect()
+}
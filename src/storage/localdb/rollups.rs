@@ -0,0 +1,153 @@
+//! Hourly and daily rollups of `input_buckets` and `focus_buckets`.
+//!
+//! Every write to the bucket tables adds the same amounts to the rollup rows covering it, so
+//! long ranges can be charted without reading every fine-grained bucket. Rollups are aligned to
+//! UTC like the dashboard series, and a bucket is counted in the rollup its start falls in.
+//! Databases from before the rollups existed, and imports, rebuild them from the buckets.
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, Connection};
+
+use crate::common::{FocusBucketRecord, InputBucketRecord};
+
+/// Granularities, in minutes, that rollups are kept at: hourly and daily.
+pub const ROLLUP_GRANULARITIES: [u32; 2] = [60, 1440];
+
+/// The start of the `granularity_minutes` rollup covering `at`.
+pub fn rollup_start(at: DateTime<Utc>, granularity_minutes: u32) -> DateTime<Utc> {
+    let seconds = granularity_minutes as i64 * 60;
+    Utc.timestamp_opt(at.timestamp().div_euclid(seconds) * seconds, 0)
+        .single()
+        .unwrap_or(at)
+}
+
+/// The coarsest rollup that charts `bucket_minutes` wide buckets exactly, if any.
+pub fn rollup_for_series(bucket_minutes: i64) -> Option<u32> {
+    ROLLUP_GRANULARITIES
+        .into_iter()
+        .rev()
+        .find(|granularity| bucket_minutes > 0 && bucket_minutes % *granularity as i64 == 0)
+}
+
+pub fn add_input_rollups(conn: &Connection, rows: &[InputBucketRecord]) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "
+        INSERT INTO input_rollups (
+            source_id,
+            granularity_minutes,
+            bucket_start_utc,
+            left_clicks,
+            right_clicks,
+            middle_clicks,
+            key_presses,
+            mouse_distance_cm,
+            scroll_vertical_cm,
+            scroll_horizontal_cm,
+            dropped_events
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(source_id, granularity_minutes, bucket_start_utc) DO UPDATE SET
+            left_clicks = input_rollups.left_clicks + excluded.left_clicks,
+            right_clicks = input_rollups.right_clicks + excluded.right_clicks,
+            middle_clicks = input_rollups.middle_clicks + excluded.middle_clicks,
+            key_presses = input_rollups.key_presses + excluded.key_presses,
+            mouse_distance_cm = input_rollups.mouse_distance_cm + excluded.mouse_distance_cm,
+            scroll_vertical_cm = input_rollups.scroll_vertical_cm + excluded.scroll_vertical_cm,
+            scroll_horizontal_cm = input_rollups.scroll_horizontal_cm + excluded.scroll_horizontal_cm,
+            dropped_events = input_rollups.dropped_events + excluded.dropped_events
+        ",
+    )?;
+
+    for row in rows {
+        for granularity in ROLLUP_GRANULARITIES {
+            stmt.execute(params![
+                row.source_id,
+                granularity,
+                rollup_start(row.bucket_start_utc, granularity).to_rfc3339(),
+                row.left_clicks,
+                row.right_clicks,
+                row.middle_clicks,
+                row.key_presses,
+                row.mouse_distance_cm,
+                row.scroll_vertical_cm,
+                row.scroll_horizontal_cm,
+                row.dropped_events,
+            ])
+            .with_context(|| "Failed to update input rollup row")?;
+        }
+    }
+    Ok(())
+}
+
+pub fn add_focus_rollups(conn: &Connection, rows: &[FocusBucketRecord]) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "
+        INSERT INTO focus_rollups (
+            source_id,
+            granularity_minutes,
+            bucket_start_utc,
+            app_identifier,
+            focus_seconds
+        ) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(source_id, granularity_minutes, bucket_start_utc, app_identifier) DO UPDATE SET
+            focus_seconds = focus_rollups.focus_seconds + excluded.focus_seconds
+        ",
+    )?;
+
+    for row in rows {
+        for granularity in ROLLUP_GRANULARITIES {
+            stmt.execute(params![
+                row.source_id,
+                granularity,
+                rollup_start(row.bucket_start_utc, granularity).to_rfc3339(),
+                row.app_identifier,
+                row.focus_seconds,
+            ])
+            .with_context(|| "Failed to update focus rollup row")?;
+        }
+    }
+    Ok(())
+}
+
+/// Recomputes every rollup from the bucket tables. Bucket starts are stored as UTC RFC 3339
+/// strings, so the hour and the day are prefixes of them.
+pub fn rebuild_rollups(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        DELETE FROM input_rollups;
+        DELETE FROM focus_rollups;
+
+        INSERT INTO input_rollups (
+            source_id, granularity_minutes, bucket_start_utc,
+            left_clicks, right_clicks, middle_clicks, key_presses,
+            mouse_distance_cm, scroll_vertical_cm, scroll_horizontal_cm, dropped_events
+        )
+        SELECT
+            source_id, r.granularity_minutes,
+            CASE r.granularity_minutes
+                WHEN 60 THEN substr(bucket_start_utc, 1, 13) || ':00:00+00:00'
+                ELSE substr(bucket_start_utc, 1, 10) || 'T00:00:00+00:00'
+            END AS rollup_start,
+            SUM(left_clicks), SUM(right_clicks), SUM(middle_clicks), SUM(key_presses),
+            SUM(mouse_distance_cm), SUM(scroll_vertical_cm), SUM(scroll_horizontal_cm),
+            SUM(dropped_events)
+        FROM input_buckets
+        CROSS JOIN (SELECT 60 AS granularity_minutes UNION ALL SELECT 1440) r
+        GROUP BY source_id, r.granularity_minutes, rollup_start;
+
+        INSERT INTO focus_rollups (
+            source_id, granularity_minutes, bucket_start_utc, app_identifier, focus_seconds
+        )
+        SELECT
+            source_id, r.granularity_minutes,
+            CASE r.granularity_minutes
+                WHEN 60 THEN substr(bucket_start_utc, 1, 13) || ':00:00+00:00'
+                ELSE substr(bucket_start_utc, 1, 10) || 'T00:00:00+00:00'
+            END AS rollup_start,
+            app_identifier, SUM(focus_seconds)
+        FROM focus_buckets
+        CROSS JOIN (SELECT 60 AS granularity_minutes UNION ALL SELECT 1440) r
+        GROUP BY source_id, r.granularity_minutes, rollup_start, app_identifier;
+        ",
+    )
+    .with_context(|| "Failed to rebuild the hourly and daily rollups")
+}
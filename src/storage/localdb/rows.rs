@@ -4,7 +4,14 @@ use std::fs;
 use std::path::Path;
 use std::time::Duration;
 
-use crate::common::{FocusBucketRecord, InputBucketRecord, SourceInfo};
+use crate::common::{
+    CollectorEventRecord, DomainBucketRecord, FocusBucketRecord, InputBucketRecord,
+    ProjectBucketRecord, SourceInfo, SuspendRecord, TerminalBucketRecord,
+};
+
+use super::encryption::{apply_passphrase, database_passphrase, ensure_readable};
+use super::journal::configure_journal_mode;
+use super::rollups::{add_focus_rollups, add_input_rollups};
 
 pub fn insert_input_buckets(conn: &Connection, rows: &[InputBucketRecord]) -> Result<()> {
     if rows.is_empty() {
@@ -27,8 +34,9 @@ pub fn insert_input_buckets(conn: &Connection, rows: &[InputBucketRecord]) -> Re
             key_presses,
             mouse_distance_cm,
             scroll_vertical_cm,
-            scroll_horizontal_cm
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            scroll_horizontal_cm,
+            dropped_events
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         ON CONFLICT(source_id, bucket_start_utc, granularity_minutes) DO UPDATE SET
             bucket_end_utc = excluded.bucket_end_utc,
             local_date = excluded.local_date,
@@ -40,7 +48,8 @@ pub fn insert_input_buckets(conn: &Connection, rows: &[InputBucketRecord]) -> Re
             key_presses = input_buckets.key_presses + excluded.key_presses,
             mouse_distance_cm = input_buckets.mouse_distance_cm + excluded.mouse_distance_cm,
             scroll_vertical_cm = input_buckets.scroll_vertical_cm + excluded.scroll_vertical_cm,
-            scroll_horizontal_cm = input_buckets.scroll_horizontal_cm + excluded.scroll_horizontal_cm
+            scroll_horizontal_cm = input_buckets.scroll_horizontal_cm + excluded.scroll_horizontal_cm,
+            dropped_events = input_buckets.dropped_events + excluded.dropped_events
         ",
     )?;
 
@@ -60,11 +69,12 @@ pub fn insert_input_buckets(conn: &Connection, rows: &[InputBucketRecord]) -> Re
             row.mouse_distance_cm,
             row.scroll_vertical_cm,
             row.scroll_horizontal_cm,
+            row.dropped_events,
         ])
         .with_context(|| "Failed to insert input bucket row")?;
     }
 
-    Ok(())
+    add_input_rollups(conn, rows)
 }
 
 pub fn insert_focus_buckets(conn: &Connection, rows: &[FocusBucketRecord]) -> Result<()> {
@@ -112,6 +122,233 @@ pub fn insert_focus_buckets(conn: &Connection, rows: &[FocusBucketRecord]) -> Re
         .with_context(|| "Failed to insert focus bucket row")?;
     }
 
+    add_focus_rollups(conn, rows)
+}
+
+pub fn insert_domain_buckets(conn: &Connection, rows: &[DomainBucketRecord]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "
+        INSERT INTO browser_domain_buckets (
+            source_id,
+            bucket_start_utc,
+            bucket_end_utc,
+            local_date,
+            local_hour,
+            timezone_offset_minutes,
+            app_identifier,
+            domain,
+            last_url,
+            focus_seconds
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(source_id, bucket_start_utc, app_identifier, domain) DO UPDATE SET
+            bucket_end_utc = excluded.bucket_end_utc,
+            local_date = excluded.local_date,
+            local_hour = excluded.local_hour,
+            timezone_offset_minutes = excluded.timezone_offset_minutes,
+            last_url = COALESCE(excluded.last_url, browser_domain_buckets.last_url),
+            focus_seconds = browser_domain_buckets.focus_seconds + excluded.focus_seconds
+        ",
+    )?;
+
+    for row in rows {
+        stmt.execute(params![
+            row.source_id,
+            row.bucket_start_utc.to_rfc3339(),
+            row.bucket_end_utc.to_rfc3339(),
+            row.local_date,
+            row.local_hour,
+            row.timezone_offset_minutes,
+            row.app_identifier,
+            row.domain,
+            row.last_url,
+            row.focus_seconds,
+        ])
+        .with_context(|| "Failed to insert browser domain bucket row")?;
+    }
+
+    Ok(())
+}
+
+pub fn insert_terminal_buckets(conn: &Connection, rows: &[TerminalBucketRecord]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "
+        INSERT INTO terminal_context_buckets (
+            source_id,
+            bucket_start_utc,
+            bucket_end_utc,
+            local_date,
+            local_hour,
+            timezone_offset_minutes,
+            app_identifier,
+            command,
+            working_dir,
+            focus_seconds
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(source_id, bucket_start_utc, app_identifier, command, working_dir) DO UPDATE SET
+            bucket_end_utc = excluded.bucket_end_utc,
+            local_date = excluded.local_date,
+            local_hour = excluded.local_hour,
+            timezone_offset_minutes = excluded.timezone_offset_minutes,
+            focus_seconds = terminal_context_buckets.focus_seconds + excluded.focus_seconds
+        ",
+    )?;
+
+    for row in rows {
+        stmt.execute(params![
+            row.source_id,
+            row.bucket_start_utc.to_rfc3339(),
+            row.bucket_end_utc.to_rfc3339(),
+            row.local_date,
+            row.local_hour,
+            row.timezone_offset_minutes,
+            row.app_identifier,
+            row.command,
+            row.working_dir,
+            row.focus_seconds,
+        ])
+        .with_context(|| "Failed to insert terminal context bucket row")?;
+    }
+
+    Ok(())
+}
+
+pub fn insert_project_buckets(conn: &Connection, rows: &[ProjectBucketRecord]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "
+        INSERT INTO project_buckets (
+            source_id,
+            bucket_start_utc,
+            bucket_end_utc,
+            local_date,
+            local_hour,
+            timezone_offset_minutes,
+            project,
+            project_root,
+            app_identifier,
+            focus_seconds
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(source_id, bucket_start_utc, project, app_identifier) DO UPDATE SET
+            bucket_end_utc = excluded.bucket_end_utc,
+            local_date = excluded.local_date,
+            local_hour = excluded.local_hour,
+            timezone_offset_minutes = excluded.timezone_offset_minutes,
+            project_root = COALESCE(excluded.project_root, project_buckets.project_root),
+            focus_seconds = project_buckets.focus_seconds + excluded.focus_seconds
+        ",
+    )?;
+
+    for row in rows {
+        stmt.execute(params![
+            row.source_id,
+            row.bucket_start_utc.to_rfc3339(),
+            row.bucket_end_utc.to_rfc3339(),
+            row.local_date,
+            row.local_hour,
+            row.timezone_offset_minutes,
+            row.project,
+            row.project_root,
+            row.app_identifier,
+            row.focus_seconds,
+        ])
+        .with_context(|| "Failed to insert project bucket row")?;
+    }
+
+    Ok(())
+}
+
+pub fn insert_suspend_period(conn: &Connection, period: &SuspendRecord) -> Result<()> {
+    let duration_seconds = (period.ended_at_utc - period.started_at_utc)
+        .num_seconds()
+        .max(0);
+    conn.execute(
+        "
+        INSERT INTO suspend_periods (
+            source_id,
+            started_at_utc,
+            ended_at_utc,
+            duration_seconds,
+            detected_by
+        ) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(source_id, started_at_utc) DO NOTHING
+        ",
+        params![
+            period.source_id,
+            period.started_at_utc.to_rfc3339(),
+            period.ended_at_utc.to_rfc3339(),
+            duration_seconds,
+            period.detected_by.as_str(),
+        ],
+    )
+    .with_context(|| "Failed to insert suspend period row")?;
+
+    Ok(())
+}
+
+pub fn insert_collector_event(conn: &Connection, event: &CollectorEventRecord) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO collector_events (
+            source_id,
+            occurred_at_utc,
+            task,
+            kind,
+            message
+        ) VALUES (?, ?, ?, ?, ?)
+        ",
+        params![
+            event.source_id,
+            event.occurred_at_utc.to_rfc3339(),
+            event.task,
+            event.kind.as_str(),
+            event.message,
+        ],
+    )
+    .with_context(|| "Failed to insert collector event row")?;
+
+    Ok(())
+}
+
+/// Generation and last sequence number of spool `spool` that are in the database.
+pub fn spool_checkpoint(conn: &Connection, spool: &str) -> Result<Option<(String, u64)>> {
+    conn.query_row(
+        "SELECT generation, last_seq FROM spool_checkpoints WHERE spool = ?1",
+        [spool],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .with_context(|| format!("Failed to read the checkpoint of spool {spool}"))
+}
+
+pub fn set_spool_checkpoint(
+    conn: &Connection,
+    spool: &str,
+    generation: &str,
+    last_seq: u64,
+) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO spool_checkpoints (spool, generation, last_seq, applied_at_utc)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(spool) DO UPDATE SET
+            generation = excluded.generation,
+            last_seq = excluded.last_seq,
+            applied_at_utc = excluded.applied_at_utc
+        ",
+        params![spool, generation, last_seq, chrono::Utc::now().to_rfc3339()],
+    )
+    .with_context(|| format!("Failed to store the checkpoint of spool {spool}"))?;
     Ok(())
 }
 
@@ -177,6 +414,14 @@ pub fn upsert_source_by_uuid(
 }
 
 pub fn open_con_at(db_path: &Path) -> Result<Connection> {
+    open_con_with_passphrase(db_path, database_passphrase()?.as_deref())
+}
+
+/// [`open_con_at`] with an explicit passphrase instead of the configured one.
+pub(crate) fn open_con_with_passphrase(
+    db_path: &Path,
+    passphrase: Option<&str>,
+) -> Result<Connection> {
     if let Some(parent) = db_path.parent() {
         fs::create_dir_all(parent).with_context(|| {
             format!(
@@ -196,7 +441,10 @@ pub fn open_con_at(db_path: &Path) -> Result<Connection> {
             db_path.display()
         )
     })?;
+    apply_passphrase(&conn, passphrase)?;
     conn.busy_timeout(Duration::from_secs(5))
         .with_context(|| "Failed to configure sqlite busy timeout")?;
+    ensure_readable(&conn, db_path)?;
+    configure_journal_mode(&conn, db_path)?;
     Ok(conn)
 }
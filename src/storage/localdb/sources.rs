@@ -0,0 +1,434 @@
+//! Managing the sources a database holds rows for: `vigil sources list|rename|merge|delete`.
+//!
+//! A merged or deleted source is remembered in `retired_sources`, so rows that sync brings in
+//! for it later land in the source it was merged into, or are dropped, instead of bringing
+//! it back.
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::common::{
+    DomainBucketRecord, FocusBucketRecord, InputBucketRecord, ProjectBucketRecord, SourceInfo,
+    TerminalBucketRecord, DEFAULT_SOURCE_ID,
+};
+
+use super::retention::BUCKET_TABLES;
+use super::rollups::rebuild_rollups;
+use super::rows::{
+    get_source_by_uuid, insert_domain_buckets, insert_focus_buckets, insert_input_buckets,
+    insert_project_buckets, insert_terminal_buckets,
+};
+use super::schema::set_own_source_name;
+
+/// Tables besides the buckets whose rows belong to a source.
+const SOURCE_TABLES: [&str; 3] = ["sessions", "suspend_periods", "collector_events"];
+
+#[derive(Debug, Clone)]
+pub struct SourceSummary {
+    pub source: SourceInfo,
+    pub created_at_utc: String,
+    /// Rows across all bucket tables.
+    pub bucket_rows: u64,
+    pub last_bucket_utc: Option<String>,
+}
+
+impl SourceSummary {
+    /// This machine's source, the one the collector writes to.
+    pub fn is_own(&self) -> bool {
+        self.source.id == DEFAULT_SOURCE_ID
+    }
+}
+
+/// What became of a source that is no longer in `sources`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetiredSource {
+    MergedInto(String),
+    Deleted,
+}
+
+pub fn list_sources(conn: &Connection) -> Result<Vec<SourceSummary>> {
+    let buckets = BUCKET_TABLES
+        .iter()
+        .map(|table| format!("SELECT source_id, bucket_end_utc FROM {}", table.name))
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT s.id, s.source_uuid, s.source_name, s.platform, s.created_at_utc,
+               COUNT(b.source_id), MAX(b.bucket_end_utc)
+        FROM sources s
+        LEFT JOIN ({buckets}) b ON b.source_id = s.id
+        GROUP BY s.id
+        ORDER BY s.id
+        "
+    ))?;
+    let sources = stmt
+        .query_map([], |row| {
+            Ok(SourceSummary {
+                source: SourceInfo {
+                    id: row.get(0)?,
+                    source_uuid: row.get(1)?,
+                    source_name: row.get(2)?,
+                    platform: row.get(3)?,
+                },
+                created_at_utc: row.get(4)?,
+                bucket_rows: row.get(5)?,
+                last_bucket_utc: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .with_context(|| "Failed to list sources")?;
+    Ok(sources)
+}
+
+pub fn render_source_list(sources: &[SourceSummary]) -> String {
+    let name_width = sources
+        .iter()
+        .map(|summary| summary.source.source_name.len())
+        .max()
+        .unwrap_or(0);
+    let mut lines = Vec::new();
+    for summary in sources {
+        lines.push(format!(
+            "{} {}  {:<name_width$}  {:<8} added {}  {:>10} rows  last {}",
+            if summary.is_own() { "*" } else { " " },
+            summary.source.source_uuid,
+            summary.source.source_name,
+            summary.source.platform,
+            summary
+                .created_at_utc
+                .get(..10)
+                .unwrap_or(&summary.created_at_utc),
+            summary.bucket_rows,
+            summary.last_bucket_utc.as_deref().unwrap_or("never"),
+        ));
+    }
+    lines.push("* this machine".to_string());
+    lines.join("\n")
+}
+
+/// Finds the source `selector` names: its UUID, a unique UUID prefix, or its name.
+pub fn find_source(conn: &Connection, selector: &str) -> Result<SourceInfo> {
+    if let Some(source) = get_source_by_uuid(conn, selector)? {
+        return Ok(source);
+    }
+    let sources = list_sources(conn)?;
+    let by_name: Vec<_> = sources
+        .iter()
+        .filter(|summary| summary.source.source_name == selector)
+        .collect();
+    let matches = if by_name.is_empty() {
+        sources
+            .iter()
+            .filter(|summary| summary.source.source_uuid.starts_with(selector))
+            .collect()
+    } else {
+        by_name
+    };
+    match matches.as_slice() {
+        [summary] => Ok(summary.source.clone()),
+        [] => bail!("No source matches '{selector}'; see `vigil sources list`"),
+        _ => bail!(
+            "'{selector}' matches several sources ({}); use the UUID",
+            matches
+                .iter()
+                .map(|summary| summary.source.source_uuid.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Renames the source with `source_uuid`, if this database has it. This machine's own
+/// source keeps the name from then on instead of taking the host name.
+pub fn rename_source(conn: &Connection, source_uuid: &str, source_name: &str) -> Result<()> {
+    if source_name.trim().is_empty() {
+        bail!("A source name cannot be empty");
+    }
+    let Some(source) = get_source_by_uuid(conn, source_uuid)? else {
+        return Ok(());
+    };
+    conn.execute(
+        "UPDATE sources SET source_name = ?2 WHERE id = ?1",
+        params![source.id, source_name],
+    )
+    .with_context(|| format!("Failed to rename source {source_uuid}"))?;
+    if source.id == DEFAULT_SOURCE_ID {
+        set_own_source_name(conn, source_name)?;
+    }
+    Ok(())
+}
+
+/// Moves every row of the source `from_uuid` into `into_uuid`, adding up the buckets both
+/// have, and retires `from_uuid`. When this database doesn't know `into_uuid` yet, the rows
+/// simply take it over. Run it in a transaction.
+pub fn merge_sources(conn: &Connection, from_uuid: &str, into_uuid: &str) -> Result<()> {
+    if from_uuid == into_uuid {
+        bail!("Cannot merge a source into itself");
+    }
+    let into_uuid = match retired_source(conn, into_uuid)? {
+        // Already merged the other way round.
+        Some(RetiredSource::MergedInto(uuid)) if uuid == from_uuid => return Ok(()),
+        Some(RetiredSource::MergedInto(uuid)) => uuid,
+        Some(RetiredSource::Deleted) => return delete_source(conn, from_uuid),
+        None => into_uuid.to_string(),
+    };
+    let from = get_source_by_uuid(conn, from_uuid)?;
+    if from
+        .as_ref()
+        .is_some_and(|from| from.id == DEFAULT_SOURCE_ID)
+    {
+        bail!("Cannot merge this machine's own source into another one; merge the other one into it instead");
+    }
+
+    if let Some(from) = from {
+        match get_source_by_uuid(conn, &into_uuid)? {
+            Some(into) => move_source_rows(conn, from.id, into.id)?,
+            None => {
+                conn.execute(
+                    "UPDATE sources SET source_uuid = ?2 WHERE id = ?1",
+                    params![from.id, into_uuid],
+                )?;
+            }
+        }
+    }
+    retire_source(conn, from_uuid, Some(&into_uuid))
+}
+
+/// Deletes the source `source_uuid` with all its rows and retires it. Run it in a
+/// transaction.
+pub fn delete_source(conn: &Connection, source_uuid: &str) -> Result<()> {
+    if let Some(source) = get_source_by_uuid(conn, source_uuid)? {
+        if source.id == DEFAULT_SOURCE_ID {
+            bail!("Cannot delete this machine's own source");
+        }
+        for table in BUCKET_TABLES
+            .iter()
+            .map(|table| table.name)
+            .chain(SOURCE_TABLES)
+        {
+            conn.execute(
+                &format!("DELETE FROM {table} WHERE source_id = ?1"),
+                [source.id],
+            )
+            .with_context(|| format!("Failed to delete the rows of source {source_uuid}"))?;
+        }
+        conn.execute("DELETE FROM sources WHERE id = ?1", [source.id])?;
+        rebuild_rollups(conn)?;
+    }
+    for table in [
+        "sync_outbox_sources",
+        "sync_outbox_input_buckets",
+        "sync_outbox_focus_buckets",
+    ] {
+        conn.execute(
+            &format!("DELETE FROM {table} WHERE source_uuid = ?1 AND sent_at_utc IS NULL"),
+            [source_uuid],
+        )?;
+    }
+    retire_source(conn, source_uuid, None)
+}
+
+/// Whether `source_uuid` was merged away or deleted.
+pub fn retired_source(conn: &Connection, source_uuid: &str) -> Result<Option<RetiredSource>> {
+    let retired: Option<Option<String>> = conn
+        .query_row(
+            "SELECT merged_into_uuid FROM retired_sources WHERE source_uuid = ?1",
+            [source_uuid],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(retired.map(|merged_into| match merged_into {
+        Some(uuid) => RetiredSource::MergedInto(uuid),
+        None => RetiredSource::Deleted,
+    }))
+}
+
+fn move_source_rows(conn: &Connection, from_id: i64, into_id: i64) -> Result<()> {
+    // Rows both sources have for the same bucket are added up, as if they were flushed again.
+    let input = source_rows(
+        conn,
+        "input_buckets",
+        "granularity_minutes, left_clicks, right_clicks, middle_clicks, key_presses,
+         mouse_distance_cm, scroll_vertical_cm, scroll_horizontal_cm, dropped_events",
+        from_id,
+        |row| {
+            Ok(InputBucketRecord {
+                source_id: into_id,
+                bucket_start_utc: utc_column(row, 0)?,
+                bucket_end_utc: utc_column(row, 1)?,
+                local_date: row.get(2)?,
+                local_hour: row.get(3)?,
+                timezone_offset_minutes: row.get(4)?,
+                granularity_minutes: row.get(5)?,
+                left_clicks: row.get(6)?,
+                right_clicks: row.get(7)?,
+                middle_clicks: row.get(8)?,
+                key_presses: row.get(9)?,
+                mouse_distance_cm: row.get(10)?,
+                scroll_vertical_cm: row.get(11)?,
+                scroll_horizontal_cm: row.get(12)?,
+                dropped_events: row.get(13)?,
+            })
+        },
+    )?;
+    insert_input_buckets(conn, &input)?;
+
+    let focus = source_rows(
+        conn,
+        "focus_buckets",
+        "app_identifier, window_title, window_class, focus_seconds",
+        from_id,
+        |row| {
+            Ok(FocusBucketRecord {
+                source_id: into_id,
+                bucket_start_utc: utc_column(row, 0)?,
+                bucket_end_utc: utc_column(row, 1)?,
+                local_date: row.get(2)?,
+                local_hour: row.get(3)?,
+                timezone_offset_minutes: row.get(4)?,
+                app_identifier: row.get(5)?,
+                window_title: row.get(6)?,
+                window_class: row.get(7)?,
+                focus_seconds: row.get(8)?,
+            })
+        },
+    )?;
+    insert_focus_buckets(conn, &focus)?;
+
+    let domains = source_rows(
+        conn,
+        "browser_domain_buckets",
+        "app_identifier, domain, last_url, focus_seconds",
+        from_id,
+        |row| {
+            Ok(DomainBucketRecord {
+                source_id: into_id,
+                bucket_start_utc: utc_column(row, 0)?,
+                bucket_end_utc: utc_column(row, 1)?,
+                local_date: row.get(2)?,
+                local_hour: row.get(3)?,
+                timezone_offset_minutes: row.get(4)?,
+                app_identifier: row.get(5)?,
+                domain: row.get(6)?,
+                last_url: row.get(7)?,
+                focus_seconds: row.get(8)?,
+            })
+        },
+    )?;
+    insert_domain_buckets(conn, &domains)?;
+
+    let terminals = source_rows(
+        conn,
+        "terminal_context_buckets",
+        "app_identifier, command, working_dir, focus_seconds",
+        from_id,
+        |row| {
+            Ok(TerminalBucketRecord {
+                source_id: into_id,
+                bucket_start_utc: utc_column(row, 0)?,
+                bucket_end_utc: utc_column(row, 1)?,
+                local_date: row.get(2)?,
+                local_hour: row.get(3)?,
+                timezone_offset_minutes: row.get(4)?,
+                app_identifier: row.get(5)?,
+                command: row.get(6)?,
+                working_dir: row.get(7)?,
+                focus_seconds: row.get(8)?,
+            })
+        },
+    )?;
+    insert_terminal_buckets(conn, &terminals)?;
+
+    let projects = source_rows(
+        conn,
+        "project_buckets",
+        "project, project_root, app_identifier, focus_seconds",
+        from_id,
+        |row| {
+            Ok(ProjectBucketRecord {
+                source_id: into_id,
+                bucket_start_utc: utc_column(row, 0)?,
+                bucket_end_utc: utc_column(row, 1)?,
+                local_date: row.get(2)?,
+                local_hour: row.get(3)?,
+                timezone_offset_minutes: row.get(4)?,
+                project: row.get(5)?,
+                project_root: row.get(6)?,
+                app_identifier: row.get(7)?,
+                focus_seconds: row.get(8)?,
+            })
+        },
+    )?;
+    insert_project_buckets(conn, &projects)?;
+
+    for table in &BUCKET_TABLES {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE source_id = ?1", table.name),
+            [from_id],
+        )?;
+    }
+
+    conn.execute_batch(&format!(
+        "
+        UPDATE sessions SET source_id = {into_id} WHERE source_id = {from_id};
+        UPDATE collector_events SET source_id = {into_id} WHERE source_id = {from_id};
+        UPDATE OR IGNORE suspend_periods SET source_id = {into_id} WHERE source_id = {from_id};
+        DELETE FROM suspend_periods WHERE source_id = {from_id};
+        DELETE FROM sources WHERE id = {from_id};
+        "
+    ))
+    .with_context(|| "Failed to move sessions and events to the merged source")?;
+    rebuild_rollups(conn)
+}
+
+/// Reads the bucket rows of source `source_id` from `table`. `map` gets the bucket bounds,
+/// local date and hour and timezone offset first, then `columns`.
+fn source_rows<T>(
+    conn: &Connection,
+    table: &str,
+    columns: &str,
+    source_id: i64,
+    map: impl FnMut(&Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>> {
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT bucket_start_utc, bucket_end_utc, local_date, local_hour, timezone_offset_minutes,
+               {columns}
+        FROM {table}
+        WHERE source_id = ?1
+        "
+    ))?;
+    let rows = stmt
+        .query_map([source_id], map)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read {table} of the merged source"))?;
+    Ok(rows)
+}
+
+fn utc_column(row: &Row, index: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let value: String = row.get(index)?;
+    DateTime::parse_from_rfc3339(&value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
+
+fn retire_source(conn: &Connection, source_uuid: &str, merged_into: Option<&str>) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO retired_sources (source_uuid, merged_into_uuid, retired_at_utc)
+        VALUES (?1, ?2, ?3)
+        ON CONFLICT(source_uuid) DO UPDATE SET
+            merged_into_uuid = excluded.merged_into_uuid,
+            retired_at_utc = excluded.retired_at_utc
+        ",
+        params![source_uuid, merged_into, Utc::now().to_rfc3339()],
+    )?;
+    // Sources merged into this one earlier follow it.
+    conn.execute(
+        "UPDATE retired_sources SET merged_into_uuid = ?2 WHERE merged_into_uuid = ?1",
+        params![source_uuid, merged_into],
+    )?;
+    Ok(())
+}
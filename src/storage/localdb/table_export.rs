@@ -0,0 +1,606 @@
+//! `vigil export`: tables as CSV, JSON Lines or Parquet files, for pandas, DuckDB and the like.
+//!
+//! Unlike the SQLite snapshot of `--export-db`, these files are not meant to be imported back.
+//! Every table has a fixed list of columns, documented in the README, so scripts reading the
+//! files keep working across versions. Rows go from the query straight to the file, Parquet
+//! in row groups, so a long history never has to fit in memory.
+use anyhow::{bail, Context, Result};
+use rusqlite::{types::Value, Connection};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> std::result::Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::JsonLines),
+            "parquet" => Ok(Self::Parquet),
+            _ => Err(format!(
+                "unknown format `{format}`, expected csv, jsonl or parquet"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportTable {
+    Sources,
+    Sessions,
+    InputBuckets,
+    FocusBuckets,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnKind {
+    Text,
+    Integer,
+    Real,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Column {
+    name: &'static str,
+    kind: ColumnKind,
+    #[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+    nullable: bool,
+}
+
+const fn column(name: &'static str, kind: ColumnKind) -> Column {
+    Column {
+        name,
+        kind,
+        nullable: false,
+    }
+}
+
+const SOURCE_COLUMNS: &[Column] = &[
+    column("source_uuid", ColumnKind::Text),
+    column("source_name", ColumnKind::Text),
+    column("platform", ColumnKind::Text),
+    column("created_at_utc", ColumnKind::Text),
+];
+
+const SESSION_COLUMNS: &[Column] = &[
+    column("session_uuid", ColumnKind::Text),
+    column("source_uuid", ColumnKind::Text),
+    column("platform", ColumnKind::Text),
+    column("started_at_utc", ColumnKind::Text),
+    Column {
+        nullable: true,
+        ..column("ended_at_utc", ColumnKind::Text)
+    },
+    Column {
+        nullable: true,
+        ..column("end_reason", ColumnKind::Text)
+    },
+];
+
+const INPUT_BUCKET_COLUMNS: &[Column] = &[
+    column("source_uuid", ColumnKind::Text),
+    column("bucket_start_utc", ColumnKind::Text),
+    column("bucket_end_utc", ColumnKind::Text),
+    column("local_date", ColumnKind::Text),
+    column("local_hour", ColumnKind::Integer),
+    column("timezone_offset_minutes", ColumnKind::Integer),
+    column("granularity_minutes", ColumnKind::Integer),
+    column("left_clicks", ColumnKind::Integer),
+    column("right_clicks", ColumnKind::Integer),
+    column("middle_clicks", ColumnKind::Integer),
+    column("key_presses", ColumnKind::Integer),
+    column("mouse_distance_cm", ColumnKind::Real),
+    column("scroll_vertical_cm", ColumnKind::Real),
+    column("scroll_horizontal_cm", ColumnKind::Real),
+    column("dropped_events", ColumnKind::Integer),
+];
+
+const FOCUS_BUCKET_COLUMNS: &[Column] = &[
+    column("source_uuid", ColumnKind::Text),
+    column("bucket_start_utc", ColumnKind::Text),
+    column("bucket_end_utc", ColumnKind::Text),
+    column("local_date", ColumnKind::Text),
+    column("local_hour", ColumnKind::Integer),
+    column("timezone_offset_minutes", ColumnKind::Integer),
+    column("app_identifier", ColumnKind::Text),
+    column("window_title", ColumnKind::Text),
+    column("window_class", ColumnKind::Text),
+    column("focus_seconds", ColumnKind::Integer),
+];
+
+impl ExportTable {
+    pub const ALL: [ExportTable; 4] = [
+        ExportTable::Sources,
+        ExportTable::Sessions,
+        ExportTable::InputBuckets,
+        ExportTable::FocusBuckets,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportTable::Sources => "sources",
+            ExportTable::Sessions => "sessions",
+            ExportTable::InputBuckets => "input_buckets",
+            ExportTable::FocusBuckets => "focus_buckets",
+        }
+    }
+
+    fn columns(self) -> &'static [Column] {
+        match self {
+            ExportTable::Sources => SOURCE_COLUMNS,
+            ExportTable::Sessions => SESSION_COLUMNS,
+            ExportTable::InputBuckets => INPUT_BUCKET_COLUMNS,
+            ExportTable::FocusBuckets => FOCUS_BUCKET_COLUMNS,
+        }
+    }
+
+    /// Selects [`Self::columns`] in order. Sessions are kept when they overlap the range, and
+    /// sources ignore it.
+    fn query(self) -> &'static str {
+        match self {
+            ExportTable::Sources => {
+                "
+                SELECT source_uuid, source_name, platform, created_at_utc
+                FROM sources
+                WHERE :source_uuid IS NULL OR source_uuid = :source_uuid
+                ORDER BY id
+                "
+            }
+            ExportTable::Sessions => {
+                "
+                SELECT se.session_uuid, s.source_uuid, se.platform, se.started_at_utc,
+                       se.ended_at_utc, se.end_reason
+                FROM sessions se
+                JOIN sources s ON s.id = se.source_id
+                WHERE (:source_uuid IS NULL OR s.source_uuid = :source_uuid)
+                  AND (:until IS NULL OR se.started_at_utc < :until)
+                  AND (:since IS NULL OR se.ended_at_utc IS NULL OR se.ended_at_utc >= :since)
+                ORDER BY se.started_at_utc, se.id
+                "
+            }
+            ExportTable::InputBuckets => {
+                "
+                SELECT s.source_uuid, i.bucket_start_utc, i.bucket_end_utc, i.local_date,
+                       i.local_hour, i.timezone_offset_minutes, i.granularity_minutes,
+                       i.left_clicks, i.right_clicks, i.middle_clicks, i.key_presses,
+                       i.mouse_distance_cm, i.scroll_vertical_cm, i.scroll_horizontal_cm,
+                       i.dropped_events
+                FROM input_buckets i
+                JOIN sources s ON s.id = i.source_id
+                WHERE (:source_uuid IS NULL OR s.source_uuid = :source_uuid)
+                  AND (:since IS NULL OR i.bucket_start_utc >= :since)
+                  AND (:until IS NULL OR i.bucket_start_utc < :until)
+                ORDER BY i.bucket_start_utc, s.source_uuid, i.granularity_minutes
+                "
+            }
+            ExportTable::FocusBuckets => {
+                "
+                SELECT s.source_uuid, f.bucket_start_utc, f.bucket_end_utc, f.local_date,
+                       f.local_hour, f.timezone_offset_minutes, f.app_identifier,
+                       f.window_title, f.window_class, f.focus_seconds
+                FROM focus_buckets f
+                JOIN sources s ON s.id = f.source_id
+                WHERE (:source_uuid IS NULL OR s.source_uuid = :source_uuid)
+                  AND (:since IS NULL OR f.bucket_start_utc >= :since)
+                  AND (:until IS NULL OR f.bucket_start_utc < :until)
+                ORDER BY f.bucket_start_utc, s.source_uuid, f.window_class, f.window_title
+                "
+            }
+        }
+    }
+}
+
+impl FromStr for ExportTable {
+    type Err = String;
+
+    fn from_str(table: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.name() == table)
+            .ok_or_else(|| {
+                format!(
+                    "unknown table `{table}`, expected sources, sessions, input_buckets or focus_buckets"
+                )
+            })
+    }
+}
+
+impl fmt::Display for ExportTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which rows to export. Times are UTC RFC 3339 and select the buckets starting in
+/// `[since_utc, until_utc)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableExportFilter {
+    pub source_uuid: Option<String>,
+    pub since_utc: Option<String>,
+    pub until_utc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedTable {
+    pub table: ExportTable,
+    pub path: PathBuf,
+    pub rows: u64,
+}
+
+/// Writes each of `tables` to `<dir>/<table>.<extension>`. Existing files are never
+/// overwritten, and a file only gets its name once it is complete. All tables are read from
+/// one snapshot, so they agree with each other while the collector keeps writing.
+pub fn export_tables(
+    conn: &Connection,
+    dir: &Path,
+    format: ExportFormat,
+    tables: &[ExportTable],
+    filter: &TableExportFilter,
+) -> Result<Vec<ExportedTable>> {
+    let paths = tables
+        .iter()
+        .map(|table| dir.join(format!("{}.{}", table.name(), format.extension())))
+        .collect::<Vec<_>>();
+    if let Some(existing) = paths.iter().find(|path| path.exists()) {
+        bail!("Refusing to overwrite {}", existing.display());
+    }
+    if format == ExportFormat::Parquet && !cfg!(feature = "parquet") {
+        bail!(NO_PARQUET);
+    }
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create export directory {}", dir.display()))?;
+
+    let snapshot = conn
+        .unchecked_transaction()
+        .with_context(|| "Failed to start a read transaction")?;
+    let mut exported = Vec::new();
+    for (table, path) in tables.iter().zip(paths) {
+        let mut partial_path = path.clone().into_os_string();
+        partial_path.push(".partial");
+        let partial_path = PathBuf::from(partial_path);
+        let rows = export_table(conn, *table, format, &partial_path, filter)
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&partial_path);
+            })
+            .with_context(|| format!("Failed to export {table} to {}", path.display()))?;
+        fs::rename(&partial_path, &path)
+            .with_context(|| format!("Failed to move the export into {}", path.display()))?;
+        exported.push(ExportedTable {
+            table: *table,
+            path,
+            rows,
+        });
+    }
+    drop(snapshot);
+    Ok(exported)
+}
+
+pub fn render_table_export(exported: &[ExportedTable]) -> String {
+    exported
+        .iter()
+        .map(|table| {
+            format!(
+                "  {:>10}  {:<14} {}",
+                table.rows,
+                table.table.name(),
+                table.path.display()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn export_table(
+    conn: &Connection,
+    table: ExportTable,
+    format: ExportFormat,
+    path: &Path,
+    filter: &TableExportFilter,
+) -> Result<u64> {
+    let columns = table.columns();
+    let file = File::create(path)?;
+    let mut writer: Box<dyn RowWriter> = match format {
+        ExportFormat::Csv => Box::new(CsvRows::new(file, columns)?),
+        ExportFormat::JsonLines => Box::new(JsonRows::new(file, columns)),
+        ExportFormat::Parquet => parquet_rows(file, columns)?,
+    };
+
+    let mut stmt = conn.prepare(table.query())?;
+    for (name, value) in [
+        (":source_uuid", &filter.source_uuid),
+        (":since", &filter.since_utc),
+        (":until", &filter.until_utc),
+    ] {
+        if let Some(index) = stmt.parameter_index(name)? {
+            stmt.raw_bind_parameter(index, value)?;
+        }
+    }
+    let mut rows = stmt.raw_query();
+    let mut count = 0;
+    let mut values = Vec::with_capacity(columns.len());
+    while let Some(row) = rows.next()? {
+        values.clear();
+        for index in 0..columns.len() {
+            values.push(row.get::<_, Value>(index)?);
+        }
+        writer.write_row(&values)?;
+        count += 1;
+    }
+    writer.finish()?;
+    Ok(count)
+}
+
+trait RowWriter {
+    fn write_row(&mut self, values: &[Value]) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+struct CsvRows {
+    writer: csv::Writer<File>,
+}
+
+impl CsvRows {
+    fn new(file: File, columns: &[Column]) -> Result<Self> {
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(columns.iter().map(|column| column.name))?;
+        Ok(Self { writer })
+    }
+}
+
+impl RowWriter for CsvRows {
+    /// NULL is an empty field.
+    fn write_row(&mut self, values: &[Value]) -> Result<()> {
+        self.writer
+            .write_record(values.iter().map(|value| match value {
+                Value::Null => String::new(),
+                Value::Integer(value) => value.to_string(),
+                Value::Real(value) => value.to_string(),
+                Value::Text(value) => value.clone(),
+                Value::Blob(_) => String::new(),
+            }))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct JsonRows {
+    out: BufWriter<File>,
+    columns: &'static [Column],
+}
+
+impl JsonRows {
+    fn new(file: File, columns: &'static [Column]) -> Self {
+        Self {
+            out: BufWriter::new(file),
+            columns,
+        }
+    }
+}
+
+impl RowWriter for JsonRows {
+    fn write_row(&mut self, values: &[Value]) -> Result<()> {
+        let object = self
+            .columns
+            .iter()
+            .zip(values)
+            .map(|(column, value)| {
+                let value = match (column.kind, value) {
+                    (_, Value::Null | Value::Blob(_)) => serde_json::Value::Null,
+                    (ColumnKind::Real, Value::Integer(value)) => (*value as f64).into(),
+                    (_, Value::Integer(value)) => (*value).into(),
+                    (_, Value::Real(value)) => (*value).into(),
+                    (_, Value::Text(value)) => value.clone().into(),
+                };
+                (column.name.to_string(), value)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::to_writer(&mut self.out, &object)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+const NO_PARQUET: &str = "Parquet export needs a vigil built with the `parquet` feature";
+
+#[cfg(not(feature = "parquet"))]
+fn parquet_rows(_file: File, _columns: &'static [Column]) -> Result<Box<dyn RowWriter>> {
+    bail!(NO_PARQUET)
+}
+
+#[cfg(feature = "parquet")]
+fn parquet_rows(file: File, columns: &'static [Column]) -> Result<Box<dyn RowWriter>> {
+    Ok(Box::new(parquet_file::ParquetRows::new(file, columns)?))
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_file {
+    use anyhow::{bail, Context, Result};
+    use parquet::basic::{Compression, LogicalType, Repetition, Type as PhysicalType};
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::types::Type;
+    use rusqlite::types::Value;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    use super::{Column, ColumnKind, RowWriter};
+
+    /// Rows buffered before they are written out as a row group.
+    const ROW_GROUP_ROWS: usize = 64 * 1024;
+
+    enum Values {
+        Text(Vec<ByteArray>),
+        Integer(Vec<i64>),
+        Real(Vec<f64>),
+    }
+
+    /// One column of the row group being filled. Definition levels mark NULLs in nullable
+    /// columns, which have no entry in `values`.
+    struct ColumnBuffer {
+        column: Column,
+        values: Values,
+        levels: Vec<i16>,
+    }
+
+    impl ColumnBuffer {
+        fn new(column: Column) -> Self {
+            let values = match column.kind {
+                ColumnKind::Text => Values::Text(Vec::new()),
+                ColumnKind::Integer => Values::Integer(Vec::new()),
+                ColumnKind::Real => Values::Real(Vec::new()),
+            };
+            Self {
+                column,
+                values,
+                levels: Vec::new(),
+            }
+        }
+
+        fn push(&mut self, value: &Value) -> Result<()> {
+            match (&mut self.values, value) {
+                (_, Value::Null) if self.column.nullable => {
+                    self.levels.push(0);
+                    return Ok(());
+                }
+                (Values::Text(values), Value::Text(value)) => {
+                    values.push(ByteArray::from(value.as_str()))
+                }
+                (Values::Integer(values), Value::Integer(value)) => values.push(*value),
+                (Values::Real(values), Value::Real(value)) => values.push(*value),
+                (Values::Real(values), Value::Integer(value)) => values.push(*value as f64),
+                (_, value) => bail!(
+                    "Unexpected {:?} in column {}",
+                    value.data_type(),
+                    self.column.name
+                ),
+            }
+            self.levels.push(1);
+            Ok(())
+        }
+    }
+
+    pub(super) struct ParquetRows {
+        writer: SerializedFileWriter<File>,
+        buffers: Vec<ColumnBuffer>,
+        buffered_rows: usize,
+    }
+
+    impl ParquetRows {
+        pub(super) fn new(file: File, columns: &'static [Column]) -> Result<Self> {
+            let fields = columns
+                .iter()
+                .map(|column| {
+                    let (physical, logical) = match column.kind {
+                        ColumnKind::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+                        ColumnKind::Integer => (PhysicalType::INT64, None),
+                        ColumnKind::Real => (PhysicalType::DOUBLE, None),
+                    };
+                    let repetition = if column.nullable {
+                        Repetition::OPTIONAL
+                    } else {
+                        Repetition::REQUIRED
+                    };
+                    Type::primitive_type_builder(column.name, physical)
+                        .with_repetition(repetition)
+                        .with_logical_type(logical)
+                        .build()
+                        .map(Arc::new)
+                })
+                .collect::<parquet::errors::Result<Vec<_>>>()?;
+            let schema = Type::group_type_builder("vigil")
+                .with_fields(fields)
+                .build()?;
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            Ok(Self {
+                writer: SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties))?,
+                buffers: columns.iter().copied().map(ColumnBuffer::new).collect(),
+                buffered_rows: 0,
+            })
+        }
+
+        fn write_row_group(&mut self) -> Result<()> {
+            let mut row_group = self.writer.next_row_group()?;
+            for buffer in &mut self.buffers {
+                let mut writer = row_group
+                    .next_column()?
+                    .context("Parquet schema has fewer columns than the table")?;
+                let levels = buffer.column.nullable.then_some(buffer.levels.as_slice());
+                match &buffer.values {
+                    Values::Text(values) => {
+                        writer
+                            .typed::<ByteArrayType>()
+                            .write_batch(values, levels, None)?;
+                    }
+                    Values::Integer(values) => {
+                        writer
+                            .typed::<Int64Type>()
+                            .write_batch(values, levels, None)?;
+                    }
+                    Values::Real(values) => {
+                        writer
+                            .typed::<DoubleType>()
+                            .write_batch(values, levels, None)?;
+                    }
+                }
+                writer.close()?;
+                *buffer = ColumnBuffer::new(buffer.column);
+            }
+            row_group.close()?;
+            self.buffered_rows = 0;
+            Ok(())
+        }
+    }
+
+    impl RowWriter for ParquetRows {
+        fn write_row(&mut self, values: &[Value]) -> Result<()> {
+            for (buffer, value) in self.buffers.iter_mut().zip(values) {
+                buffer.push(value)?;
+            }
+            self.buffered_rows += 1;
+            if self.buffered_rows == ROW_GROUP_ROWS {
+                self.write_row_group()?;
+            }
+            Ok(())
+        }
+
+        fn finish(mut self: Box<Self>) -> Result<()> {
+            if self.buffered_rows > 0 {
+                self.write_row_group()?;
+            }
+            self.writer.close()?;
+            Ok(())
+        }
+    }
+}
@@ -1,2 +1,5 @@
 pub mod backend;
 pub mod localdb;
+pub mod offline;
+pub mod sink;
+pub mod spool;
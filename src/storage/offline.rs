@@ -0,0 +1,372 @@
+//! Local fallback for a database that is out of reach.
+//!
+//! `--db-path` may point at a Samba or NFS share. While the share is unavailable, at startup
+//! or in the middle of a run, committed spool entries go into `offline.db` in the program data
+//! directory instead of being dropped. Each entry remembers which database it was meant for.
+//! Every [`RECONNECT_INTERVAL`] the collector tries to open that database again; once it
+//! succeeds, the kept entries are merged into it through [`write_spool_entries`], in the same
+//! transaction that removes them from the fallback, so an interrupted merge is simply redone.
+//! An entry the database refuses for what it holds rather than for being out of reach is moved
+//! to `offline_quarantine` in the same file, so it is kept without blocking the ones after it.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, ErrorCode};
+use tracing::*;
+
+use crate::common::program_data_dir;
+#[cfg(target_os = "linux")]
+use crate::control::CollectorControl;
+use crate::storage::backend::StorageBackend;
+use crate::storage::localdb::{attach_database, open_con_at};
+use crate::storage::spool::{write_spool_entries, SpoolEntry};
+
+/// How often the collector tries to reach the database again while rows are kept offline.
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn offline_db_path() -> Result<PathBuf> {
+    Ok(program_data_dir()
+        .with_context(|| "Could not determine the directory for the offline database")?
+        .join("offline.db"))
+}
+
+/// Opens the fallback database at `path`, creating it if needed.
+pub fn open_offline_db(path: &Path) -> Result<Connection> {
+    let conn = open_con_at(path)?;
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS offline_entries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            db_path TEXT NOT NULL,
+            spool TEXT NOT NULL,
+            entry TEXT NOT NULL,
+            row_count INTEGER NOT NULL,
+            stored_at_utc TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS offline_entries_db_path_idx
+            ON offline_entries (db_path, id);
+
+        CREATE TABLE IF NOT EXISTS offline_quarantine (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            db_path TEXT NOT NULL,
+            spool TEXT NOT NULL,
+            entry TEXT NOT NULL,
+            row_count INTEGER NOT NULL,
+            stored_at_utc TEXT NOT NULL,
+            error TEXT NOT NULL,
+            quarantined_at_utc TEXT NOT NULL
+        );
+        ",
+    )
+    .with_context(|| format!("Failed to prepare the offline database {}", path.display()))?;
+    Ok(conn)
+}
+
+/// Keeps the entries of spool `spool` until the database at `db_path` is reachable again.
+pub fn store_offline_entries(
+    conn: &mut Connection,
+    db_path: &Path,
+    spool: &str,
+    entries: &[SpoolEntry],
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let stored_at = Utc::now().to_rfc3339();
+    for entry in entries {
+        tx.execute(
+            "
+            INSERT INTO offline_entries (db_path, spool, entry, row_count, stored_at_utc)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+            params![
+                db_path.to_string_lossy(),
+                spool,
+                serde_json::to_string(entry)?,
+                entry.rows.len() as i64,
+                stored_at,
+            ],
+        )?;
+    }
+    tx.commit()
+        .with_context(|| format!("Failed to keep rows of {spool} in the offline database"))
+}
+
+/// How many rows the fallback at `offline_path` keeps for the database at `db_path`.
+pub fn pending_offline_rows(offline_path: &Path, db_path: &Path) -> Result<u64> {
+    if !offline_path.exists() {
+        return Ok(0);
+    }
+    let conn = open_offline_db(offline_path)?;
+    let rows: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(row_count), 0) FROM offline_entries WHERE db_path = ?1",
+        [db_path.to_string_lossy()],
+        |row| row.get(0),
+    )?;
+    Ok(rows as u64)
+}
+
+/// Merges what the fallback at `offline_path` keeps for the database at `db_path` into `conn`,
+/// a connection to that database. Returns how many rows were merged.
+pub fn merge_offline_entries(
+    conn: &mut Connection,
+    db_path: &Path,
+    offline_path: &Path,
+) -> Result<u64> {
+    if pending_offline_rows(offline_path, db_path)? == 0 {
+        return Ok(0);
+    }
+    attach_database(conn, offline_path, "offline")
+        .with_context(|| format!("Failed to attach {}", offline_path.display()))?;
+    let merged = merge_attached_entries(conn, db_path);
+    conn.execute_batch("DETACH DATABASE offline")?;
+    merged
+}
+
+fn merge_attached_entries(conn: &mut Connection, db_path: &Path) -> Result<u64> {
+    let mut tx = conn.transaction()?;
+    let pending = {
+        let mut stmt = tx.prepare(
+            "SELECT id, spool, entry FROM offline.offline_entries WHERE db_path = ?1 ORDER BY id",
+        )?;
+        let pending = stmt
+            .query_map([db_path.to_string_lossy()], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        pending
+    };
+
+    let mut merged = 0;
+    let mut quarantined = 0;
+    for (id, spool, entry) in pending {
+        let savepoint = tx.savepoint()?;
+        let written = serde_json::from_str::<SpoolEntry>(&entry)
+            .with_context(|| format!("Failed to decode offline entry {id} of {spool}"))
+            .and_then(|entry| {
+                let written =
+                    write_spool_entries(&savepoint, &spool, std::slice::from_ref(&entry))?;
+                Ok(if written > 0 {
+                    entry.rows.len() as u64
+                } else {
+                    0
+                })
+            });
+        match written {
+            Result::Ok(rows) => {
+                savepoint.commit()?;
+                merged += rows;
+            }
+            Err(err) if is_database_error(&err) => return Err(err),
+            Err(err) => {
+                drop(savepoint);
+                tx.execute(
+                    "
+                    INSERT INTO offline.offline_quarantine
+                        (db_path, spool, entry, row_count, stored_at_utc, error, quarantined_at_utc)
+                    SELECT db_path, spool, entry, row_count, stored_at_utc, ?2, ?3
+                    FROM offline.offline_entries WHERE id = ?1
+                    ",
+                    params![id, format!("{err:#}"), Utc::now().to_rfc3339()],
+                )?;
+                quarantined += 1;
+            }
+        }
+        tx.execute("DELETE FROM offline.offline_entries WHERE id = ?1", [id])?;
+    }
+    tx.commit()
+        .with_context(|| "Failed to commit the rows kept in the offline database")?;
+    if quarantined > 0 {
+        warn!(
+            "Moved {quarantined} entries kept for {} to offline_quarantine in the offline database, since they could not be merged",
+            db_path.display()
+        );
+    }
+    Ok(merged)
+}
+
+/// Whether `err` means the database can't be reached right now, as opposed to refusing the
+/// rows written to it.
+pub fn is_unreachable_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.is::<std::io::Error>()
+            || sqlite_error_code(cause).is_some_and(|code| {
+                matches!(code, ErrorCode::CannotOpen | ErrorCode::SystemIoFailure)
+            })
+    })
+}
+
+/// Whether `err` comes from the database as a whole rather than from one entry, so writing
+/// the same entry again later can succeed.
+fn is_database_error(err: &anyhow::Error) -> bool {
+    is_unreachable_error(err)
+        || err.chain().any(|cause| {
+            sqlite_error_code(cause).is_some_and(|code| {
+                matches!(
+                    code,
+                    ErrorCode::DatabaseBusy
+                        | ErrorCode::DatabaseLocked
+                        | ErrorCode::DatabaseCorrupt
+                        | ErrorCode::NotADatabase
+                        | ErrorCode::DiskFull
+                        | ErrorCode::ReadOnly
+                )
+            })
+        })
+}
+
+fn sqlite_error_code(cause: &(dyn std::error::Error + 'static)) -> Option<ErrorCode> {
+    match cause.downcast_ref::<rusqlite::Error>()? {
+        rusqlite::Error::SqliteFailure(failure, _) => Some(failure.code),
+        _ => None,
+    }
+}
+
+/// Tries to reach the database every [`RECONNECT_INTERVAL`] while rows are kept offline and
+/// publishes how many are still waiting.
+pub async fn run_reconnect(
+    backend: StorageBackend,
+    #[cfg(target_os = "linux")] control: CollectorControl,
+) -> Result<()> {
+    let mut tick = tokio::time::interval(RECONNECT_INTERVAL);
+    loop {
+        tick.tick().await;
+        let pending = match backend.reconnect().await {
+            Ok(pending) => pending,
+            Err(err) => {
+                error!("Failed to merge rows kept offline: {err:#}");
+                continue;
+            }
+        };
+        #[cfg(target_os = "linux")]
+        control.set_offline_rows(backend.is_reachable(), pending);
+        #[cfg(not(target_os = "linux"))]
+        let _ = pending;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+    use crate::storage::localdb::{scalar_query_u64, setup_database};
+    use crate::storage::spool::SpoolRows;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    /// Verifies that rows kept for an unreachable database are merged into it once, leave
+    /// the fallback empty, and that rows kept for another database stay where they are.
+    #[test]
+    fn merge_offline_entries_moves_rows_once() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vigil-offline-{}", Uuid::new_v4()));
+        let db_path = dir.join("share.db");
+        let other_db_path = dir.join("other.db");
+        let offline_path = dir.join("offline.db");
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        let entry = |seconds| SpoolEntry {
+            generation: Uuid::new_v4().to_string(),
+            seq: 1,
+            rows: SpoolRows::Focus(vec![FocusBucketRecord {
+                source_id: DEFAULT_SOURCE_ID,
+                bucket_start_utc: start,
+                bucket_end_utc: start + chrono::Duration::minutes(15),
+                local_date: "2026-04-18".to_string(),
+                local_hour: 12,
+                timezone_offset_minutes: 0,
+                app_identifier: "nvim".to_string(),
+                window_title: "notes.md".to_string(),
+                window_class: "kitty".to_string(),
+                focus_seconds: seconds,
+            }]),
+        };
+
+        let mut offline = open_offline_db(&offline_path)?;
+        store_offline_entries(&mut offline, &db_path, "focus", &[entry(20), entry(40)])?;
+        store_offline_entries(&mut offline, &other_db_path, "focus", &[entry(5)])?;
+        drop(offline);
+        assert_eq!(pending_offline_rows(&offline_path, &db_path)?, 2);
+
+        let mut conn = open_con_at(&db_path)?;
+        setup_database(&conn)?;
+        assert_eq!(
+            merge_offline_entries(&mut conn, &db_path, &offline_path)?,
+            2
+        );
+        assert_eq!(
+            merge_offline_entries(&mut conn, &db_path, &offline_path)?,
+            0
+        );
+
+        let focus_seconds =
+            scalar_query_u64(&conn, "SELECT SUM(focus_seconds) FROM focus_buckets")?;
+        assert_eq!(focus_seconds, 60);
+        assert_eq!(pending_offline_rows(&offline_path, &db_path)?, 0);
+        assert_eq!(pending_offline_rows(&offline_path, &other_db_path)?, 1);
+
+        drop(conn);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    /// Verifies that an entry which can't be merged is moved to the quarantine table instead
+    /// of blocking the entries after it on every reconnect.
+    #[test]
+    fn merge_offline_entries_quarantines_unmergeable_entries() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vigil-offline-{}", Uuid::new_v4()));
+        let db_path = dir.join("share.db");
+        let offline_path = dir.join("offline.db");
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        let entry = SpoolEntry {
+            generation: Uuid::new_v4().to_string(),
+            seq: 1,
+            rows: SpoolRows::Focus(vec![FocusBucketRecord {
+                source_id: DEFAULT_SOURCE_ID,
+                bucket_start_utc: start,
+                bucket_end_utc: start + chrono::Duration::minutes(15),
+                local_date: "2026-04-18".to_string(),
+                local_hour: 12,
+                timezone_offset_minutes: 0,
+                app_identifier: "nvim".to_string(),
+                window_title: "notes.md".to_string(),
+                window_class: "kitty".to_string(),
+                focus_seconds: 30,
+            }]),
+        };
+
+        let mut offline = open_offline_db(&offline_path)?;
+        offline.execute(
+            "
+            INSERT INTO offline_entries (db_path, spool, entry, row_count, stored_at_utc)
+            VALUES (?1, 'focus', '{\"truncated', 3, ?2)
+            ",
+            params![db_path.to_string_lossy(), start.to_rfc3339()],
+        )?;
+        store_offline_entries(&mut offline, &db_path, "focus", &[entry])?;
+
+        let mut conn = open_con_at(&db_path)?;
+        setup_database(&conn)?;
+        assert_eq!(
+            merge_offline_entries(&mut conn, &db_path, &offline_path)?,
+            1
+        );
+        assert_eq!(pending_offline_rows(&offline_path, &db_path)?, 0);
+        assert_eq!(
+            scalar_query_u64(&conn, "SELECT SUM(focus_seconds) FROM focus_buckets")?,
+            30
+        );
+        assert_eq!(
+            scalar_query_u64(&offline, "SELECT COUNT(*) FROM offline_quarantine")?,
+            1
+        );
+
+        drop(conn);
+        drop(offline);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+//! Secondary sinks that receive a copy of every row written to the primary database.
+//!
+//! Each sink is configured with `--sink <KIND>:<PATH>` and runs in its own task behind an
+//! unbounded queue, so a slow or failing sink never holds up the primary database or the other
+//! sinks. A failed write keeps its batches queued and is retried with a growing delay, up to
+//! [`MAX_PENDING_BATCHES`]; past that the oldest batches are dropped.
+//!
+//! - `sqlite:<PATH>` keeps a second Vigil database. Rows go through the same spool checkpoints
+//!   as the primary, so retrying a batch never counts anything twice.
+//! - `jsonl:<PATH>` appends one line per spool entry to an archive, with the spool name next to
+//!   the entry's generation and sequence number:
+//!
+//! ```text
+//! {"spool":"input","generation":"8c5e…","seq":3,"rows":{"table":"input","rows":[…]}}
+//! ```
+//!
+//!   A retry after a partly written batch can repeat lines; `spool`, `generation` and `seq`
+//!   identify an entry.
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::*;
+
+use crate::common::{SourceInfo, DEFAULT_SOURCE_ID};
+use crate::storage::localdb::{open_con_at, setup_database};
+use crate::storage::spool::{apply_spool_entries, SpoolEntry};
+use crate::utils::lock::acquire_db_operation_lock;
+
+/// Batches kept per sink while it is failing.
+const MAX_PENDING_BATCHES: usize = 10_000;
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(5);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// A sink as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkSpec {
+    Sqlite(PathBuf),
+    JsonLines(PathBuf),
+}
+
+impl SinkSpec {
+    pub fn path(&self) -> &Path {
+        match self {
+            SinkSpec::Sqlite(path) | SinkSpec::JsonLines(path) => path,
+        }
+    }
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, Self::Err> {
+        match spec.split_once(':') {
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Self::Sqlite(PathBuf::from(path))),
+            Some(("jsonl", path)) if !path.is_empty() => Ok(Self::JsonLines(PathBuf::from(path))),
+            _ => Err(format!(
+                "invalid sink `{spec}`, expected sqlite:<PATH> or jsonl:<PATH>"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SinkSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkSpec::Sqlite(path) => write!(f, "sqlite:{}", path.display()),
+            SinkSpec::JsonLines(path) => write!(f, "jsonl:{}", path.display()),
+        }
+    }
+}
+
+/// Rows of one store call, as handed to every sink.
+#[derive(Debug, Clone)]
+pub struct SinkBatch {
+    pub spool: String,
+    pub entries: Vec<SpoolEntry>,
+}
+
+#[derive(Debug)]
+enum SinkMessage {
+    Write(SinkBatch),
+    /// Tries once more to write what is queued and answers when done.
+    Flush(oneshot::Sender<()>),
+}
+
+/// The sending side of a running sink.
+#[derive(Debug)]
+pub struct SinkHandle {
+    spec: SinkSpec,
+    messages: mpsc::UnboundedSender<SinkMessage>,
+}
+
+impl SinkHandle {
+    /// Starts the task writing to the sink described by `spec`. `source` is the primary
+    /// database's own source row, which a SQLite sink takes over so its rows keep the
+    /// primary's identity.
+    pub fn spawn(spec: SinkSpec, source: Option<SourceInfo>) -> Self {
+        let sink = match &spec {
+            SinkSpec::Sqlite(path) => Sink::Sqlite(SqliteSink {
+                path: path.clone(),
+                source,
+                conn: None,
+            }),
+            SinkSpec::JsonLines(path) => Sink::JsonLines(JsonLinesSink { path: path.clone() }),
+        };
+        let (messages, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run_sink(spec.clone(), sink, receiver));
+        Self { spec, messages }
+    }
+
+    /// Queues `batch` for the sink without waiting for it to be written.
+    pub fn send(&self, batch: SinkBatch) {
+        if self.messages.send(SinkMessage::Write(batch)).is_err() {
+            warn!("Sink {} has stopped, dropping rows for it", self.spec);
+        }
+    }
+
+    /// Waits until the sink made one more attempt at everything queued so far.
+    pub async fn flush(&self) {
+        let (reply, done) = oneshot::channel();
+        if self.messages.send(SinkMessage::Flush(reply)).is_ok() {
+            let _ = done.await;
+        }
+    }
+}
+
+enum Sink {
+    Sqlite(SqliteSink),
+    JsonLines(JsonLinesSink),
+}
+
+impl Sink {
+    fn write(&mut self, batch: &SinkBatch) -> Result<()> {
+        match self {
+            Sink::Sqlite(sink) => sink.write(batch),
+            Sink::JsonLines(sink) => sink.write(batch),
+        }
+    }
+
+    /// Writes queued batches in order and removes each one once it is written.
+    fn write_pending(&mut self, pending: &mut VecDeque<SinkBatch>) -> Result<()> {
+        while let Some(batch) = pending.front() {
+            self.write(batch)?;
+            pending.pop_front();
+        }
+        Ok(())
+    }
+}
+
+struct SqliteSink {
+    path: PathBuf,
+    source: Option<SourceInfo>,
+    /// Opened on first use and again after a failed write, so a remounted share is picked up.
+    conn: Option<Connection>,
+}
+
+impl SqliteSink {
+    fn write(&mut self, batch: &SinkBatch) -> Result<()> {
+        let conn = match self.conn.as_mut() {
+            Some(conn) => conn,
+            None => self
+                .conn
+                .insert(open_sink_database(&self.path, self.source.as_ref())?),
+        };
+        let written = acquire_db_operation_lock(&self.path)
+            .and_then(|_op_lock| apply_spool_entries(conn, &batch.spool, &batch.entries));
+        if written.is_err() {
+            self.conn = None;
+        }
+        written.map(|_| ())
+    }
+}
+
+fn open_sink_database(path: &Path, source: Option<&SourceInfo>) -> Result<Connection> {
+    let conn = open_con_at(path)?;
+    setup_database(&conn)
+        .with_context(|| format!("Failed to set up sink database {}", path.display()))?;
+    if let Some(source) = source {
+        conn.execute(
+            "UPDATE sources SET source_uuid = ?1, source_name = ?2, platform = ?3 WHERE id = ?4",
+            params![
+                source.source_uuid,
+                source.source_name,
+                source.platform,
+                DEFAULT_SOURCE_ID
+            ],
+        )
+        .with_context(|| {
+            format!(
+                "Failed to set the source of sink database {}",
+                path.display()
+            )
+        })?;
+    }
+    Ok(conn)
+}
+
+struct JsonLinesSink {
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct ArchiveLine<'a> {
+    spool: &'a str,
+    #[serde(flatten)]
+    entry: &'a SpoolEntry,
+}
+
+impl JsonLinesSink {
+    fn write(&mut self, batch: &SinkBatch) -> Result<()> {
+        let mut lines = Vec::new();
+        for entry in &batch.entries {
+            serde_json::to_writer(
+                &mut lines,
+                &ArchiveLine {
+                    spool: &batch.spool,
+                    entry,
+                },
+            )?;
+            lines.push(b'\n');
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create archive directory {}", parent.display())
+            })?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open archive {}", self.path.display()))?;
+        file.write_all(&lines)
+            .and_then(|()| file.sync_data())
+            .with_context(|| format!("Failed to append to archive {}", self.path.display()))
+    }
+}
+
+async fn run_sink(
+    spec: SinkSpec,
+    mut sink: Sink,
+    mut messages: mpsc::UnboundedReceiver<SinkMessage>,
+) {
+    let mut pending = VecDeque::new();
+    let mut retry_delay = RETRY_DELAY_MIN;
+    let mut retry_at: Option<Instant> = None;
+
+    loop {
+        let message = match retry_at {
+            Some(at) => tokio::select! {
+                message = messages.recv() => message.map(Some),
+                _ = tokio::time::sleep_until(at) => Some(None),
+            },
+            None => messages.recv().await.map(Some),
+        };
+        // `None` once every handle is gone, `Some(None)` when the retry delay ran out.
+        let Some(message) = message else {
+            break;
+        };
+
+        let mut reply = None;
+        match message {
+            Some(SinkMessage::Write(batch)) => {
+                pending.push_back(batch);
+                if pending.len() > MAX_PENDING_BATCHES {
+                    pending.pop_front();
+                    warn!("Sink {spec} is too far behind, dropping its oldest queued rows");
+                }
+            }
+            Some(SinkMessage::Flush(sender)) => reply = Some(sender),
+            None => {}
+        }
+
+        // While waiting out a failure, new rows only join the queue.
+        if retry_at.is_none_or(|at| at <= Instant::now()) || reply.is_some() {
+            let written = tokio::task::spawn_blocking(move || {
+                let result = sink.write_pending(&mut pending);
+                (sink, pending, result)
+            })
+            .await;
+            let result;
+            (sink, pending, result) = match written {
+                Ok(written) => written,
+                Err(err) => {
+                    error!("Sink {spec} stopped after a panic: {err}");
+                    return;
+                }
+            };
+            match result {
+                Ok(()) => {
+                    if retry_delay > RETRY_DELAY_MIN {
+                        info!("Sink {spec} caught up");
+                    }
+                    retry_delay = RETRY_DELAY_MIN;
+                    retry_at = None;
+                }
+                Err(err) => {
+                    if retry_delay == RETRY_DELAY_MIN {
+                        warn!(
+                            "Failed to write to sink {spec}, keeping {} batches queued for it: {err:#}",
+                            pending.len()
+                        );
+                    } else {
+                        debug!("Sink {spec} still failing: {err:#}");
+                    }
+                    retry_at = Some(Instant::now() + retry_delay);
+                    retry_delay = (retry_delay * 2).min(RETRY_DELAY_MAX);
+                }
+            }
+        }
+
+        if let Some(reply) = reply {
+            let _ = reply.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::InputBucketRecord;
+    use crate::storage::localdb::scalar_query_u64;
+    use crate::storage::spool::SpoolRows;
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    fn input_batch(key_presses: u64) -> SinkBatch {
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        SinkBatch {
+            spool: "input".to_string(),
+            entries: vec![SpoolEntry::standalone(SpoolRows::Input(vec![
+                InputBucketRecord {
+                    source_id: DEFAULT_SOURCE_ID,
+                    bucket_start_utc: start,
+                    bucket_end_utc: start + chrono::Duration::minutes(15),
+                    local_date: "2026-04-18".to_string(),
+                    local_hour: 12,
+                    timezone_offset_minutes: 0,
+                    granularity_minutes: 15,
+                    left_clicks: 0,
+                    right_clicks: 0,
+                    middle_clicks: 0,
+                    key_presses,
+                    mouse_distance_cm: 0.0,
+                    scroll_vertical_cm: 0.0,
+                    scroll_horizontal_cm: 0.0,
+                    dropped_events: 0,
+                },
+            ]))],
+        }
+    }
+
+    /// Verifies that a failing SQLite sink keeps its rows queued without holding up the JSON
+    /// Lines archive, and writes them exactly once as soon as its path works again.
+    #[tokio::test]
+    async fn failing_sink_keeps_rows_until_it_recovers() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("vigil-sinks-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir)?;
+        // a file where the sink's directory should be makes every open fail
+        let blocked = dir.join("share");
+        fs::write(&blocked, b"")?;
+        let db_path = blocked.join("mirror.db");
+        let archive_path = dir.join("archive.jsonl");
+
+        let mirror = SinkHandle::spawn(SinkSpec::Sqlite(db_path.clone()), None);
+        let archive = SinkHandle::spawn(SinkSpec::JsonLines(archive_path.clone()), None);
+        for sink in [&mirror, &archive] {
+            sink.send(input_batch(2));
+            sink.send(input_batch(3));
+        }
+        archive.flush().await;
+        mirror.flush().await;
+        assert_eq!(fs::read_to_string(&archive_path)?.lines().count(), 2);
+        assert!(!db_path.exists());
+
+        fs::remove_file(&blocked)?;
+        mirror.flush().await;
+        let conn = open_con_at(&db_path)?;
+        assert_eq!(
+            scalar_query_u64(&conn, "SELECT SUM(key_presses) FROM input_buckets")?,
+            5
+        );
+
+        drop(conn);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! Write-ahead spool for the collector's in-memory buckets.
+//!
+//! Buckets are written to SQLite every `--interval` seconds, which would lose up to that much
+//! activity on a crash or power loss. Every [`SPOOL_INTERVAL`] the collector tasks therefore
+//! drain their buffers into an append-only spool file in the program data directory and fsync
+//! it. Each line is one [`SpoolEntry`]:
+//!
+//! ```text
+//! {"generation":"8c5e…","seq":3,"rows":{"table":"input","rows":[{"source_id":1,…}]}}
+//! ```
+//!
+//! The regular database update commits everything spooled since the previous one, together
+//! with a checkpoint (the spool's generation and last sequence number) in the same
+//! transaction, and then starts a fresh spool file. Whatever is still in a spool when the
+//! collector or one of its tasks starts is replayed first; the checkpoint skips entries that
+//! already made it into the database, so replaying twice never counts anything twice.
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+use uuid::Uuid;
+
+use crate::common::*;
+use crate::storage::backend::{DataStore, StorageBackend};
+use crate::storage::localdb::*;
+#[cfg(feature = "multi-sync")]
+use crate::sync::{apply_local_focus_rows, apply_local_input_rows};
+
+/// How often buffers are drained into the spool and the spool is synced to disk.
+pub const SPOOL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Rows drained from one buffer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "table", content = "rows", rename_all = "kebab-case")]
+pub enum SpoolRows {
+    Input(Vec<InputBucketRecord>),
+    Focus(Vec<FocusBucketRecord>),
+    Domains(Vec<DomainBucketRecord>),
+    Terminals(Vec<TerminalBucketRecord>),
+    Projects(Vec<ProjectBucketRecord>),
+    Suspends(Vec<SuspendRecord>),
+    Events(Vec<CollectorEventRecord>),
+}
+
+impl SpoolRows {
+    pub fn len(&self) -> usize {
+        match self {
+            SpoolRows::Input(rows) => rows.len(),
+            SpoolRows::Focus(rows) => rows.len(),
+            SpoolRows::Domains(rows) => rows.len(),
+            SpoolRows::Terminals(rows) => rows.len(),
+            SpoolRows::Projects(rows) => rows.len(),
+            SpoolRows::Suspends(rows) => rows.len(),
+            SpoolRows::Events(rows) => rows.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// One line of a spool file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpoolEntry {
+    /// Changes whenever a spool file starts over, so sequence numbers never repeat.
+    pub generation: String,
+    pub seq: u64,
+    pub rows: SpoolRows,
+}
+
+impl SpoolEntry {
+    /// An entry for rows that are written straight away instead of through a spool file.
+    pub fn standalone(rows: SpoolRows) -> Self {
+        Self {
+            generation: Uuid::new_v4().to_string(),
+            seq: 1,
+            rows,
+        }
+    }
+}
+
+/// The spool directory, `spool` in the program data directory. It stays local even when the
+/// database lives on a network share, so spooling keeps working while the share is gone.
+pub fn spool_dir() -> Result<PathBuf> {
+    Ok(program_data_dir()
+        .with_context(|| "Could not determine the directory for the spool")?
+        .join("spool"))
+}
+
+/// Reads the entries of a spool file. A line cut short by a crash ends the spool; entries
+/// after it were never acknowledged by an fsync anyway.
+pub fn read_spool_file(path: &Path) -> Result<Vec<SpoolEntry>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to open spool {}", path.display()))
+        }
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read spool {}", path.display()))?;
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                warn!(
+                    "Ignoring the rest of spool {} after a damaged line: {err}",
+                    path.display()
+                );
+                break;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Only input and focus rows go through the sync outbox. Domain, terminal and project rows
+/// are derived from titles and paths, so they stay local.
+fn insert_spooled_rows(conn: &Connection, rows: &SpoolRows) -> Result<()> {
+    match rows {
+        #[cfg(feature = "multi-sync")]
+        SpoolRows::Input(rows) => apply_local_input_rows(conn, rows),
+        #[cfg(not(feature = "multi-sync"))]
+        SpoolRows::Input(rows) => insert_input_buckets(conn, rows),
+        #[cfg(feature = "multi-sync")]
+        SpoolRows::Focus(rows) => apply_local_focus_rows(conn, rows),
+        #[cfg(not(feature = "multi-sync"))]
+        SpoolRows::Focus(rows) => insert_focus_buckets(conn, rows),
+        SpoolRows::Domains(rows) => insert_domain_buckets(conn, rows),
+        SpoolRows::Terminals(rows) => insert_terminal_buckets(conn, rows),
+        SpoolRows::Projects(rows) => insert_project_buckets(conn, rows),
+        SpoolRows::Suspends(rows) => rows
+            .iter()
+            .try_for_each(|period| insert_suspend_period(conn, period)),
+        SpoolRows::Events(rows) => rows
+            .iter()
+            .try_for_each(|event| insert_collector_event(conn, event)),
+    }
+}
+
+/// Writes the entries of spool `spool` the database doesn't have yet and moves its checkpoint
+/// past them, in one transaction. Returns how many entries were written.
+pub fn apply_spool_entries(
+    conn: &mut Connection,
+    spool: &str,
+    entries: &[SpoolEntry],
+) -> Result<usize> {
+    let tx = conn.transaction()?;
+    let applied = write_spool_entries(&tx, spool, entries)?;
+    tx.commit()
+        .with_context(|| format!("Failed to commit spooled rows of {spool}"))?;
+    Ok(applied)
+}
+
+/// [`apply_spool_entries`] inside a transaction the caller already opened.
+pub fn write_spool_entries(
+    conn: &Connection,
+    spool: &str,
+    entries: &[SpoolEntry],
+) -> Result<usize> {
+    let checkpoint = spool_checkpoint(conn, spool)?;
+    let mut applied = 0;
+    for entry in entries {
+        let already_applied = checkpoint.as_ref().is_some_and(|(generation, last_seq)| {
+            *generation == entry.generation && entry.seq <= *last_seq
+        });
+        if already_applied {
+            continue;
+        }
+        insert_spooled_rows(conn, &entry.rows)
+            .with_context(|| format!("Failed to write spooled rows of {spool}"))?;
+        set_spool_checkpoint(conn, spool, &entry.generation, entry.seq)?;
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// Replays every spool file left in `dir` and removes it. Runs before collection starts.
+pub fn replay_spools(conn: &mut Connection, dir: &Path) -> Result<()> {
+    let files = match fs::read_dir(dir) {
+        Ok(files) => files,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to list spool directory {}", dir.display()))
+        }
+    };
+
+    for file in files {
+        let path = file?.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "jsonl")
+        {
+            continue;
+        }
+        let Some(spool) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let entries = read_spool_file(&path)?;
+        let applied = apply_spool_entries(conn, spool, &entries)?;
+        if applied > 0 {
+            info!(
+                "Recovered {applied} unflushed spool entries of {spool} from {}",
+                path.display()
+            );
+        }
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove replayed spool {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The spool of one collector task.
+#[derive(Debug)]
+pub struct Spool {
+    name: &'static str,
+    path: PathBuf,
+    file: File,
+    generation: String,
+    seq: u64,
+    /// Appended since the last commit.
+    entries: Vec<SpoolEntry>,
+}
+
+impl Spool {
+    /// Opens the spool of task `name`, after replaying what a previous run of the task left
+    /// in it.
+    pub async fn open(backend: &StorageBackend, name: &'static str) -> Result<Self> {
+        let dir = backend.spool_dir().to_path_buf();
+        let path = dir.join(format!("{name}.jsonl"));
+        let leftover = read_spool_file(&path)?;
+        if !leftover.is_empty() {
+            backend
+                .store_spool_entries(name, &leftover)
+                .await
+                .with_context(|| format!("Failed to replay spool {}", path.display()))?;
+        }
+        Self::create(&dir, name)
+    }
+
+    /// Starts an empty spool file `<name>.jsonl` in `dir`.
+    pub fn create(dir: &Path, name: &'static str) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create spool directory {}", dir.display()))?;
+        let path = dir.join(format!("{name}.jsonl"));
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open spool {}", path.display()))?;
+        Ok(Self {
+            name,
+            path,
+            file,
+            generation: Uuid::new_v4().to_string(),
+            seq: 0,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Appends drained rows and syncs them to disk. The rows are committed with the next
+    /// [`Spool::commit`] even when writing them fails.
+    pub fn append(&mut self, rows: SpoolRows) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.seq += 1;
+        let entry = SpoolEntry {
+            generation: self.generation.clone(),
+            seq: self.seq,
+            rows,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.entries.push(entry);
+
+        self.file
+            .write_all(&line)
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("Failed to write spool {}", self.path.display()))
+    }
+
+    /// Writes the spooled entries to the database and starts the spool file over. On failure
+    /// the entries stay and are committed again next time.
+    pub async fn commit(&mut self, backend: &StorageBackend) -> Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        backend
+            .store_spool_entries(self.name, &self.entries)
+            .await?;
+        self.entries.clear();
+
+        // Until this succeeds the committed entries stay in the file, and the checkpoint keeps
+        // a replay from writing them again.
+        self.file
+            .set_len(0)
+            .and_then(|()| self.file.rewind())
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("Failed to truncate spool {}", self.path.display()))?;
+        self.generation = Uuid::new_v4().to_string();
+        self.seq = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn input_rows(key_presses: u64) -> SpoolRows {
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        SpoolRows::Input(vec![InputBucketRecord {
+            source_id: DEFAULT_SOURCE_ID,
+            bucket_start_utc: start,
+            bucket_end_utc: start + chrono::Duration::minutes(15),
+            local_date: "2026-04-18".to_string(),
+            local_hour: 12,
+            timezone_offset_minutes: 0,
+            granularity_minutes: 15,
+            left_clicks: 0,
+            right_clicks: 0,
+            middle_clicks: 0,
+            key_presses,
+            mouse_distance_cm: 0.0,
+            scroll_vertical_cm: 0.0,
+            scroll_horizontal_cm: 0.0,
+            dropped_events: 0,
+        }])
+    }
+
+    /// Verifies that a spool left behind by a crash is replayed once: entries past the
+    /// checkpoint are written, replaying again adds nothing, and a torn last line is ignored.
+    #[test]
+    fn spool_replay_is_idempotent() -> anyhow::Result<()> {
+        let db_path = std::env::temp_dir().join(format!("vigil-spool-{}.db", Uuid::new_v4()));
+        let dir = std::env::temp_dir().join(format!("vigil-spool-{}", Uuid::new_v4()));
+        let mut conn = open_con_at(&db_path)?;
+        setup_database(&conn)?;
+
+        let mut spool = Spool::create(&dir, "input")?;
+        spool.append(input_rows(2))?;
+        spool.append(input_rows(3))?;
+        // the first entry made it into the database before the crash
+        apply_spool_entries(&mut conn, "input", &spool.entries[..1])?;
+        spool.append(input_rows(5))?;
+        let path = dir.join("input.jsonl");
+        OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"{\"generation\":")?;
+        drop(spool);
+
+        let key_presses = |conn: &Connection| {
+            scalar_query_u64(
+                conn,
+                "SELECT COALESCE(SUM(key_presses), 0) FROM input_buckets",
+            )
+        };
+        let leftover = read_spool_file(&path)?;
+        assert_eq!(leftover.len(), 3);
+        assert_eq!(apply_spool_entries(&mut conn, "input", &leftover)?, 2);
+        assert_eq!(key_presses(&conn)?, 10);
+
+        replay_spools(&mut conn, &dir)?;
+        assert_eq!(key_presses(&conn)?, 10);
+        assert!(!path.exists());
+
+        drop(conn);
+        fs::remove_dir_all(dir)?;
+        fs::remove_file(db_path)?;
+        Ok(())
+    }
+}
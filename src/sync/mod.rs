@@ -12,8 +12,9 @@ mod tests;
 
 #[allow(unused_imports)]
 pub use outbox::{
-    apply_local_focus_rows, apply_local_input_rows, apply_local_source, list_pending_outbox,
-    mark_batch_sent, prepare_pending_batch, seed_outbox_for_owned_rows,
+    apply_local_focus_rows, apply_local_forget, apply_local_input_rows, apply_local_source,
+    apply_local_source_action, list_pending_outbox, mark_batch_sent, prepare_pending_batch,
+    seed_outbox_for_owned_rows,
 };
 pub use pull::sync_pull;
 pub use push::sync_push;
@@ -28,7 +29,7 @@ pub use state::{
 pub use status::{render_sync_status, sync_status_snapshot};
 #[allow(unused_imports)]
 pub use types::{
-    ChangePayload, EntityType, FocusBucketChange, InputBucketChange, OutboxEntry, PullResponse,
-    PushAck, PushBatch, RemoteChange, RemoteStatus, SourceChange, SyncStateRecord,
-    SyncStatusSnapshot,
+    ChangePayload, EntityType, FocusBucketChange, ForgetChange, InputBucketChange, OutboxEntry,
+    PullResponse, PushAck, PushBatch, RemoteChange, RemoteStatus, SourceAction, SourceActionChange,
+    SourceChange, SyncStateRecord, SyncStatusSnapshot,
 };
@@ -1,18 +1,29 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::{params, types::Type, Connection, OptionalExtension};
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::common::{FocusBucketRecord, InputBucketRecord, SourceInfo};
+use crate::common::{FocusBucketRecord, InputBucketRecord, SourceInfo, DEFAULT_SOURCE_ID};
 use crate::storage::localdb::{
-    get_source, get_source_by_uuid, insert_focus_buckets, insert_input_buckets,
-    upsert_source_by_uuid,
+    compact_late_focus_row, compact_late_input_row, delete_source, forget, get_source,
+    get_source_by_uuid, insert_focus_buckets, insert_input_buckets, merge_sources, rename_source,
+    retired_source, upsert_source_by_uuid, ForgetFilter, ForgetReport, RetiredSource,
 };
 
 use super::types::{
-    ChangePayload, EntityType, FocusBucketChange, InputBucketChange, OutboxEntry, SourceChange,
+    ChangePayload, EntityType, FocusBucketChange, ForgetChange, ForgetScreen, InputBucketChange,
+    OutboxEntry, SourceAction, SourceActionChange, SourceChange,
 };
 
+const OUTBOX_TABLES: [&str; 5] = [
+    "sync_outbox_sources",
+    "sync_outbox_input_buckets",
+    "sync_outbox_focus_buckets",
+    "sync_outbox_source_actions",
+    "sync_outbox_forgets",
+];
+
 pub fn apply_local_source(conn: &Connection, source: &SourceInfo) -> Result<()> {
     let change = SourceChange {
         source_uuid: source.source_uuid.clone(),
@@ -56,8 +67,111 @@ pub fn apply_local_focus_rows(conn: &Connection, rows: &[FocusBucketRecord]) ->
     Ok(())
 }
 
-pub fn apply_remote_change(conn: &Connection, change: &ChangePayload) -> Result<()> {
+/// Renames, merges or deletes `target_source_uuid` here and queues the action for the other
+/// devices. A rename of this machine's own source goes out as its source row instead.
+pub fn apply_local_source_action(
+    conn: &Connection,
+    own_source_uuid: &str,
+    target_source_uuid: &str,
+    action: SourceAction,
+) -> Result<()> {
+    match &action {
+        SourceAction::Rename { source_name } => {
+            rename_source(conn, target_source_uuid, source_name)?;
+            if target_source_uuid == own_source_uuid {
+                let source = get_source(conn, DEFAULT_SOURCE_ID)?;
+                return apply_local_source(conn, &source);
+            }
+        }
+        SourceAction::Merge { merged_into_uuid } => {
+            merge_sources(conn, target_source_uuid, merged_into_uuid)?
+        }
+        SourceAction::Delete => delete_source(conn, target_source_uuid)?,
+    }
+    enqueue_source_action(
+        conn,
+        &SourceActionChange {
+            source_uuid: own_source_uuid.to_string(),
+            target_source_uuid: target_source_uuid.to_string(),
+            action,
+            created_at_utc: Utc::now().to_rfc3339(),
+        },
+    )
+}
+
+/// Forgets the rows `filter` selects here and queues it for the other devices and the remote,
+/// which scrubs its own copy and change log.
+pub fn apply_local_forget(
+    conn: &Connection,
+    own_source_uuid: &str,
+    filter: &ForgetFilter,
+) -> Result<ForgetReport> {
+    let report = forget(conn, filter)?;
+    let change = ForgetChange {
+        source_uuid: own_source_uuid.to_string(),
+        filter: filter.clone(),
+        created_at_utc: Utc::now().to_rfc3339(),
+    };
+    record_forget(conn, &change)?;
+    enqueue_forget(conn, &change)?;
+    Ok(report)
+}
+
+/// The forgets this device ran or pulled, for [`apply_remote_change`] to screen the bucket
+/// changes that other devices pushed before they heard of them.
+pub fn load_forget_screen(conn: &Connection) -> Result<ForgetScreen> {
+    let mut statement = conn.prepare(
+        "
+        SELECT source_uuid, target_source_uuid, app_identifier, title_pattern, from_utc,
+               until_utc, created_at_utc
+        FROM sync_forgets
+        ORDER BY created_at_utc ASC
+        ",
+    )?;
+    let forgets = statement
+        .query_map([], |row| {
+            Ok(ForgetChange {
+                source_uuid: row.get(0)?,
+                filter: ForgetFilter {
+                    source_uuid: row.get(1)?,
+                    app_identifier: row.get(2)?,
+                    title_pattern: row.get(3)?,
+                    from_utc: row.get(4)?,
+                    until_utc: row.get(5)?,
+                },
+                created_at_utc: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .with_context(|| "Failed to load forgets")?;
+    ForgetScreen::new(&forgets)
+}
+
+/// Bucket changes for a range that maintenance already compacted are folded into the compacted
+/// rows, so the detail does not come back next to their totals.
+/// Changes for a merged source land in the one it was merged into, and changes for a deleted
+/// source are dropped. Bucket changes pass through `forgets` first, and forgets join it.
+pub fn apply_remote_change(
+    conn: &Connection,
+    forgets: &mut ForgetScreen,
+    change: &ChangePayload,
+) -> Result<()> {
+    let redirected;
+    let change = match redirect_retired_source(conn, change)? {
+        Redirect::Keep => change,
+        Redirect::To(change) => {
+            redirected = change;
+            &redirected
+        }
+        Redirect::Drop => return Ok(()),
+    };
     match change {
+        ChangePayload::SourceAction(change) => apply_remote_source_action(conn, change)?,
+        ChangePayload::Forget(change) => {
+            forget(conn, &change.effective_filter())?;
+            record_forget(conn, change)?;
+            forgets.push(change)?;
+        }
         ChangePayload::Source(change) => {
             upsert_source_by_uuid(
                 conn,
@@ -68,21 +182,125 @@ pub fn apply_remote_change(conn: &Connection, change: &ChangePayload) -> Result<
             )?;
         }
         ChangePayload::InputBucket(change) => {
+            let Some(change) = forgets.screen_input(change.clone()) else {
+                return Ok(());
+            };
             let source_id =
                 resolve_remote_source_id(conn, &change.source_uuid, &change.bucket_start_utc)?;
-            let row = input_row_from_change(change, source_id)?;
+            let mut row = input_row_from_change(&change, source_id)?;
+            compact_late_input_row(conn, &mut row)?;
             insert_input_buckets(conn, &[row])?;
         }
         ChangePayload::FocusBucket(change) => {
+            let Some(change) = forgets.screen_focus(change.clone()) else {
+                return Ok(());
+            };
             let source_id =
                 resolve_remote_source_id(conn, &change.source_uuid, &change.bucket_start_utc)?;
-            let row = focus_row_from_change(change, source_id)?;
+            let mut row = focus_row_from_change(&change, source_id)?;
+            compact_late_focus_row(conn, &mut row)?;
             insert_focus_buckets(conn, &[row])?;
         }
     }
     Ok(())
 }
 
+enum Redirect {
+    Keep,
+    To(ChangePayload),
+    Drop,
+}
+
+fn redirect_retired_source(conn: &Connection, change: &ChangePayload) -> Result<Redirect> {
+    if let ChangePayload::Forget(change) = change {
+        return redirect_forget(conn, change);
+    }
+    if matches!(change, ChangePayload::SourceAction(_)) {
+        return Ok(Redirect::Keep);
+    }
+    let merged_into_uuid = match retired_source(conn, change.source_uuid())? {
+        None => return Ok(Redirect::Keep),
+        Some(RetiredSource::Deleted) => return Ok(Redirect::Drop),
+        Some(RetiredSource::MergedInto(uuid)) => uuid,
+    };
+    Ok(match change {
+        ChangePayload::InputBucket(change) => {
+            Redirect::To(ChangePayload::InputBucket(InputBucketChange {
+                source_uuid: merged_into_uuid,
+                ..change.clone()
+            }))
+        }
+        ChangePayload::FocusBucket(change) => {
+            Redirect::To(ChangePayload::FocusBucket(FocusBucketChange {
+                source_uuid: merged_into_uuid,
+                ..change.clone()
+            }))
+        }
+        // Pulls send source rows ahead of their buckets, so this one stands in for the source
+        // it was merged into until that one's own row arrives.
+        ChangePayload::Source(change) if get_source_by_uuid(conn, &merged_into_uuid)?.is_none() => {
+            Redirect::To(ChangePayload::Source(SourceChange {
+                source_uuid: merged_into_uuid,
+                ..change.clone()
+            }))
+        }
+        _ => Redirect::Drop,
+    })
+}
+
+/// A forget follows the source it targets: into the source it was merged into, or nowhere
+/// once it is deleted.
+fn redirect_forget(conn: &Connection, change: &ForgetChange) -> Result<Redirect> {
+    let Some(target) = change.filter.source_uuid.as_deref() else {
+        return Ok(Redirect::Keep);
+    };
+    Ok(match retired_source(conn, target)? {
+        None => Redirect::Keep,
+        Some(RetiredSource::Deleted) => Redirect::Drop,
+        Some(RetiredSource::MergedInto(merged_into_uuid)) => {
+            Redirect::To(ChangePayload::Forget(ForgetChange {
+                filter: ForgetFilter {
+                    source_uuid: Some(merged_into_uuid),
+                    ..change.filter.clone()
+                },
+                ..change.clone()
+            }))
+        }
+    })
+}
+
+/// Actions come back to the device that ran them on later pulls, so applying one twice has to
+/// leave the database as it was. This machine's own source can be renamed from elsewhere but
+/// not merged away or deleted.
+fn apply_remote_source_action(conn: &Connection, change: &SourceActionChange) -> Result<()> {
+    let own_source = get_source(conn, DEFAULT_SOURCE_ID)?;
+    let targets_own_source = change.target_source_uuid == own_source.source_uuid;
+    match &change.action {
+        SourceAction::Rename { source_name } => {
+            if targets_own_source && own_source.source_name == *source_name {
+                return Ok(());
+            }
+            rename_source(conn, &change.target_source_uuid, source_name)?;
+            if targets_own_source {
+                apply_local_source(conn, &get_source(conn, DEFAULT_SOURCE_ID)?)?;
+            }
+        }
+        SourceAction::Merge { .. } | SourceAction::Delete if targets_own_source => {
+            warn!(
+                "Ignoring a {} of this machine's source {} from {}",
+                change.action.as_str(),
+                change.target_source_uuid,
+                change.source_uuid
+            );
+        }
+        SourceAction::Merge { merged_into_uuid } => {
+            merge_sources(conn, &change.target_source_uuid, merged_into_uuid)?
+        }
+        SourceAction::Delete => delete_source(conn, &change.target_source_uuid)?,
+    }
+    Ok(())
+}
+
 pub fn list_pending_outbox(conn: &Connection, own_source_uuid: &str) -> Result<Vec<OutboxEntry>> {
     let mut entries = Vec::new();
 
@@ -191,6 +409,78 @@ pub fn list_pending_outbox(conn: &Connection, own_source_uuid: &str) -> Result<V
     })?;
     entries.extend(focus_rows.collect::<rusqlite::Result<Vec<_>>>()?);
 
+    let mut action_stmt = conn.prepare(
+        "
+        SELECT id, batch_uuid, source_uuid, target_source_uuid, action, source_name,
+               merged_into_uuid, created_at_utc, sent_at_utc, attempt_count
+        FROM sync_outbox_source_actions
+        WHERE sent_at_utc IS NULL AND source_uuid = ?1
+        ",
+    )?;
+    let action_rows = action_stmt.query_map([own_source_uuid], |row| {
+        let action_name: String = row.get(4)?;
+        let action =
+            SourceAction::from_parts(&action_name, row.get(5)?, row.get(6)?).ok_or_else(|| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    4,
+                    Type::Text,
+                    format!("unknown source action {action_name}").into(),
+                )
+            })?;
+        let change = SourceActionChange {
+            source_uuid: row.get(2)?,
+            target_source_uuid: row.get(3)?,
+            action,
+            created_at_utc: row.get(7)?,
+        };
+        Ok(OutboxEntry {
+            id: row.get(0)?,
+            batch_uuid: row.get(1)?,
+            entity_type: EntityType::SourceAction,
+            entity_key: change.entity_key(),
+            source_uuid: change.source_uuid.clone(),
+            created_at_utc: change.created_at_utc.clone(),
+            payload: ChangePayload::SourceAction(change),
+            sent_at_utc: row.get(8)?,
+            attempt_count: row.get::<_, i64>(9)? as u32,
+        })
+    })?;
+    entries.extend(action_rows.collect::<rusqlite::Result<Vec<_>>>()?);
+
+    let mut forget_stmt = conn.prepare(
+        "
+        SELECT id, batch_uuid, source_uuid, target_source_uuid, app_identifier, title_pattern,
+               from_utc, until_utc, created_at_utc, sent_at_utc, attempt_count
+        FROM sync_outbox_forgets
+        WHERE sent_at_utc IS NULL AND source_uuid = ?1
+        ",
+    )?;
+    let forget_rows = forget_stmt.query_map([own_source_uuid], |row| {
+        let change = ForgetChange {
+            source_uuid: row.get(2)?,
+            filter: ForgetFilter {
+                source_uuid: row.get(3)?,
+                app_identifier: row.get(4)?,
+                title_pattern: row.get(5)?,
+                from_utc: row.get(6)?,
+                until_utc: row.get(7)?,
+            },
+            created_at_utc: row.get(8)?,
+        };
+        Ok(OutboxEntry {
+            id: row.get(0)?,
+            batch_uuid: row.get(1)?,
+            entity_type: EntityType::Forget,
+            entity_key: change.entity_key(),
+            source_uuid: change.source_uuid.clone(),
+            created_at_utc: change.created_at_utc.clone(),
+            payload: ChangePayload::Forget(change),
+            sent_at_utc: row.get(9)?,
+            attempt_count: row.get::<_, i64>(10)? as u32,
+        })
+    })?;
+    entries.extend(forget_rows.collect::<rusqlite::Result<Vec<_>>>()?);
+
     entries.sort_by(|left, right| {
         left.created_at_utc
             .cmp(&right.created_at_utc)
@@ -220,6 +510,14 @@ pub fn prepare_pending_batch(
                 SELECT batch_uuid, created_at_utc
                 FROM sync_outbox_focus_buckets
                 WHERE sent_at_utc IS NULL AND source_uuid = ?1 AND batch_uuid IS NOT NULL
+                UNION ALL
+                SELECT batch_uuid, created_at_utc
+                FROM sync_outbox_source_actions
+                WHERE sent_at_utc IS NULL AND source_uuid = ?1 AND batch_uuid IS NOT NULL
+                UNION ALL
+                SELECT batch_uuid, created_at_utc
+                FROM sync_outbox_forgets
+                WHERE sent_at_utc IS NULL AND source_uuid = ?1 AND batch_uuid IS NOT NULL
             )
             ORDER BY created_at_utc ASC
             LIMIT 1
@@ -249,21 +547,19 @@ pub fn prepare_pending_batch(
 
 pub fn mark_batch_sent(conn: &Connection, batch_uuid: &str) -> Result<()> {
     let sent_at = Utc::now().to_rfc3339();
-    conn.execute(
-        "UPDATE sync_outbox_sources SET sent_at_utc = ?2 WHERE batch_uuid = ?1 AND sent_at_utc IS NULL",
-        params![batch_uuid, sent_at],
-    )?;
-    conn.execute(
-        "UPDATE sync_outbox_input_buckets SET sent_at_utc = ?2 WHERE batch_uuid = ?1 AND sent_at_utc IS NULL",
-        params![batch_uuid, sent_at],
-    )?;
-    conn.execute(
-        "UPDATE sync_outbox_focus_buckets SET sent_at_utc = ?2 WHERE batch_uuid = ?1 AND sent_at_utc IS NULL",
-        params![batch_uuid, sent_at],
-    )?;
+    for table in OUTBOX_TABLES {
+        conn.execute(
+            &format!(
+                "UPDATE {table} SET sent_at_utc = ?2 WHERE batch_uuid = ?1 AND sent_at_utc IS NULL"
+            ),
+            params![batch_uuid, sent_at],
+        )?;
+    }
     Ok(())
 }
 
+/// Compacted history goes out too, in its compacted form, so a remote seeded from this device
+/// holds all of its totals rather than only the range that still has full detail.
 pub fn seed_outbox_for_owned_rows(conn: &Connection, own_source_uuid: &str) -> Result<()> {
     let Some(source) = get_source_by_uuid(conn, own_source_uuid)? else {
         return Ok(());
@@ -288,7 +584,7 @@ pub fn seed_outbox_for_owned_rows(conn: &Connection, own_source_uuid: &str) -> R
         WHERE source_id = ?1
         ",
     )?;
-    let input_rows = input_stmt.query_map([source.id], |row| {
+    let input_rows = input_stmt.query_map(params![source.id], |row| {
         Ok((
             row.get::<_, String>(0)?,
             row.get::<_, String>(1)?,
@@ -336,7 +632,7 @@ pub fn seed_outbox_for_owned_rows(conn: &Connection, own_source_uuid: &str) -> R
         WHERE source_id = ?1
         ",
     )?;
-    let focus_rows = focus_stmt.query_map([source.id], |row| {
+    let focus_rows = focus_stmt.query_map(params![source.id], |row| {
         Ok((
             row.get::<_, String>(0)?,
             row.get::<_, String>(1)?,
@@ -424,6 +720,7 @@ pub fn input_row_from_change(
         mouse_distance_cm: change.mouse_distance_cm,
         scroll_vertical_cm: change.scroll_vertical_cm,
         scroll_horizontal_cm: change.scroll_horizontal_cm,
+        dropped_events: 0,
     })
 }
 
@@ -547,12 +844,72 @@ fn enqueue_focus_change(conn: &Connection, change: &FocusBucketChange) -> Result
     Ok(())
 }
 
+fn enqueue_source_action(conn: &Connection, change: &SourceActionChange) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO sync_outbox_source_actions (
+            source_uuid, target_source_uuid, action, source_name, merged_into_uuid, created_at_utc
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ",
+        params![
+            change.source_uuid,
+            change.target_source_uuid,
+            change.action.as_str(),
+            change.action.source_name(),
+            change.action.merged_into_uuid(),
+            change.created_at_utc
+        ],
+    )?;
+    Ok(())
+}
+
+/// Forgets come back to the device that ran them on later pulls, so each is kept once.
+fn record_forget(conn: &Connection, change: &ForgetChange) -> Result<()> {
+    conn.execute(
+        "
+        INSERT OR IGNORE INTO sync_forgets (
+            source_uuid, target_source_uuid, app_identifier, title_pattern, from_utc, until_utc,
+            created_at_utc
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ",
+        params![
+            change.source_uuid,
+            change.filter.source_uuid,
+            change.filter.app_identifier,
+            change.filter.title_pattern,
+            change.filter.from_utc,
+            change.filter.until_utc,
+            change.created_at_utc
+        ],
+    )?;
+    Ok(())
+}
+
+fn enqueue_forget(conn: &Connection, change: &ForgetChange) -> Result<()> {
+    conn.execute(
+        "
+        INSERT INTO sync_outbox_forgets (
+            source_uuid, target_source_uuid, app_identifier, title_pattern, from_utc, until_utc,
+            created_at_utc
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ",
+        params![
+            change.source_uuid,
+            change.filter.source_uuid,
+            change.filter.app_identifier,
+            change.filter.title_pattern,
+            change.filter.from_utc,
+            change.filter.until_utc,
+            change.created_at_utc
+        ],
+    )?;
+    Ok(())
+}
+
 fn pending_outbox_count(conn: &Connection, own_source_uuid: &str) -> Result<i64> {
-    Ok(
-        count_pending_table(conn, "sync_outbox_sources", own_source_uuid)?
-            + count_pending_table(conn, "sync_outbox_input_buckets", own_source_uuid)?
-            + count_pending_table(conn, "sync_outbox_focus_buckets", own_source_uuid)?,
-    )
+    OUTBOX_TABLES.iter().try_fold(0, |count, table| {
+        Ok(count + count_pending_table(conn, table, own_source_uuid)?)
+    })
 }
 
 fn count_pending_table(conn: &Connection, table: &str, own_source_uuid: &str) -> Result<i64> {
@@ -562,11 +919,7 @@ fn count_pending_table(conn: &Connection, table: &str, own_source_uuid: &str) ->
 }
 
 fn assign_batch_uuid(conn: &Connection, own_source_uuid: &str, batch_uuid: &str) -> Result<()> {
-    for table in [
-        "sync_outbox_sources",
-        "sync_outbox_input_buckets",
-        "sync_outbox_focus_buckets",
-    ] {
+    for table in OUTBOX_TABLES {
         let sql = format!(
             "UPDATE {table} SET batch_uuid = ?2, attempt_count = attempt_count + 1 \
              WHERE sent_at_utc IS NULL AND source_uuid = ?1 AND batch_uuid IS NULL"
@@ -581,11 +934,7 @@ fn increment_batch_attempts(
     own_source_uuid: &str,
     batch_uuid: &str,
 ) -> Result<()> {
-    for table in [
-        "sync_outbox_sources",
-        "sync_outbox_input_buckets",
-        "sync_outbox_focus_buckets",
-    ] {
+    for table in OUTBOX_TABLES {
         let sql = format!(
             "UPDATE {table} SET attempt_count = attempt_count + 1 \
              WHERE sent_at_utc IS NULL AND source_uuid = ?1 AND batch_uuid = ?2"
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use rusqlite::Connection;
 
-use super::outbox::apply_remote_change;
+use super::outbox::{apply_remote_change, load_forget_screen};
 use super::remote::SyncRemote;
 use super::state::{
     clear_sync_error, load_sync_state, record_sync_error, record_sync_pull_success,
@@ -16,8 +16,9 @@ pub fn apply_pull_response(
     response: &PullResponse,
 ) -> Result<()> {
     let tx = conn.transaction()?;
+    let mut forgets = load_forget_screen(&tx)?;
     for change in &response.changes {
-        apply_remote_change(&tx, &change.payload)?;
+        apply_remote_change(&tx, &mut forgets, &change.payload)?;
     }
     tx.commit()?;
 
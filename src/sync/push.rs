@@ -30,11 +30,15 @@ pub fn prepare_sync_push(
     let mut source_changes = Vec::new();
     let mut input_changes = Vec::new();
     let mut focus_changes = Vec::new();
+    let mut source_actions = Vec::new();
+    let mut forgets = Vec::new();
     for entry in entries {
         match entry.payload {
             super::types::ChangePayload::Source(change) => source_changes.push(change),
             super::types::ChangePayload::InputBucket(change) => input_changes.push(change),
             super::types::ChangePayload::FocusBucket(change) => focus_changes.push(change),
+            super::types::ChangePayload::SourceAction(change) => source_actions.push(change),
+            super::types::ChangePayload::Forget(change) => forgets.push(change),
         }
     }
 
@@ -46,6 +50,8 @@ pub fn prepare_sync_push(
             source_changes,
             input_changes,
             focus_changes,
+            source_actions,
+            forgets,
         },
     }))
 }
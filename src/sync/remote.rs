@@ -4,9 +4,12 @@ use std::sync::{Arc, Mutex};
 use anyhow::{bail, Context, Result};
 use chrono::Utc;
 
+use crate::storage::localdb::ForgetFilter;
+
 use super::types::{
-    ChangePayload, EntityType, FocusBucketChange, InputBucketChange, PullResponse, PushAck,
-    PushBatch, RemoteChange, RemoteStatus, SourceChange,
+    ChangePayload, EntityType, FocusBucketChange, ForgetChange, ForgetScreen, InputBucketChange,
+    PullResponse, PushAck, PushBatch, RemoteChange, RemoteStatus, SourceAction, SourceActionChange,
+    SourceChange,
 };
 
 #[allow(async_fn_in_trait)]
@@ -35,6 +38,7 @@ struct RemoteState {
     input_rows: BTreeMap<String, InputBucketChange>,
     focus_rows: BTreeMap<String, FocusBucketChange>,
     change_log: Vec<RemoteChange>,
+    forgets: Vec<ForgetChange>,
 }
 
 impl InMemoryRemote {
@@ -55,11 +59,82 @@ impl InMemoryRemote {
             ChangePayload::FocusBucket(change) => {
                 state.focus_rows.insert(change.entity_key(), change.clone());
             }
+            ChangePayload::SourceAction(change) => {
+                if let SourceAction::Rename { source_name } = &change.action {
+                    let key = format!("source:{}", change.target_source_uuid);
+                    if let Some(source) = state.source_rows.get_mut(&key) {
+                        source.source_name = source_name.clone();
+                    }
+                }
+            }
+            ChangePayload::Forget(_) => {}
         }
 
         state.change_log.push(RemoteChange { revision, payload });
     }
 
+    /// Removes what a forget selects from the rows and from the change log, so devices pulling
+    /// from scratch never see it. Redacted titles are folded into the untitled bucket.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn scrub_forgotten(state: &mut RemoteState, change: &ForgetChange) -> Result<()> {
+        let filter = change.effective_filter();
+        let matcher = filter.matcher()?;
+        let redact = filter.redacts_titles();
+        let untitled = |change: &FocusBucketChange| FocusBucketChange {
+            window_title: String::new(),
+            ..change.clone()
+        };
+
+        state
+            .input_rows
+            .retain(|_, row| !matcher.matches_input(&row.source_uuid, &row.bucket_start_utc));
+        let forgotten = state
+            .focus_rows
+            .iter()
+            .filter(|(_, row)| {
+                matcher.matches_focus(
+                    &row.source_uuid,
+                    &row.bucket_start_utc,
+                    &row.app_identifier,
+                    &row.window_title,
+                )
+            })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in forgotten {
+            let row = state.focus_rows.remove(&key).expect("key was just listed");
+            if redact {
+                let row = untitled(&row);
+                state
+                    .focus_rows
+                    .entry(row.entity_key())
+                    .and_modify(|blank| blank.focus_seconds += row.focus_seconds)
+                    .or_insert(row);
+            }
+        }
+
+        state
+            .change_log
+            .retain_mut(|logged| match &mut logged.payload {
+                ChangePayload::InputBucket(row) => {
+                    !matcher.matches_input(&row.source_uuid, &row.bucket_start_utc)
+                }
+                ChangePayload::FocusBucket(row)
+                    if matcher.matches_focus(
+                        &row.source_uuid,
+                        &row.bucket_start_utc,
+                        &row.app_identifier,
+                        &row.window_title,
+                    ) =>
+                {
+                    *row = untitled(row);
+                    redact
+                }
+                _ => true,
+            });
+        Ok(())
+    }
+
     fn source_metadata_changes(
         state: &RemoteState,
         changes: &[RemoteChange],
@@ -117,6 +192,7 @@ impl SyncRemote for InMemoryRemote {
             });
         }
 
+        let screen = ForgetScreen::new(&state.forgets)?;
         let mut applied_revision = state.head_revision;
         for change in batch.source_changes {
             if change.source_uuid != batch.source_uuid {
@@ -129,6 +205,9 @@ impl SyncRemote for InMemoryRemote {
             if change.source_uuid != batch.source_uuid {
                 bail!("push batch contains foreign-source payload");
             }
+            let Some(change) = screen.screen_input(change) else {
+                continue;
+            };
             Self::append_change(&mut state, ChangePayload::InputBucket(change));
             applied_revision = state.head_revision;
         }
@@ -136,7 +215,36 @@ impl SyncRemote for InMemoryRemote {
             if change.source_uuid != batch.source_uuid {
                 bail!("push batch contains foreign-source payload");
             }
+            let titled = !change.window_title.is_empty();
+            let Some(change) = screen.screen_focus(change) else {
+                continue;
+            };
+            // A redacted title joins the untitled bucket rather than replacing its time.
+            let key = change.entity_key();
+            let folded = match state.focus_rows.get(&key) {
+                Some(blank) if titled && change.window_title.is_empty() => blank.focus_seconds,
+                _ => 0,
+            };
             Self::append_change(&mut state, ChangePayload::FocusBucket(change));
+            if let Some(row) = state.focus_rows.get_mut(&key) {
+                row.focus_seconds += folded;
+            }
+            applied_revision = state.head_revision;
+        }
+        for change in batch.source_actions {
+            if change.source_uuid != batch.source_uuid {
+                bail!("push batch contains foreign-source payload");
+            }
+            Self::append_change(&mut state, ChangePayload::SourceAction(change));
+            applied_revision = state.head_revision;
+        }
+        for change in batch.forgets {
+            if change.source_uuid != batch.source_uuid {
+                bail!("push batch contains foreign-source payload");
+            }
+            Self::scrub_forgotten(&mut state, &change)?;
+            state.forgets.push(change.clone());
+            Self::append_change(&mut state, ChangePayload::Forget(change));
             applied_revision = state.head_revision;
         }
 
@@ -182,6 +290,13 @@ impl SyncRemote for InMemoryRemote {
     }
 }
 
+#[cfg(feature = "multi-sync")]
+const FORGET_CHANGE_COLUMNS: &str = "
+    SELECT source_uuid, target_source_uuid, app_identifier, title_pattern, from_utc, until_utc,
+           created_at_utc
+    FROM sync_forget_changes
+";
+
 #[cfg(feature = "multi-sync")]
 #[derive(Debug, Clone)]
 pub struct SqldRemote {
@@ -306,6 +421,27 @@ impl SqldRemote {
                 window_class TEXT NOT NULL,
                 focus_seconds INTEGER NOT NULL
             );
+
+            CREATE TABLE IF NOT EXISTS sync_source_action_changes (
+                revision INTEGER PRIMARY KEY,
+                source_uuid TEXT NOT NULL,
+                target_source_uuid TEXT NOT NULL,
+                action TEXT NOT NULL,
+                source_name TEXT,
+                merged_into_uuid TEXT,
+                created_at_utc TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS sync_forget_changes (
+                revision INTEGER PRIMARY KEY,
+                source_uuid TEXT NOT NULL,
+                target_source_uuid TEXT,
+                app_identifier TEXT,
+                title_pattern TEXT,
+                from_utc TEXT,
+                until_utc TEXT,
+                created_at_utc TEXT NOT NULL
+            );
             ",
         )
         .await
@@ -359,6 +495,12 @@ impl SqldRemote {
             "focus_bucket" => Self::load_focus_change(conn, revision)
                 .await
                 .map(ChangePayload::FocusBucket),
+            "source_action" => Self::load_source_action_change(conn, revision)
+                .await
+                .map(ChangePayload::SourceAction),
+            "forget" => Self::load_forget_change(conn, revision)
+                .await
+                .map(ChangePayload::Forget),
             other => bail!("unsupported sync entity type {other}"),
         }
     }
@@ -546,6 +688,228 @@ impl SqldRemote {
         })
     }
 
+    async fn load_source_action_change(
+        conn: &libsql::Connection,
+        revision: i64,
+    ) -> Result<SourceActionChange> {
+        let mut rows = conn
+            .query(
+                "
+                SELECT source_uuid, target_source_uuid, action, source_name, merged_into_uuid,
+                       created_at_utc
+                FROM sync_source_action_changes
+                WHERE revision = ?1
+                ",
+                libsql::params![revision],
+            )
+            .await?;
+        let row = rows
+            .next()
+            .await?
+            .context("missing source action change row")?;
+        let text = |index| -> Result<Option<String>> {
+            Ok(row.get_value(index)?.as_text().map(ToString::to_string))
+        };
+        let action_name = text(2)?.unwrap_or_default();
+        let action = SourceAction::from_parts(&action_name, text(3)?, text(4)?)
+            .with_context(|| format!("unsupported source action {action_name}"))?;
+        Ok(SourceActionChange {
+            source_uuid: text(0)?.unwrap_or_default(),
+            target_source_uuid: text(1)?.unwrap_or_default(),
+            action,
+            created_at_utc: text(5)?.unwrap_or_default(),
+        })
+    }
+
+    async fn load_forget_change(conn: &libsql::Connection, revision: i64) -> Result<ForgetChange> {
+        let mut rows = conn
+            .query(
+                &format!("{FORGET_CHANGE_COLUMNS} WHERE revision = ?1"),
+                libsql::params![revision],
+            )
+            .await?;
+        let row = rows.next().await?.context("missing forget change row")?;
+        Self::forget_change_from_row(&row)
+    }
+
+    /// Every forget pushed so far, oldest first.
+    async fn load_forget_changes(conn: &libsql::Connection) -> Result<Vec<ForgetChange>> {
+        let mut rows = conn
+            .query(
+                &format!("{FORGET_CHANGE_COLUMNS} ORDER BY revision ASC"),
+                (),
+            )
+            .await?;
+        let mut changes = Vec::new();
+        while let Some(row) = rows.next().await? {
+            changes.push(Self::forget_change_from_row(&row)?);
+        }
+        Ok(changes)
+    }
+
+    fn forget_change_from_row(row: &libsql::Row) -> Result<ForgetChange> {
+        let text = |index| -> Result<Option<String>> {
+            Ok(row.get_value(index)?.as_text().map(ToString::to_string))
+        };
+        Ok(ForgetChange {
+            source_uuid: text(0)?.unwrap_or_default(),
+            filter: ForgetFilter {
+                source_uuid: text(1)?,
+                app_identifier: text(2)?,
+                title_pattern: text(3)?,
+                from_utc: text(4)?,
+                until_utc: text(5)?,
+            },
+            created_at_utc: text(6)?.unwrap_or_default(),
+        })
+    }
+
+    /// Removes what a forget selects from the canonical buckets and from the change log, so
+    /// devices pulling from scratch never see it. Redacted titles are folded into the untitled
+    /// bucket and the logged changes lose their title.
+    async fn scrub_forgotten(tx: &libsql::Transaction, change: &ForgetChange) -> Result<()> {
+        let filter = change.effective_filter();
+        let matcher = filter.matcher()?;
+        let scope = || {
+            libsql::params![
+                filter.source_uuid.clone(),
+                filter.from_utc.clone(),
+                filter.until_utc.clone()
+            ]
+        };
+        const IN_SCOPE: &str = "
+            (?1 IS NULL OR source_uuid = ?1)
+            AND (?2 IS NULL OR bucket_start_utc >= ?2)
+            AND (?3 IS NULL OR bucket_start_utc < ?3)
+        ";
+
+        if filter.covers_input() {
+            tx.execute(
+                &format!("DELETE FROM input_buckets WHERE {IN_SCOPE}"),
+                scope(),
+            )
+            .await?;
+            tx.execute(
+                &format!(
+                    "
+                    DELETE FROM sync_revisions
+                    WHERE revision IN (SELECT revision FROM sync_input_changes WHERE {IN_SCOPE})
+                    "
+                ),
+                scope(),
+            )
+            .await?;
+            tx.execute(
+                &format!("DELETE FROM sync_input_changes WHERE {IN_SCOPE}"),
+                scope(),
+            )
+            .await?;
+        }
+
+        let mut forgotten = Vec::new();
+        let mut rows = tx
+            .query(
+                &format!(
+                    "
+                    SELECT id, source_uuid, bucket_start_utc, app_identifier, window_title
+                    FROM focus_buckets
+                    WHERE {IN_SCOPE}
+                    "
+                ),
+                scope(),
+            )
+            .await?;
+        while let Some(row) = rows.next().await? {
+            if matcher.matches_focus(
+                &row.get::<String>(1)?,
+                &row.get::<String>(2)?,
+                &row.get::<String>(3)?,
+                &row.get::<String>(4)?,
+            ) {
+                forgotten.push(row.get::<i64>(0)?);
+            }
+        }
+        for id in forgotten {
+            if filter.redacts_titles() {
+                tx.execute(
+                    "
+                    INSERT INTO focus_buckets (
+                        source_uuid, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+                        timezone_offset_minutes, app_identifier, window_title, window_class,
+                        focus_seconds
+                    )
+                    SELECT
+                        source_uuid, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+                        timezone_offset_minutes, app_identifier, '', window_class, focus_seconds
+                    FROM focus_buckets WHERE id = ?1
+                    ON CONFLICT(source_uuid, bucket_start_utc, window_title, window_class)
+                    DO UPDATE SET focus_seconds = focus_buckets.focus_seconds + excluded.focus_seconds
+                    ",
+                    libsql::params![id],
+                )
+                .await?;
+            }
+            tx.execute(
+                "DELETE FROM focus_buckets WHERE id = ?1",
+                libsql::params![id],
+            )
+            .await?;
+        }
+
+        let mut forgotten = Vec::new();
+        let mut rows = tx
+            .query(
+                &format!(
+                    "
+                    SELECT revision, source_uuid, bucket_start_utc, app_identifier, window_title,
+                           window_class
+                    FROM sync_focus_changes
+                    WHERE {IN_SCOPE}
+                    "
+                ),
+                scope(),
+            )
+            .await?;
+        while let Some(row) = rows.next().await? {
+            let (source_uuid, bucket_start_utc) = (row.get::<String>(1)?, row.get::<String>(2)?);
+            if matcher.matches_focus(
+                &source_uuid,
+                &bucket_start_utc,
+                &row.get::<String>(3)?,
+                &row.get::<String>(4)?,
+            ) {
+                let untitled_key = format!(
+                    "focus:{source_uuid}:{bucket_start_utc}::{}",
+                    row.get::<String>(5)?
+                );
+                forgotten.push((row.get::<i64>(0)?, untitled_key));
+            }
+        }
+        for (revision, untitled_key) in forgotten {
+            if filter.redacts_titles() {
+                tx.execute(
+                    "UPDATE sync_focus_changes SET window_title = '' WHERE revision = ?1",
+                    libsql::params![revision],
+                )
+                .await?;
+                tx.execute(
+                    "UPDATE sync_revisions SET entity_key = ?2 WHERE revision = ?1",
+                    libsql::params![revision, untitled_key],
+                )
+                .await?;
+            } else {
+                for table in ["sync_focus_changes", "sync_revisions"] {
+                    tx.execute(
+                        &format!("DELETE FROM {table} WHERE revision = ?1"),
+                        libsql::params![revision],
+                    )
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn prepend_missing_source_rows(
         conn: &libsql::Connection,
         changes: Vec<RemoteChange>,
@@ -609,6 +973,7 @@ impl SyncRemote for SqldRemote {
         }
 
         let tx = conn.transaction().await?;
+        let screen = ForgetScreen::new(&Self::load_forget_changes(&tx).await?)?;
         let mut applied_revision = self.remote_head_revision().await?;
 
         for change in &batch.source_changes {
@@ -660,6 +1025,9 @@ impl SyncRemote for SqldRemote {
             if change.source_uuid != batch.source_uuid {
                 bail!("push batch contains foreign-source payload");
             }
+            let Some(change) = screen.screen_input(change.clone()) else {
+                continue;
+            };
             tx.execute(
                 "
                 INSERT INTO input_buckets (
@@ -740,20 +1108,32 @@ impl SyncRemote for SqldRemote {
             if change.source_uuid != batch.source_uuid {
                 bail!("push batch contains foreign-source payload");
             }
+            let titled = !change.window_title.is_empty();
+            let Some(change) = screen.screen_focus(change.clone()) else {
+                continue;
+            };
+            // A redacted title joins the untitled bucket rather than replacing its time.
+            let focus_seconds = if titled && change.window_title.is_empty() {
+                "focus_buckets.focus_seconds + excluded.focus_seconds"
+            } else {
+                "excluded.focus_seconds"
+            };
             tx.execute(
-                "
-                INSERT INTO focus_buckets (
-                    source_uuid, bucket_start_utc, bucket_end_utc, local_date, local_hour,
-                    timezone_offset_minutes, app_identifier, window_title, window_class, focus_seconds
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
-                ON CONFLICT(source_uuid, bucket_start_utc, window_title, window_class) DO UPDATE SET
-                    bucket_end_utc = excluded.bucket_end_utc,
-                    local_date = excluded.local_date,
-                    local_hour = excluded.local_hour,
-                    timezone_offset_minutes = excluded.timezone_offset_minutes,
-                    app_identifier = excluded.app_identifier,
-                    focus_seconds = excluded.focus_seconds
-                ",
+                &format!(
+                    "
+                    INSERT INTO focus_buckets (
+                        source_uuid, bucket_start_utc, bucket_end_utc, local_date, local_hour,
+                        timezone_offset_minutes, app_identifier, window_title, window_class, focus_seconds
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                    ON CONFLICT(source_uuid, bucket_start_utc, window_title, window_class) DO UPDATE SET
+                        bucket_end_utc = excluded.bucket_end_utc,
+                        local_date = excluded.local_date,
+                        local_hour = excluded.local_hour,
+                        timezone_offset_minutes = excluded.timezone_offset_minutes,
+                        app_identifier = excluded.app_identifier,
+                        focus_seconds = {focus_seconds}
+                    "
+                ),
                 libsql::params![
                     change.source_uuid.clone(),
                     change.bucket_start_utc.clone(),
@@ -800,6 +1180,79 @@ impl SyncRemote for SqldRemote {
             applied_revision = revision;
         }
 
+        for change in &batch.source_actions {
+            if change.source_uuid != batch.source_uuid {
+                bail!("push batch contains foreign-source payload");
+            }
+            if let SourceAction::Rename { source_name } = &change.action {
+                tx.execute(
+                    "UPDATE sources SET source_name = ?2 WHERE source_uuid = ?1",
+                    libsql::params![change.target_source_uuid.clone(), source_name.clone()],
+                )
+                .await?;
+            }
+            let revision = Self::insert_revision(
+                &tx,
+                &change.source_uuid,
+                EntityType::SourceAction,
+                &change.entity_key(),
+            )
+            .await?;
+            tx.execute(
+                "
+                INSERT INTO sync_source_action_changes (
+                    revision, source_uuid, target_source_uuid, action, source_name,
+                    merged_into_uuid, created_at_utc
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ",
+                libsql::params![
+                    revision,
+                    change.source_uuid.clone(),
+                    change.target_source_uuid.clone(),
+                    change.action.as_str(),
+                    change.action.source_name().map(ToString::to_string),
+                    change.action.merged_into_uuid().map(ToString::to_string),
+                    change.created_at_utc.clone()
+                ],
+            )
+            .await?;
+            applied_revision = revision;
+        }
+
+        for change in &batch.forgets {
+            if change.source_uuid != batch.source_uuid {
+                bail!("push batch contains foreign-source payload");
+            }
+            Self::scrub_forgotten(&tx, change).await?;
+            let revision = Self::insert_revision(
+                &tx,
+                &change.source_uuid,
+                EntityType::Forget,
+                &change.entity_key(),
+            )
+            .await?;
+            tx.execute(
+                "
+                INSERT INTO sync_forget_changes (
+                    revision, source_uuid, target_source_uuid, app_identifier, title_pattern,
+                    from_utc, until_utc, created_at_utc
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ",
+                libsql::params![
+                    revision,
+                    change.source_uuid.clone(),
+                    change.filter.source_uuid.clone(),
+                    change.filter.app_identifier.clone(),
+                    change.filter.title_pattern.clone(),
+                    change.filter.from_utc.clone(),
+                    change.filter.until_utc.clone(),
+                    change.created_at_utc.clone()
+                ],
+            )
+            .await?;
+            applied_revision = revision;
+        }
+
         tx.execute(
             "
             INSERT INTO sync_applied_batches (batch_uuid, source_uuid, applied_revision, applied_at_utc)
@@ -10,10 +10,16 @@ use rusqlite::Connection;
 use uuid::Uuid;
 
 use crate::common::{FocusBucketRecord, InputBucketRecord, DEFAULT_SOURCE_ID};
-use crate::storage::localdb::{get_source, open_con_at, setup_database};
+use crate::storage::localdb::{
+    get_source, get_source_by_uuid, open_con_at, run_maintenance, setup_database, ForgetFilter,
+    RetentionPolicy,
+};
 
-use super::outbox::{apply_local_focus_rows, apply_local_input_rows, list_pending_outbox};
-use super::pull::sync_pull;
+use super::outbox::{
+    apply_local_focus_rows, apply_local_forget, apply_local_input_rows, apply_local_source_action,
+    input_change_from_row, list_pending_outbox,
+};
+use super::pull::{apply_pull_response, sync_pull};
 use super::push::{prepare_sync_push, sync_push};
 use super::remote::{InMemoryRemote, SyncRemote};
 use super::state::{
@@ -21,7 +27,8 @@ use super::state::{
 };
 use super::status::sync_status_snapshot;
 use super::types::{
-    FocusBucketChange, PullResponse, PushAck, PushBatch, RemoteChange, RemoteStatus,
+    ChangePayload, FocusBucketChange, PullResponse, PushAck, PushBatch, RemoteChange, RemoteStatus,
+    SourceAction,
 };
 
 fn unique_temp_db(name: &str) -> PathBuf {
@@ -68,6 +75,7 @@ fn sample_input_row(source_id: i64, minute: u32, key_presses: u64) -> InputBucke
         mouse_distance_cm: 3.0,
         scroll_vertical_cm: 0.4,
         scroll_horizontal_cm: 0.0,
+        dropped_events: 0,
     }
 }
 
@@ -465,6 +473,8 @@ async fn push_rejects_foreign_source_mutation() -> Result<()> {
             scroll_horizontal_cm: 0.0,
         }],
         focus_changes: Vec::new(),
+        source_actions: Vec::new(),
+        forgets: Vec::new(),
     };
 
     assert!(remote.push_batch(batch).await.is_err());
@@ -554,6 +564,170 @@ async fn two_devices_converge_after_push_then_pull() -> Result<()> {
     Ok(())
 }
 
+/// Proves a pull folds changes for a range that maintenance already compacted into the
+/// compacted bucket. It compacts pulled rows into an hourly bucket, then pulls a later change
+/// for the same range. This catches regressions where sync resurrects detail next to the
+/// compacted totals, or drops the late activity altogether.
+#[tokio::test]
+async fn pull_folds_changes_for_compacted_ranges() -> Result<()> {
+    let remote = InMemoryRemote::default();
+    let source_path = unique_temp_db("compacted-source");
+    let target_path = unique_temp_db("compacted-target");
+    let source = build_test_db(&source_path)?;
+    let mut target = build_test_db(&target_path)?;
+    let source_config = sync_config(&source, "memory://compacted")?;
+    let target_config = sync_config(&target, "memory://compacted")?;
+
+    apply_local_input_rows(&source, &[sample_input_row(DEFAULT_SOURCE_ID, 0, 10)])?;
+    apply_local_focus_rows(&source, &[sample_focus_row(DEFAULT_SOURCE_ID, 0, "Mail")])?;
+    sync_push(&source, &remote, &source_config).await?;
+    sync_pull(&mut target, &remote, &target_config).await?;
+
+    let policy = RetentionPolicy {
+        full_detail_days: 30,
+        hourly_days: 0,
+        title_days: 30,
+    };
+    let now = Utc.with_ymd_and_hms(2026, 6, 1, 0, 0, 0).unwrap();
+    run_maintenance(&mut target, policy, now, false)?;
+    let compacted = input_snapshot(&target)?;
+    assert_eq!(compacted.len(), 1);
+    assert!(compacted[0].contains("|2026-04-19T12:00:00+00:00|10|"));
+
+    apply_local_input_rows(&source, &[sample_input_row(DEFAULT_SOURCE_ID, 15, 7)])?;
+    apply_local_focus_rows(&source, &[sample_focus_row(DEFAULT_SOURCE_ID, 15, "Bank")])?;
+    sync_push(&source, &remote, &source_config).await?;
+    sync_pull(&mut target, &remote, &target_config).await?;
+
+    let folded = input_snapshot(&target)?;
+    assert_eq!(folded.len(), 1);
+    assert!(folded[0].contains("|2026-04-19T12:00:00+00:00|17|"));
+    assert_eq!(
+        focus_snapshot(&target)?,
+        vec![format!(
+            "{}|2026-04-19T12:00:00+00:00||240",
+            source_config.own_source_uuid
+        )]
+    );
+
+    Ok(())
+}
+
+/// Proves a merge and a rename run on one device are replayed on the others through the remote.
+/// It merges a reinstalled laptop's old source into its new one from a third device.
+/// This catches regressions where merged sources come back, or their later rows land apart.
+#[tokio::test]
+async fn source_merge_and_rename_propagate_to_other_devices() -> Result<()> {
+    let remote = InMemoryRemote::default();
+    let old_path = unique_temp_db("merge-old");
+    let laptop_path = unique_temp_db("merge-laptop");
+    let desktop_path = unique_temp_db("merge-desktop");
+    let old = build_test_db(&old_path)?;
+    let mut laptop = build_test_db(&laptop_path)?;
+    let mut desktop = build_test_db(&desktop_path)?;
+    let old_config = sync_config(&old, "memory://merge")?;
+    let laptop_config = sync_config(&laptop, "memory://merge")?;
+    let desktop_config = sync_config(&desktop, "memory://merge")?;
+
+    apply_local_input_rows(&old, &[sample_input_row(DEFAULT_SOURCE_ID, 0, 10)])?;
+    apply_local_focus_rows(&old, &[sample_focus_row(DEFAULT_SOURCE_ID, 0, "Mail")])?;
+    apply_local_input_rows(&laptop, &[sample_input_row(DEFAULT_SOURCE_ID, 0, 5)])?;
+    sync_push(&old, &remote, &old_config).await?;
+    sync_push(&laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut desktop, &remote, &desktop_config).await?;
+
+    let tx = desktop.transaction()?;
+    apply_local_source_action(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &old_config.own_source_uuid,
+        SourceAction::Merge {
+            merged_into_uuid: laptop_config.own_source_uuid.clone(),
+        },
+    )?;
+    apply_local_source_action(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &laptop_config.own_source_uuid,
+        SourceAction::Rename {
+            source_name: "laptop".to_string(),
+        },
+    )?;
+    tx.commit()?;
+    sync_push(&desktop, &remote, &desktop_config).await?;
+    sync_pull(&mut laptop, &remote, &laptop_config).await?;
+
+    let merged = vec![format!(
+        "{}|2026-04-19T12:00:00+00:00|15|4|6",
+        laptop_config.own_source_uuid
+    )];
+    assert_eq!(input_snapshot(&laptop)?, merged);
+    assert_eq!(input_snapshot(&desktop)?, merged);
+    assert_eq!(focus_snapshot(&laptop)?, focus_snapshot(&desktop)?);
+    assert!(get_source_by_uuid(&laptop, &old_config.own_source_uuid)?.is_none());
+    assert_eq!(
+        get_source(&laptop, DEFAULT_SOURCE_ID)?.source_name,
+        "laptop"
+    );
+
+    // The old install pushes once more before it is wiped.
+    apply_local_input_rows(&old, &[sample_input_row(DEFAULT_SOURCE_ID, 15, 7)])?;
+    sync_push(&old, &remote, &old_config).await?;
+    sync_push(&laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut desktop, &remote, &desktop_config).await?;
+    sync_pull(&mut laptop, &remote, &laptop_config).await?;
+
+    assert_eq!(input_snapshot(&laptop)?, input_snapshot(&desktop)?);
+    assert_eq!(input_snapshot(&desktop)?.len(), 2);
+    assert_eq!(source_snapshot(&laptop)?, source_snapshot(&desktop)?);
+
+    Ok(())
+}
+
+/// Proves a deleted source is removed on the other devices and stays gone as it keeps pushing.
+/// It deletes a source from one device and lets the deleted device pull the action too.
+/// This catches regressions where deleted sources are resurrected or wipe their own device.
+#[tokio::test]
+async fn source_delete_propagates_but_leaves_the_deleted_device_alone() -> Result<()> {
+    let remote = InMemoryRemote::default();
+    let gone_path = unique_temp_db("delete-gone");
+    let desktop_path = unique_temp_db("delete-desktop");
+    let laptop_path = unique_temp_db("delete-laptop");
+    let mut gone = build_test_db(&gone_path)?;
+    let mut desktop = build_test_db(&desktop_path)?;
+    let mut laptop = build_test_db(&laptop_path)?;
+    let gone_config = sync_config(&gone, "memory://delete")?;
+    let desktop_config = sync_config(&desktop, "memory://delete")?;
+    let laptop_config = sync_config(&laptop, "memory://delete")?;
+
+    apply_local_input_rows(&gone, &[sample_input_row(DEFAULT_SOURCE_ID, 0, 10)])?;
+    sync_push(&gone, &remote, &gone_config).await?;
+    sync_pull(&mut desktop, &remote, &desktop_config).await?;
+    sync_pull(&mut laptop, &remote, &laptop_config).await?;
+    assert_eq!(input_snapshot(&laptop)?.len(), 1);
+
+    let tx = desktop.transaction()?;
+    apply_local_source_action(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &gone_config.own_source_uuid,
+        SourceAction::Delete,
+    )?;
+    tx.commit()?;
+    sync_push(&desktop, &remote, &desktop_config).await?;
+
+    apply_local_input_rows(&gone, &[sample_input_row(DEFAULT_SOURCE_ID, 15, 7)])?;
+    sync_push(&gone, &remote, &gone_config).await?;
+    sync_pull(&mut laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut gone, &remote, &gone_config).await?;
+
+    assert!(input_snapshot(&laptop)?.is_empty());
+    assert!(get_source_by_uuid(&laptop, &gone_config.own_source_uuid)?.is_none());
+    assert_eq!(input_snapshot(&gone)?.len(), 2);
+
+    Ok(())
+}
+
 /// Proves status prefers live remote information when the remote is reachable.
 /// It uses a deterministic in-memory remote and asserts only on the observable status snapshot.
 /// This catches stale-status regressions where the command stops reflecting the current remote head.
@@ -593,3 +767,175 @@ async fn sync_status_falls_back_to_persisted_remote_head_after_remote_error() ->
 
     Ok(())
 }
+
+/// Proves a forget run on one device is replayed on the others and scrubbed from the remote.
+/// It blanks a title and deletes a time range, then bootstraps a fresh device from revision 0.
+/// This catches regressions where forgotten detail survives in the change log or on peers.
+#[tokio::test]
+async fn forget_propagates_and_scrubs_the_remote_history() -> Result<()> {
+    let remote = InMemoryRemote::default();
+    let laptop_path = unique_temp_db("forget-laptop");
+    let desktop_path = unique_temp_db("forget-desktop");
+    let fresh_path = unique_temp_db("forget-fresh");
+    let mut laptop = build_test_db(&laptop_path)?;
+    let mut desktop = build_test_db(&desktop_path)?;
+    let mut fresh = build_test_db(&fresh_path)?;
+    let laptop_config = sync_config(&laptop, "memory://forget")?;
+    let desktop_config = sync_config(&desktop, "memory://forget")?;
+    let fresh_config = sync_config(&fresh, "memory://forget")?;
+
+    apply_local_input_rows(
+        &laptop,
+        &[
+            sample_input_row(DEFAULT_SOURCE_ID, 0, 10),
+            sample_input_row(DEFAULT_SOURCE_ID, 15, 4),
+        ],
+    )?;
+    apply_local_focus_rows(
+        &laptop,
+        &[
+            sample_focus_row(DEFAULT_SOURCE_ID, 0, "Bank of Somewhere"),
+            sample_focus_row(DEFAULT_SOURCE_ID, 15, "Mail"),
+        ],
+    )?;
+    sync_push(&laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut desktop, &remote, &desktop_config).await?;
+
+    let tx = desktop.transaction()?;
+    let report = apply_local_forget(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &ForgetFilter {
+            title_pattern: Some("Bank".to_string()),
+            ..ForgetFilter::default()
+        },
+    )?;
+    assert_eq!(report.redacted_titles, 1);
+    apply_local_forget(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &ForgetFilter {
+            source_uuid: Some(laptop_config.own_source_uuid.clone()),
+            from_utc: Some("2026-04-19T12:15:00+00:00".to_string()),
+            until_utc: Some("2026-04-19T12:30:00+00:00".to_string()),
+            ..ForgetFilter::default()
+        },
+    )?;
+    tx.commit()?;
+    sync_push(&desktop, &remote, &desktop_config).await?;
+    sync_pull(&mut laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut fresh, &remote, &fresh_config).await?;
+
+    let remaining = vec![format!(
+        "{}|2026-04-19T12:00:00+00:00||120",
+        laptop_config.own_source_uuid
+    )];
+    assert_eq!(focus_snapshot(&laptop)?, remaining);
+    assert_eq!(focus_snapshot(&desktop)?, remaining);
+    assert_eq!(focus_snapshot(&fresh)?, remaining);
+    assert_eq!(input_snapshot(&laptop)?.len(), 1);
+    assert_eq!(input_snapshot(&fresh)?, input_snapshot(&desktop)?);
+    assert_eq!(input_snapshot(&fresh)?.len(), 1);
+
+    Ok(())
+}
+
+/// Proves a forget keeps applying to rows a device recorded before it heard of it: a laptop
+/// that pushes matching buckets after the forget reached the remote gets them dropped or
+/// redacted there, and a device holding the forget screens them on pull as well.
+#[tokio::test]
+async fn forget_screens_changes_pushed_after_it() -> Result<()> {
+    let remote = InMemoryRemote::default();
+    let laptop_path = unique_temp_db("late-forget-laptop");
+    let desktop_path = unique_temp_db("late-forget-desktop");
+    let fresh_path = unique_temp_db("late-forget-fresh");
+    let mut laptop = build_test_db(&laptop_path)?;
+    let mut desktop = build_test_db(&desktop_path)?;
+    let mut fresh = build_test_db(&fresh_path)?;
+    let laptop_config = sync_config(&laptop, "memory://late-forget")?;
+    let desktop_config = sync_config(&desktop, "memory://late-forget")?;
+    let fresh_config = sync_config(&fresh, "memory://late-forget")?;
+
+    apply_local_input_rows(&laptop, &[sample_input_row(DEFAULT_SOURCE_ID, 0, 10)])?;
+    apply_local_focus_rows(&laptop, &[sample_focus_row(DEFAULT_SOURCE_ID, 0, "Mail")])?;
+    sync_push(&laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut desktop, &remote, &desktop_config).await?;
+
+    let tx = desktop.transaction()?;
+    apply_local_forget(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &ForgetFilter {
+            title_pattern: Some("Bank".to_string()),
+            ..ForgetFilter::default()
+        },
+    )?;
+    apply_local_forget(
+        &tx,
+        &desktop_config.own_source_uuid,
+        &ForgetFilter {
+            source_uuid: Some(laptop_config.own_source_uuid.clone()),
+            from_utc: Some("2026-04-19T12:15:00+00:00".to_string()),
+            until_utc: Some("2026-04-19T12:30:00+00:00".to_string()),
+            ..ForgetFilter::default()
+        },
+    )?;
+    tx.commit()?;
+    sync_push(&desktop, &remote, &desktop_config).await?;
+
+    apply_local_input_rows(&laptop, &[sample_input_row(DEFAULT_SOURCE_ID, 15, 9)])?;
+    apply_local_focus_rows(
+        &laptop,
+        &[
+            sample_focus_row(DEFAULT_SOURCE_ID, 0, "Bank of Somewhere"),
+            sample_focus_row(DEFAULT_SOURCE_ID, 15, "Bank of Somewhere"),
+        ],
+    )?;
+    sync_push(&laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut laptop, &remote, &laptop_config).await?;
+    sync_pull(&mut desktop, &remote, &desktop_config).await?;
+    sync_pull(&mut fresh, &remote, &fresh_config).await?;
+
+    let focus = vec![
+        format!(
+            "{}|2026-04-19T12:00:00+00:00||120",
+            laptop_config.own_source_uuid
+        ),
+        format!(
+            "{}|2026-04-19T12:00:00+00:00|Mail|120",
+            laptop_config.own_source_uuid
+        ),
+    ];
+    assert_eq!(focus_snapshot(&laptop)?, focus);
+    assert_eq!(focus_snapshot(&desktop)?, focus);
+    assert_eq!(focus_snapshot(&fresh)?, focus);
+    for conn in [&laptop, &desktop, &fresh] {
+        assert_eq!(input_snapshot(conn)?.len(), 1);
+    }
+
+    let late = input_change_from_row(
+        &sample_input_row(DEFAULT_SOURCE_ID, 15, 9),
+        &laptop_config.own_source_uuid,
+    );
+    let head = load_sync_state(
+        &desktop,
+        &desktop_config.own_source_uuid,
+        &desktop_config.remote_url,
+    )?
+    .last_pulled_revision;
+    apply_pull_response(
+        &mut desktop,
+        &desktop_config,
+        head,
+        &PullResponse {
+            remote_head_revision: head + 1,
+            changes: vec![RemoteChange {
+                revision: head + 1,
+                payload: ChangePayload::InputBucket(late),
+            }],
+        },
+    )?;
+    assert_eq!(input_snapshot(&desktop)?.len(), 1);
+
+    Ok(())
+}
@@ -1,8 +1,14 @@
+use anyhow::Result;
+
+use crate::storage::localdb::{ForgetFilter, ForgetMatcher};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EntityType {
     Source,
     InputBucket,
     FocusBucket,
+    SourceAction,
+    Forget,
 }
 
 impl EntityType {
@@ -11,6 +17,8 @@ impl EntityType {
             EntityType::Source => "source",
             EntityType::InputBucket => "input_bucket",
             EntityType::FocusBucket => "focus_bucket",
+            EntityType::SourceAction => "source_action",
+            EntityType::Forget => "forget",
         }
     }
 }
@@ -79,11 +87,155 @@ impl FocusBucketChange {
     }
 }
 
+/// What `vigil sources` did to another device's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceAction {
+    Rename { source_name: String },
+    Merge { merged_into_uuid: String },
+    Delete,
+}
+
+impl SourceAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceAction::Rename { .. } => "rename",
+            SourceAction::Merge { .. } => "merge",
+            SourceAction::Delete => "delete",
+        }
+    }
+
+    pub fn from_parts(
+        action: &str,
+        source_name: Option<String>,
+        merged_into_uuid: Option<String>,
+    ) -> Option<Self> {
+        match (action, source_name, merged_into_uuid) {
+            ("rename", Some(source_name), _) => Some(SourceAction::Rename { source_name }),
+            ("merge", _, Some(merged_into_uuid)) => Some(SourceAction::Merge { merged_into_uuid }),
+            ("delete", _, _) => Some(SourceAction::Delete),
+            _ => None,
+        }
+    }
+
+    pub fn source_name(&self) -> Option<&str> {
+        match self {
+            SourceAction::Rename { source_name } => Some(source_name),
+            _ => None,
+        }
+    }
+
+    pub fn merged_into_uuid(&self) -> Option<&str> {
+        match self {
+            SourceAction::Merge { merged_into_uuid } => Some(merged_into_uuid),
+            _ => None,
+        }
+    }
+}
+
+/// A rename, merge or delete of `target_source_uuid`, pushed by the device `source_uuid`
+/// that ran it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceActionChange {
+    pub source_uuid: String,
+    pub target_source_uuid: String,
+    pub action: SourceAction,
+    pub created_at_utc: String,
+}
+
+impl SourceActionChange {
+    pub fn entity_key(&self) -> String {
+        format!(
+            "source_action:{}:{}",
+            self.target_source_uuid, self.created_at_utc
+        )
+    }
+}
+
+/// A `vigil forget` run on the device `source_uuid`. The filter's `source_uuid` is the source
+/// whose activity was forgotten, or none for all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForgetChange {
+    pub source_uuid: String,
+    pub filter: ForgetFilter,
+    pub created_at_utc: String,
+}
+
+impl ForgetChange {
+    pub fn entity_key(&self) -> String {
+        format!("forget:{}:{}", self.source_uuid, self.created_at_utc)
+    }
+
+    /// The filter other devices apply. It never reaches past the moment of forgetting, so
+    /// activity recorded afterwards is kept even if the range was left open.
+    pub fn effective_filter(&self) -> ForgetFilter {
+        let until_utc = match self.filter.until_utc.as_deref() {
+            Some(until) if until < self.created_at_utc.as_str() => until,
+            _ => &self.created_at_utc,
+        };
+        ForgetFilter {
+            until_utc: Some(until_utc.to_string()),
+            ..self.filter.clone()
+        }
+    }
+}
+
+/// Every forget seen so far, for bucket changes that arrive after it: a device that had not
+/// heard of a forget yet can still push rows it selects. Those rows are dropped, or lose their
+/// title when the forget only redacts titles.
+#[derive(Default)]
+pub struct ForgetScreen {
+    /// Each matcher with whether it only redacts titles.
+    matchers: Vec<(ForgetMatcher, bool)>,
+}
+
+impl ForgetScreen {
+    pub fn new<'a>(forgets: impl IntoIterator<Item = &'a ForgetChange>) -> Result<Self> {
+        let mut screen = Self::default();
+        for change in forgets {
+            screen.push(change)?;
+        }
+        Ok(screen)
+    }
+
+    pub fn push(&mut self, change: &ForgetChange) -> Result<()> {
+        let filter = change.effective_filter();
+        self.matchers
+            .push((filter.matcher()?, filter.redacts_titles()));
+        Ok(())
+    }
+
+    pub fn screen_input(&self, change: InputBucketChange) -> Option<InputBucketChange> {
+        let forgotten = self.matchers.iter().any(|(matcher, _)| {
+            matcher.matches_input(&change.source_uuid, &change.bucket_start_utc)
+        });
+        (!forgotten).then_some(change)
+    }
+
+    pub fn screen_focus(&self, mut change: FocusBucketChange) -> Option<FocusBucketChange> {
+        for (matcher, redacts_titles) in &self.matchers {
+            if matcher.matches_focus(
+                &change.source_uuid,
+                &change.bucket_start_utc,
+                &change.app_identifier,
+                &change.window_title,
+            ) {
+                if !redacts_titles {
+                    return None;
+                }
+                change.window_title.clear();
+            }
+        }
+        Some(change)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ChangePayload {
     Source(SourceChange),
     InputBucket(InputBucketChange),
     FocusBucket(FocusBucketChange),
+    SourceAction(SourceActionChange),
+    Forget(ForgetChange),
 }
 
 impl ChangePayload {
@@ -92,6 +244,8 @@ impl ChangePayload {
             ChangePayload::Source(payload) => &payload.source_uuid,
             ChangePayload::InputBucket(payload) => &payload.source_uuid,
             ChangePayload::FocusBucket(payload) => &payload.source_uuid,
+            ChangePayload::SourceAction(payload) => &payload.source_uuid,
+            ChangePayload::Forget(payload) => &payload.source_uuid,
         }
     }
 }
@@ -116,6 +270,8 @@ pub struct PushBatch {
     pub source_changes: Vec<SourceChange>,
     pub input_changes: Vec<InputBucketChange>,
     pub focus_changes: Vec<FocusBucketChange>,
+    pub source_actions: Vec<SourceActionChange>,
+    pub forgets: Vec<ForgetChange>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
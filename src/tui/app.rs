@@ -1,8 +1,9 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
+use crate::storage::localdb::ReadPool;
 use crate::tui::data::{
     load_dashboard_snapshot, ChartMetric, DashboardSnapshot, DEFAULT_BUCKET_COUNT,
     DEFAULT_BUCKET_MINUTES,
@@ -18,13 +19,15 @@ pub enum ChartMode {
 pub enum AppListMode {
     Generic,
     Specific,
+    Projects,
 }
 
 impl AppListMode {
     pub fn next(self) -> Self {
         match self {
             AppListMode::Generic => AppListMode::Specific,
-            AppListMode::Specific => AppListMode::Generic,
+            AppListMode::Specific => AppListMode::Projects,
+            AppListMode::Projects => AppListMode::Generic,
         }
     }
 
@@ -32,6 +35,7 @@ impl AppListMode {
         match self {
             AppListMode::Generic => "generic",
             AppListMode::Specific => "specific",
+            AppListMode::Projects => "projects",
         }
     }
 }
@@ -98,10 +102,10 @@ impl TimeWindow {
     pub fn series_params(self) -> (i64, usize) {
         match self {
             TimeWindow::All => (0, 0),
-            // 1 h  → 4 × 15-min buckets (SQLite resolution limit)
-            TimeWindow::OneHour => (15, 4),
-            // 6 h  → 24 × 15-min buckets
-            TimeWindow::SixHours => (15, 24),
+            // 1 h  → 60 × 1-min buckets, or fewer when recorded coarser (4 × 15 min by default)
+            TimeWindow::OneHour => (1, 60),
+            // 6 h  → 72 × 5-min buckets, or fewer when recorded coarser
+            TimeWindow::SixHours => (5, 72),
             // 24 h → 96 × 15-min buckets (default)
             TimeWindow::TwentyFourHours => (DEFAULT_BUCKET_MINUTES, DEFAULT_BUCKET_COUNT),
             // 7 d  → 168 × 60-min buckets
@@ -157,7 +161,7 @@ pub enum AppAction {
 }
 
 pub struct DashboardApp {
-    db_path: PathBuf,
+    readers: ReadPool,
     pub ascii: bool,
     pub snapshot: DashboardSnapshot,
     pub focused_section: FocusSection,
@@ -183,15 +187,17 @@ impl DashboardApp {
             _ => TimeWindow::TwentyFourHours,
         };
         let (bucket_minutes, bucket_count) = time_window.series_params();
+        let readers = ReadPool::new(db_path);
+        let snapshot = load_dashboard_snapshot(
+            &readers,
+            time_window.range_days(),
+            bucket_minutes,
+            bucket_count,
+        )?;
         Ok(Self {
-            db_path: db_path.to_path_buf(),
+            readers,
             ascii,
-            snapshot: load_dashboard_snapshot(
-                db_path,
-                time_window.range_days(),
-                bucket_minutes,
-                bucket_count,
-            )?,
+            snapshot,
             focused_section: FocusSection::Activity,
             selected_summary_index: 0,
             selected_app_index: 0,
@@ -210,7 +216,7 @@ impl DashboardApp {
     pub fn refresh(&mut self) {
         let (bucket_minutes, bucket_count) = self.time_window.series_params();
         match load_dashboard_snapshot(
-            &self.db_path,
+            &self.readers,
             self.time_window.range_days(),
             bucket_minutes,
             bucket_count,
@@ -310,6 +316,17 @@ impl DashboardApp {
                 self.status_message = format!("apps mode: {}", self.app_list_mode.label());
                 AppAction::None
             }
+            KeyCode::Char('p') => {
+                self.focused_section = FocusSection::Apps;
+                self.app_list_mode = if self.app_list_mode == AppListMode::Projects {
+                    AppListMode::Generic
+                } else {
+                    AppListMode::Projects
+                };
+                self.clamp_app_selection();
+                self.status_message = format!("apps mode: {}", self.app_list_mode.label());
+                AppAction::None
+            }
             KeyCode::Char('u') => {
                 self.ascii = !self.ascii;
                 self.status_message = if self.ascii {
@@ -404,6 +421,7 @@ impl DashboardApp {
         match self.app_list_mode {
             AppListMode::Generic => &self.snapshot.top_apps,
             AppListMode::Specific => &self.snapshot.top_app_details,
+            AppListMode::Projects => &self.snapshot.top_projects,
         }
     }
 }
@@ -420,7 +438,7 @@ mod tests {
 
     fn sample_app() -> DashboardApp {
         DashboardApp {
-            db_path: PathBuf::from("/tmp/vigil.db"),
+            readers: ReadPool::new(Path::new("/tmp/vigil.db")),
             ascii: false,
             snapshot: DashboardSnapshot {
                 generated_at_local: Local::now(),
@@ -437,6 +455,7 @@ mod tests {
                 top_activities: Vec::new(),
                 top_apps: Vec::new(),
                 top_app_details: Vec::new(),
+                top_projects: Vec::new(),
                 categories: Vec::new(),
                 series_start_utc: Utc::now(),
                 series_buckets: Vec::new(),
@@ -449,6 +468,7 @@ mod tests {
                     last_activity_at_utc: None,
                     sync_summary: "local-only".to_string(),
                     db_path_display: "/tmp/vigil.db".to_string(),
+                    collector_health: None,
                 },
             },
             focused_section: FocusSection::Summary,
@@ -601,6 +621,32 @@ mod tests {
         assert!(app.status_message.contains("specific"));
 
         app.handle_key(key(KeyCode::Char('a')));
+        assert_eq!(app.app_list_mode, AppListMode::Projects);
+
+        app.handle_key(key(KeyCode::Char('a')));
+        assert_eq!(app.app_list_mode, AppListMode::Generic);
+    }
+
+    /// Verifies that `p` jumps to the projects list from any panel and that pressing it again
+    /// returns the apps panel to the generic list.
+    #[test]
+    fn p_toggles_projects_list_from_any_panel() {
+        let mut app = sample_app();
+        app.focused_section = FocusSection::Heatmap;
+        app.snapshot.top_projects = vec![crate::tui::data::AppShare {
+            label: "vigil".to_string(),
+            detail: Some("~/work/vigil".to_string()),
+            focus_seconds: 120,
+            share_percent: 100,
+            sparkline: vec![1; 8],
+        }];
+
+        app.handle_key(key(KeyCode::Char('p')));
+        assert_eq!(app.focused_section, FocusSection::Apps);
+        assert_eq!(app.app_list_mode, AppListMode::Projects);
+        assert_eq!(app.current_app_list()[0].label, "vigil");
+
+        app.handle_key(key(KeyCode::Char('p')));
         assert_eq!(app.app_list_mode, AppListMode::Generic);
     }
 
@@ -4,8 +4,9 @@ use rusqlite::{Connection, OptionalExtension};
 use std::collections::HashMap;
 use std::path::Path;
 
+use crate::common::is_browser_app;
 use crate::storage::localdb::{
-    daily_activity_report, open_con_at, setup_database, DailyActivityRow,
+    bucket_granularity, daily_activity_report, rollup_for_series, DailyActivityRow, ReadPool,
 };
 
 const SERIES_BUCKET_MINUTES: i64 = 15;
@@ -117,6 +118,8 @@ pub struct CategoryShare {
 struct FocusUsageRow {
     app_identifier: String,
     window_title: String,
+    /// Set when the time was reported by the browser extension instead of guessed from titles.
+    domain: Option<String>,
     focus_seconds: u64,
 }
 
@@ -147,6 +150,15 @@ pub struct DashboardStatus {
     pub last_activity_at_utc: Option<DateTime<Utc>>,
     pub sync_summary: String,
     pub db_path_display: String,
+    /// Collector task failures in the last 24 hours, if there were any.
+    pub collector_health: Option<CollectorHealth>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectorHealth {
+    pub failures_24h: usize,
+    /// The most recent failure, e.g. `focus error 14:02: Wayland listener failed`.
+    pub last_failure: String,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -160,6 +172,8 @@ pub struct DashboardSnapshot {
     pub top_activities: Vec<AppShare>,
     pub top_apps: Vec<AppShare>,
     pub top_app_details: Vec<AppShare>,
+    /// Focus time per detected project, with the repository root as detail.
+    pub top_projects: Vec<AppShare>,
     pub categories: Vec<CategoryShare>,
     pub series_start_utc: DateTime<Utc>,
     pub series_buckets: Vec<ActivityBucket>,
@@ -169,13 +183,12 @@ pub struct DashboardSnapshot {
 }
 
 pub fn load_dashboard_snapshot(
-    db_path: &Path,
+    readers: &ReadPool,
     range_days: u32,
     bucket_minutes: i64,
     bucket_count: usize,
 ) -> Result<DashboardSnapshot> {
-    let conn = open_con_at(db_path)?;
-    setup_database(&conn)?;
+    let conn = readers.get()?;
     let desktop_entries = load_desktop_entries();
 
     let history_days = if range_days == 0 {
@@ -197,6 +210,7 @@ pub fn load_dashboard_snapshot(
     let mut top_apps = aggregate_top_apps(&focus_rows, usize::MAX, &desktop_entries);
     let mut top_app_details = aggregate_top_app_details(&focus_rows, usize::MAX, &desktop_entries);
     let categories = aggregate_categories(&focus_rows, CATEGORY_LIMIT, CATEGORY_MEMBER_LIMIT);
+    let top_projects = load_top_projects(&conn, history_days, APP_SPARKLINE_SAMPLES)?;
     let series_buckets = load_activity_series(
         &conn,
         series_start,
@@ -204,7 +218,7 @@ pub fn load_dashboard_snapshot(
         effective_bucket_count,
     )?;
     let (heatmap_rows, heatmap_maxima) = build_daily_average_heatmap(&daily_rows, heatmap_days)?;
-    let status = load_dashboard_status(&conn, db_path, &desktop_entries)?;
+    let status = load_dashboard_status(&conn, readers.db_path(), &desktop_entries)?;
     attach_app_sparklines(
         &conn,
         &mut top_apps,
@@ -229,6 +243,7 @@ pub fn load_dashboard_snapshot(
         top_activities,
         top_apps,
         top_app_details,
+        top_projects,
         categories,
         series_start_utc: series_start,
         series_buckets,
@@ -289,23 +304,73 @@ fn load_summary_totals(conn: &Connection, days: Option<u32>) -> Result<SummaryTo
     .with_context(|| "Failed to load summary totals for dashboard")
 }
 
+/// Focus time per bucket where richer context replaces the title-based rows of the same
+/// source, bucket and app: browser domains reported by the extension, and terminal commands
+/// as `cargo in ~/work/vigil`. The context stands in for the window title so the title
+/// heuristics keep working; without it they remain the only source. Focus time the context
+/// doesn't cover stays with the app as an untitled row.
+/// Expects the lower bound for `bucket_start_utc` (or NULL) as `?1`.
+const FOCUS_USAGE_CTE: &str = "
+    WITH context_usage AS (
+        SELECT source_id, bucket_start_utc, app_identifier, domain AS window_title,
+               domain, focus_seconds
+        FROM browser_domain_buckets
+        WHERE (?1 IS NULL OR bucket_start_utc >= ?1)
+        UNION ALL
+        SELECT source_id, bucket_start_utc, app_identifier,
+               command || ' in ' || working_dir AS window_title, NULL AS domain, focus_seconds
+        FROM terminal_context_buckets
+        WHERE (?1 IS NULL OR bucket_start_utc >= ?1)
+    ),
+    context_coverage AS (
+        SELECT source_id, bucket_start_utc, app_identifier, SUM(focus_seconds) AS covered_seconds
+        FROM context_usage
+        GROUP BY source_id, bucket_start_utc, app_identifier
+    ),
+    focus_usage AS (
+        SELECT f.bucket_start_utc, f.app_identifier, f.window_title, NULL AS domain, f.focus_seconds
+        FROM focus_buckets f
+        LEFT JOIN context_coverage c
+          ON c.source_id = f.source_id
+         AND c.bucket_start_utc = f.bucket_start_utc
+         AND c.app_identifier = f.app_identifier
+        WHERE (?1 IS NULL OR f.bucket_start_utc >= ?1)
+          AND c.source_id IS NULL
+        UNION ALL
+        SELECT bucket_start_utc, app_identifier, window_title, domain, focus_seconds
+        FROM context_usage
+        UNION ALL
+        SELECT f.bucket_start_utc, f.app_identifier, '' AS window_title, NULL AS domain,
+               SUM(f.focus_seconds) - MAX(c.covered_seconds) AS focus_seconds
+        FROM focus_buckets f
+        JOIN context_coverage c
+          ON c.source_id = f.source_id
+         AND c.bucket_start_utc = f.bucket_start_utc
+         AND c.app_identifier = f.app_identifier
+        WHERE (?1 IS NULL OR f.bucket_start_utc >= ?1)
+        GROUP BY f.source_id, f.bucket_start_utc, f.app_identifier
+        HAVING SUM(f.focus_seconds) > MAX(c.covered_seconds)
+    )
+";
+
 fn load_focus_usage_rows(conn: &Connection, days: Option<u32>) -> Result<Vec<FocusUsageRow>> {
     let since = days.map(|d| (Utc::now() - Duration::days(d.max(1) as i64)).to_rfc3339());
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         "
-        SELECT app_identifier, window_title, SUM(focus_seconds) AS total_focus_seconds
-        FROM focus_buckets
-        WHERE (?1 IS NULL OR bucket_start_utc >= ?1)
-        GROUP BY app_identifier, window_title
+        {FOCUS_USAGE_CTE}
+        SELECT app_identifier, window_title, domain, SUM(focus_seconds) AS total_focus_seconds
+        FROM focus_usage
+        GROUP BY app_identifier, window_title, domain
         ORDER BY total_focus_seconds DESC, app_identifier ASC, window_title ASC
         ",
-    )?;
+    ))?;
 
     let rows = stmt.query_map([since.as_deref()], |row| {
         Ok(FocusUsageRow {
             app_identifier: row.get(0)?,
             window_title: row.get(1)?,
-            focus_seconds: row.get::<_, Option<u64>>(2)?.unwrap_or(0),
+            domain: row.get(2)?,
+            focus_seconds: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
         })
     })?;
 
@@ -395,7 +460,11 @@ fn aggregate_categories(
 
     for row in rows {
         let category = classify_category(&row.app_identifier, &row.window_title).to_string();
-        let member = category_member_label(&row.app_identifier, &row.window_title);
+        let member = category_member_label(
+            &row.app_identifier,
+            &row.window_title,
+            row.domain.as_deref(),
+        );
         let entry = categories
             .entry(category)
             .or_insert_with(|| (0, std::collections::BTreeMap::new()));
@@ -511,15 +580,15 @@ fn attach_app_sparklines(
         .map(|app| (app.label.clone(), vec![0_u64; sample_count]))
         .collect::<std::collections::BTreeMap<_, _>>();
 
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         "
+        {FOCUS_USAGE_CTE}
         SELECT app_identifier, window_title, bucket_start_utc, COALESCE(SUM(focus_seconds), 0)
-        FROM focus_buckets
-        WHERE bucket_start_utc >= ?1
+        FROM focus_usage
         GROUP BY app_identifier, window_title, bucket_start_utc
         ORDER BY bucket_start_utc ASC
         ",
-    )?;
+    ))?;
     let rows = stmt.query_map([since.to_rfc3339()], |row| {
         Ok((
             row.get::<_, String>(0)?,
@@ -579,15 +648,15 @@ fn attach_specific_app_sparklines(
         .map(|app| (app.label.clone(), vec![0_u64; sample_count]))
         .collect::<std::collections::BTreeMap<_, _>>();
 
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         "
+        {FOCUS_USAGE_CTE}
         SELECT app_identifier, window_title, bucket_start_utc, COALESCE(SUM(focus_seconds), 0)
-        FROM focus_buckets
-        WHERE bucket_start_utc >= ?1
+        FROM focus_usage
         GROUP BY app_identifier, window_title, bucket_start_utc
         ORDER BY bucket_start_utc ASC
         ",
-    )?;
+    ))?;
     let rows = stmt.query_map([since.to_rfc3339()], |row| {
         Ok((
             row.get::<_, String>(0)?,
@@ -625,12 +694,98 @@ fn attach_specific_app_sparklines(
     Ok(())
 }
 
+/// Ranks projects by focus time and attaches their sparklines in one pass over
+/// `project_buckets`, since project rows need none of the title heuristics above.
+fn load_top_projects(
+    conn: &Connection,
+    days: Option<u32>,
+    sample_count: usize,
+) -> Result<Vec<AppShare>> {
+    let since = match days {
+        Some(days) => Utc::now() - Duration::days(days.max(1) as i64),
+        None => earliest_activity_at(conn)?.unwrap_or_else(|| Utc::now() - Duration::days(1)),
+    };
+    let until = Utc::now();
+    let total_seconds = (until - since).num_seconds().max(1) as u64;
+
+    let mut stmt = conn.prepare(
+        "
+        SELECT project, MAX(project_root), bucket_start_utc, COALESCE(SUM(focus_seconds), 0)
+        FROM project_buckets
+        WHERE bucket_start_utc >= ?1
+        GROUP BY project, bucket_start_utc
+        ORDER BY bucket_start_utc ASC
+        ",
+    )?;
+    let rows = stmt.query_map([since.to_rfc3339()], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+        ))
+    })?;
+
+    let mut projects = std::collections::BTreeMap::<String, (u64, Option<String>, Vec<u64>)>::new();
+    for row in rows {
+        let (project, project_root, bucket_start_raw, focus_seconds) = row?;
+        let entry = projects
+            .entry(project)
+            .or_insert_with(|| (0, None, vec![0; sample_count]));
+        entry.0 += focus_seconds;
+        if project_root.is_some() {
+            entry.1 = project_root;
+        }
+        let Ok(bucket_start) = DateTime::parse_from_rfc3339(&bucket_start_raw) else {
+            continue;
+        };
+        if sample_count == 0 {
+            continue;
+        }
+        let elapsed = bucket_start
+            .with_timezone(&Utc)
+            .signed_duration_since(since)
+            .num_seconds()
+            .clamp(0, total_seconds as i64) as u64;
+        let index = ((elapsed * sample_count as u64) / total_seconds)
+            .min(sample_count.saturating_sub(1) as u64) as usize;
+        entry.2[index] = entry.2[index].saturating_add(focus_seconds);
+    }
+
+    let mut sparklines = std::collections::BTreeMap::new();
+    let shares = projects
+        .into_iter()
+        .map(|(label, (focus_seconds, root, sparkline))| {
+            sparklines.insert(label.clone(), sparkline);
+            AppShare {
+                label,
+                detail: root,
+                focus_seconds,
+                share_percent: 0,
+                sparkline: Vec::new(),
+            }
+        })
+        .collect();
+    let mut ranked = ranked_shares(shares, usize::MAX);
+    for project in &mut ranked {
+        project.sparkline = sparklines
+            .remove(&project.label)
+            .unwrap_or_else(|| vec![0; sample_count]);
+    }
+    Ok(ranked)
+}
+
 fn resolve_series_window(
     conn: &Connection,
     bucket_minutes: i64,
     bucket_count: usize,
 ) -> Result<(DateTime<Utc>, i64, usize)> {
     if bucket_minutes > 0 && bucket_count > 0 {
+        // Series buckets finer than what was recorded would only repeat the same values.
+        let span_minutes = bucket_minutes * bucket_count as i64;
+        let window_start = Utc::now() - Duration::minutes(span_minutes);
+        let bucket_minutes = bucket_minutes.max(finest_granularity_since(conn, window_start)?);
+        let bucket_count = ((span_minutes + bucket_minutes - 1) / bucket_minutes).max(1) as usize;
         let start = aligned_series_start(Utc::now(), bucket_minutes, bucket_count)?;
         return Ok((start, bucket_minutes, bucket_count));
     }
@@ -650,9 +805,29 @@ fn resolve_series_window(
     Ok((start, bucket_minutes, bucket_count))
 }
 
+/// Rounds up to whole days or hours once buckets get that wide, so the series can be read
+/// from the rollups, and to quarter hours below that.
 fn align_bucket_minutes(raw_minutes: i64) -> i64 {
-    let aligned = ((raw_minutes + 14) / 15) * 15;
-    aligned.max(15)
+    let step = match raw_minutes {
+        minutes if minutes > 1440 => 1440,
+        minutes if minutes > 60 => 60,
+        _ => 15,
+    };
+    (((raw_minutes + step - 1) / step) * step).max(15)
+}
+
+/// The finest input bucket granularity recorded since `since`, or the configured one when
+/// nothing was recorded yet.
+fn finest_granularity_since(conn: &Connection, since: DateTime<Utc>) -> Result<i64> {
+    let finest: Option<i64> = conn.query_row(
+        "SELECT MIN(granularity_minutes) FROM input_buckets WHERE bucket_end_utc > ?1",
+        [since.to_rfc3339()],
+        |row| row.get(0),
+    )?;
+    Ok(match finest {
+        Some(finest) => finest,
+        None => bucket_granularity(conn)?.map_or(SERIES_BUCKET_MINUTES, i64::from),
+    })
 }
 
 fn earliest_activity_at(conn: &Connection) -> Result<Option<DateTime<Utc>>> {
@@ -954,20 +1129,6 @@ fn activity_context(
     }
 }
 
-fn is_browser_app(normalized: &str) -> bool {
-    matches!(
-        normalized,
-        "brave-browser"
-            | "brave.exe"
-            | "firefox"
-            | "firefox.exe"
-            | "chromium"
-            | "chromium-browser"
-            | "chrome"
-            | "chrome.exe"
-    )
-}
-
 fn browser_context_label(window_title: &str) -> Option<String> {
     let title = window_title.trim();
     if title.is_empty() {
@@ -1054,9 +1215,10 @@ fn classify_category(app_identifier: &str, window_title: &str) -> &'static str {
     }
 }
 
-fn category_member_label(app_identifier: &str, window_title: &str) -> String {
-    browser_category_site(window_title)
+fn category_member_label(app_identifier: &str, window_title: &str, domain: Option<&str>) -> String {
+    domain
         .map(String::from)
+        .or_else(|| browser_category_site(window_title).map(String::from))
         .unwrap_or_else(|| fallback_display_name(&normalize_app_id(app_identifier)))
 }
 
@@ -1185,66 +1347,147 @@ fn load_activity_series(
 ) -> Result<Vec<ActivityBucket>> {
     let mut buckets = empty_series(series_start, bucket_minutes, bucket_count);
 
-    let mut input_stmt = conn.prepare(
-        "
-        SELECT bucket_start_utc, key_presses, left_clicks, right_clicks, middle_clicks, mouse_distance_cm
-        FROM input_buckets
-        WHERE bucket_start_utc >= ?1
-        ORDER BY bucket_start_utc ASC
-        ",
-    )?;
+    // Rollup rows fit in one series bucket each; bucket rows may be coarser than the series
+    // and are spread over the buckets they cover.
+    let (input_sql, focus_sql) = match rollup_for_series(bucket_minutes) {
+        Some(granularity) => (
+            format!(
+                "
+                SELECT bucket_start_utc, bucket_start_utc, key_presses, left_clicks, right_clicks, middle_clicks, mouse_distance_cm
+                FROM input_rollups
+                WHERE granularity_minutes = {granularity} AND bucket_start_utc >= ?1
+                "
+            ),
+            format!(
+                "
+                SELECT bucket_start_utc, bucket_start_utc, focus_seconds
+                FROM focus_rollups
+                WHERE granularity_minutes = {granularity} AND bucket_start_utc >= ?1
+                "
+            ),
+        ),
+        None => (
+            "
+            SELECT bucket_start_utc, bucket_end_utc, key_presses, left_clicks, right_clicks, middle_clicks, mouse_distance_cm
+            FROM input_buckets
+            WHERE bucket_end_utc > ?1
+            "
+            .to_string(),
+            "
+            SELECT bucket_start_utc, bucket_end_utc, focus_seconds
+            FROM focus_buckets
+            WHERE bucket_end_utc > ?1
+            "
+            .to_string(),
+        ),
+    };
+
+    let mut input_stmt = conn.prepare(&input_sql)?;
     let input_rows = input_stmt.query_map([series_start.to_rfc3339()], |row| {
         Ok((
             row.get::<_, String>(0)?,
-            row.get::<_, u64>(1)?,
+            row.get::<_, String>(1)?,
             row.get::<_, u64>(2)?,
             row.get::<_, u64>(3)?,
             row.get::<_, u64>(4)?,
-            row.get::<_, f64>(5)?,
+            row.get::<_, u64>(5)?,
+            row.get::<_, f64>(6)?,
         ))
     })?;
     for row in input_rows {
-        let (started_at_utc, key_presses, left_clicks, right_clicks, middle_clicks, mouse_cm) =
-            row?;
-        let started_at_utc = parse_rfc3339(&started_at_utc)?;
-        if let Some(bucket) = bucket_mut(&mut buckets, series_start, started_at_utc, bucket_minutes)
-        {
-            let clicks = (left_clicks + right_clicks + middle_clicks) as f64;
-            bucket.key_presses += key_presses as f64;
-            bucket.clicks += clicks;
-            bucket.left_clicks += left_clicks as f64;
-            bucket.right_clicks += right_clicks as f64;
-            bucket.middle_clicks += middle_clicks as f64;
-            bucket.mouse_distance_cm += mouse_cm;
-            bucket.activity_score += key_presses as f64 + clicks * 6.0 + mouse_cm * 8.0;
-        }
+        let (
+            started_at_utc,
+            ended_at_utc,
+            key_presses,
+            left_clicks,
+            right_clicks,
+            middle_clicks,
+            mouse_cm,
+        ) = row?;
+        let clicks = (left_clicks + right_clicks + middle_clicks) as f64;
+        spread_over_series(
+            &mut buckets,
+            series_start,
+            bucket_minutes,
+            parse_rfc3339(&started_at_utc)?,
+            parse_rfc3339(&ended_at_utc)?,
+            |bucket, share| {
+                bucket.key_presses += key_presses as f64 * share;
+                bucket.clicks += clicks * share;
+                bucket.left_clicks += left_clicks as f64 * share;
+                bucket.right_clicks += right_clicks as f64 * share;
+                bucket.middle_clicks += middle_clicks as f64 * share;
+                bucket.mouse_distance_cm += mouse_cm * share;
+                bucket.activity_score +=
+                    (key_presses as f64 + clicks * 6.0 + mouse_cm * 8.0) * share;
+            },
+        );
     }
 
-    let mut focus_stmt = conn.prepare(
-        "
-        SELECT bucket_start_utc, focus_seconds
-        FROM focus_buckets
-        WHERE bucket_start_utc >= ?1
-        ORDER BY bucket_start_utc ASC
-        ",
-    )?;
+    let mut focus_stmt = conn.prepare(&focus_sql)?;
     let focus_rows = focus_stmt.query_map([series_start.to_rfc3339()], |row| {
-        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, u64>(2)?,
+        ))
     })?;
     for row in focus_rows {
-        let (started_at_utc, focus_seconds) = row?;
-        let started_at_utc = parse_rfc3339(&started_at_utc)?;
-        if let Some(bucket) = bucket_mut(&mut buckets, series_start, started_at_utc, bucket_minutes)
-        {
-            let focus_minutes = focus_seconds as f64 / 60.0;
-            bucket.focus_minutes += focus_minutes;
-            bucket.activity_score += focus_minutes * 2.5;
-        }
+        let (started_at_utc, ended_at_utc, focus_seconds) = row?;
+        let focus_minutes = focus_seconds as f64 / 60.0;
+        spread_over_series(
+            &mut buckets,
+            series_start,
+            bucket_minutes,
+            parse_rfc3339(&started_at_utc)?,
+            parse_rfc3339(&ended_at_utc)?,
+            |bucket, share| {
+                bucket.focus_minutes += focus_minutes * share;
+                bucket.activity_score += focus_minutes * 2.5 * share;
+            },
+        );
     }
 
     Ok(buckets)
 }
 
+/// Adds a row covering `start..end` to the series buckets it overlaps, each getting the share
+/// of the row that overlaps it. A row without a length goes to the bucket it starts in.
+fn spread_over_series(
+    buckets: &mut [ActivityBucket],
+    series_start: DateTime<Utc>,
+    bucket_minutes: i64,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    mut add: impl FnMut(&mut ActivityBucket, f64),
+) {
+    let total_seconds = end.signed_duration_since(start).num_seconds();
+    if total_seconds <= 0 {
+        if let Some(bucket) = bucket_mut(buckets, series_start, start, bucket_minutes) {
+            add(bucket, 1.0);
+        }
+        return;
+    }
+
+    let bucket_seconds = bucket_minutes * 60;
+    let mut cursor = start;
+    while cursor < end {
+        let slot = cursor
+            .signed_duration_since(series_start)
+            .num_seconds()
+            .div_euclid(bucket_seconds);
+        let slot_end = (series_start + Duration::seconds((slot + 1) * bucket_seconds)).min(end);
+        let share =
+            slot_end.signed_duration_since(cursor).num_seconds() as f64 / total_seconds as f64;
+        if slot >= 0 {
+            if let Some(bucket) = buckets.get_mut(slot as usize) {
+                add(bucket, share);
+            }
+        }
+        cursor = slot_end;
+    }
+}
+
 fn aligned_series_start(
     now: DateTime<Utc>,
     bucket_minutes: i64,
@@ -1415,9 +1658,54 @@ fn load_dashboard_status(
         last_activity_at_utc,
         sync_summary: load_sync_summary(conn)?,
         db_path_display: db_path.display().to_string(),
+        collector_health: load_collector_health(conn, Utc::now())?,
     })
 }
 
+/// Failures the collector's supervisor recorded and recovered from in the last day.
+fn load_collector_health(conn: &Connection, now: DateTime<Utc>) -> Result<Option<CollectorHealth>> {
+    let since = (now - Duration::hours(24)).to_rfc3339();
+    let failures_24h = conn.query_row(
+        "
+        SELECT COUNT(*)
+        FROM collector_events
+        WHERE kind != 'restarted' AND occurred_at_utc >= ?1
+        ",
+        [&since],
+        |row| row.get::<_, i64>(0),
+    )? as usize;
+    if failures_24h == 0 {
+        return Ok(None);
+    }
+
+    let (task, kind, message, occurred_at) = conn.query_row(
+        "
+        SELECT task, kind, message, occurred_at_utc
+        FROM collector_events
+        WHERE kind != 'restarted' AND occurred_at_utc >= ?1
+        ORDER BY occurred_at_utc DESC, id DESC
+        LIMIT 1
+        ",
+        [&since],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        },
+    )?;
+    let at = parse_rfc3339(&occurred_at)?
+        .with_timezone(&Local)
+        .format("%H:%M");
+
+    Ok(Some(CollectorHealth {
+        failures_24h,
+        last_failure: format!("{task} {kind} {at}: {message}"),
+    }))
+}
+
 fn load_source_names(conn: &Connection) -> Result<Vec<String>> {
     let mut stmt = conn.prepare("SELECT source_name FROM sources ORDER BY source_name ASC")?;
     let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
@@ -1477,7 +1765,7 @@ fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::localdb::DailyActivityRow;
+    use crate::storage::localdb::{open_con_at, setup_database, DailyActivityRow};
 
     fn sample_desktop_entries() -> DesktopEntries {
         HashMap::from([
@@ -1527,21 +1815,25 @@ mod tests {
             FocusUsageRow {
                 app_identifier: "firefox".to_string(),
                 window_title: "YouTube - Mozilla Firefox".to_string(),
+                domain: None,
                 focus_seconds: 100,
             },
             FocusUsageRow {
                 app_identifier: "kitty".to_string(),
                 window_title: "shell".to_string(),
+                domain: None,
                 focus_seconds: 80,
             },
             FocusUsageRow {
                 app_identifier: "nvim".to_string(),
                 window_title: "main.rs".to_string(),
+                domain: None,
                 focus_seconds: 70,
             },
             FocusUsageRow {
                 app_identifier: "discord".to_string(),
                 window_title: "general".to_string(),
+                domain: None,
                 focus_seconds: 50,
             },
         ];
@@ -1562,11 +1854,13 @@ mod tests {
             FocusUsageRow {
                 app_identifier: "brave-browser".to_string(),
                 window_title: "TikTok - Brave".to_string(),
+                domain: None,
                 focus_seconds: 120,
             },
             FocusUsageRow {
                 app_identifier: "brave-browser".to_string(),
                 window_title: "GitHub - Brave".to_string(),
+                domain: None,
                 focus_seconds: 30,
             },
         ];
@@ -1583,11 +1877,13 @@ mod tests {
             FocusUsageRow {
                 app_identifier: "brave-browser".to_string(),
                 window_title: "TikTok - Brave".to_string(),
+                domain: None,
                 focus_seconds: 120,
             },
             FocusUsageRow {
                 app_identifier: "brave-browser".to_string(),
                 window_title: "GitHub - Brave".to_string(),
+                domain: None,
                 focus_seconds: 30,
             },
         ];
@@ -1634,21 +1930,25 @@ mod tests {
             FocusUsageRow {
                 app_identifier: "nvim".to_string(),
                 window_title: "main.rs".to_string(),
+                domain: None,
                 focus_seconds: 200,
             },
             FocusUsageRow {
                 app_identifier: "firefox".to_string(),
                 window_title: "GitHub - Mozilla Firefox".to_string(),
+                domain: None,
                 focus_seconds: 120,
             },
             FocusUsageRow {
                 app_identifier: "ghostty".to_string(),
                 window_title: "shell".to_string(),
+                domain: None,
                 focus_seconds: 100,
             },
             FocusUsageRow {
                 app_identifier: "discord".to_string(),
                 window_title: "general".to_string(),
+                domain: None,
                 focus_seconds: 80,
             },
         ];
@@ -1697,4 +1997,210 @@ mod tests {
         assert!(maxima[0] >= 10.0);
         Ok(())
     }
+
+    /// Verifies that the dashboard counts failures of the last day only, leaves restart rows
+    /// out of the count and describes the most recent failure.
+    #[test]
+    fn collector_health_counts_recent_failures_but_not_restarts() -> Result<()> {
+        use crate::common::{CollectorEventKind, CollectorEventRecord, DEFAULT_SOURCE_ID};
+        use crate::storage::localdb::insert_collector_event;
+
+        let path = std::env::temp_dir().join(format!("vigil-tui-{}.db", uuid::Uuid::new_v4()));
+        let conn = open_con_at(&path)?;
+        setup_database(&conn)?;
+        let now = Utc::now();
+        assert_eq!(load_collector_health(&conn, now)?, None);
+
+        let event = |hours_ago, kind, message: &str| CollectorEventRecord {
+            source_id: DEFAULT_SOURCE_ID,
+            occurred_at_utc: now - Duration::hours(hours_ago),
+            task: "focus".to_string(),
+            kind,
+            message: message.to_string(),
+        };
+        for record in [
+            event(30, CollectorEventKind::Panic, "too old"),
+            event(3, CollectorEventKind::Error, "Wayland listener failed"),
+            event(2, CollectorEventKind::Exited, "exited unexpectedly"),
+            event(1, CollectorEventKind::Restarted, "restart #2 after 2s"),
+        ] {
+            insert_collector_event(&conn, &record)?;
+        }
+
+        let health = load_collector_health(&conn, now)?.unwrap();
+        assert_eq!(health.failures_24h, 2);
+        assert!(health.last_failure.starts_with("focus exited "));
+        assert!(health.last_failure.ends_with(": exited unexpectedly"));
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    /// Verifies that a 15-minute bucket is spread evenly over a 1-minute series and that
+    /// hourly series read the same totals from the rollups.
+    #[test]
+    fn activity_series_spreads_coarse_buckets_and_reads_rollups() -> Result<()> {
+        use crate::common::{InputBucketRecord, DEFAULT_SOURCE_ID};
+        use crate::storage::localdb::insert_input_buckets;
+
+        let path = std::env::temp_dir().join(format!("vigil-tui-{}.db", uuid::Uuid::new_v4()));
+        let conn = open_con_at(&path)?;
+        setup_database(&conn)?;
+        let start = Utc.with_ymd_and_hms(2026, 4, 18, 12, 0, 0).unwrap();
+        insert_input_buckets(
+            &conn,
+            &[InputBucketRecord {
+                source_id: DEFAULT_SOURCE_ID,
+                bucket_start_utc: start,
+                bucket_end_utc: start + Duration::minutes(15),
+                local_date: "2026-04-18".to_string(),
+                local_hour: 12,
+                timezone_offset_minutes: 0,
+                granularity_minutes: 15,
+                left_clicks: 0,
+                right_clicks: 0,
+                middle_clicks: 0,
+                key_presses: 30,
+                mouse_distance_cm: 0.0,
+                scroll_vertical_cm: 0.0,
+                scroll_horizontal_cm: 0.0,
+                dropped_events: 0,
+            }],
+        )?;
+
+        let minutes = load_activity_series(&conn, start, 1, 60)?;
+        assert!(minutes[..15].iter().all(|bucket| bucket.key_presses == 2.0));
+        assert!(minutes[15..].iter().all(|bucket| bucket.key_presses == 0.0));
+
+        let hours = load_activity_series(&conn, start - Duration::hours(1), 60, 3)?;
+        let key_presses: Vec<f64> = hours.iter().map(|bucket| bucket.key_presses).collect();
+        assert_eq!(key_presses, vec![0.0, 30.0, 0.0]);
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    /// Verifies that reported context replaces title guesses only for the buckets and seconds
+    /// it covers, by mixing covered and uncovered browser buckets, an editor bucket and a
+    /// terminal command; the 20 seconds no domain covers stay with Firefox untitled.
+    #[test]
+    fn focus_usage_prefers_reported_context_over_window_titles() -> Result<()> {
+        use crate::common::{
+            DomainBucketRecord, FocusBucketRecord, TerminalBucketRecord, DEFAULT_SOURCE_ID,
+        };
+        use crate::storage::localdb::{
+            insert_domain_buckets, insert_focus_buckets, insert_terminal_buckets,
+        };
+
+        let path = std::env::temp_dir().join(format!("vigil-tui-{}.db", uuid::Uuid::new_v4()));
+        let conn = open_con_at(&path)?;
+        setup_database(&conn)?;
+
+        let covered = Utc::now() - Duration::hours(2);
+        let uncovered = covered + Duration::minutes(15);
+        let focus = |start: DateTime<Utc>, app: &str, title: &str, seconds| FocusBucketRecord {
+            source_id: DEFAULT_SOURCE_ID,
+            bucket_start_utc: start,
+            bucket_end_utc: start + Duration::minutes(15),
+            local_date: "2026-04-20".to_string(),
+            local_hour: 10,
+            timezone_offset_minutes: 0,
+            app_identifier: app.to_string(),
+            window_title: title.to_string(),
+            window_class: app.to_string(),
+            focus_seconds: seconds,
+        };
+        insert_focus_buckets(
+            &conn,
+            &[
+                focus(
+                    covered,
+                    "firefox",
+                    "Pull requests - GitHub - Mozilla Firefox",
+                    300,
+                ),
+                focus(
+                    uncovered,
+                    "firefox",
+                    "Rust - YouTube - Mozilla Firefox",
+                    120,
+                ),
+                focus(covered, "code", "main.rs - Visual Studio Code", 60),
+            ],
+        )?;
+        insert_domain_buckets(
+            &conn,
+            &[DomainBucketRecord {
+                source_id: DEFAULT_SOURCE_ID,
+                bucket_start_utc: covered,
+                bucket_end_utc: covered + Duration::minutes(15),
+                local_date: "2026-04-20".to_string(),
+                local_hour: 10,
+                timezone_offset_minutes: 0,
+                app_identifier: "firefox".to_string(),
+                domain: "news.ycombinator.com".to_string(),
+                last_url: None,
+                focus_seconds: 280,
+            }],
+        )?;
+
+        let rows = load_focus_usage_rows(&conn, None)?;
+        let firefox_titles = rows
+            .iter()
+            .filter(|row| row.app_identifier == "firefox")
+            .map(|row| {
+                (
+                    row.window_title.as_str(),
+                    row.domain.as_deref(),
+                    row.focus_seconds,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            firefox_titles,
+            vec![
+                ("news.ycombinator.com", Some("news.ycombinator.com"), 280),
+                ("Rust - YouTube - Mozilla Firefox", None, 120),
+                ("", None, 20),
+            ]
+        );
+        assert!(rows.iter().any(|row| row.app_identifier == "code"));
+        assert!(!rows.iter().any(|row| row.app_identifier == "kitty"));
+
+        insert_focus_buckets(&conn, &[focus(covered, "kitty", "~/work/vigil", 90)])?;
+        insert_terminal_buckets(
+            &conn,
+            &[TerminalBucketRecord {
+                source_id: DEFAULT_SOURCE_ID,
+                bucket_start_utc: covered,
+                bucket_end_utc: covered + Duration::minutes(15),
+                local_date: "2026-04-20".to_string(),
+                local_hour: 10,
+                timezone_offset_minutes: 0,
+                app_identifier: "kitty".to_string(),
+                command: "cargo".to_string(),
+                working_dir: "~/work/vigil".to_string(),
+                focus_seconds: 90,
+            }],
+        )?;
+        let rows = load_focus_usage_rows(&conn, None)?;
+        let kitty_titles = rows
+            .iter()
+            .filter(|row| row.app_identifier == "kitty")
+            .map(|row| row.window_title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(kitty_titles, vec!["cargo in ~/work/vigil"]);
+        assert_eq!(
+            category_member_label(
+                "firefox",
+                "news.ycombinator.com",
+                Some("news.ycombinator.com")
+            ),
+            "news.ycombinator.com"
+        );
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
 }
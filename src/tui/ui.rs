@@ -265,7 +265,11 @@ fn render_activity_bars(frame: &mut Frame, area: Rect, app: &DashboardApp) {
 
     let apps = app.current_app_list();
     if apps.is_empty() {
-        render_empty_panel(frame, inner, "No focused-app data in the selected range.");
+        let message = match app.app_list_mode {
+            AppListMode::Projects => "No project activity in the selected range.",
+            _ => "No focused-app data in the selected range.",
+        };
+        render_empty_panel(frame, inner, message);
         return;
     }
 
@@ -753,6 +757,12 @@ fn render_footer(frame: &mut Frame, area: Rect, app: &DashboardApp) {
             Style::default().fg(WARN),
         ));
     }
+    if let Some(health) = &app.snapshot.status.collector_health {
+        left_spans.push(Span::styled(
+            format!("  |  {} task failures (24h)", health.failures_24h),
+            Style::default().fg(WARN),
+        ));
+    }
     if !app.status_message.is_empty() {
         left_spans.push(Span::styled("  |  ", Style::default().fg(PANEL)));
         left_spans.push(Span::styled(
@@ -788,11 +798,12 @@ fn render_help_modal(frame: &mut Frame, area: Rect, app: &DashboardApp) {
         .borders(Borders::ALL)
         .border_style(Style::default().fg(ACCENT))
         .style(Style::default().bg(BG));
-    let lines = vec![
+    let mut lines = vec![
         Line::from("q / Esc        quit or close help"),
         Line::from("Tab / Shift-Tab  cycle focus sections"),
         Line::from("1..4           jump: totals, apps, chart, daily"),
-        Line::from("a              toggle apps list mode"),
+        Line::from("a              cycle apps list mode"),
+        Line::from("p              show / hide projects in the apps panel"),
         Line::from("m / j / k      next / advance / reverse chart metric"),
         Line::from("v              toggle chart mode"),
         Line::from("[ / ]          previous / next time window"),
@@ -804,14 +815,27 @@ fn render_help_modal(frame: &mut Frame, area: Rect, app: &DashboardApp) {
         Line::from("The ⚠ indicator in the status bar means the collector"),
         Line::from("has not written new data in the last 20 minutes."),
         Line::from(""),
-        Line::from(format!(
-            "db: {}",
+    ];
+    if let Some(health) = &app.snapshot.status.collector_health {
+        lines.push(Line::from(format!(
+            "last task failure: {}",
             truncate(
-                &app.snapshot.status.db_path_display,
-                popup.width.saturating_sub(8) as usize
+                &health.last_failure,
+                popup.width.saturating_sub(24) as usize
             )
-        )),
-    ];
+        )));
+        lines.push(Line::from(
+            "Failed collector tasks restart on their own after a short delay.",
+        ));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(format!(
+        "db: {}",
+        truncate(
+            &app.snapshot.status.db_path_display,
+            popup.width.saturating_sub(8) as usize
+        )
+    )));
     frame.render_widget(
         Paragraph::new(lines)
             .block(block)
@@ -1509,7 +1533,7 @@ fn focused_panel_hint(app: &DashboardApp) -> String {
         }
         FocusSection::Apps => {
             format!(
-                "\u{2191}\u{2193} scroll apps  a {} mode  u ascii/unicode  tab focus",
+                "\u{2191}\u{2193} scroll apps  a {} mode  p projects  tab focus",
                 app_list_mode_label(app.app_list_mode)
             )
         }
@@ -1529,6 +1553,7 @@ fn app_list_mode_label(mode: AppListMode) -> &'static str {
     match mode {
         AppListMode::Generic => "generic",
         AppListMode::Specific => "specific",
+        AppListMode::Projects => "projects",
     }
 }
 
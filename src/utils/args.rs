@@ -1,12 +1,17 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use clap::{
     builder::styling::{AnsiColor, Effects, Styles},
-    value_parser, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+    value_parser, ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
 };
 use std::path::PathBuf;
 
 use tracing::info;
 
 use crate::common::DEFAULT_MOUSE_DPI;
+#[cfg(target_os = "linux")]
+use crate::platform::linux::evdev::DeviceRule;
+use crate::storage::localdb::{ExportFormat, ExportTable};
+use crate::storage::sink::SinkSpec;
 
 #[cfg(target_os = "linux")]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,6 +28,48 @@ pub enum SyncCommand {
     Status,
 }
 
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Subcommand)]
+pub enum ControlCommand {
+    /// Show uptime, devices, the focused window and buffered row counts.
+    Status,
+    /// Write all buffered activity to the database now.
+    FlushNow,
+    /// Stop recording activity until `resume` is sent.
+    Pause,
+    /// Resume recording after `pause`.
+    Resume,
+    /// Re-read remembered settings such as the mouse DPI.
+    ReloadConfig,
+    /// Flush buffered activity and stop the collector.
+    Shutdown,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Args)]
+#[command(
+    about = "Native messaging host for the Vigil browser extension; reports the active tab's domain."
+)]
+pub struct BrowserHostCli {
+    #[arg(
+        long,
+        help = "Register the native messaging host with Firefox and Chromium-based browsers and exit"
+    )]
+    pub install: bool,
+
+    #[arg(
+        long,
+        value_name = "ID",
+        requires = "install",
+        help = "Extension ID to allow in Chromium-based browsers (shown on chrome://extensions)"
+    )]
+    pub chrome_extension_id: Option<String>,
+
+    /// Arguments browsers pass when launching the host (manifest path, extension origin, ...).
+    #[arg(hide = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub browser_args: Vec<String>,
+}
+
 #[derive(Debug, Clone, Args)]
 #[command(about = "Run the background collector and all collector-related maintenance commands.")]
 pub struct CollectorCli {
@@ -114,6 +161,56 @@ pub struct CollectorCli {
     )]
     pub dpi: Option<u32>,
 
+    #[arg(
+        short = 'g',
+        long,
+        help_heading = "Collection",
+        value_name = "MINUTES",
+        help = "Width of the activity buckets written to the database.",
+        long_help = "Sets how many minutes of activity each row in the database covers.\n\nDefault: 15 minutes. Allowed: 1, 2, 3, 4, 5, 6, 10, 12, 15, 20, 30 or 60.\n\nFiner buckets give the dashboard more detail for short time windows at the cost of a larger database; hourly and daily rollups keep long ranges fast either way. The value is remembered in the database. Rows written earlier keep their granularity.",
+        value_parser = parse_granularity,
+    )]
+    pub granularity: Option<u32>,
+
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long = "include-device",
+        help_heading = "Collection",
+        value_name = "RULE",
+        help = "Linux only: always count input from matching devices. Repeatable.",
+        long_help = "Linux only.\n\nCounts keyboards and mice matching RULE even when an --exclude-device rule or the virtual device default would ignore them. Repeat the flag for several rules.\n\nRULE is one of:\n  name:<text>   device name contains <text> (any case)\n  bus:<bus>     usb, bluetooth, i8042, i2c, host, pci, virtual or a 0x.. id\n  virtual       created in software (uinput), e.g. ydotool, KDE Connect, Barrier\n  physical      everything else\n\nThe startup log lists every device with the decision taken for it."
+    )]
+    pub include_devices: Vec<DeviceRule>,
+
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long = "exclude-device",
+        help_heading = "Collection",
+        value_name = "RULE",
+        help = "Linux only: ignore input from matching devices. Repeatable.",
+        long_help = "Linux only.\n\nIgnores keyboards and mice matching RULE. Virtual devices are already ignored unless an --include-device rule matches them. See --include-device for the rule syntax."
+    )]
+    pub exclude_devices: Vec<DeviceRule>,
+
+    #[arg(
+        long,
+        help_heading = "Database",
+        value_name = "HOURS",
+        value_parser = value_parser!(u64).range(1..),
+        help = "Apply the retention rules in the background every HOURS hours.",
+        long_help = "Runs `vigil maintenance` with the remembered retention rules every HOURS hours while collecting, starting right away.\n\nOff by default; old history is only compacted when you run `vigil maintenance` yourself."
+    )]
+    pub maintenance_interval: Option<u64>,
+
+    #[arg(
+        long = "sink",
+        help_heading = "Database",
+        value_name = "KIND:PATH",
+        help = "Also write every row to a secondary sink. Repeatable.",
+        long_help = "Writes a copy of every row to a secondary sink besides the database. Repeat the flag for several sinks.\n\nKIND:PATH is one of:\n  sqlite:<PATH>  a second Vigil database, e.g. on a network share\n  jsonl:<PATH>   an append-only JSON Lines archive\n\nEach sink is written in the background. A sink that fails keeps its rows queued and is retried, without holding up the database or the other sinks."
+    )]
+    pub sinks: Vec<SinkSpec>,
+
     #[arg(
         short = 'c',
         long,
@@ -185,6 +282,323 @@ pub struct CollectorCli {
 #[command(about = "Open the interactive read-only dashboard backed by the local SQLite database.")]
 pub struct DashboardCli {}
 
+#[derive(Debug, Clone, Args)]
+#[command(about = "Report focus time per detected project.")]
+pub struct ProjectsCli {
+    #[arg(
+        long,
+        value_name = "DAYS",
+        default_value_t = 7,
+        help = "How many days back to report; 0 covers all recorded history."
+    )]
+    pub days: u32,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Show per-day totals for a single project instead of the project ranking."
+    )]
+    pub project: Option<String>,
+}
+
+#[derive(Debug, Clone, Args, Default)]
+#[command(
+    about = "Compact old history according to the retention rules.",
+    long_about = "Compacts old history according to the retention rules: buckets older than --full-days are merged into hourly buckets, those older than --hourly-days into daily ones, and window titles older than --title-days are dropped while app totals are kept.\n\nRules given here are remembered in the database and used by later runs and by `vigil collector --maintenance-interval`. A value of 0 keeps that detail forever. Every compaction is recorded in the `compactions` table."
+)]
+pub struct MaintenanceCli {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Maintain the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DAYS",
+        help = "Keep buckets at full granularity for DAYS days, then hourly (default 90)."
+    )]
+    pub full_days: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "DAYS",
+        help = "Keep hourly buckets for DAYS days, then daily (default 730)."
+    )]
+    pub hourly_days: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "DAYS",
+        help = "Drop window titles older than DAYS days, keeping app totals (default 0: never)."
+    )]
+    pub title_days: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Show what would be compacted without changing the database."
+    )]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum DbCommand {
+    /// Run SQLite's integrity and foreign key checks.
+    Check(DbFileCli),
+    /// Show row counts per table and source, date coverage and file usage.
+    Stats(DbFileCli),
+    /// Rebuild the indexes and compact the file.
+    Vacuum(DbFileCli),
+    /// Copy what is still readable from a corrupt database into a new file.
+    Repair(DbRepairCli),
+    /// Encrypt the database with a new passphrase, or decrypt it.
+    Rekey(DbRekeyCli),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct DbFileCli {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Use the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(
+    long_about = "Copies every row that SQLite can still read from a corrupt database into a new file, skipping damaged pages.\n\nThe database itself is left untouched. Check the new file with `vigil db check --db-path <FILE>`, then stop the collector and move it into place."
+)]
+pub struct DbRepairCli {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Recover the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the recovered database to FILE (default: next to the database)."
+    )]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(
+    long_about = "Rewrites the database so it is encrypted with a new passphrase, encrypting a plain database or decrypting one with --decrypt.\n\nThe current passphrase comes from VIGIL_DB_PASSPHRASE or VIGIL_DB_PASSPHRASE_FILE, as for every other command; leave both unset for a plain database. The new one comes from --new-passphrase-file or VIGIL_DB_NEW_PASSPHRASE. Stop the collector first. The original file is kept next to the database, still readable with the old passphrase.\n\nNeeds a vigil built with the `encryption` feature."
+)]
+pub struct DbRekeyCli {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Rekey the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read the new passphrase from the first line of FILE."
+    )]
+    pub new_passphrase_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "new_passphrase_file",
+        help = "Remove the encryption instead of changing the passphrase."
+    )]
+    pub decrypt: bool,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum SourcesCommand {
+    /// Show every source with its row count and latest bucket.
+    List(DbFileCli),
+    /// Give a source a new name.
+    Rename(SourcesRenameCli),
+    /// Fold one source's activity into another.
+    Merge(SourcesMergeCli),
+    /// Delete a source and all of its activity.
+    Delete(SourcesDeleteCli),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SourcesRenameCli {
+    #[arg(help = "The source's UUID, a unique prefix of it, or its name.")]
+    pub source: String,
+
+    #[arg(help = "The new name.")]
+    pub name: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Use the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(
+    long_about = "Moves every bucket, session and event of FROM into INTO and removes FROM, for a machine that was reinstalled and came back with a new source UUID. Buckets both sources have for the same period are added up.\n\nWith sync, the other devices do the same with the rows they have, and rows FROM pushes later land in INTO."
+)]
+pub struct SourcesMergeCli {
+    #[arg(help = "The source to fold away: its UUID, a unique prefix of it, or its name.")]
+    pub from: String,
+
+    #[arg(help = "The source that keeps the activity.")]
+    pub into: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Use the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SourcesDeleteCli {
+    #[arg(help = "The source's UUID, a unique prefix of it, or its name.")]
+    pub source: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Use the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(
+    about = "Delete or redact recorded activity.",
+    long_about = "Deletes the activity of an app, blanks window titles matching a pattern, or deletes everything recorded in a time range, in every source unless --source picks one. Options combine: `--app firefox --from 2026-03-01` only forgets Firefox from March on.\n\nWith --title-match alone, matching titles are blanked and their focus time is kept for the app. With --app, matching buckets are deleted along with their focus time. Input counts carry no app or title, so only a bare time range deletes them.\n\nThe collector must be stopped first, since it holds up to one interval of activity in memory; rows it left in the spool or offline.db are merged and forgotten too. With sync, the other devices and the remote forget the same rows.\n\nSinks keep their own copy: pass them with --sink. JSON Lines archives are never rewritten, so matching rows stay in them until removed by hand.",
+    group(
+        ArgGroup::new("selection")
+            .required(true)
+            .multiple(true)
+            .args(["app", "title_match", "from", "until"])
+    )
+)]
+pub struct ForgetCli {
+    #[arg(
+        long,
+        value_name = "ID",
+        help = "Forget the activity of the app with this identifier."
+    )]
+    pub app: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Forget window titles, domains, commands and projects matching REGEX."
+    )]
+    pub title_match: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Only forget activity from TIME on (RFC 3339, or local `YYYY-MM-DD [HH:MM]`)."
+    )]
+    pub from: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Only forget activity before TIME."
+    )]
+    pub until: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "SOURCE",
+        help = "Only forget the activity of this source: its UUID, a unique prefix, or its name."
+    )]
+    pub source: Option<String>,
+
+    #[arg(
+        long = "sink",
+        value_name = "KIND:PATH",
+        help = "Also forget in this sink of the collector. Repeatable.",
+        long_help = "Forgets the same rows in a sink the collector writes to with --sink. Repeat the flag for several sinks.\n\nsqlite:<PATH> databases are changed like the main one. jsonl:<PATH> archives are append-only and left as they are, so remove the lines from them by hand."
+    )]
+    pub sinks: Vec<SinkSpec>,
+
+    #[arg(
+        long,
+        help = "Show what would be forgotten without changing the database."
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Use the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(
+    about = "Export tables as CSV, JSON Lines or Parquet files for analysis.",
+    long_about = "Writes sources, sessions, input buckets and focus buckets to one file per table in --output, for loading into pandas, DuckDB or a spreadsheet. The columns of every table are fixed and listed in the README. Times are UTC RFC 3339 text.\n\nThis is not a backup: use `vigil collector --export-db` for a snapshot that can be imported again.\n\nParquet needs a vigil built with the `parquet` feature."
+)]
+pub struct ExportCli {
+    #[arg(long, value_name = "FORMAT", help = "csv, jsonl or parquet.")]
+    pub format: ExportFormat,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write the files into DIR, which may not hold files of the same names yet."
+    )]
+    pub output: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Only export activity from TIME on (RFC 3339, or local `YYYY-MM-DD [HH:MM]`)."
+    )]
+    pub since: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Only export activity before TIME."
+    )]
+    pub until: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "SOURCE",
+        help = "Only export this source: its UUID, a unique prefix, or its name."
+    )]
+    pub source: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "TABLE,...",
+        value_delimiter = ',',
+        help = "Export only these of sources, sessions, input_buckets and focus_buckets (default: all)."
+    )]
+    pub tables: Vec<ExportTable>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Use the SQLite database at a custom path."
+    )]
+    pub db_path: Option<PathBuf>,
+}
+
 #[cfg(feature = "multi-sync")]
 #[derive(Debug, Clone, Args, Default)]
 pub struct SyncCli {
@@ -217,6 +631,20 @@ pub struct SyncCli {
 pub enum Command {
     Collector(CollectorCli),
     Dashboard(DashboardCli),
+    Projects(ProjectsCli),
+    Maintenance(MaintenanceCli),
+    /// Manage the database file itself.
+    Db {
+        #[command(subcommand)]
+        action: DbCommand,
+    },
+    /// List, rename, merge or delete the machines the database holds activity for.
+    Sources {
+        #[command(subcommand)]
+        action: SourcesCommand,
+    },
+    Forget(ForgetCli),
+    Export(ExportCli),
     #[cfg(feature = "multi-sync")]
     Sync {
         #[command(subcommand)]
@@ -224,6 +652,14 @@ pub enum Command {
         #[command(flatten)]
         args: SyncCli,
     },
+    /// Send a command to the running collector through its control socket.
+    #[cfg(target_os = "linux")]
+    Control {
+        #[command(subcommand)]
+        action: ControlCommand,
+    },
+    #[cfg(target_os = "linux")]
+    BrowserHost(BrowserHostCli),
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -235,7 +671,7 @@ pub enum Command {
     long_about = "Vigil records keyboard, mouse, scroll, and focused-window activity into a local SQLite database.\n\nUse `collector` to run the background collector and maintenance commands.\nUse `dashboard` to inspect the database through the interactive terminal dashboard."
 )]
 #[command(
-    after_long_help = "Examples:\n  vigil collector\n  vigil collector --debug --interval 10\n  vigil collector --db-path /mnt/shared/vigil/data.db\n  vigil collector --export-db ./snapshot.sqlite\n  vigil collector --import-db ./snapshot.sqlite --dry-run\n  vigil dashboard"
+    after_long_help = "Examples:\n  vigil collector\n  vigil collector --debug --interval 10\n  vigil collector --db-path /mnt/shared/vigil/data.db\n  vigil collector --export-db ./snapshot.sqlite\n  vigil collector --import-db ./snapshot.sqlite --dry-run\n  vigil control status\n  vigil dashboard\n  vigil projects --days 30\n  vigil maintenance --full-days 90 --dry-run\n  vigil db check\n  vigil sources merge old-laptop laptop\n  vigil forget --title-match '(?i)bank' --dry-run\n  vigil export --format parquet --output ./vigil-export --since 2026-01-01\n  vigil db rekey --new-passphrase-file ./passphrase.txt"
 )]
 pub struct Cli {
     #[command(subcommand)]
@@ -259,6 +695,45 @@ fn cli_styles() -> Styles {
         .error(AnsiColor::Red.on_default() | Effects::BOLD)
 }
 
+/// Bucket granularities divide an hour, so a bucket always fits in one hourly rollup.
+fn parse_granularity(value: &str) -> Result<u32, String> {
+    let minutes: u32 = value
+        .parse()
+        .map_err(|_| format!("'{value}' is not a number of minutes"))?;
+    if minutes == 0 || 60 % minutes != 0 {
+        return Err("must divide an hour: 1, 2, 3, 4, 5, 6, 10, 12, 15, 20, 30 or 60".to_string());
+    }
+    Ok(minutes)
+}
+
+/// Times without an offset are local, and a bare date is its midnight.
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    })
+    .ok_or_else(|| {
+        format!("'{value}' is not a date or time like 2026-03-01 or 2026-03-01 14:30")
+    })?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("'{value}' does not exist in the local time zone"))
+}
+
 impl CollectorCli {
     #[allow(dead_code)]
     pub fn print_args(&self) {
@@ -272,6 +747,12 @@ impl CollectorCli {
         info!("Import database: {:?}", self.import_db);
         info!("Dry-run import: {:?}", self.dry_run);
         info!("Mouse DPI: {:?}", self.dpi.unwrap_or(DEFAULT_MOUSE_DPI));
+        info!("Bucket granularity: {:?}", self.granularity);
+        info!(
+            "Maintenance interval (hours): {:?}",
+            self.maintenance_interval
+        );
+        info!("Sinks: {:?}", self.sinks);
         info!("Clear database: {:?}", self.clear);
         info!("Enable startup: {:?}", self.enable_startup);
         info!("Disable startup: {:?}", self.disable_startup);
@@ -317,6 +798,18 @@ mod tests {
         assert!(rendered.contains("--dpi"));
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn control_subcommand_uses_kebab_case_actions() {
+        let cli = Cli::try_parse_from(["vigil", "control", "flush-now"])
+            .expect("control command should parse");
+
+        let Command::Control { action } = cli.command else {
+            panic!("expected control command");
+        };
+        assert_eq!(action, ControlCommand::FlushNow);
+    }
+
     #[test]
     fn root_command_requires_explicit_subcommand() {
         let err =
@@ -97,7 +97,7 @@ fn mouse_dpi_file() -> Result<PathBuf> {
         .join("mouse-dpi.txt"))
 }
 
-fn load_mouse_dpi() -> Result<Option<u32>> {
+pub fn load_mouse_dpi() -> Result<Option<u32>> {
     let path = mouse_dpi_file()?;
     match fs::read_to_string(&path) {
         Ok(contents) => Ok(parse_mouse_dpi(contents.trim())),
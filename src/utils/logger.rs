@@ -9,6 +9,7 @@ use anyhow::Result;
 
 use tracing::*;
 use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::{fmt, prelude::*};
 
@@ -33,7 +34,8 @@ impl FormatTime for CustomTime {
 
 // This function will define the level that logs will be displayed and also will create a file
 // called spy.log in different paths depending on the platform.
-pub fn init(enable_debug: bool) {
+// `console_to_stderr` is for commands whose stdout is a protocol channel, like `browser-host`.
+pub fn init(enable_debug: bool, console_to_stderr: bool) {
     if enable_debug {
         // We disable logs from other crates that also use tracing so we don't pollute the log file/stdout
         // These unwraps are fine too
@@ -46,7 +48,12 @@ pub fn init(enable_debug: bool) {
             .add_directive("hyper=off".parse().unwrap())
             .add_directive("hyper_util=off".parse().unwrap());
 
-        registry(env_filter_file, env_filter_std, enable_debug);
+        registry(
+            env_filter_file,
+            env_filter_std,
+            enable_debug,
+            console_to_stderr,
+        );
     } else {
         // Display only error, info, and warns to stdout by default.
         let env_filter_std = EnvFilter::try_from_default_env()
@@ -59,11 +66,29 @@ pub fn init(enable_debug: bool) {
             .add_directive("hyper=off".parse().unwrap())
             .add_directive("hyper_util=off".parse().unwrap());
 
-        registry(env_filter_file, env_filter_std, enable_debug);
+        registry(
+            env_filter_file,
+            env_filter_std,
+            enable_debug,
+            console_to_stderr,
+        );
     }
 }
 
-fn registry(env_filter_file: EnvFilter, env_filter_std: EnvFilter, enable_debug: bool) {
+fn console_writer(console_to_stderr: bool) -> BoxMakeWriter {
+    if console_to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    }
+}
+
+fn registry(
+    env_filter_file: EnvFilter,
+    env_filter_std: EnvFilter,
+    enable_debug: bool,
+    console_to_stderr: bool,
+) {
     // This doesn't need to propagate!
     if let Ok((file, path)) = create_file() {
         info!("Log file created at: {}", path.display());
@@ -84,6 +109,7 @@ fn registry(env_filter_file: EnvFilter, env_filter_std: EnvFilter, enable_debug:
         // yeah i am repeating this code bc otherwise it will fail with a error message with a hundred traits and 8 hundred types 1923123 lines long and aint
         // gonna try to fix it
         let stdout_layer = fmt::layer()
+            .with_writer(console_writer(console_to_stderr))
             .with_target(false)
             .without_time()
             .event_format(
@@ -103,6 +129,7 @@ fn registry(env_filter_file: EnvFilter, env_filter_std: EnvFilter, enable_debug:
         error!("Failed to create log file, logging to file is disabled.");
 
         let stdout_layer = fmt::layer()
+            .with_writer(console_writer(console_to_stderr))
             .with_target(false)
             .without_time()
             .event_format(
@@ -2,3 +2,5 @@ pub mod args;
 pub mod dpi;
 pub mod lock;
 pub mod logger;
+pub mod shutdown;
+pub mod supervisor;
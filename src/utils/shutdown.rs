@@ -0,0 +1,148 @@
+//! Stopping the collector without losing what it buffered.
+//!
+//! SIGTERM (systemd stopping the unit, logout) and SIGINT stop intake, drain every buffer
+//! through the collector tasks' regular flush, and close the session as of the moment the
+//! signal arrived, with the signal as the reason. The whole sequence is bounded by
+//! [`SHUTDOWN_TIMEOUT`], so a hung database never keeps the process from exiting. Linux asks
+//! the tasks to flush through the control state; Windows, which has no control socket, through
+//! a [`FinalFlush`].
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+#[cfg(target_os = "windows")]
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::*;
+
+#[cfg(target_os = "linux")]
+use crate::control::CollectorControl;
+use crate::storage::backend::StorageBackend;
+
+/// Upper bound for flushing, stopping the tasks and closing the session. systemd sends
+/// SIGKILL after 90 seconds by default.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The signals that ask the collector to stop, registered up front so none is missed.
+pub struct ShutdownSignals {
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+}
+
+impl ShutdownSignals {
+    pub fn new() -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                terminate: signal(SignalKind::terminate())?,
+                interrupt: signal(SignalKind::interrupt())?,
+            })
+        }
+        #[cfg(not(unix))]
+        Ok(Self {})
+    }
+
+    /// Waits for the next signal and returns the reason recorded for it.
+    pub async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.terminate.recv() => "sigterm",
+            _ = self.interrupt.recv() => "sigint",
+        }
+        #[cfg(not(unix))]
+        {
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            "ctrl-c"
+        }
+    }
+}
+
+/// Asks the Windows collector tasks to drain their buffers one last time and stop counting.
+#[cfg(target_os = "windows")]
+#[derive(Debug, Clone)]
+pub struct FinalFlush {
+    requested: watch::Sender<bool>,
+    completed: watch::Sender<usize>,
+}
+
+#[cfg(target_os = "windows")]
+impl Default for FinalFlush {
+    fn default() -> Self {
+        let (requested, _) = watch::channel(false);
+        let (completed, _) = watch::channel(0);
+        Self {
+            requested,
+            completed,
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl FinalFlush {
+    /// For a collector task: changes once shutdown asks for the final flush.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.requested.subscribe()
+    }
+
+    /// Called by a collector task once its buffers are committed.
+    pub fn ack(&self) {
+        self.completed.send_modify(|completed| *completed += 1);
+    }
+
+    /// Asks every subscribed task to flush and waits until each acknowledged it. Returns how
+    /// many tasks flushed.
+    async fn run(&self) -> usize {
+        let mut completed = self.completed.subscribe();
+        let expected = self.requested.receiver_count();
+        self.requested.send_replace(true);
+        let _ = completed.wait_for(|completed| *completed >= expected).await;
+        expected
+    }
+}
+
+/// Stops intake, flushes every task's buffers, stops the tasks and closes the session.
+pub async fn shut_down(
+    reason: &'static str,
+    tasks: &mut JoinSet<Result<()>>,
+    #[cfg(target_os = "linux")] control: &CollectorControl,
+    #[cfg(target_os = "windows")] final_flush: &FinalFlush,
+    backend: &StorageBackend,
+) {
+    let stopped_at = Utc::now();
+    info!("Shutting down the collector ({reason})");
+
+    let finish = async {
+        #[cfg(target_os = "linux")]
+        {
+            control.stop_intake();
+            match control.flush_now().await {
+                Ok(tasks) => info!("Flushed buffered activity of {tasks} tasks"),
+                Err(err) => error!("Failed to flush buffered activity before shutdown: {err:#}"),
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            let tasks = final_flush.run().await;
+            info!("Flushed buffered activity of {tasks} tasks");
+        }
+        tasks.shutdown().await;
+        if let Err(err) = backend.end_session(stopped_at, reason).await {
+            error!("Failed to close the collection session: {err:#}");
+        }
+        backend.flush_sinks().await;
+    };
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, finish)
+        .await
+        .is_err()
+    {
+        error!(
+            "Shutdown did not finish within {}s, exiting anyway",
+            SHUTDOWN_TIMEOUT.as_secs()
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Keeps the collector's long-running tasks alive.
+//!
+//! A task that fails, panics or returns is started again after a backoff that doubles with
+//! every failure in a row, up to [`MAX_BACKOFF`], and starts over once the task stayed up for
+//! [`STABLE_AFTER`]. Failures and restarts go to the `collector_events` table, which the
+//! dashboard summarizes, so a collector that keeps recovering is still noticed.
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::task::{JoinError, JoinHandle};
+use tracing::*;
+
+use crate::common::{CollectorEventKind, CollectorEventRecord};
+use crate::storage::backend::{DataStore, StorageBackend};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// A task that ran this long before failing is considered healthy again.
+const STABLE_AFTER: Duration = Duration::from_secs(600);
+
+/// Restart delays for one task.
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            next: INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before restarting a task that failed after running for `uptime`.
+    fn after_failure(&mut self, uptime: Duration) -> Duration {
+        if uptime >= STABLE_AFTER {
+            self.next = INITIAL_BACKOFF;
+        }
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+}
+
+fn describe_join_error(err: JoinError) -> (CollectorEventKind, String) {
+    if !err.is_panic() {
+        return (CollectorEventKind::Error, err.to_string());
+    }
+    let payload = err.into_panic();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panicked".to_string());
+    (CollectorEventKind::Panic, message)
+}
+
+async fn record_event(
+    backend: &StorageBackend,
+    task: &str,
+    kind: CollectorEventKind,
+    message: String,
+) {
+    let event = CollectorEventRecord {
+        source_id: backend.source_id(),
+        occurred_at_utc: chrono::Utc::now(),
+        task: task.to_string(),
+        kind,
+        message,
+    };
+    if let Err(err) = backend.store_collector_event(&event).await {
+        error!("Error sending data to collector_events table: {err:?}");
+    }
+}
+
+/// A spawned task that is aborted when the handle is dropped, so cancelling the supervisor
+/// also stops the task it is waiting on instead of leaving it detached.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = std::result::Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Runs the task built by `start` and starts a fresh one whenever it ends. Only returns when
+/// the surrounding task is cancelled.
+pub async fn supervise<F, Fut>(
+    task: &'static str,
+    backend: StorageBackend,
+    mut start: F,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut backoff = Backoff::default();
+    let mut restarts = 0u32;
+    loop {
+        let started_at = Instant::now();
+        // A separate task, so a panic ends up here instead of unwinding the supervisor, and
+        // aborted along with it.
+        let (kind, message) = match AbortOnDrop(tokio::spawn(start())).await {
+            Ok(Ok(())) => (
+                CollectorEventKind::Exited,
+                "exited unexpectedly".to_string(),
+            ),
+            Ok(Err(err)) => (CollectorEventKind::Error, format!("{err:#}")),
+            Err(err) => describe_join_error(err),
+        };
+        error!(
+            "Collector task {task} stopped ({}): {message}",
+            kind.as_str()
+        );
+        record_event(&backend, task, kind, message).await;
+
+        let delay = backoff.after_failure(started_at.elapsed());
+        info!("Restarting collector task {task} in {}s", delay.as_secs());
+        tokio::time::sleep(delay).await;
+
+        restarts += 1;
+        record_event(
+            &backend,
+            task,
+            CollectorEventKind::Restarted,
+            format!("restart #{restarts} after {}s", delay.as_secs()),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verifies that restart delays double while a task keeps failing, stop growing at the
+    /// cap, and start over once the task stayed up long enough.
+    #[test]
+    fn backoff_doubles_and_resets_after_a_stable_run() {
+        let mut backoff = Backoff::default();
+        let quick = Duration::from_secs(2);
+        let delays = (0..11)
+            .map(|_| backoff.after_failure(quick).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]);
+
+        assert_eq!(backoff.after_failure(STABLE_AFTER), INITIAL_BACKOFF);
+        assert_eq!(backoff.after_failure(quick), Duration::from_secs(2));
+    }
+
+    /// Verifies that panics are told apart from other join errors and keep their message.
+    #[tokio::test]
+    async fn panics_are_described_with_their_message() {
+        let err = tokio::spawn(async { panic!("listener died") })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            describe_join_error(err),
+            (CollectorEventKind::Panic, "listener died".to_string())
+        );
+
+        let handle = tokio::spawn(std::future::pending::<()>());
+        handle.abort();
+        let (kind, _) = describe_join_error(handle.await.unwrap_err());
+        assert_eq!(kind, CollectorEventKind::Error);
+    }
+
+    /// Verifies that cancelling whoever awaits a supervised task also stops the task.
+    #[tokio::test]
+    async fn dropping_the_guard_aborts_the_task() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+        let waiter = tokio::spawn(AbortOnDrop(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                if tx.send(()).is_err() {
+                    return;
+                }
+            }
+        })));
+        rx.recv().await.unwrap();
+        waiter.abort();
+        assert!(waiter.await.unwrap_err().is_cancelled());
+
+        // The sender is dropped with the aborted task, which closes the channel.
+        while rx.recv().await.is_some() {}
+    }
+}